@@ -0,0 +1,177 @@
+//! Fixture builders shared between benchmarks.
+//!
+//! Fixtures are built outside of the measured closures. Key pairs are derived from
+//! `KEY_SEED`, so every run signs and hashes the same bytes and runs are comparable.
+
+#![allow(dead_code)]
+
+use rand::{rngs::StdRng, SeedableRng};
+use std::collections::BTreeMap;
+use tw_chain::crypto::hd_ed25519::HdKey;
+use tw_chain::crypto::sign_ed25519::{self as sign, PublicKey, SecretKey};
+use tw_chain::primitives::asset::{Asset, TokenAmount};
+use tw_chain::primitives::druid::{DdeValues, DruidExpectation};
+use tw_chain::primitives::transaction::{OutPoint, Transaction, TxIn, TxOut};
+use tw_chain::script::lang::Script;
+use tw_chain::script::{OpCodes, StackEntry};
+use tw_chain::utils::druid_utils::generate_druid;
+use tw_chain::utils::transaction_utils::{
+    construct_address, construct_tx_hash, update_input_signatures,
+};
+
+/// Amount held by every fixture UTXO
+pub const UTXO_AMOUNT: u64 = 1000;
+
+/// Seed every fixture key pair is derived from
+pub const KEY_SEED: &[u8] = b"tw_chain benchmarks";
+
+/// Derives the key pair at `index` from `KEY_SEED`
+pub fn key_pair(index: u32) -> (PublicKey, SecretKey) {
+    let child = HdKey::from_seed(KEY_SEED).derive_child(index);
+    child
+        .expect("fixture key index is below HARDENED_OFFSET")
+        .key_pair()
+}
+
+/// Derives the first `n` key pairs from `KEY_SEED`
+pub fn key_pairs(n: usize) -> Vec<(PublicKey, SecretKey)> {
+    (0..n as u32).map(key_pair).collect()
+}
+
+/// Builds a signed P2PKH transaction spending `num_inputs` UTXOs into `num_outputs` outputs,
+/// along with the UTXO set it spends from
+pub fn p2pkh_tx(num_inputs: usize, num_outputs: usize) -> (Transaction, BTreeMap<OutPoint, TxOut>) {
+    let keys = key_pairs(num_inputs);
+    let mut utxo_set = BTreeMap::new();
+    let mut key_material = BTreeMap::new();
+    let mut inputs = Vec::with_capacity(num_inputs);
+
+    for (i, (pk, sk)) in keys.into_iter().enumerate() {
+        let out_point = OutPoint::new(format!("g{i:031x}"), 0);
+        let tx_out =
+            TxOut::new_token_amount(construct_address(&pk), TokenAmount(UTXO_AMOUNT), None);
+
+        utxo_set.insert(out_point.clone(), tx_out);
+        key_material.insert(out_point.clone(), (pk, sk));
+        inputs.push(TxIn {
+            previous_out: Some(out_point),
+            script_signature: Script::new(),
+        });
+    }
+
+    let outputs = token_outputs(num_inputs as u64 * UTXO_AMOUNT, num_outputs);
+    let tx = Transaction {
+        inputs: update_input_signatures(&inputs, &outputs, &key_material),
+        outputs,
+        ..Default::default()
+    };
+
+    (tx, utxo_set)
+}
+
+/// Splits `total` tokens evenly into `num_outputs` outputs, with any remainder in the first
+pub fn token_outputs(total: u64, num_outputs: usize) -> Vec<TxOut> {
+    let (pk, _) = key_pair(0);
+    let address = construct_address(&pk);
+    let share = total / num_outputs as u64;
+    let remainder = total - share * num_outputs as u64;
+
+    (0..num_outputs)
+        .map(|i| {
+            let amount = if i == 0 { share + remainder } else { share };
+            TxOut::new_token_amount(address.clone(), TokenAmount(amount), None)
+        })
+        .collect()
+}
+
+/// Builds an `m`-of-`n` multisig validation script whose signatures are made by the
+/// last `m` keys, so that verification has to scan the most keys
+pub fn multisig_script(m: usize, n: usize) -> Script {
+    let check_data = "g48dda5bbe9171a6656206ec56c595c5".to_owned();
+    let keys = key_pairs(n);
+    let signatures = keys[n - m..]
+        .iter()
+        .map(|(_, sk)| sign::sign_detached(check_data.as_bytes(), sk))
+        .collect();
+    let pub_keys = keys.iter().map(|(pk, _)| *pk).collect();
+
+    Script::multisig_validation(m, n, check_data, signatures, pub_keys)
+}
+
+/// Builds a P2PKH script of `num_entries` entries, padding a valid signature check
+/// with leading `OP_1 OP_DROP` pairs
+pub fn p2pkh_script(num_entries: usize) -> Script {
+    let (pk, sk) = key_pair(0);
+    let check_data = "g48dda5bbe9171a6656206ec56c595c5".to_owned();
+    let signature = sign::sign_detached(check_data.as_bytes(), &sk);
+    let p2pkh = Script::pay2pkh(check_data, signature, pk, None);
+    let padding = (p2pkh.stack.len()..num_entries).step_by(2).flat_map(|_| {
+        [
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_DROP),
+        ]
+    });
+
+    Script::from(padding.chain(p2pkh.stack).collect::<Vec<_>>())
+}
+
+/// Outpoint spent by the first transaction of `chained_txs`
+pub fn chain_start() -> OutPoint {
+    OutPoint::new("g0".to_owned(), 0)
+}
+
+/// Builds `num_txs` transactions, each spending the first output of the one before,
+/// in the order they spend each other
+pub fn chained_txs(num_txs: usize) -> Vec<Transaction> {
+    let mut txs = Vec::with_capacity(num_txs);
+    let mut previous_out = chain_start();
+
+    for _ in 0..num_txs {
+        let tx = Transaction {
+            inputs: vec![TxIn {
+                previous_out: Some(previous_out),
+                script_signature: Script::new(),
+            }],
+            outputs: token_outputs(UTXO_AMOUNT, 2),
+            ..Default::default()
+        };
+        previous_out = OutPoint::new(construct_tx_hash(&tx), 0);
+        txs.push(tx);
+    }
+
+    txs
+}
+
+/// Builds a block of `num_txs` chained transactions, keyed by the outpoint of their
+/// first output
+pub fn chained_block(num_txs: usize) -> BTreeMap<OutPoint, Transaction> {
+    chained_txs(num_txs)
+        .into_iter()
+        .map(|tx| (OutPoint::new(construct_tx_hash(&tx), 0), tx))
+        .collect()
+}
+
+/// Builds one signed transaction of a DDE trade between `participants` parties,
+/// each expecting a payment from the previous party
+pub fn dde_tx(participants: usize) -> Transaction {
+    let addresses: Vec<String> = key_pairs(participants)
+        .iter()
+        .map(|(pk, _)| construct_address(pk))
+        .collect();
+    let expectations = (0..participants)
+        .map(|i| DruidExpectation {
+            from: addresses[(i + participants - 1) % participants].clone(),
+            to: addresses[i].clone(),
+            asset: Asset::token_u64(UTXO_AMOUNT),
+        })
+        .collect();
+
+    let (mut tx, _) = p2pkh_tx(1, 1);
+    tx.druid_info = Some(DdeValues {
+        druid: generate_druid(&mut StdRng::seed_from_u64(0)),
+        participants,
+        expectations,
+        genesis_hash: None,
+    });
+    tx
+}
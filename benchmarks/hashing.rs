@@ -0,0 +1,120 @@
+mod common;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tw_chain::constants::{TX_HASH_LENGTH, TX_PREPEND};
+use tw_chain::crypto::sha3_256;
+use tw_chain::primitives::transaction::{OutPoint, Transaction};
+use tw_chain::utils::transaction_utils::{
+    construct_address, construct_address_raw, construct_tx_hash_streaming,
+    construct_tx_in_signable_hash, construct_tx_in_signable_hash_raw,
+};
+
+/// System allocator recording the peak number of bytes allocated at once
+struct PeakAlloc {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = self.current.fetch_add(layout.size(), Ordering::Relaxed);
+            self.peak
+                .fetch_max(current + layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        self.current.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc {
+    current: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+};
+
+/// Bytes allocated at once by `f` beyond what was already allocated
+fn peak_allocation(f: impl FnOnce()) -> usize {
+    let base = ALLOCATOR.current.load(Ordering::Relaxed);
+    ALLOCATOR.peak.store(base, Ordering::Relaxed);
+    f();
+    ALLOCATOR.peak.load(Ordering::Relaxed) - base
+}
+
+/// Hashes a transaction by serializing it whole first, as `construct_tx_hash` used to
+fn construct_tx_hash_buffered(tx: &Transaction) -> String {
+    let bytes = bincode::serialize(tx).unwrap_or_default();
+    let mut hash = hex::encode(sha3_256::digest(&bytes));
+    hash.insert(0, TX_PREPEND as char);
+    hash.truncate(TX_HASH_LENGTH);
+    hash
+}
+
+fn out_point_hashing(c: &mut Criterion) {
+    let out_point = OutPoint::new("g48dda5bbe9171a6656206ec56c595c5".to_owned(), 1234);
+
+    let mut group = c.benchmark_group("tx_in_signable_hash");
+    group.bench_function("string", |b| {
+        b.iter(|| construct_tx_in_signable_hash(black_box(&out_point)))
+    });
+    group.bench_function("raw", |b| {
+        b.iter(|| construct_tx_in_signable_hash_raw(black_box(&out_point)))
+    });
+    group.finish();
+}
+
+fn address_hashing(c: &mut Criterion) {
+    let (pk, _) = common::key_pair(0);
+
+    let mut group = c.benchmark_group("address");
+    group.bench_function("string", |b| b.iter(|| construct_address(black_box(&pk))));
+    group.bench_function("raw", |b| b.iter(|| construct_address_raw(black_box(&pk))));
+    group.finish();
+}
+
+/// Compares hashing a 5,000-output transaction by streaming its serialization into the
+/// hash against serializing it whole first, printing the peak allocation of each first.
+///
+/// Measured on x86_64 Linux, release profile, `-- --warm-up-time 1 --measurement-time 3`:
+/// buffered peaks at 465,225 bytes with a 2.49 ms median, streaming at 192 bytes with a
+/// 2.77 ms median. Streaming is slightly slower because bincode feeds the hasher many
+/// small writes, but its memory no longer grows with the transaction
+fn tx_hashing(c: &mut Criterion) {
+    let tx = Transaction {
+        outputs: common::token_outputs(5000 * common::UTXO_AMOUNT, 5000),
+        ..Default::default()
+    };
+    assert_eq!(
+        construct_tx_hash_buffered(&tx),
+        construct_tx_hash_streaming(&tx)
+    );
+    for (name, hash) in [
+        (
+            "buffered",
+            construct_tx_hash_buffered as fn(&Transaction) -> String,
+        ),
+        ("streaming", construct_tx_hash_streaming),
+    ] {
+        let peak = peak_allocation(|| drop(black_box(hash(&tx))));
+        println!("tx_hash_5000_outputs/{name}: peak allocation {peak} bytes");
+    }
+
+    let mut group = c.benchmark_group("tx_hash_5000_outputs");
+    group.bench_function("buffered", |b| {
+        b.iter(|| construct_tx_hash_buffered(black_box(&tx)))
+    });
+    group.bench_function("streaming", |b| {
+        b.iter(|| construct_tx_hash_streaming(black_box(&tx)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, out_point_hashing, address_hashing, tx_hashing);
+criterion_main!(benches);
@@ -0,0 +1,219 @@
+//! Benchmarks `construct_tx_hash`, which hashes the bincode serialization of a transaction,
+//! against hashing a hand-rolled canonical encoding of the same transaction.
+//!
+//! Methodology: `cargo bench --bench serialization`. Throughput is the size of the
+//! encoding being hashed, so the two encoders are reported against their own sizes.
+//! Baselines were taken with `-- --warm-up-time 1 --measurement-time 3`
+//! on an x86_64 Linux machine, release profile, reporting the criterion median.
+//!
+//! | Benchmark                                      | Bytes | Latency  | Throughput   |
+//! |------------------------------------------------|-------|----------|--------------|
+//! | construct_tx_hash/minimal_1_in_1_out/bincode   | 483   | 3.37 µs  | 136.5 MiB/s  |
+//! | construct_tx_hash/minimal_1_in_1_out/canonical | 396   | 3.05 µs  | 123.7 MiB/s  |
+//! | construct_tx_hash/p2pkh_10_in_10_out/bincode   | 4533  | 36.8 µs  | 117.4 MiB/s  |
+//! | construct_tx_hash/p2pkh_10_in_10_out/canonical | 3771  | 30.3 µs  | 118.7 MiB/s  |
+//! | construct_tx_hash/dde_3_participants/bincode   | 1011  | 8.68 µs  | 111.1 MiB/s  |
+//! | construct_tx_hash/dde_3_participants/canonical | 883   | 7.72 µs  | 109.0 MiB/s  |
+//!
+//! Both encoders hash at about the same rate per byte, so hashing dominates and encoding
+//! itself is not a bottleneck. The canonical encoding is 10-18% faster only because its
+//! `u32` length prefixes make it 13-18% smaller than bincode's `u64` ones. Speed is
+//! therefore no reason to keep bincode; a canonical encoder should be chosen for being
+//! stable across serializer versions, with compact length prefixes as a small bonus.
+
+mod common;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use tw_chain::constants::{TX_HASH_LENGTH, TX_PREPEND};
+use tw_chain::crypto::sha3_256;
+use tw_chain::primitives::asset::Asset;
+use tw_chain::primitives::druid::DdeValues;
+use tw_chain::primitives::transaction::{OutPoint, Transaction, TxIn, TxOut};
+use tw_chain::script::StackEntry;
+use tw_chain::utils::transaction_utils::construct_tx_hash;
+
+/// Canonical encoding: fields in declaration order, little-endian fixed-width integers,
+/// `u32` length prefixes on strings and sequences, and a tag byte before every enum
+/// variant and option
+#[derive(Default)]
+struct Canonical(Vec<u8>);
+
+impl Canonical {
+    fn tag(&mut self, tag: u8) {
+        self.0.push(tag);
+    }
+
+    fn u64(&mut self, v: u64) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn bytes(&mut self, b: &[u8]) {
+        self.len(b.len());
+        self.0.extend_from_slice(b);
+    }
+
+    fn len(&mut self, len: usize) {
+        self.0.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+
+    fn opt_str(&mut self, s: Option<&str>) {
+        match s {
+            Some(s) => {
+                self.tag(1);
+                self.bytes(s.as_bytes());
+            }
+            None => self.tag(0),
+        }
+    }
+
+    fn out_point(&mut self, out_point: &OutPoint) {
+        self.bytes(out_point.t_hash.as_bytes());
+        self.0.extend_from_slice(&out_point.n.to_le_bytes());
+    }
+
+    fn stack_entry(&mut self, entry: &StackEntry) {
+        match entry {
+            StackEntry::Op(op) => {
+                self.tag(0);
+                self.tag(op.clone() as u8);
+            }
+            StackEntry::Signature(sig) => {
+                self.tag(1);
+                self.0.extend_from_slice(sig.as_ref());
+            }
+            StackEntry::PubKey(pk) => {
+                self.tag(2);
+                self.0.extend_from_slice(pk.as_ref());
+            }
+            StackEntry::Num(n) => {
+                self.tag(3);
+                self.u64(*n as u64);
+            }
+            StackEntry::Bytes(s) => {
+                self.tag(4);
+                self.bytes(s.as_bytes());
+            }
+        }
+    }
+
+    fn tx_in(&mut self, tx_in: &TxIn) {
+        match &tx_in.previous_out {
+            Some(out_point) => {
+                self.tag(1);
+                self.out_point(out_point);
+            }
+            None => self.tag(0),
+        }
+        self.len(tx_in.script_signature.stack.len());
+        for entry in &tx_in.script_signature.stack {
+            self.stack_entry(entry);
+        }
+    }
+
+    fn asset(&mut self, asset: &Asset) {
+        match asset {
+            Asset::Token(amount) => {
+                self.tag(0);
+                self.u64(amount.0);
+            }
+            Asset::Item(item) => {
+                self.tag(1);
+                self.u64(item.amount);
+                self.opt_str(item.genesis_hash.as_deref());
+                self.opt_str(item.metadata.as_deref());
+            }
+            Asset::TokenWithMetadata {
+                amount,
+                metadata,
+                genesis_hash,
+            } => {
+                self.tag(2);
+                self.u64(amount.0);
+                self.bytes(metadata.name.as_bytes());
+                self.bytes(metadata.symbol.as_bytes());
+                self.tag(metadata.decimals);
+                self.u64(metadata.total_supply);
+                self.opt_str(genesis_hash.as_deref());
+            }
+        }
+    }
+
+    fn tx_outs(&mut self, tx_outs: &[TxOut]) {
+        self.len(tx_outs.len());
+        for tx_out in tx_outs {
+            self.asset(&tx_out.value);
+            self.u64(tx_out.locktime);
+            self.opt_str(tx_out.script_public_key.as_deref());
+        }
+    }
+
+    fn druid_info(&mut self, druid_info: &DdeValues) {
+        self.bytes(druid_info.druid.as_bytes());
+        self.u64(druid_info.participants as u64);
+        self.len(druid_info.expectations.len());
+        for expectation in &druid_info.expectations {
+            self.bytes(expectation.from.as_bytes());
+            self.bytes(expectation.to.as_bytes());
+            self.asset(&expectation.asset);
+        }
+        self.opt_str(druid_info.genesis_hash.as_deref());
+    }
+
+    fn encode(tx: &Transaction) -> Vec<u8> {
+        let mut c = Canonical::default();
+        c.len(tx.inputs.len());
+        for tx_in in &tx.inputs {
+            c.tx_in(tx_in);
+        }
+        c.tx_outs(&tx.outputs);
+        c.u64(tx.version as u64);
+        c.tx_outs(&tx.fees);
+        match &tx.druid_info {
+            Some(druid_info) => {
+                c.tag(1);
+                c.druid_info(druid_info);
+            }
+            None => c.tag(0),
+        }
+        c.0
+    }
+}
+
+/// Hashes a transaction as `construct_tx_hash` does, but over its canonical encoding
+fn construct_tx_hash_canonical(tx: &Transaction) -> String {
+    let mut hash = hex::encode(sha3_256::digest(&Canonical::encode(tx)));
+    hash.insert(0, TX_PREPEND as char);
+    hash.truncate(TX_HASH_LENGTH);
+    hash
+}
+
+fn tx_hash_encoding(c: &mut Criterion) {
+    let fixtures = [
+        ("minimal_1_in_1_out", common::p2pkh_tx(1, 1).0),
+        ("p2pkh_10_in_10_out", common::p2pkh_tx(10, 10).0),
+        ("dde_3_participants", common::dde_tx(3)),
+    ];
+
+    for (name, tx) in &fixtures {
+        // Distinct encodings must give distinct hashes, so neither side can be elided
+        assert_ne!(construct_tx_hash(tx), construct_tx_hash_canonical(tx));
+
+        let mut group = c.benchmark_group(format!("construct_tx_hash/{name}"));
+        let bincode_len = bincode::serialize(tx).unwrap().len();
+        group.throughput(Throughput::Bytes(bincode_len as u64));
+        group.bench_function("bincode", |b| b.iter(|| construct_tx_hash(black_box(tx))));
+        let canonical_len = Canonical::encode(tx).len();
+        group.throughput(Throughput::Bytes(canonical_len as u64));
+        group.bench_function("canonical", |b| {
+            b.iter(|| construct_tx_hash_canonical(black_box(tx)))
+        });
+        group.finish();
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().noise_threshold(0.05);
+    targets = tx_hash_encoding
+}
+criterion_main!(benches);
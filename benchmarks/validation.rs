@@ -0,0 +1,163 @@
+//! Benchmarks for the transaction validation hot paths.
+//!
+//! Methodology: `cargo bench --bench validation`. Fixtures are built once per benchmark,
+//! outside of the measured closure; `update_utxo_set` and the `UtxoSet` benchmarks clone
+//! their input per batch since they mutate it. Baselines were taken with `-- --warm-up-time 1 --measurement-time 3`
+//! on an x86_64 Linux machine, release profile, reporting the criterion median.
+//!
+//! | Benchmark                           | Baseline  |
+//! |-------------------------------------|-----------|
+//! | tx_is_valid/p2pkh_1_in_2_out        | 64.8 µs   |
+//! | tx_is_valid/consolidation_200_in    | 20.0 ms   |
+//! | interpret/multisig_2_of_3           | 128.1 µs  |
+//! | interpret/multisig_max              | 869.9 µs  |
+//! | interpret/p2pkh_20_entries          | 57.6 µs   |
+//! | interpret/p2pkh_20_entries_execute  | 61.9 µs   |
+//! | tx_hashing/construct_tx_hash_50_out | 28.6 µs   |
+//! | tx_hashing/signable_hash_50_out     | 49.1 µs   |
+//! | utxo_set/update_1000_tx_block       | 458.7 µs  |
+//! | utxo_set/apply_1000_tx_block        | 4.7 ms    |
+//! | utxo_set/undo_1000_tx_block         | 5.6 ms    |
+//!
+//! Changes within the 5% noise threshold configured below are reported as noise;
+//! anything criterion flags as a regression beyond it should be investigated.
+
+mod common;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use tw_chain::constants::MAX_PUB_KEYS_PER_MULTISIG;
+use tw_chain::primitives::asset::TokenAmount;
+use tw_chain::primitives::transaction::TxOut;
+use tw_chain::utils::script_utils::tx_is_valid;
+use tw_chain::utils::transaction_utils::{
+    construct_tx_hash, construct_tx_in_out_signable_hash, update_utxo_set, UtxoMap,
+};
+use tw_chain::utils::utxo_set::UtxoSet;
+
+fn tx_validation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tx_is_valid");
+
+    let (tx, utxo_set) = common::p2pkh_tx(1, 2);
+    assert!(tx_is_valid(&tx, 0, |v| utxo_set.get(v)).is_ok());
+    group.bench_function("p2pkh_1_in_2_out", |b| {
+        b.iter(|| tx_is_valid(black_box(&tx), 0, |v| utxo_set.get(v)))
+    });
+
+    let (tx, utxo_set) = common::p2pkh_tx(200, 1);
+    assert!(tx_is_valid(&tx, 0, |v| utxo_set.get(v)).is_ok());
+    group.bench_function("consolidation_200_in", |b| {
+        b.iter(|| tx_is_valid(black_box(&tx), 0, |v| utxo_set.get(v)))
+    });
+
+    group.finish();
+}
+
+fn script_interpret(c: &mut Criterion) {
+    let mut group = c.benchmark_group("interpret");
+
+    let script = common::multisig_script(2, 3);
+    assert!(script.interpret());
+    group.bench_function("multisig_2_of_3", |b| {
+        b.iter(|| black_box(&script).interpret())
+    });
+
+    let n = MAX_PUB_KEYS_PER_MULTISIG;
+    let script = common::multisig_script(n / 2 + 1, n);
+    assert!(script.interpret());
+    group.bench_function("multisig_max", |b| {
+        b.iter(|| black_box(&script).interpret())
+    });
+
+    // `interpret` clones the script, `execute` consumes it: only the former is measured
+    // with the clone
+    let script = common::p2pkh_script(20);
+    assert_eq!(script.stack.len(), 20);
+    assert!(script.interpret());
+    group.bench_function("p2pkh_20_entries", |b| {
+        b.iter(|| black_box(&script).interpret())
+    });
+    group.bench_function("p2pkh_20_entries_execute", |b| {
+        b.iter_batched(
+            || script.clone(),
+            |script| black_box(script).execute(),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn tx_hashing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tx_hashing");
+    let (tx, _) = common::p2pkh_tx(1, 50);
+
+    group.bench_function("construct_tx_hash_50_out", |b| {
+        b.iter(|| construct_tx_hash(black_box(&tx)))
+    });
+    group.bench_function("signable_hash_50_out", |b| {
+        b.iter(|| construct_tx_in_out_signable_hash(black_box(&tx.inputs[0]), &tx.outputs))
+    });
+
+    group.finish();
+}
+
+fn utxo_set_update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("utxo_set");
+    let block = common::chained_block(1000);
+
+    group.bench_function("update_1000_tx_block", |b| {
+        b.iter_batched(
+            || block.clone(),
+            |mut utxo_set| update_utxo_set(&mut utxo_set),
+            BatchSize::LargeInput,
+        )
+    });
+
+    // Applying the chained block to a set holding its first spent output, then rolling
+    // it back, as on a reorg
+    let txs = common::chained_txs(1000);
+    let amount = TokenAmount(common::UTXO_AMOUNT);
+    let start = TxOut::new_token_amount(String::new(), amount, None);
+    let utxo = UtxoMap::from([(common::chain_start(), start)]);
+    let initial = UtxoSet::new(utxo, u64::MAX);
+    let mut applied = initial.clone();
+    for tx in &txs {
+        applied.apply_transaction(tx, 1).unwrap();
+    }
+    let mut undone = applied.clone();
+    undone.undo_block(&txs).unwrap();
+    assert_eq!(undone.utxo(), initial.utxo());
+
+    group.bench_function("apply_1000_tx_block", |b| {
+        b.iter_batched(
+            || initial.clone(),
+            |mut utxo_set| {
+                for tx in &txs {
+                    utxo_set.apply_transaction(tx, 1).unwrap();
+                }
+                utxo_set
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("undo_1000_tx_block", |b| {
+        b.iter_batched(
+            || applied.clone(),
+            |mut utxo_set| {
+                utxo_set.undo_block(&txs).unwrap();
+                utxo_set
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().noise_threshold(0.05);
+    targets = tx_validation, script_interpret, tx_hashing, utxo_set_update
+}
+criterion_main!(benches);
@@ -0,0 +1,44 @@
+//! Builds a signed payment spending a single UTXO and validates it, using only the prelude.
+//!
+//! Run with `cargo run --example build_and_validate_tx`
+
+use std::collections::BTreeMap;
+use tw_chain::prelude::*;
+
+fn main() {
+    // A UTXO set holding 10 tokens locked to our key
+    let (pk, sk) = sign::gen_keypair();
+    let out_point = OutPoint::new(construct_tx_hash(&Transaction::new()), 0);
+    let tx_out = TxOut::new_token_amount(construct_address(&pk), TokenAmount(10), None);
+    let utxo: UtxoMap = vec![(out_point.clone(), tx_out)].into_iter().collect();
+
+    // Pay them all to another key, signing the input
+    let (receiver_pk, _) = sign::gen_keypair();
+    let key_material = BTreeMap::from([(out_point.clone(), (pk, sk))]);
+    let pay = |amount: u64| {
+        let tx_ins = construct_payment_tx_ins(vec![TxConstructor {
+            previous_out: out_point.clone(),
+            signatures: vec![],
+            pub_keys: vec![pk],
+            address_version: None,
+        }]);
+        let receiver = ReceiverInfo {
+            address: construct_address(&receiver_pk),
+            asset: Asset::Token(TokenAmount(amount)),
+        };
+        construct_payment_tx(tx_ins, receiver, None, 0, &key_material)
+            .expect("payment is well formed")
+    };
+
+    let tx = pay(10);
+    match tx_is_valid(&tx, 0, |op| utxo.get(op)) {
+        Ok(()) => println!("Transaction {} is valid", construct_tx_hash(&tx)),
+        Err(e) => panic!("Transaction is invalid: {}", e),
+    }
+
+    // Spending more than the inputs hold is rejected
+    let overspend = pay(11);
+    let result = tx_is_valid(&overspend, 0, |op| utxo.get(op));
+    assert!(matches!(result, Err(TxValidationError::Unbalanced { .. })));
+    println!("Overspending transaction rejected: {}", result.unwrap_err());
+}
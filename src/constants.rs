@@ -1,14 +1,38 @@
+use crate::script::OpCodes;
+
 /*------- TRANSACTION CONSTANTS -------*/
 pub const TX_PREPEND: u8 = b'g';
 pub const ITEM_DEFAULT_DRS_TX_HASH: &str = "default_genesis_hash";
 pub const MAX_METADATA_BYTES: usize = 800;
 pub const TX_HASH_LENGTH: usize = 32;
+// Serialized size in bytes of a transaction with no inputs, outputs, fees or DRUID
+pub const TX_BASE_SIZE: usize = 33;
+// Prepending character for a DRUID
+pub const DRUID_PREPEND: u8 = b'D';
+// Length of a DRUID in characters
+pub const DRUID_LENGTH: usize = 32;
+// Fewest participants a DDE transaction can have
+pub const MIN_DDE_PARTICIPANTS: usize = 2;
+// Field of item metadata JSON declaring the version of its schema
+pub const ITEM_METADATA_SCHEMA_FIELD: &str = "schema";
+// Prefix of item metadata holding a commitment to private metadata
+pub const METADATA_COMMITMENT_PREFIX: &str = "commit:";
+// Domain separator for hashing item metadata commitments
+pub const METADATA_COMMITMENT_DOMAIN: &[u8] = b"ITEM_METADATA_COMMITMENT";
+// Domain separator for hashing the leaves of a UTXO set commitment
+pub const UTXO_LEAF_DOMAIN: &[u8] = b"UTXO_COMMITMENT_LEAF";
+// Domain separator for hashing the inner nodes of a UTXO set commitment
+pub const UTXO_NODE_DOMAIN: &[u8] = b"UTXO_COMMITMENT_NODE";
 
 /*------- ADDRESS CONSTANTS -------*/
 pub const V0_ADDRESS_LENGTH: usize = 16;
 pub const STANDARD_ADDRESS_LENGTH: usize = 64;
 // Prepending character for a P2SH address
 pub const P2SH_PREPEND: u8 = b'H';
+// Domain separator for hashing the public keys of a dual signature address
+pub const DUAL_ADDRESS_DOMAIN: &[u8] = b"TW_DUAL_ADDRESS";
+// Domain separator prefixed to messages signed outside of transactions
+pub const MESSAGE_SIGNING_DOMAIN: &[u8] = b"TW_SIGNED_MESSAGE";
 
 /*------- NETWORK CONSTANTS --------*/
 // Current network version: Always bump immediately after a version is deployed.
@@ -19,11 +43,32 @@ pub const NETWORK_VERSION_V0: u64 = 0;
 // Network version to support temporary address structure on wallet
 // TODO: Deprecate after addresses retire
 pub const NETWORK_VERSION_TEMP: u64 = 99999;
+// First transaction version whose create scripts are interpreted rather than pattern matched
+pub const CREATE_SCRIPT_INTERPRETER_VERSION: usize = 7;
+// Most blocks a create script's block number may be from the current height, either way
+pub const CREATE_BLOCK_WINDOW: u64 = 100;
+// Blocks a spent output stays in a `UtxoSet` spent index for after the block spending it
+pub const SPENT_INDEX_RETENTION: u64 = 100;
+// First transaction version whose signable hashes commit to the transaction version
+pub const SIGNABLE_VERSION_COMMITMENT_VERSION: usize = 7;
+// Domain separator for signable hashes committing to the transaction version
+pub const SIGNABLE_HASH_DOMAIN: &[u8] = b"TW_SIGNABLE_HASH";
+// First transaction version whose DRUID input addresses commit only to previous outpoints
+pub const STABLE_TX_INS_ADDRESS_VERSION: usize = 7;
+// Domain separator for hashing the previous outpoints of a DRUID input address
+pub const TX_INS_ADDRESS_DOMAIN: &[u8] = b"TW_TX_INS_ADDRESS";
+// First transaction version whose P2SH addresses hash the script wire format
+pub const P2SH_WIRE_ADDRESS_VERSION: usize = 7;
+// Domain separator for hashing the wire format of a P2SH script
+pub const P2SH_ADDRESS_DOMAIN: &[u8] = b"TW_P2SH_ADDRESS";
 
 /*------- VALUE HANDLING CONSTANTS --------*/
 // Number of decimal places to divide to in display
 pub const D_DISPLAY_PLACES_U64: u64 = 72072000;
 pub const D_DISPLAY_PLACES: f64 = 72072000.0;
+// Number of decimal places token amounts are displayed and parsed with. 10^-8 is finer
+// than the smallest unit, so every amount round-trips through its display string
+pub const TOKEN_DISPLAY_DECIMALS: u32 = 8;
 // Number of possible tokens in existence (5 billion)
 pub const TOTAL_TOKENS: u64 = D_DISPLAY_PLACES_U64 * 5000000000;
 
@@ -37,15 +82,68 @@ pub const MAX_BLOCK_SIZE: usize = 1000;
 
 /*------- SCRIPT CONSTANTS -------*/
 // Maximum number of bytes pushable to the stack
-pub const MAX_SCRIPT_ITEM_SIZE: u16 = 520;
+pub const MAX_SCRIPT_ITEM_SIZE: usize = 520;
 // Maximum number of non-push operations per script
-pub const MAX_OPS_PER_SCRIPT: u8 = 201;
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
 // Maximum number of public keys per multisig
-pub const MAX_PUB_KEYS_PER_MULTISIG: u8 = 20;
+pub const MAX_PUB_KEYS_PER_MULTISIG: usize = 20;
+// Number of public keys above which a multisig is linted as costly
+pub const LINT_MULTISIG_KEYS_THRESHOLD: usize = 10;
+// Default fuel charged for executing an opcode. Changing any default script cost
+// changes which scripts fit a fuel budget, so is consensus-affecting
+pub const SCRIPT_BASE_OP_COST: u64 = 1;
+// Default fuel charged for pushing a data entry
+pub const SCRIPT_PUSH_COST: u64 = 1;
+// Default fuel charged per byte of the item a splice or hash opcode reads
+pub const SCRIPT_PER_BYTE_COST: u64 = 1;
+// Default fuel charged for verifying a signature
+pub const SCRIPT_SIG_VERIFY_COST: u64 = 100;
+// Default fuel charged per public key a multisig checks
+pub const SCRIPT_MULTISIG_KEY_COST: u64 = 100;
+// Default fuel charged for executing a hash opcode, before its per-byte cost
+pub const SCRIPT_HASH_OP_COST: u64 = 10;
+// Maximum number of scripts executing nested in one another
+pub const MAX_SCRIPT_RECURSION_DEPTH: usize = 4;
 // Maximum script length in bytes
-pub const MAX_SCRIPT_SIZE: u16 = 10000;
+pub const MAX_SCRIPT_SIZE: usize = 10000;
+// Maximum size in bytes of the wire encoding of a script behind a v2 P2SH address
+pub const MAX_P2SH_SCRIPT_SIZE: usize = 520;
 // Maximum number of values on script interpreter stack
-pub const MAX_STACK_SIZE: u16 = 1000;
+pub const MAX_STACK_SIZE: usize = 1000;
+// Maximum number of bytes held by the interpreter main and alt stacks together
+pub const MAX_STACK_BYTES: usize = 100000;
+// Script limits are counted in `usize` but must fit in a `u32`, so that every
+// platform enforces them identically
+const _: () = assert!(MAX_SCRIPT_ITEM_SIZE <= u32::MAX as usize);
+const _: () = assert!(MAX_OPS_PER_SCRIPT <= u32::MAX as usize);
+const _: () = assert!(MAX_PUB_KEYS_PER_MULTISIG <= u32::MAX as usize);
+const _: () = assert!(MAX_SCRIPT_RECURSION_DEPTH <= u32::MAX as usize);
+const _: () = assert!(MAX_SCRIPT_SIZE <= u32::MAX as usize);
+const _: () = assert!(MAX_P2SH_SCRIPT_SIZE <= u32::MAX as usize);
+const _: () = assert!(MAX_STACK_SIZE <= u32::MAX as usize);
+const _: () = assert!(MAX_STACK_BYTES <= u32::MAX as usize);
+// Block heights from which soft-forked opcodes take effect, executing as OP_NOP before.
+// `None` means the opcode has always been active. Opcodes not listed are always active
+pub const SOFT_FORK_HEIGHTS: &[(OpCodes, Option<u64>)] = &[
+    (OpCodes::OP_HASH256_V0, Some(0)),
+    (OpCodes::OP_HASH256_TEMP, Some(0)),
+    (OpCodes::OP_NOP1, None),
+    (OpCodes::OP_NOP2, None),
+    (OpCodes::OP_NOP3, None),
+    (OpCodes::OP_NOP4, None),
+    (OpCodes::OP_NOP5, None),
+    (OpCodes::OP_NOP6, None),
+    (OpCodes::OP_NOP7, None),
+    (OpCodes::OP_NOP8, None),
+    (OpCodes::OP_NOP9, None),
+    (OpCodes::OP_NOP10, None),
+    (OpCodes::OP_TXOUTPUTCOUNT, Some(0)),
+    (OpCodes::OP_TXOUTVALUE, Some(0)),
+    (OpCodes::OP_TXINCOUNT, Some(0)),
+];
+// Block height from which an `OP_ELSE` repeated within one conditional block fails the
+// script. Below it, each further `OP_ELSE` toggles the block again. Not yet scheduled
+pub const DUPLICATE_ELSE_FORK_HEIGHT: u64 = u64::MAX;
 
 /*------- NUMBERS -------*/
 pub const ZERO: usize = 0;
@@ -155,6 +253,8 @@ pub const OPOR: &str = "OP_OR";
 pub const OPXOR: &str = "OP_XOR";
 pub const OPEQUAL: &str = "OP_EQUAL";
 pub const OPEQUALVERIFY: &str = "OP_EQUALVERIFY";
+pub const OPLOOSEEQUAL: &str = "OP_LOOSEEQUAL";
+pub const OPLOOSEEQUALVERIFY: &str = "OP_LOOSEEQUALVERIFY";
 
 pub const OPINVERT_DESC: &str = "Computes bitwise NOT of the number on top of the stack";
 pub const OPAND_DESC: &str = "Computes bitwise AND between the two numbers on top of the stack";
@@ -163,6 +263,8 @@ pub const OPXOR_DESC: &str = "Computes bitwise XOR between the two numbers on to
 pub const OPEQUAL_DESC: &str =
     "Substitutes the top two items on the stack with ONE if they are equal, with ZERO otherwise";
 pub const OPEQUALVERIFY_DESC: &str = "Computes OP_EQUAL and OP_VERIFY in sequence";
+pub const OPLOOSEEQUAL_DESC: &str = "Substitutes the top two items on the stack with ONE if they are equal, with ZERO otherwise. Unlike OP_EQUAL, a bytes item is also equal to a number item if its hex content decodes to that number";
+pub const OPLOOSEEQUALVERIFY_DESC: &str = "Computes OP_LOOSEEQUAL and OP_VERIFY in sequence";
 
 // arithmetic
 pub const OP1ADD: &str = "OP_1ADD";
@@ -224,7 +326,7 @@ pub const OPMIN_DESC: &str =
     "Substitutes the two numbers on top of the stack with the minimum between the two";
 pub const OPMAX_DESC: &str =
     "Substitutes the two numbers on top of the stack with the maximum between the two";
-pub const OPWITHIN_DESC: &str = "Substitutes the three numbers on top of the the stack with ONE if the third-to-top is greater or equal to the second-to-top and less than the top item, with ZERO otherwise";
+pub const OPWITHIN_DESC: &str = "Substitutes the three numbers on top of the the stack with ONE if the third-to-top is within the half-open interval [second-to-top, top), with ZERO otherwise: [1, 1, 3] -> [1], [3, 1, 3] -> [0], [1, 1, 1] -> [0]";
 
 // crypto
 pub const OPSHA3: &str = "OP_SHA3";
@@ -250,14 +352,29 @@ pub const OPCHECKMULTISIG_DESC: &str =
     "Pushes ONE onto the stack if the m-of-n multi-signature is valid, ZERO otherwise";
 pub const OPCHECKMULTISIGVERIFY_DESC: &str = "Runs OP_CHECKMULTISIG and OP_VERIFY in sequence";
 
+// introspection
+pub const OPTXOUTPUTCOUNT: &str = "OP_TXOUTPUTCOUNT";
+pub const OPTXOUTVALUE: &str = "OP_TXOUTVALUE";
+pub const OPTXINCOUNT: &str = "OP_TXINCOUNT";
+
+pub const OPTXOUTPUTCOUNT_DESC: &str =
+    "Pushes the number of outputs of the spending transaction onto the stack";
+pub const OPTXINCOUNT_DESC: &str =
+    "Pushes the number of inputs of the spending transaction onto the stack";
+pub const OPTXOUTVALUE_DESC: &str =
+    "Substitutes the output index on top of the stack with the token amount of that output of the spending transaction";
+
 /*------- ERROR MESSAGES -------*/
 // opcodes
 pub const ERROR_EMPTY_CONDITION: &str = "Condition stack is empty";
+pub const ERROR_DUPLICATE_ELSE: &str = "Conditional block already has an OP_ELSE";
 pub const ERROR_VERIFY: &str = "The top item on the stack is ZERO";
 pub const ERROR_BURN: &str = "OP_BURN executed";
 pub const ERROR_NUM_ITEMS: &str = "Not enough items on the stack";
 pub const ERROR_ITEM_TYPE: &str = "Item type is not correct";
+pub const ERROR_INVALID_HEX: &str = "Item is not lowercase hex of even length";
 pub const ERROR_ITEM_INDEX: &str = "Index is out of bound";
+pub const ERROR_SLICE_BOUNDS: &str = "Slice end is out of bound";
 pub const ERROR_ITEM_SIZE: &str = "Item size exceeds MAX_SCRIPT_ITEM_SIZE-byte limit";
 pub const ERROR_NOT_EQUAL_ITEMS: &str = "The two top items are not equal";
 pub const ERROR_OVERFLOW: &str = "Attempt to overflow";
@@ -266,8 +383,13 @@ pub const ERROR_INVALID_SIGNATURE: &str = "Signature is not valid";
 pub const ERROR_INVALID_MULTISIGNATURE: &str = "Multi-signature is not valid";
 pub const ERROR_NUM_PUBKEYS: &str = "Number of public keys provided is not correct";
 pub const ERROR_NUM_SIGNATURES: &str = "Number of signatures provided is not correct";
+pub const ERROR_NO_TX_CONTEXT: &str = "Script is not executed with a spending transaction";
+pub const ERROR_OUTPUT_NOT_TOKEN: &str = "Output does not hold tokens";
 // script
 pub const ERROR_MAX_SCRIPT_SIZE: &str = "Script size exceeds MAX_SCRIPT_SIZE-byte limit";
 pub const ERROR_MAX_STACK_SIZE: &str = "Stack size exceeds MAX_STACK_SIZE limit";
+pub const ERROR_MAX_RECURSION_DEPTH: &str =
+    "Script nesting exceeds MAX_SCRIPT_RECURSION_DEPTH limit";
+pub const ERROR_MAX_STACK_BYTES: &str = "Stack memory exceeds MAX_STACK_BYTES limit";
 pub const ERROR_MAX_OPS_SCRIPT: &str =
     "Number of opcodes in script exceeds MAX_OPS_PER_SCRIPT limit";
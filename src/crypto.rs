@@ -1,17 +1,45 @@
+pub mod mnemonic;
+
 pub use ring;
 use std::convert::TryInto;
+use std::fmt;
 use tracing::warn;
 
+/// Errors loading cryptographic values from raw bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CryptoError {
+    /// Public key of the given length, with the expected length
+    InvalidKeyLength(usize, usize),
+    /// Signature of the given length, with the expected length
+    InvalidSignatureLength(usize, usize),
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidKeyLength(actual, expected) => {
+                write!(f, "Public key is {actual} bytes, expected {expected}")
+            }
+            Self::InvalidSignatureLength(actual, expected) => {
+                write!(f, "Signature is {actual} bytes, expected {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
 pub mod sign_ed25519 {
-    use super::deserialize_slice;
+    use super::{deserialize_slice, CryptoError};
     pub use ring::signature::Ed25519KeyPair as SecretKeyBase;
     use ring::signature::KeyPair;
     pub use ring::signature::Signature as SignatureBase;
     pub use ring::signature::UnparsedPublicKey;
     pub use ring::signature::{ED25519, ED25519_PUBLIC_KEY_LEN};
     use serde::{Deserialize, Serialize};
-    use std::convert::TryInto;
+    use std::convert::{TryFrom, TryInto};
     use tracing::warn;
+    use zeroize::{Zeroize, ZeroizeOnDrop};
 
     pub type PublicKeyBase = <SecretKey as KeyPair>::PublicKey;
 
@@ -36,6 +64,17 @@ pub mod sign_ed25519 {
         }
     }
 
+    impl TryFrom<&[u8]> for Signature {
+        type Error = CryptoError;
+
+        fn try_from(slice: &[u8]) -> Result<Self, CryptoError> {
+            Self::from_slice(slice).ok_or(CryptoError::InvalidSignatureLength(
+                slice.len(),
+                SIGNATURE_LEN,
+            ))
+        }
+    }
+
     impl AsRef<[u8]> for Signature {
         fn as_ref(&self) -> &[u8] {
             self.0.as_ref()
@@ -57,6 +96,17 @@ pub mod sign_ed25519 {
         }
     }
 
+    impl TryFrom<&[u8]> for PublicKey {
+        type Error = CryptoError;
+
+        fn try_from(slice: &[u8]) -> Result<Self, CryptoError> {
+            Self::from_slice(slice).ok_or(CryptoError::InvalidKeyLength(
+                slice.len(),
+                ED25519_PUBLIC_KEY_LEN,
+            ))
+        }
+    }
+
     impl AsRef<[u8]> for PublicKey {
         fn as_ref(&self) -> &[u8] {
             self.0.as_ref()
@@ -66,6 +116,10 @@ pub mod sign_ed25519 {
     /// PKCS8 encoded secret key pair
     /// We used sodiumoxide serialization before (treated it as slice with 64 bit length prefix).
     /// Slice and vector are serialized the same.
+    ///
+    /// The key bytes are overwritten with zeros when the key is dropped, with writes the
+    /// compiler cannot elide. This only covers the buffer owned by the key: clones,
+    /// serialized copies and the slices it is built from must be wiped by their owners.
     #[derive(Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize)]
     pub struct SecretKey(Vec<u8>);
 
@@ -75,12 +129,39 @@ pub mod sign_ed25519 {
         }
     }
 
+    impl Zeroize for SecretKey {
+        fn zeroize(&mut self) {
+            self.0.zeroize();
+        }
+    }
+
+    impl Drop for SecretKey {
+        fn drop(&mut self) {
+            self.zeroize();
+        }
+    }
+
+    impl ZeroizeOnDrop for SecretKey {}
+
     impl AsRef<[u8]> for SecretKey {
         fn as_ref(&self) -> &[u8] {
             self.0.as_ref()
         }
     }
 
+    /// Verifies an Ed25519 signature over `msg` with `ring`.
+    ///
+    /// Verification only handles public data: no secret key is involved, so its timing
+    /// can't reveal one, even for attacker-supplied keys. `ring`'s curve arithmetic is
+    /// constant-time regardless. Every failure is folded into `false` with `is_ok`,
+    /// so callers can't branch on why a signature was rejected
+    ///
+    /// ### Arguments
+    ///
+    /// * `sig`  - Signature to verify
+    /// * `msg`  - Signed message
+    /// * `pk`   - Public key of the signer
+    #[must_use]
     pub fn verify_detached(sig: &Signature, msg: &[u8], pk: &PublicKey) -> bool {
         let upk = UnparsedPublicKey::new(&ED25519, pk);
         upk.verify(msg, sig.as_ref()).is_ok()
@@ -165,6 +246,139 @@ pub mod sign_ed25519 {
 
         (public, secret)
     }
+
+    // PKCS8 v2 document layout used by ring: prefix, seed, middle, public key
+    const PKCS8_PREFIX: &[u8] = &[
+        0x30, 0x53, 0x02, 0x01, 0x01, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x04, 0x22, 0x04,
+        0x20,
+    ];
+    const PKCS8_MIDDLE: &[u8] = &[0xa1, 0x23, 0x03, 0x21, 0x00];
+
+    /// Builds the key pair for a 32-byte Ed25519 seed, such as one from key derivation
+    ///
+    /// ### Arguments
+    ///
+    /// * `seed`  - Private key seed
+    pub fn keypair_from_seed(seed: &[u8; 32]) -> (PublicKey, SecretKey) {
+        let public = match SecretKeyBase::from_seed_unchecked(seed) {
+            Ok(secret) => PublicKey::from_slice(secret.public_key().as_ref()),
+            Err(_) => None,
+        };
+        let public = match public {
+            Some(public) => public,
+            None => {
+                warn!("Invalid secret key seed");
+                return (PublicKey([0; ED25519_PUBLIC_KEY_LEN]), SecretKey(vec![]));
+            }
+        };
+
+        let secret = SecretKey([PKCS8_PREFIX, seed, PKCS8_MIDDLE, public.as_ref()].concat());
+        (public, secret)
+    }
+}
+
+/// Hierarchical deterministic Ed25519 keys, derived as in SLIP-0010.
+/// Ed25519 only supports hardened derivation, so children are derived from the parent's
+/// private key and chain code, never from a public key alone
+pub mod hd_ed25519 {
+    use super::mnemonic::mnemonic_to_seed;
+    use super::sign_ed25519::{keypair_from_seed, PublicKey, SecretKey};
+    use ring::hmac::{self, HMAC_SHA512};
+    use std::convert::TryInto;
+    use zeroize::{Zeroize, ZeroizeOnDrop};
+
+    /// Key of the HMAC deriving a master key from a seed
+    const MASTER_HMAC_KEY: &[u8] = b"ed25519 seed";
+    /// Offset added to every child index, marking the derivation as hardened
+    pub const HARDENED_OFFSET: u32 = 0x8000_0000;
+
+    /// Extended private key: a private key seed along with its chain code.
+    /// Both are overwritten with zeros when the key is dropped
+    #[derive(Clone, PartialEq, Eq)]
+    pub struct HdKey {
+        secret: [u8; 32],
+        chain_code: [u8; 32],
+    }
+
+    impl HdKey {
+        /// Derives the master key for a seed
+        ///
+        /// ### Arguments
+        ///
+        /// * `seed`  - Seed bytes, such as one from a mnemonic phrase
+        pub fn from_seed(seed: &[u8]) -> HdKey {
+            Self::from_hmac(MASTER_HMAC_KEY, &[seed])
+        }
+
+        /// Derives the master key for a mnemonic phrase, from its seed as given by
+        /// `mnemonic_to_seed`
+        ///
+        /// ### Arguments
+        ///
+        /// * `words`       - Words of the phrase
+        /// * `passphrase`  - Passphrase, empty if none
+        pub fn from_mnemonic(words: &[&str], passphrase: &str) -> HdKey {
+            let mut seed = mnemonic_to_seed(words, passphrase);
+            let key = Self::from_seed(&seed);
+            seed.zeroize();
+            key
+        }
+
+        /// Derives the hardened child at `index`, or `None` if `index` is not below
+        /// `HARDENED_OFFSET`
+        ///
+        /// ### Arguments
+        ///
+        /// * `index`  - Index of the child, before adding `HARDENED_OFFSET`
+        pub fn derive_child(&self, index: u32) -> Option<HdKey> {
+            let index = index.checked_add(HARDENED_OFFSET)?;
+            Some(Self::from_hmac(
+                &self.chain_code,
+                &[&[0], &self.secret, &index.to_be_bytes()],
+            ))
+        }
+
+        /// Returns the Ed25519 key pair of this key
+        pub fn key_pair(&self) -> (PublicKey, SecretKey) {
+            keypair_from_seed(&self.secret)
+        }
+
+        /// Returns the Ed25519 public key of this key
+        pub fn public_key(&self) -> PublicKey {
+            self.key_pair().0
+        }
+
+        /// Returns the chain code of this key
+        pub fn chain_code(&self) -> &[u8; 32] {
+            &self.chain_code
+        }
+
+        fn from_hmac(key: &[u8], data: &[&[u8]]) -> HdKey {
+            let mut context = hmac::Context::with_key(&hmac::Key::new(HMAC_SHA512, key));
+            data.iter().for_each(|d| context.update(d));
+            let tag = context.sign();
+            let (secret, chain_code) = tag.as_ref().split_at(32);
+            HdKey {
+                secret: secret.try_into().unwrap_or_default(),
+                chain_code: chain_code.try_into().unwrap_or_default(),
+            }
+        }
+    }
+
+    impl Zeroize for HdKey {
+        fn zeroize(&mut self) {
+            self.secret.zeroize();
+            self.chain_code.zeroize();
+        }
+    }
+
+    impl Drop for HdKey {
+        fn drop(&mut self) {
+            self.zeroize();
+        }
+    }
+
+    impl ZeroizeOnDrop for HdKey {}
 }
 
 pub mod secretbox_chacha20_poly1305 {
@@ -344,3 +558,64 @@ pub fn generate_random<const N: usize>() -> [u8; N] {
 
     value
 }
+
+#[cfg(test)]
+mod tests {
+    use super::hd_ed25519::{HdKey, HARDENED_OFFSET};
+    use super::sign_ed25519::{self as sign, SecretKey};
+    use zeroize::Zeroize;
+
+    #[test]
+    /// Checks derivation against SLIP-0010 Ed25519 test vector 1 for m and m/0H
+    fn test_hd_key_slip10_vectors() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        // Private key seeds sit at bytes 16..48 of the PKCS8 document
+        let check = |key: &HdKey, chain_code: &str, secret: &str| {
+            assert_eq!(hex::encode(key.chain_code()), chain_code);
+            assert_eq!(hex::encode(&key.key_pair().1.as_ref()[16..48]), secret);
+        };
+
+        let master = HdKey::from_seed(&seed);
+        check(
+            &master,
+            "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb",
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+        );
+        check(
+            &master.derive_child(0).unwrap(),
+            "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69",
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+        );
+
+        let (pk, sk) = master.derive_child(1).unwrap().key_pair();
+        let signature = sign::sign_detached(b"data", &sk);
+        assert!(sign::verify_detached(&signature, b"data", &pk));
+        assert!(master.derive_child(HARDENED_OFFSET - 1).is_some());
+        assert!(master.derive_child(HARDENED_OFFSET).is_none());
+    }
+
+    #[test]
+    /// Checks zeroizing a secret key wipes its whole buffer.
+    /// Dropping zeroizes the same way, but freed memory cannot be inspected soundly
+    fn test_secret_key_zeroize() {
+        let (pk, mut sk) = sign::gen_keypair();
+        let signature = sign::sign_detached(b"data", &sk);
+        assert!(sign::verify_detached(&signature, b"data", &pk));
+
+        let len = sk.as_ref().len();
+        let ptr = sk.as_ref().as_ptr();
+        assert!(len > 0);
+        assert!(unsafe { std::slice::from_raw_parts(ptr, len) }
+            .iter()
+            .any(|b| *b != 0));
+
+        sk.zeroize();
+
+        // Clearing keeps the allocation, so its former contents can still be read
+        assert!(sk.as_ref().is_empty());
+        assert!(unsafe { std::slice::from_raw_parts(ptr, len) }
+            .iter()
+            .all(|b| *b == 0));
+        assert_eq!(SecretKey::from_slice(&[]), Some(sk));
+    }
+}
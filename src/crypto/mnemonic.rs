@@ -0,0 +1,339 @@
+//! BIP39 mnemonic phrases for backing up and restoring key seeds.
+//!
+//! Entropy is encoded to and from words of the BIP39 English wordlist exactly as in BIP39,
+//! with a SHA-256 checksum, so phrases are interchangeable with other BIP39 wallets.
+//! Seeds are derived with PBKDF2-HMAC-SHA3-512 rather than BIP39's PBKDF2-HMAC-SHA512,
+//! so the same phrase yields different seeds, and keys, than in other wallets.
+
+use ring::digest::{digest, SHA256};
+use sha3::{Digest, Sha3_512};
+use std::fmt;
+use tracing::warn;
+use zeroize::Zeroize;
+
+/// BIP39 English wordlist, one word per line in sorted order
+const WORDLIST: &str = include_str!("bip39_english.txt");
+/// Number of bits encoded by each word
+const BITS_PER_WORD: usize = 11;
+/// Number of PBKDF2 iterations deriving a seed from a mnemonic
+pub const MNEMONIC_PBKDF2_ROUNDS: u32 = 2048;
+/// Length in bytes of a seed derived from a mnemonic
+pub const MNEMONIC_SEED_LEN: usize = 64;
+
+/// Errors decoding a mnemonic phrase
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MnemonicError {
+    InvalidWord(String),
+    InvalidChecksum,
+    InvalidLength,
+}
+
+impl fmt::Display for MnemonicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidWord(word) => write!(f, "{word} is not in the mnemonic wordlist"),
+            Self::InvalidChecksum => write!(f, "Mnemonic checksum does not match"),
+            Self::InvalidLength => write!(f, "Mnemonic must have 12, 15, 18, 21 or 24 words"),
+        }
+    }
+}
+
+impl std::error::Error for MnemonicError {}
+
+/// Encodes entropy as a mnemonic phrase. Entropy must be 16 to 32 bytes long, in steps
+/// of 4 bytes; any other length gives an empty phrase
+///
+/// ### Arguments
+///
+/// * `entropy`  - Entropy to encode
+pub fn entropy_to_mnemonic(entropy: &[u8]) -> Vec<String> {
+    if !(16..=32).contains(&entropy.len()) || !entropy.len().is_multiple_of(4) {
+        warn!("Invalid mnemonic entropy length {}", entropy.len());
+        return Vec::new();
+    }
+
+    let checksum = digest(&SHA256, entropy);
+    let bytes = [entropy, checksum.as_ref()].concat();
+    let num_words = (entropy.len() * 8 + entropy.len() / 4) / BITS_PER_WORD;
+    let words: Vec<&str> = WORDLIST.lines().collect();
+
+    (0..num_words)
+        .map(|w| {
+            let index = (0..BITS_PER_WORD)
+                .map(|b| bit(&bytes, w * BITS_PER_WORD + b))
+                .fold(0, |acc, bit| (acc << 1) | bit as usize);
+            words[index].to_owned()
+        })
+        .collect()
+}
+
+/// Decodes a mnemonic phrase back to its entropy, checking its checksum
+///
+/// ### Arguments
+///
+/// * `words`  - Words of the phrase
+pub fn mnemonic_to_entropy(words: &[&str]) -> Result<Vec<u8>, MnemonicError> {
+    if !(12..=24).contains(&words.len()) || !words.len().is_multiple_of(3) {
+        return Err(MnemonicError::InvalidLength);
+    }
+
+    let mut bits = Vec::with_capacity(words.len() * BITS_PER_WORD);
+    for word in words {
+        let index = WORDLIST
+            .lines()
+            .position(|w| w == *word)
+            .ok_or_else(|| MnemonicError::InvalidWord(word.to_string()))?;
+        bits.extend((0..BITS_PER_WORD).rev().map(|b| (index >> b) & 1 == 1));
+    }
+
+    let checksum_bits = bits.len() / 33;
+    let (entropy_bits, checksum) = bits.split_at(bits.len() - checksum_bits);
+    let entropy: Vec<u8> = entropy_bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, bit| (acc << 1) | *bit as u8))
+        .collect();
+
+    let expected = digest(&SHA256, &entropy);
+    if (0..checksum_bits).any(|b| bit(expected.as_ref(), b) != checksum[b]) {
+        return Err(MnemonicError::InvalidChecksum);
+    }
+    Ok(entropy)
+}
+
+/// Derives a seed from a mnemonic phrase and optional passphrase with PBKDF2-HMAC-SHA3-512,
+/// salted with `"mnemonic"` followed by the passphrase. The phrase is not validated, and
+/// both are used as given, without Unicode normalization
+///
+/// ### Arguments
+///
+/// * `mnemonic`    - Words of the phrase
+/// * `passphrase`  - Passphrase, empty if none
+pub fn mnemonic_to_seed(mnemonic: &[&str], passphrase: &str) -> [u8; MNEMONIC_SEED_LEN] {
+    let mut password = mnemonic.join(" ");
+    let mut salt = format!("mnemonic{passphrase}");
+    let mut seed = [0; MNEMONIC_SEED_LEN];
+    pbkdf2(
+        &SHA3_512,
+        password.as_bytes(),
+        salt.as_bytes(),
+        MNEMONIC_PBKDF2_ROUNDS,
+        &mut seed,
+    );
+    password.zeroize();
+    salt.zeroize();
+    seed
+}
+
+/// Reads bit `n` of `bytes`, most significant bit first
+fn bit(bytes: &[u8], n: usize) -> bool {
+    bytes[n / 8] & (0x80 >> (n % 8)) != 0
+}
+
+/// Hash function an HMAC is built on
+pub(super) struct HmacHash {
+    pub block_len: usize,
+    pub digest: fn(&[&[u8]]) -> Vec<u8>,
+}
+
+/// SHA3-512, whose HMAC block length is its 72-byte rate
+pub(super) const SHA3_512: HmacHash = HmacHash {
+    block_len: 72,
+    digest: |data| {
+        let mut hasher = Sha3_512::new();
+        data.iter().for_each(|d| hasher.update(d));
+        hasher.finalize().to_vec()
+    },
+};
+
+/// Computes the HMAC of `data` under `key`
+///
+/// ### Arguments
+///
+/// * `hash`  - Hash function to build the HMAC on
+/// * `key`   - HMAC key
+/// * `data`  - Message, in parts
+pub(super) fn hmac(hash: &HmacHash, key: &[u8], data: &[&[u8]]) -> Vec<u8> {
+    let mut block = match key.len() > hash.block_len {
+        true => (hash.digest)(&[key]),
+        false => key.to_vec(),
+    };
+    block.resize(hash.block_len, 0);
+
+    let inner_pad: Vec<u8> = block.iter().map(|b| b ^ 0x36).collect();
+    let outer_pad: Vec<u8> = block.iter().map(|b| b ^ 0x5c).collect();
+    block.zeroize();
+
+    let inner = (hash.digest)(&[&[inner_pad.as_slice()], data].concat());
+    (hash.digest)(&[&outer_pad, &inner])
+}
+
+/// Fills `out` with the PBKDF2 key derived from `password` and `salt`
+///
+/// ### Arguments
+///
+/// * `hash`        - Hash function to build the HMAC on
+/// * `password`    - Password to derive from
+/// * `salt`        - Salt
+/// * `iterations`  - Number of HMAC iterations per output block
+/// * `out`         - Buffer to fill with the derived key
+pub(super) fn pbkdf2(
+    hash: &HmacHash,
+    password: &[u8],
+    salt: &[u8],
+    iterations: u32,
+    out: &mut [u8],
+) {
+    let digest_len = (hash.digest)(&[]).len();
+    for (i, chunk) in out.chunks_mut(digest_len).enumerate() {
+        let block_index = (i as u32 + 1).to_be_bytes();
+        let mut u = hmac(hash, password, &[salt, &block_index]);
+        let mut t = u.clone();
+        for _ in 1..iterations {
+            u = hmac(hash, password, &[&u]);
+            t.iter_mut().zip(&u).for_each(|(t, u)| *t ^= u);
+        }
+        chunk.copy_from_slice(&t[..chunk.len()]);
+        u.zeroize();
+        t.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::hd_ed25519::HdKey;
+    use ring::pbkdf2::PBKDF2_HMAC_SHA512;
+    use std::num::NonZeroU32;
+
+    /// SHA-512 from ring, to check the HMAC and PBKDF2 implementations against BIP39
+    const SHA512: HmacHash = HmacHash {
+        block_len: 128,
+        digest: |data| {
+            let mut context = ring::digest::Context::new(&ring::digest::SHA512);
+            data.iter().for_each(|d| context.update(d));
+            context.finish().as_ref().to_vec()
+        },
+    };
+
+    /// BIP39 test vectors: entropy and its phrase
+    const VECTORS: &[(&str, &str)] = &[
+        (
+            "00000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        ),
+        (
+            "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+            "legal winner thank year wave sausage worth useful legal winner thank yellow",
+        ),
+        (
+            "80808080808080808080808080808080",
+            "letter advice cage absurd amount doctor acoustic avoid letter advice cage above",
+        ),
+        (
+            "ffffffffffffffffffffffffffffffff",
+            "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo wrong",
+        ),
+        (
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art",
+        ),
+        (
+            "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+        ),
+    ];
+
+    #[test]
+    /// Checks the embedded wordlist is the BIP39 English wordlist
+    fn test_wordlist() {
+        assert_eq!(WORDLIST.lines().count(), 2048);
+        assert_eq!(
+            hex::encode(digest(&SHA256, WORDLIST.as_bytes())),
+            "2f5eed53a4727b4bf8880d8f3f199efc90e58503646d9ff8eff3a2ed3b24dbda"
+        );
+    }
+
+    #[test]
+    /// Checks entropy round-trips through the phrases of the BIP39 test vectors
+    fn test_mnemonic_vectors() {
+        for (entropy, phrase) in VECTORS {
+            let entropy = hex::decode(entropy).unwrap();
+            let words: Vec<&str> = phrase.split(' ').collect();
+            assert_eq!(entropy_to_mnemonic(&entropy), words);
+            assert_eq!(mnemonic_to_entropy(&words), Ok(entropy));
+        }
+    }
+
+    #[test]
+    /// Checks malformed entropy and phrases are rejected
+    fn test_mnemonic_errors() {
+        assert!(entropy_to_mnemonic(&[0; 15]).is_empty());
+        assert!(entropy_to_mnemonic(&[0; 18]).is_empty());
+        assert!(entropy_to_mnemonic(&[0; 36]).is_empty());
+
+        let words: Vec<&str> = VECTORS[0].1.split(' ').collect();
+        assert_eq!(
+            mnemonic_to_entropy(&words[1..]),
+            Err(MnemonicError::InvalidLength)
+        );
+        assert_eq!(mnemonic_to_entropy(&[]), Err(MnemonicError::InvalidLength));
+
+        let mut misspelt = words.clone();
+        misspelt[3] = "abut";
+        assert_eq!(
+            mnemonic_to_entropy(&misspelt),
+            Err(MnemonicError::InvalidWord("abut".to_owned()))
+        );
+
+        let mut swapped = words.clone();
+        swapped[11] = "abandon";
+        assert_eq!(
+            mnemonic_to_entropy(&swapped),
+            Err(MnemonicError::InvalidChecksum)
+        );
+    }
+
+    #[test]
+    /// Checks PBKDF2-HMAC over SHA-512 reproduces the BIP39 seed and ring's PBKDF2,
+    /// then pins the SHA3-512 seeds used by this crate
+    fn test_mnemonic_to_seed() {
+        let words: Vec<&str> = VECTORS[0].1.split(' ').collect();
+        let password = words.join(" ");
+
+        let mut seed = [0; MNEMONIC_SEED_LEN];
+        pbkdf2(
+            &SHA512,
+            password.as_bytes(),
+            b"mnemonicTREZOR",
+            2048,
+            &mut seed,
+        );
+        assert_eq!(
+            hex::encode(seed),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+
+        let mut ring_key = [0; 100];
+        let mut key = [0; 100];
+        let iterations = NonZeroU32::new(3).unwrap();
+        ring::pbkdf2::derive(
+            PBKDF2_HMAC_SHA512,
+            iterations,
+            b"salt",
+            &[7; 200],
+            &mut ring_key,
+        );
+        pbkdf2(&SHA512, &[7; 200], b"salt", 3, &mut key);
+        assert_eq!(key, ring_key);
+
+        assert_eq!(
+            hex::encode(mnemonic_to_seed(&words, "TREZOR")),
+            "19afceb5205a1a116052c80ef5650d04bce01ea76e759aa8e0307b43d33c3e01da80ef484079a8af3c830427e7632f4c7012b406585d4573482e5b7a2a6cfb09"
+        );
+        assert_eq!(hex::encode(mnemonic_to_seed(&words, "")), "60581be778bf3fa980c0a4389f53395a385b3cbac2873ea6f73fe0c6605c7f5e921dd6b254b27ddb6d1e9ab6feb26257b1533b325f6e5292bf602a8e89fe43f2");
+        assert!(
+            HdKey::from_mnemonic(&words, "TREZOR")
+                == HdKey::from_seed(&mnemonic_to_seed(&words, "TREZOR"))
+        );
+    }
+}
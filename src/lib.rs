@@ -1,5 +1,6 @@
 pub mod constants;
 pub mod crypto;
+pub mod prelude;
 pub mod primitives;
 pub mod script;
 pub mod utils;
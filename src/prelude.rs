@@ -0,0 +1,64 @@
+//! Intended public API of the crate, for glob importing with `use tw_chain::prelude::*`.
+//!
+//! Items re-exported here are covered by semver: they are only renamed or removed in a
+//! major release. Anything reached through a deeper module path and not listed here may
+//! be reorganized in a minor release.
+
+// ------- PRIMITIVES ------- //
+
+pub use crate::primitives::asset::{
+    Asset, AssetDiff, AssetError, AssetValues, ItemAsset, TokenAmount,
+};
+pub use crate::primitives::block::{Block, BlockHeader};
+pub use crate::primitives::compact::{decode_out_points, encode_out_points, CompactTxError};
+pub use crate::primitives::druid::{
+    DdeValues, DruidExpectation, ExpectationTerms, OutputConstraint,
+};
+pub use crate::primitives::transaction::{
+    InputIndex, OutPoint, OutputIndex, Transaction, TxConstructor, TxIn, TxOut, TxStructureError,
+};
+
+// ------- CRYPTO ------- //
+
+pub use crate::crypto::sha3_256;
+pub use crate::crypto::sign_ed25519::{self as sign, PublicKey, SecretKey, Signature};
+pub use crate::utils::message_utils::{sign_message, verify_message, SignedMessage};
+
+// ------- SCRIPT ------- //
+
+pub use crate::script::cost::ScriptCostTable;
+pub use crate::script::lang::{ExecutionContext, Script, ScriptError, TxContext, TxOutConstraint};
+pub use crate::script::{OpCodes, StackEntry};
+
+// ------- CONSTRUCTION ------- //
+
+pub use crate::utils::transaction_utils::{
+    construct_address, construct_address_for, construct_burn_tx, construct_consolidation_tx,
+    construct_dde_tx, construct_dual_address, construct_dual_tx_ins, construct_escrow_address,
+    construct_escrow_check_data, construct_escrow_settlement_tx, construct_escrow_tx,
+    construct_item_create_tx, construct_p2sh_address, construct_p2sh_address_at_version,
+    construct_p2sh_address_streaming, construct_p2sh_address_v2, construct_p2sh_redeem_tx_ins,
+    construct_p2sh_tx, construct_payment_tx, construct_payment_tx_ins,
+    construct_payment_tx_with_change, construct_rb_payments_send_tx,
+    construct_rb_receive_payment_tx, construct_token_create_tx, construct_tx_core,
+    construct_tx_hash, construct_tx_hash_streaming, construct_tx_ins_address_at_version,
+    is_p2sh_address_of, select_utxos, transaction_dependencies, ConsensusHashWriter, CreateTxError,
+    P2SHError, PaymentTxError, ReceiverInfo, SignableMessage, UtxoMap,
+};
+
+// ------- VALIDATION ------- //
+
+pub use crate::utils::block_utils::{
+    assemble_block_template, tx_sig_ops, validate_block_transactions, BlockLimits, BlockTemplate,
+    BlockTxError, ExclusionReason,
+};
+pub use crate::utils::druid_utils::{
+    druid_expectations_are_met, druid_expectations_are_met_in_context,
+    druid_expectations_are_met_with_terms,
+};
+pub use crate::utils::script_utils::{
+    tx_is_valid, tx_is_valid_in_context, tx_outs_are_valid, tx_outs_are_valid_in_context,
+    validate_create_tx, InputError, OutputError, TxValidationError, ValidationContext,
+};
+pub use crate::utils::utxo_set::{UtxoLookup, UtxoSet, UtxoSetError};
+pub use crate::utils::validation_cache::ValidationCache;
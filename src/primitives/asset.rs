@@ -1,17 +1,123 @@
+use crate::constants::{D_DISPLAY_PLACES_U64, TOKEN_DISPLAY_DECIMALS, TOTAL_TOKENS};
 use crate::primitives::transaction::OutPoint;
-use crate::utils::{add_btreemap, format_for_display};
+use crate::utils::add_btreemap;
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, fmt, iter, mem::size_of, ops};
+use std::collections::{BTreeMap, BTreeSet};
+use std::{fmt, iter, mem::size_of, ops, str::FromStr};
 use tracing::debug;
 
 /// A structure representing the amount of tokens in an instance
 #[derive(Deserialize, Serialize, Default, Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
 pub struct TokenAmount(pub u64);
 
+impl TokenAmount {
+    /// Formats the amount as a decimal number of tokens, with at most
+    /// `TOKEN_DISPLAY_DECIMALS` places and no trailing zeros, using integer math only
+    pub fn to_display_string(&self) -> String {
+        let scale = 10u64.pow(TOKEN_DISPLAY_DECIMALS);
+        let mut whole = self.0 / D_DISPLAY_PLACES_U64;
+        let remainder = self.0 % D_DISPLAY_PLACES_U64;
+
+        // Round the fraction to the nearest display place, carrying into the whole part
+        let mut fraction = (remainder * scale + D_DISPLAY_PLACES_U64 / 2) / D_DISPLAY_PLACES_U64;
+        if fraction == scale {
+            whole += 1;
+            fraction = 0;
+        }
+
+        if fraction == 0 {
+            return whole.to_string();
+        }
+        let fraction = format!(
+            "{:0width$}",
+            fraction,
+            width = TOKEN_DISPLAY_DECIMALS as usize
+        );
+        format!("{whole}.{}", fraction.trim_end_matches('0'))
+    }
+
+    /// Parses a decimal number of tokens, such as `"12"` or `"0.5"`, rounding to the
+    /// nearest smallest unit
+    ///
+    /// ### Arguments
+    ///
+    /// * `s`   - Decimal string with at most `TOKEN_DISPLAY_DECIMALS` places
+    pub fn from_display_str(s: &str) -> Result<TokenAmount, AmountParseError> {
+        if s.is_empty() {
+            return Err(AmountParseError::Empty);
+        }
+        if s.starts_with('-') {
+            return Err(AmountParseError::Negative);
+        }
+
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+            return Err(AmountParseError::InvalidDigit);
+        }
+        if fraction.len() > TOKEN_DISPLAY_DECIMALS as usize {
+            return Err(AmountParseError::TooManyDecimals(fraction.len()));
+        }
+
+        let whole: u64 = match whole {
+            "" => 0,
+            _ => whole.parse().map_err(|_| AmountParseError::Overflow)?,
+        };
+        let places = TOKEN_DISPLAY_DECIMALS - fraction.len() as u32;
+        let fraction: u64 = match fraction {
+            "" => 0,
+            _ => fraction.parse::<u64>().unwrap_or_default() * 10u64.pow(places),
+        };
+
+        let scale = 10u64.pow(TOKEN_DISPLAY_DECIMALS);
+        let fraction_units = (fraction * D_DISPLAY_PLACES_U64 + scale / 2) / scale;
+        whole
+            .checked_mul(D_DISPLAY_PLACES_U64)
+            .and_then(|units| units.checked_add(fraction_units))
+            .filter(|units| *units <= TOTAL_TOKENS)
+            .map(TokenAmount)
+            .ok_or(AmountParseError::Overflow)
+    }
+}
+
+/// Reasons a token amount can't be parsed from its display string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmountParseError {
+    Empty,
+    Negative,
+    InvalidDigit,
+    TooManyDecimals(usize),
+    Overflow,
+}
+
+impl fmt::Display for AmountParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "Amount is empty"),
+            Self::Negative => write!(f, "Amount is negative"),
+            Self::InvalidDigit => write!(f, "Amount is not a decimal number"),
+            Self::TooManyDecimals(places) => write!(
+                f,
+                "Amount has {places} decimal places, at most {TOKEN_DISPLAY_DECIMALS} are allowed"
+            ),
+            Self::Overflow => write!(f, "Amount exceeds the total token supply"),
+        }
+    }
+}
+
+impl std::error::Error for AmountParseError {}
+
 impl fmt::Display for TokenAmount {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let format_result = format_for_display(&self.0);
-        write!(f, "{format_result}")
+        write!(f, "{}", self.to_display_string())
+    }
+}
+
+impl FromStr for TokenAmount {
+    type Err = AmountParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TokenAmount::from_display_str(s)
     }
 }
 
@@ -19,19 +125,13 @@ impl ops::Add for TokenAmount {
     type Output = Self;
 
     fn add(self, other: Self) -> Self {
-        match self.0.checked_add(other.0) {
-            Some(v) => Self(v),
-            None => Self(u64::MAX),
-        }
+        Self(self.0.saturating_add(other.0))
     }
 }
 
 impl ops::AddAssign for TokenAmount {
     fn add_assign(&mut self, other: Self) {
-        self.0 = match self.0.checked_add(other.0) {
-            Some(v) => v,
-            None => u64::MAX,
-        }
+        self.0 = self.0.saturating_add(other.0)
     }
 }
 
@@ -39,19 +139,13 @@ impl ops::Sub for TokenAmount {
     type Output = Self;
 
     fn sub(self, other: Self) -> Self {
-        match self.0.checked_sub(other.0) {
-            Some(v) => Self(v),
-            None => Self(u64::MIN),
-        }
+        Self(self.0.saturating_sub(other.0))
     }
 }
 
 impl ops::SubAssign for TokenAmount {
     fn sub_assign(&mut self, other: Self) {
-        self.0 = match self.0.checked_sub(other.0) {
-            Some(v) => v,
-            None => u64::MIN,
-        }
+        self.0 = self.0.saturating_sub(other.0)
     }
 }
 
@@ -59,19 +153,13 @@ impl ops::Mul<u64> for TokenAmount {
     type Output = Self;
 
     fn mul(self, rhs: u64) -> Self {
-        match self.0.checked_mul(rhs) {
-            Some(v) => Self(v),
-            None => Self(u64::MAX),
-        }
+        Self(self.0.saturating_mul(rhs))
     }
 }
 
 impl ops::MulAssign<u64> for TokenAmount {
     fn mul_assign(&mut self, rhs: u64) {
-        self.0 = match self.0.checked_mul(rhs) {
-            Some(v) => v,
-            None => u64::MAX,
-        }
+        self.0 = self.0.saturating_mul(rhs)
     }
 }
 
@@ -79,19 +167,13 @@ impl ops::Div<u64> for TokenAmount {
     type Output = Self;
 
     fn div(self, rhs: u64) -> Self {
-        match self.0.checked_div(rhs) {
-            Some(v) => Self(v),
-            None => Self(u64::MAX),
-        }
+        Self(self.0.checked_div(rhs).unwrap_or(u64::MAX))
     }
 }
 
 impl ops::DivAssign<u64> for TokenAmount {
     fn div_assign(&mut self, rhs: u64) {
-        self.0 = match self.0.checked_div(rhs) {
-            Some(v) => v,
-            None => u64::MAX,
-        }
+        self.0 = self.0.checked_div(rhs).unwrap_or(u64::MAX)
     }
 }
 
@@ -101,7 +183,26 @@ impl iter::Sum for TokenAmount {
     }
 }
 
+/// Reasons an asset is invalid on its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssetError {
+    ZeroItemAmount,
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ZeroItemAmount => write!(f, "Item amount must be non-zero"),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
 /// Item asset struct
+///
+/// **NOTE:** The `genesis_hash` of an item is the hash of the transaction that created it,
+/// also referred to as its DRS (Digital Rights Signature) or DRS transaction hash
 #[derive(Default, Deserialize, Serialize, Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
 pub struct ItemAsset {
     pub amount: u64,
@@ -117,17 +218,68 @@ impl ItemAsset {
             metadata,
         }
     }
+
+    /// Set the `genesis_hash` of the item
+    ///
+    /// ### Arguments
+    ///
+    ///* `genesis_hash`  - Hash of the transaction that created the item
+    pub fn with_genesis_hash(mut self, genesis_hash: String) -> Self {
+        self.genesis_hash = Some(genesis_hash);
+        self
+    }
+
+    /// Get the `genesis_hash` of the item, if it has been assigned one
+    pub fn genesis_hash(&self) -> Option<&str> {
+        self.genesis_hash.as_deref()
+    }
+
+    /// Checks that the item holds a non-zero amount, as zero-amount items mean nothing
+    pub fn validate_amount(&self) -> Result<(), AssetError> {
+        match self.amount {
+            0 => Err(AssetError::ZeroItemAmount),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Metadata describing a fungible token class
+#[derive(Default, Deserialize, Serialize, Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
+pub struct TokenMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+    pub total_supply: u64,
+}
+
+impl TokenMetadata {
+    pub fn new(name: String, symbol: String, decimals: u8, total_supply: u64) -> Self {
+        Self {
+            name,
+            symbol,
+            decimals,
+            total_supply,
+        }
+    }
 }
 
 /// Asset struct
 ///
 /// * `Token`   - An asset struct representation of the ZNT token
+/// * `TokenWithMetadata` - An amount of the fungible token class created by the transaction
+///   with `genesis_hash`, carrying the metadata of the class. Unlike items, token class
+///   amounts are never interchangeable with `Token`s
 /// * `Data`    - A data asset
 /// * `Item` - A item for a payment. The value indicates the number of item assets
 #[derive(Deserialize, Serialize, Debug, Clone, Eq, Ord, PartialEq, PartialOrd)]
 pub enum Asset {
     Token(TokenAmount),
     Item(ItemAsset),
+    TokenWithMetadata {
+        amount: TokenAmount,
+        metadata: TokenMetadata,
+        genesis_hash: Option<String>,
+    },
 }
 
 impl Default for Asset {
@@ -140,39 +292,61 @@ impl Asset {
     /// Modify `self` of `Asset` struct to obtain `genesis_hash`
     /// from either the asset itself or its corresponding `OutPoint`
     pub fn with_fixed_hash(mut self, out_point: &OutPoint) -> Self {
-        if let Asset::Item(ref mut item_asset) = self {
-            if item_asset.genesis_hash.is_none() {
-                item_asset.genesis_hash = Some(&out_point.t_hash).cloned();
-            }
+        let genesis_hash = match &mut self {
+            Asset::Item(item_asset) => &mut item_asset.genesis_hash,
+            Asset::TokenWithMetadata { genesis_hash, .. } => genesis_hash,
+            Asset::Token(_) => return self,
+        };
+        if genesis_hash.is_none() {
+            *genesis_hash = Some(out_point.t_hash.clone());
         }
         self
     }
 
+    /// Get optional `genesis_hash` value for `Asset`, held by items and token classes
+    pub fn genesis_hash(&self) -> Option<&str> {
+        match self {
+            Asset::Token(_) => None,
+            Asset::Item(item) => item.genesis_hash(),
+            Asset::TokenWithMetadata { genesis_hash, .. } => genesis_hash.as_deref(),
+        }
+    }
+
     /// Get optional `genesis_hash` value for `Asset`
+    #[deprecated(note = "use `Asset::genesis_hash` instead")]
     pub fn get_genesis_hash(&self) -> Option<&String> {
         match self {
             Asset::Token(_) => None,
             Asset::Item(item) => item.genesis_hash.as_ref(),
+            Asset::TokenWithMetadata { genesis_hash, .. } => genesis_hash.as_ref(),
         }
     }
 
     pub fn get_metadata(&self) -> Option<&String> {
         match self {
-            Asset::Token(_) => None,
+            Asset::Token(_) | Asset::TokenWithMetadata { .. } => None,
             Asset::Item(item) => item.metadata.as_ref(),
         }
     }
 
+    /// Get the `TokenMetadata` of a `TokenWithMetadata` asset
+    pub fn get_token_metadata(&self) -> Option<&TokenMetadata> {
+        match self {
+            Asset::TokenWithMetadata { metadata, .. } => Some(metadata),
+            _ => None,
+        }
+    }
+
     pub fn len(&self) -> usize {
         match self {
-            Asset::Token(_) => size_of::<TokenAmount>(),
+            Asset::Token(_) | Asset::TokenWithMetadata { .. } => size_of::<TokenAmount>(),
             Asset::Item(_) => size_of::<u64>(),
         }
     }
 
     pub fn is_empty(&self) -> bool {
         match self {
-            Asset::Token(token) => token.0 == 0,
+            Asset::Token(token) | Asset::TokenWithMetadata { amount: token, .. } => token.0 == 0,
             Asset::Item(item) => item.amount == 0,
         }
     }
@@ -185,13 +359,27 @@ impl Asset {
         Asset::Item(ItemAsset::new(amount, genesis_hash, metadata))
     }
 
+    pub fn token_with_metadata(
+        amount: u64,
+        metadata: TokenMetadata,
+        genesis_hash: Option<String>,
+    ) -> Self {
+        Asset::TokenWithMetadata {
+            amount: TokenAmount(amount),
+            metadata,
+            genesis_hash,
+        }
+    }
+
     /// Add an asset of the same variant to `self` asset.
     /// TODO: Add handling for `Data` asset variant. Will return false when `Data` asset is presented.
     ///
     /// ### Note
     ///
     /// This function will return false for `Item` assets
-    /// getting added together that do not have the same `genesis_hash`
+    /// getting added together that do not have the same `genesis_hash`,
+    /// and for `TokenWithMetadata` assets that do not share the same `genesis_hash`
+    /// and `TokenMetadata`
     ///
     /// ### Arguments
     ///
@@ -209,6 +397,24 @@ impl Asset {
                 lhs_items.amount += rhs_items.amount;
                 true
             }
+            (
+                Asset::TokenWithMetadata {
+                    amount: lhs_tokens,
+                    metadata: lhs_metadata,
+                    genesis_hash: lhs_genesis_hash,
+                },
+                Asset::TokenWithMetadata {
+                    amount: rhs_tokens,
+                    metadata: rhs_metadata,
+                    genesis_hash: rhs_genesis_hash,
+                },
+            ) => {
+                if lhs_metadata != rhs_metadata || lhs_genesis_hash != rhs_genesis_hash {
+                    return false;
+                }
+                *lhs_tokens += *rhs_tokens;
+                true
+            }
             _ => false,
         }
     }
@@ -230,6 +436,23 @@ impl Asset {
                 }
                 Some(lhs_item.amount >= rhs_item.amount)
             }
+            (
+                Asset::TokenWithMetadata {
+                    amount: lhs_tokens,
+                    metadata: lhs_metadata,
+                    genesis_hash: lhs_genesis_hash,
+                },
+                Asset::TokenWithMetadata {
+                    amount: rhs_tokens,
+                    metadata: rhs_metadata,
+                    genesis_hash: rhs_genesis_hash,
+                },
+            ) => {
+                if lhs_metadata != rhs_metadata || lhs_genesis_hash != rhs_genesis_hash {
+                    return None;
+                }
+                Some(lhs_tokens >= rhs_tokens)
+            }
             _ => None,
         }
     }
@@ -264,6 +487,31 @@ impl Asset {
                     None
                 }
             }
+            (
+                Asset::TokenWithMetadata {
+                    amount: lhs_tokens,
+                    metadata: lhs_metadata,
+                    genesis_hash: lhs_genesis_hash,
+                },
+                Asset::TokenWithMetadata {
+                    amount: rhs_tokens,
+                    metadata: rhs_metadata,
+                    genesis_hash: rhs_genesis_hash,
+                },
+            ) => {
+                if lhs_tokens > rhs_tokens
+                    && lhs_metadata == rhs_metadata
+                    && lhs_genesis_hash == rhs_genesis_hash
+                {
+                    Some(Asset::TokenWithMetadata {
+                        amount: *lhs_tokens - *rhs_tokens,
+                        metadata: lhs_metadata.clone(),
+                        genesis_hash: lhs_genesis_hash.clone(),
+                    })
+                } else {
+                    None
+                }
+            }
             _ => None,
         }
     }
@@ -277,6 +525,22 @@ impl Asset {
         std::mem::discriminant(self) == std::mem::discriminant(other)
     }
 
+    /// Determine if the asset in question can be combined with `self`:
+    /// it is of the same variant and, for items and token classes, of the same genesis hash
+    ///
+    /// ### Arguments
+    ///
+    ///* `other`  - Reference to other `Asset` to test against
+    pub fn is_compatible_with(&self, other: &Asset) -> bool {
+        match (self, other) {
+            (Asset::Item(lhs), Asset::Item(rhs)) => lhs.genesis_hash == rhs.genesis_hash,
+            (Asset::TokenWithMetadata { .. }, Asset::TokenWithMetadata { .. }) => {
+                self.genesis_hash() == other.genesis_hash()
+            }
+            _ => self.is_same_type_as(other),
+        }
+    }
+
     /// Creates a default asset of a given variant.
     /// TODO: Add handling for `Data` asset variant
     ///
@@ -291,6 +555,15 @@ impl Asset {
                 item.genesis_hash.clone(),
                 item.metadata.clone(),
             ),
+            Self::TokenWithMetadata {
+                metadata,
+                genesis_hash,
+                ..
+            } => Self::TokenWithMetadata {
+                amount: Default::default(),
+                metadata: metadata.clone(),
+                genesis_hash: genesis_hash.clone(),
+            },
         }
     }
 
@@ -298,13 +571,17 @@ impl Asset {
         matches!(self, Asset::Token(_))
     }
 
+    pub fn is_token_with_metadata(&self) -> bool {
+        matches!(self, Asset::TokenWithMetadata { .. })
+    }
+
     pub fn is_item(&self) -> bool {
         matches!(self, Asset::Item(_))
     }
 
     pub fn token_amount(&self) -> TokenAmount {
         match self {
-            Asset::Token(v) => *v,
+            Asset::Token(v) | Asset::TokenWithMetadata { amount: v, .. } => *v,
             _ => TokenAmount(0),
         }
     }
@@ -317,24 +594,54 @@ impl Asset {
     }
 }
 
-/// `AssetValue` struct used to represent the a running total of `Token` and `Item` assets
+/// Identifies a kind of asset held in `AssetValues`
+///
+/// `Token`                     - Tokens
+/// `Item(genesis_hash)`        - Items created by the transaction with `genesis_hash`
+/// `TokenClass(genesis_hash)`  - `TokenWithMetadata` amounts of the token class created
+///                               by the transaction with `genesis_hash`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AssetId {
+    Token,
+    Item(String),
+    TokenClass(String),
+}
+
+/// `AssetValue` struct used to represent the a running total of `Token`, `Item` and
+/// `TokenWithMetadata` assets
+///
+/// **NOTE:** Each token class is totalled on its own, and never contributes to `tokens`
 #[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct AssetValues {
     pub tokens: TokenAmount,
     // Note: Items from create transactions will have `genesis_hash` = `t_hash`
     pub items: BTreeMap<String, u64>, /* `genesis_hash` - amount */
+    // Note: Token classes are keyed by the `t_hash` of the transaction that created them
+    pub token_classes: BTreeMap<String, u64>, /* `genesis_hash` - amount */
 }
 
 impl ops::AddAssign for AssetValues {
     fn add_assign(&mut self, rhs: Self) {
         self.tokens += rhs.tokens;
         add_btreemap(&mut self.items, rhs.items);
+        add_btreemap(&mut self.token_classes, rhs.token_classes);
     }
 }
 
 impl AssetValues {
     pub fn new(tokens: TokenAmount, items: BTreeMap<String, u64>) -> Self {
-        Self { tokens, items }
+        Self {
+            tokens,
+            items,
+            token_classes: Default::default(),
+        }
+    }
+
+    pub fn token_class(token_classes: BTreeMap<String, u64>) -> Self {
+        Self {
+            token_classes,
+            ..Default::default()
+        }
     }
 
     pub fn token_u64(tokens: u64) -> Self {
@@ -350,18 +657,98 @@ impl AssetValues {
     }
 
     pub fn is_equal(&self, rhs: &AssetValues) -> bool {
-        self.tokens == rhs.tokens && self.items == rhs.items
+        self.tokens == rhs.tokens
+            && self.items == rhs.items
+            && self.token_classes == rhs.token_classes
+    }
+
+    /// Gets how `rhs` differs from `self`, empty if they are equal
+    pub fn diff(&self, rhs: &AssetValues) -> AssetDiff {
+        let mut diff = AssetDiff {
+            tokens: rhs.tokens.0 as i128 - self.tokens.0 as i128,
+            ..Default::default()
+        };
+        let genesis_hashes: BTreeSet<&String> = self
+            .token_classes
+            .keys()
+            .chain(rhs.token_classes.keys())
+            .collect();
+        for genesis_hash in genesis_hashes {
+            let amount = |values: &AssetValues| {
+                values.token_classes.get(genesis_hash).copied().unwrap_or(0) as i128
+            };
+            let delta = amount(rhs) - amount(self);
+            if delta != 0 {
+                diff.token_classes.insert(genesis_hash.clone(), delta);
+            }
+        }
+        let genesis_hashes: BTreeSet<&String> = self.items.keys().chain(rhs.items.keys()).collect();
+        for genesis_hash in genesis_hashes {
+            let (lhs_amount, rhs_amount) =
+                (self.items.get(genesis_hash), rhs.items.get(genesis_hash));
+            let delta = *rhs_amount.unwrap_or(&0) as i128 - *lhs_amount.unwrap_or(&0) as i128;
+            let one_sided = match (lhs_amount, rhs_amount) {
+                (Some(_), None) => diff.only_in_lhs.insert(genesis_hash.clone()),
+                (None, Some(_)) => diff.only_in_rhs.insert(genesis_hash.clone()),
+                _ => false,
+            };
+            if delta != 0 || one_sided {
+                diff.items.insert(genesis_hash.clone(), delta);
+            }
+        }
+        diff
+    }
+
+    /// Gets the amount held of an asset, zero if none is held
+    pub fn get(&self, asset_id: &AssetId) -> TokenAmount {
+        match asset_id {
+            AssetId::Token => self.tokens,
+            AssetId::Item(genesis_hash) => {
+                TokenAmount(self.items.get(genesis_hash).copied().unwrap_or_default())
+            }
+            AssetId::TokenClass(genesis_hash) => TokenAmount(
+                self.token_classes
+                    .get(genesis_hash)
+                    .copied()
+                    .unwrap_or_default(),
+            ),
+        }
+    }
+
+    /// Iterates over all holdings, tokens first if any are held, then items,
+    /// then token classes
+    pub fn iter(&self) -> impl Iterator<Item = (AssetId, TokenAmount)> + '_ {
+        let tokens = (self.tokens != TokenAmount(0)).then_some((AssetId::Token, self.tokens));
+        let items = self.items.iter().map(|(genesis_hash, amount)| {
+            (AssetId::Item(genesis_hash.clone()), TokenAmount(*amount))
+        });
+        let token_classes = self.token_classes.iter().map(|(genesis_hash, amount)| {
+            (
+                AssetId::TokenClass(genesis_hash.clone()),
+                TokenAmount(*amount),
+            )
+        });
+        tokens.into_iter().chain(items).chain(token_classes)
     }
 
     // See if the running total is enough for a required `Asset` amount
     pub fn has_enough(&self, asset_required: &Asset) -> bool {
         match asset_required {
             Asset::Token(tokens) => self.tokens >= *tokens,
+            Asset::TokenWithMetadata {
+                amount,
+                genesis_hash,
+                ..
+            } => genesis_hash.as_ref().is_some_and(|genesis_hash| {
+                self.token_classes
+                    .get(genesis_hash)
+                    .is_some_and(|held| *held >= amount.0)
+            }),
             Asset::Item(items) => {
                 if let Some(genesis_hash) = &items.genesis_hash {
                     self.items
                         .get(genesis_hash)
-                        .map_or(false, |amount| *amount >= items.amount)
+                        .is_some_and(|amount| *amount >= items.amount)
                 } else {
                     false
                 }
@@ -373,6 +760,15 @@ impl AssetValues {
     pub fn update_add(&mut self, rhs: &Asset) {
         match rhs {
             Asset::Token(tokens) => self.tokens += *tokens,
+            Asset::TokenWithMetadata {
+                amount,
+                genesis_hash: Some(genesis_hash),
+                ..
+            } => {
+                let held = self.token_classes.entry(genesis_hash.clone()).or_default();
+                *held = held.saturating_add(amount.0);
+            }
+            Asset::TokenWithMetadata { .. } => {}
             Asset::Item(items) => {
                 if let Some(genesis_hash) = &items.genesis_hash {
                     self.items
@@ -388,6 +784,16 @@ impl AssetValues {
     pub fn update_sub(&mut self, rhs: &Asset) {
         match rhs {
             Asset::Token(tokens) => self.tokens -= *tokens,
+            Asset::TokenWithMetadata {
+                amount,
+                genesis_hash: Some(genesis_hash),
+                ..
+            } => {
+                if let Some(held) = self.token_classes.get_mut(genesis_hash) {
+                    *held = held.saturating_sub(amount.0);
+                }
+            }
+            Asset::TokenWithMetadata { .. } => {}
             Asset::Item(items) => {
                 items.genesis_hash.as_ref().and_then(|genesis_hash| {
                     self.items
@@ -399,6 +805,57 @@ impl AssetValues {
     }
 }
 
+/// How one `AssetValues` differs from another, as the amounts `rhs` holds beyond `lhs`
+///
+/// `tokens`        - Token delta, negative if `rhs` holds fewer tokens
+/// `items`         - Item delta per genesis hash, for those not held equally by both
+/// `only_in_lhs`   - Genesis hashes of items only `lhs` holds
+/// `only_in_rhs`   - Genesis hashes of items only `rhs` holds
+/// `token_classes` - Token class delta per genesis hash, for those not held equally by both
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct AssetDiff {
+    pub tokens: i128,
+    pub items: BTreeMap<String, i128>,
+    pub only_in_lhs: BTreeSet<String>,
+    pub only_in_rhs: BTreeSet<String>,
+    pub token_classes: BTreeMap<String, i128>,
+}
+
+impl AssetDiff {
+    pub fn is_empty(&self) -> bool {
+        self == &AssetDiff::default()
+    }
+}
+
+impl fmt::Display for AssetDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no difference");
+        }
+        let tokens = (self.tokens != 0).then(|| format!("tokens {:+}", self.tokens));
+        let items = self.items.iter().map(|(genesis_hash, delta)| {
+            let side = if self.only_in_lhs.contains(genesis_hash) {
+                " (missing)"
+            } else if self.only_in_rhs.contains(genesis_hash) {
+                " (added)"
+            } else {
+                ""
+            };
+            format!("item {genesis_hash} {delta:+}{side}")
+        });
+        let token_classes = self
+            .token_classes
+            .iter()
+            .map(|(genesis_hash, delta)| format!("token class {genesis_hash} {delta:+}"));
+        let parts: Vec<String> = tokens
+            .into_iter()
+            .chain(items)
+            .chain(token_classes)
+            .collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
 #[test]
 fn test_token_amount_operations() {
     // add
@@ -438,3 +895,224 @@ fn test_token_amount_operations() {
     token /= rhs;
     assert_eq!(token, TokenAmount(u64::MAX));
 }
+
+#[test]
+fn test_token_with_metadata_operations() {
+    let metadata = TokenMetadata::new("Test Token".to_string(), "TST".to_string(), 2, 1000);
+    let other_metadata = TokenMetadata::new("Other".to_string(), "OTH".to_string(), 0, 10);
+    let class = |amount| Asset::token_with_metadata(amount, metadata.clone(), Some("g_a".into()));
+    let mut asset = class(5);
+    let other_asset = Asset::token_with_metadata(5, other_metadata, Some("g_b".to_owned()));
+
+    assert!(asset.is_token_with_metadata());
+    assert!(!asset.is_token());
+    assert_eq!(asset.token_amount(), TokenAmount(5));
+    assert_eq!(asset.get_token_metadata(), Some(&metadata));
+    assert_eq!(asset.genesis_hash(), Some("g_a"));
+
+    // Only token amounts of the same class can be combined
+    assert!(!asset.add_assign(&other_asset));
+    assert!(!asset.add_assign(&Asset::token_u64(5)));
+    assert!(!asset.add_assign(&Asset::token_with_metadata(5, metadata.clone(), None)));
+    assert!(asset.add_assign(&class(5)));
+    assert_eq!(asset, class(10));
+    assert_eq!(asset.get_excess(&class(4)), Some(class(6)));
+    assert_eq!(asset.is_greater_or_equal_to(&other_asset), None);
+
+    // Created classes take the hash of their create transaction
+    let created = Asset::token_with_metadata(5, metadata.clone(), None);
+    let out_point = OutPoint::new("g_a".to_owned(), 0);
+    assert_eq!(created.with_fixed_hash(&out_point), class(5));
+
+    // Amounts are totalled per class, apart from tokens
+    let mut values = AssetValues::token_u64(1);
+    values.update_add(&asset);
+    values.update_add(&other_asset);
+    assert_eq!(values.tokens, TokenAmount(1));
+    assert_eq!(
+        values.get(&AssetId::TokenClass("g_a".to_owned())),
+        TokenAmount(10)
+    );
+    assert!(values.has_enough(&asset));
+    assert!(!values.has_enough(&class(11)));
+    assert!(!values.has_enough(&Asset::token_u64(2)));
+    assert!(!values.is_equal(&AssetValues::token_u64(11)));
+    assert_eq!(
+        AssetValues::token_u64(1).diff(&values).to_string(),
+        "token class g_a +10, token class g_b +5"
+    );
+    values.update_sub(&asset);
+    values.update_sub(&other_asset);
+    assert_eq!(
+        values,
+        AssetValues {
+            tokens: TokenAmount(1),
+            token_classes: BTreeMap::from([("g_a".to_owned(), 0), ("g_b".to_owned(), 0)]),
+            ..Default::default()
+        }
+    );
+
+    // Amounts of classes not yet created aren't counted
+    let mut values = AssetValues::default();
+    values.update_add(&Asset::token_with_metadata(5, metadata, None));
+    assert!(values.is_empty());
+}
+
+#[test]
+fn test_asset_values_get() {
+    let mut values = AssetValues::token_u64(10);
+    values.update_add(&Asset::item(3, Some("genesis_hash".to_owned()), None));
+
+    assert_eq!(values.get(&AssetId::Token), TokenAmount(10));
+    assert_eq!(
+        values.get(&AssetId::Item("genesis_hash".to_owned())),
+        TokenAmount(3)
+    );
+    assert_eq!(
+        values.get(&AssetId::Item("missing".to_owned())),
+        TokenAmount(0)
+    );
+    assert_eq!(
+        values.iter().collect::<Vec<_>>(),
+        vec![
+            (AssetId::Token, TokenAmount(10)),
+            (AssetId::Item("genesis_hash".to_owned()), TokenAmount(3)),
+        ]
+    );
+    assert_eq!(AssetValues::default().iter().count(), 0);
+}
+
+#[test]
+fn test_asset_values_diff() {
+    let items = |entries: &[(&str, u64)]| -> BTreeMap<String, u64> {
+        entries.iter().map(|(h, a)| (h.to_string(), *a)).collect()
+    };
+    let inputs = AssetValues::new(TokenAmount(10), items(&[("g_a", 3), ("g_b", 1)]));
+
+    let diff = inputs.diff(&inputs.clone());
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "no difference");
+
+    // Token shortfall and excess
+    let diff = inputs.diff(&AssetValues::new(TokenAmount(4), inputs.items.clone()));
+    assert_eq!(diff.tokens, -6);
+    assert!(diff.items.is_empty());
+    assert_eq!(diff.to_string(), "tokens -6");
+    let diff = inputs.diff(&AssetValues::new(TokenAmount(12), inputs.items.clone()));
+    assert_eq!(diff.to_string(), "tokens +2");
+
+    // Item amount mismatch for one genesis hash
+    let diff = inputs.diff(&AssetValues::new(
+        TokenAmount(10),
+        items(&[("g_a", 5), ("g_b", 1)]),
+    ));
+    assert_eq!(diff.tokens, 0);
+    assert_eq!(diff.items, BTreeMap::from([("g_a".to_owned(), 2)]));
+    assert!(diff.only_in_lhs.is_empty() && diff.only_in_rhs.is_empty());
+    assert_eq!(diff.to_string(), "item g_a +2");
+
+    // Item lines present on one side only
+    let diff = inputs.diff(&AssetValues::new(
+        TokenAmount(9),
+        items(&[("g_a", 3), ("g_c", 4)]),
+    ));
+    assert_eq!(
+        diff,
+        AssetDiff {
+            tokens: -1,
+            items: BTreeMap::from([("g_b".to_owned(), -1), ("g_c".to_owned(), 4)]),
+            only_in_lhs: BTreeSet::from(["g_b".to_owned()]),
+            only_in_rhs: BTreeSet::from(["g_c".to_owned()]),
+            token_classes: BTreeMap::new(),
+        }
+    );
+    assert_eq!(
+        diff.to_string(),
+        "tokens -1, item g_b -1 (missing), item g_c +4 (added)"
+    );
+}
+
+#[test]
+fn test_genesis_hash_accessors() {
+    let item = ItemAsset::new(1, None, None).with_genesis_hash("g_item".to_owned());
+    assert_eq!(item.genesis_hash(), Some("g_item"));
+    assert_eq!(ItemAsset::new(1, None, None).genesis_hash(), None);
+
+    let asset = Asset::Item(item);
+    assert_eq!(asset.genesis_hash(), Some("g_item"));
+    assert_eq!(Asset::token_u64(1).genesis_hash(), None);
+
+    #[allow(deprecated)]
+    let alias = asset.get_genesis_hash().map(String::as_str);
+    assert_eq!(alias, asset.genesis_hash());
+}
+
+#[test]
+fn test_asset_is_compatible_with() {
+    let metadata = TokenMetadata::new("Test Token".to_string(), "TST".to_string(), 2, 1000);
+    let item = |genesis_hash: Option<&str>| Asset::item(1, genesis_hash.map(str::to_owned), None);
+
+    assert!(Asset::token_u64(1).is_compatible_with(&Asset::token_u64(5)));
+    assert!(item(Some("g_a")).is_compatible_with(&item(Some("g_a"))));
+    assert!(!item(Some("g_a")).is_compatible_with(&item(Some("g_b"))));
+    assert!(!item(Some("g_a")).is_compatible_with(&item(None)));
+    assert!(!Asset::token_u64(1).is_compatible_with(&item(Some("g_a"))));
+    let class = |genesis_hash: &str| {
+        Asset::token_with_metadata(1, metadata.clone(), Some(genesis_hash.to_owned()))
+    };
+    assert!(class("g_a").is_compatible_with(&class("g_a")));
+    assert!(!class("g_a").is_compatible_with(&class("g_b")));
+    assert!(!Asset::token_u64(1).is_compatible_with(&class("g_a")));
+}
+
+#[test]
+fn test_token_amount_display_round_trip() {
+    let unit = D_DISPLAY_PLACES_U64;
+    let cases = [
+        (0, "0"),
+        (1, "0.00000001"),
+        (unit / 2, "0.5"),
+        (unit, "1"),
+        (unit + 1, "1.00000001"),
+        (2 * unit - 1, "1.99999999"),
+        (TOTAL_TOKENS - 1, "4999999999.99999999"),
+        (TOTAL_TOKENS, "5000000000"),
+    ];
+
+    for (units, display) in cases {
+        let amount = TokenAmount(units);
+        assert_eq!(amount.to_string(), display);
+        assert_eq!(display.parse(), Ok(amount));
+    }
+
+    // Amounts round-trip exactly, including where f64 would lose precision
+    for units in (0..3 * unit)
+        .step_by(997)
+        .chain(TOTAL_TOKENS - 1000..=TOTAL_TOKENS)
+    {
+        let amount = TokenAmount(units);
+        assert_eq!(amount.to_string().parse(), Ok(amount));
+    }
+    assert_ne!((TOTAL_TOKENS - 1) as f64 as u64, TOTAL_TOKENS - 1);
+}
+
+#[test]
+fn test_token_amount_parse_errors() {
+    let cases = [
+        ("", AmountParseError::Empty),
+        ("-1", AmountParseError::Negative),
+        ("1.2.3", AmountParseError::InvalidDigit),
+        ("+1", AmountParseError::InvalidDigit),
+        (".", AmountParseError::InvalidDigit),
+        ("1e5", AmountParseError::InvalidDigit),
+        ("0.000000001", AmountParseError::TooManyDecimals(9)),
+        ("5000000000.00000001", AmountParseError::Overflow),
+        ("18446744073709551616", AmountParseError::Overflow),
+    ];
+
+    for (s, error) in cases {
+        assert_eq!(TokenAmount::from_display_str(s), Err(error), "{}", s);
+    }
+    assert_eq!(".5".parse(), Ok(TokenAmount(D_DISPLAY_PLACES_U64 / 2)));
+    assert_eq!("1.".parse(), Ok(TokenAmount(D_DISPLAY_PLACES_U64)));
+}
@@ -0,0 +1,490 @@
+//! Compact transport encoding of transactions.
+//!
+//! Standard P2PKH input scripts are sent as their signature and public key only, as
+//! everything else in them is rebuilt from the transaction. Decoding restores the exact
+//! scripts, so transaction hashes and validation only ever see the expanded form.
+//!
+//! The encoding is the bincode transaction with every input script emptied, followed by
+//! one entry per input: a tag byte, then the fields of that tag as `wire` data pushes.
+//!
+//! Lists of outpoints are encoded grouped by transaction hash, as the hash pushed once
+//! followed by a push of the group's sorted indices: the first one zigzag encoded, then
+//! the difference to each next one, all as LEB128 varints.
+
+use crate::crypto::sign_ed25519::{PublicKey, Signature};
+use crate::primitives::transaction::{OutPoint, Transaction, TxIn};
+use crate::script::lang::{Script, ScriptError};
+use crate::script::wire::{decode_push, encode_push};
+use crate::script::StackEntry;
+use crate::utils::transaction_utils::SignableMessage;
+use bincode::{deserialize, deserialize_from, serialize};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Tag of an input script carried as is: `<script>`, bincode serialized
+pub const COMPACT_TAG_VERBATIM: u8 = 0x00;
+/// Tag of a `Script::pay2pkh` input script at the current address version: `<sig> <pk>`
+pub const COMPACT_TAG_P2PKH: u8 = 0x01;
+
+/// Reasons compact transaction or outpoint bytes can't be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompactTxError {
+    Body,
+    UnknownTag(u8),
+    Field(ScriptError),
+    FieldLength(usize),
+    Script,
+    TrailingBytes(usize),
+    TxHash,
+    Indices,
+}
+
+impl fmt::Display for CompactTxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Body => write!(f, "Transaction body can't be deserialized"),
+            Self::UnknownTag(tag) => write!(f, "Unknown input script tag {tag:#04x}"),
+            Self::Field(e) => write!(f, "{e}"),
+            Self::FieldLength(len) => write!(f, "Input script field has wrong length {len}"),
+            Self::Script => write!(f, "Verbatim input script can't be deserialized"),
+            Self::TrailingBytes(len) => write!(f, "{len} bytes left after the last input"),
+            Self::TxHash => write!(f, "Outpoint transaction hash is not valid UTF-8"),
+            Self::Indices => write!(f, "Outpoint indices are not increasing varints"),
+        }
+    }
+}
+
+impl std::error::Error for CompactTxError {}
+
+impl From<ScriptError> for CompactTxError {
+    fn from(e: ScriptError) -> Self {
+        Self::Field(e)
+    }
+}
+
+impl Transaction {
+    /// Encodes the transaction for transport, with standard P2PKH input scripts
+    /// reduced to their signature and public key
+    pub fn to_compact_bytes(&self) -> Vec<u8> {
+        let mut body = self.clone();
+        for tx_in in &mut body.inputs {
+            tx_in.script_signature = Script::new();
+        }
+        let mut bytes = serialize(&body).unwrap_or_default();
+
+        for tx_in in &self.inputs {
+            match p2pkh_fields(self, tx_in) {
+                Some((signature, pub_key)) => {
+                    bytes.push(COMPACT_TAG_P2PKH);
+                    bytes.extend(encode_push(signature.as_ref()));
+                    bytes.extend(encode_push(pub_key.as_ref()));
+                }
+                None => {
+                    let script = serialize(&tx_in.script_signature).unwrap_or_default();
+                    bytes.push(COMPACT_TAG_VERBATIM);
+                    bytes.extend(encode_push(&script));
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Decodes a transaction encoded with `to_compact_bytes`, rebuilding its input scripts
+    ///
+    /// ### Arguments
+    ///
+    /// * `bytes`  - Compact encoding of the transaction
+    pub fn from_compact_bytes(bytes: &[u8]) -> Result<Self, CompactTxError> {
+        let mut rest = bytes;
+        let mut tx: Transaction = deserialize_from(&mut rest).map_err(|_| CompactTxError::Body)?;
+
+        let mut scripts = Vec::with_capacity(tx.inputs.len());
+        for tx_in in &tx.inputs {
+            let (&tag, fields) = rest.split_first().ok_or(ScriptError::TruncatedPush)?;
+            let script = match tag {
+                COMPACT_TAG_P2PKH => {
+                    let (signature, fields) = decode_push(fields)?;
+                    let (pub_key, fields) = decode_push(fields)?;
+                    rest = fields;
+
+                    let signature = Signature::from_slice(signature)
+                        .ok_or(CompactTxError::FieldLength(signature.len()))?;
+                    let pub_key = PublicKey::from_slice(pub_key)
+                        .ok_or(CompactTxError::FieldLength(pub_key.len()))?;
+                    let check_data = p2pkh_check_data(&tx, tx_in);
+                    Script::pay2pkh(check_data, signature, pub_key, None)
+                }
+                COMPACT_TAG_VERBATIM => {
+                    let (script, fields) = decode_push(fields)?;
+                    rest = fields;
+                    deserialize(script).map_err(|_| CompactTxError::Script)?
+                }
+                tag => return Err(CompactTxError::UnknownTag(tag)),
+            };
+            scripts.push(script);
+        }
+        for (tx_in, script) in tx.inputs.iter_mut().zip(scripts) {
+            tx_in.script_signature = script;
+        }
+
+        match rest.len() {
+            0 => Ok(tx),
+            len => Err(CompactTxError::TrailingBytes(len)),
+        }
+    }
+}
+
+/// Encodes outpoints grouped by transaction hash, so the hash shared by outputs of
+/// the same transaction is only stored once. Outpoints are sorted and deduplicated
+///
+/// ### Arguments
+///
+/// * `out_points`  - Outpoints to encode
+pub fn encode_out_points<'a>(out_points: impl IntoIterator<Item = &'a OutPoint>) -> Vec<u8> {
+    let mut groups: BTreeMap<&str, Vec<i32>> = BTreeMap::new();
+    for out_point in out_points {
+        groups
+            .entry(&out_point.t_hash)
+            .or_default()
+            .push(out_point.n);
+    }
+
+    let mut bytes = Vec::new();
+    for (t_hash, mut indices) in groups {
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mut encoded = Vec::new();
+        let first = indices[0];
+        push_varint(&mut encoded, ((first << 1) ^ (first >> 31)) as u32);
+        for pair in indices.windows(2) {
+            push_varint(&mut encoded, (pair[1] as i64 - pair[0] as i64) as u32);
+        }
+        bytes.extend(encode_push(t_hash.as_bytes()));
+        bytes.extend(encode_push(&encoded));
+    }
+    bytes
+}
+
+/// Decodes outpoints encoded with `encode_out_points`
+///
+/// ### Arguments
+///
+/// * `bytes`  - Grouped encoding of the outpoints
+pub fn decode_out_points(bytes: &[u8]) -> Result<Vec<OutPoint>, CompactTxError> {
+    let mut out_points = Vec::new();
+    let mut rest = bytes;
+    while !rest.is_empty() {
+        let (t_hash, fields) = decode_push(rest)?;
+        let (mut encoded, fields) = decode_push(fields)?;
+        rest = fields;
+
+        let t_hash = std::str::from_utf8(t_hash).map_err(|_| CompactTxError::TxHash)?;
+        let first = split_varint(&mut encoded)?;
+        let mut n = (first >> 1) as i32 ^ -((first & 1) as i32);
+        out_points.push(OutPoint::new(t_hash.to_owned(), n));
+        while !encoded.is_empty() {
+            let delta = split_varint(&mut encoded)?;
+            n = match i32::try_from(n as i64 + delta as i64) {
+                Ok(next) if delta > 0 => next,
+                _ => return Err(CompactTxError::Indices),
+            };
+            out_points.push(OutPoint::new(t_hash.to_owned(), n));
+        }
+    }
+    Ok(out_points)
+}
+
+/// Appends `value` as a LEB128 varint
+///
+/// ### Arguments
+///
+/// * `bytes`   - Bytes to append to
+/// * `value`   - Value to encode
+fn push_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Takes a minimally encoded LEB128 varint from the start of `bytes`
+///
+/// ### Arguments
+///
+/// * `bytes`   - Bytes starting with the varint, advanced past it
+fn split_varint(bytes: &mut &[u8]) -> Result<u32, CompactTxError> {
+    let mut value: u32 = 0;
+    for (idx, &byte) in bytes.iter().enumerate().take(5) {
+        let bits = (byte & 0x7f) as u32;
+        if idx == 4 && bits > 0x0f {
+            break;
+        }
+        value |= bits << (7 * idx);
+        if byte & 0x80 == 0 {
+            if idx > 0 && byte == 0 {
+                break;
+            }
+            *bytes = &bytes[idx + 1..];
+            return Ok(value);
+        }
+    }
+    Err(CompactTxError::Indices)
+}
+
+/// Check data of a P2PKH script spending `tx_in` in `tx`
+///
+/// ### Arguments
+///
+/// * `tx`      - Transaction the input belongs to
+/// * `tx_in`   - Input being spent
+fn p2pkh_check_data(tx: &Transaction, tx_in: &TxIn) -> String {
+    SignableMessage::tx_in(tx_in, &tx.outputs).hash_at_version(tx.version)
+}
+
+/// Signature and public key of an input script, if it is exactly the `Script::pay2pkh`
+/// script that decoding would rebuild from them
+///
+/// ### Arguments
+///
+/// * `tx`      - Transaction the input belongs to
+/// * `tx_in`   - Input to check
+fn p2pkh_fields(tx: &Transaction, tx_in: &TxIn) -> Option<(Signature, PublicKey)> {
+    tx_in.previous_out.as_ref()?;
+    let (signature, pub_key) = match tx_in.script_signature.stack.as_slice() {
+        [_, StackEntry::Signature(signature), StackEntry::PubKey(pub_key), ..] => {
+            (*signature, *pub_key)
+        }
+        _ => return None,
+    };
+    let check_data = p2pkh_check_data(tx, tx_in);
+    let rebuilt = Script::pay2pkh(check_data, signature, pub_key, None);
+    (rebuilt == tx_in.script_signature).then_some((signature, pub_key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::NETWORK_VERSION_V0;
+    use crate::crypto::sign_ed25519 as sign;
+    use crate::primitives::asset::TokenAmount;
+    use crate::primitives::transaction::TxOut;
+    use crate::script::OpCodes;
+    use crate::utils::transaction_utils::{construct_tx_hash, construct_tx_ins_address_at_version};
+
+    /// Transaction spending `count` P2PKH inputs, with signatures over its outputs
+    fn p2pkh_tx(count: i32) -> Transaction {
+        let (pk, sk) = sign::keypair_from_seed(&[7; 32]);
+        let mut tx = Transaction::new();
+        tx.outputs = vec![TxOut::new_token_amount(
+            hex::encode([1; 32]),
+            TokenAmount(10),
+            None,
+        )];
+        tx.inputs = (0..count)
+            .map(|n| {
+                let previous_out = OutPoint::new(hex::encode([2; 16]), n);
+                let tx_in = TxIn::new_from_input(previous_out, Script::new());
+                let message = SignableMessage::tx_in(&tx_in, &tx.outputs);
+                let script = Script::pay2pkh(
+                    message.hash_at_version(tx.version),
+                    message.sign_at_version(tx.version, &sk),
+                    pk,
+                    None,
+                );
+                TxIn {
+                    script_signature: script,
+                    ..tx_in
+                }
+            })
+            .collect();
+        tx
+    }
+
+    #[test]
+    /// Checks that transactions mixing template and other input scripts round-trip
+    /// to identical scripts and hashes
+    fn test_compact_round_trip() {
+        let mut tx = p2pkh_tx(3);
+        let (pk, sk) = sign::keypair_from_seed(&[8; 32]);
+        let sig = sign::sign_detached(b"data", &sk);
+
+        // P2PKH at an older address version, with check data of another transaction,
+        // and scripts of other kinds
+        tx.inputs[1].script_signature = Script::pay2pkh(
+            p2pkh_check_data(&tx, &tx.inputs[1]),
+            sig,
+            pk,
+            Some(NETWORK_VERSION_V0),
+        );
+        tx.inputs.push(TxIn::new_from_input(
+            OutPoint::new("g0".to_owned(), 0),
+            Script::pay2pkh("other".to_owned(), sig, pk, None),
+        ));
+        tx.inputs.push(TxIn::new_from_input(
+            OutPoint::new("g0".to_owned(), 1),
+            Script::from(vec![StackEntry::Op(OpCodes::OP_1)]),
+        ));
+        tx.inputs.push(TxIn {
+            previous_out: None,
+            script_signature: Script::new_for_coinbase(3),
+        });
+
+        let bytes = tx.to_compact_bytes();
+        let decoded = Transaction::from_compact_bytes(&bytes).unwrap();
+        assert_eq!(decoded, tx);
+        assert_eq!(construct_tx_hash(&decoded), construct_tx_hash(&tx));
+        assert_eq!(
+            construct_tx_ins_address_at_version(&decoded.inputs, decoded.version),
+            construct_tx_ins_address_at_version(&tx.inputs, tx.version)
+        );
+
+        let tags: Vec<_> = tx
+            .inputs
+            .iter()
+            .map(|i| p2pkh_fields(&tx, i).is_some())
+            .collect();
+        assert_eq!(tags, [true, false, true, false, false, false]);
+    }
+
+    #[test]
+    /// Checks the size saved on a transaction spending many P2PKH inputs
+    fn test_compact_size() {
+        let tx = p2pkh_tx(100);
+        let full = serialize(&tx).unwrap().len();
+        let compact = tx.to_compact_bytes().len();
+
+        assert_eq!(
+            Transaction::from_compact_bytes(&tx.to_compact_bytes()),
+            Ok(tx)
+        );
+        assert!(
+            compact * 100 / full <= 45,
+            "compact {} bytes, full {} bytes",
+            compact,
+            full
+        );
+    }
+
+    #[test]
+    /// Checks that malformed input entries are rejected
+    fn test_compact_decode_errors() {
+        let tx = p2pkh_tx(1);
+        let bytes = tx.to_compact_bytes();
+        let body_len = bytes.len() - (1 + 2 + 64 + 1 + 32);
+        let (body, entry) = bytes.split_at(body_len);
+        assert_eq!(entry[0], COMPACT_TAG_P2PKH);
+
+        let with_entry = |entry: &[u8]| Transaction::from_compact_bytes(&[body, entry].concat());
+        let sig = encode_push(&[1; 64]);
+        let pk = encode_push(&[2; 32]);
+
+        // Fields of the wrong length for a signature or public key
+        let short_sig = [&[COMPACT_TAG_P2PKH][..], &encode_push(&[1; 63]), &pk].concat();
+        assert_eq!(with_entry(&short_sig), Err(CompactTxError::FieldLength(63)));
+        let long_pk = [&[COMPACT_TAG_P2PKH][..], &sig, &encode_push(&[2; 33])].concat();
+        assert_eq!(with_entry(&long_pk), Err(CompactTxError::FieldLength(33)));
+
+        // Lengths running past the end of the bytes
+        assert_eq!(
+            with_entry(&entry[..entry.len() - 1]),
+            Err(CompactTxError::Field(ScriptError::TruncatedPush))
+        );
+        assert_eq!(
+            with_entry(&[]),
+            Err(CompactTxError::Field(ScriptError::TruncatedPush))
+        );
+        assert_eq!(
+            with_entry(&[COMPACT_TAG_VERBATIM, 0xc5, 0]),
+            Err(CompactTxError::Field(ScriptError::TruncatedPush))
+        );
+
+        assert_eq!(
+            with_entry(&[COMPACT_TAG_VERBATIM, 0xc1, 0]),
+            Err(CompactTxError::Script)
+        );
+        assert_eq!(with_entry(&[0x02]), Err(CompactTxError::UnknownTag(0x02)));
+        assert_eq!(
+            with_entry(&[entry, &[0]].concat()),
+            Err(CompactTxError::TrailingBytes(1))
+        );
+        assert_eq!(
+            Transaction::from_compact_bytes(&body[..10]),
+            Err(CompactTxError::Body)
+        );
+        assert_eq!(with_entry(entry), Ok(tx));
+    }
+
+    #[test]
+    /// Checks that outputs of one transaction store its hash once and decode to
+    /// distinct outpoints
+    fn test_out_points_grouped() {
+        let tx = p2pkh_tx(1);
+        let t_hash = construct_tx_hash(&tx);
+        let out_points: Vec<_> = [0, 1, 2, 7, 300]
+            .iter()
+            .map(|n| OutPoint::new(t_hash.clone(), *n))
+            .collect();
+        let other = OutPoint::new("g0".to_owned(), -1);
+
+        let bytes = encode_out_points(out_points.iter().rev().chain([&other, &out_points[2]]));
+        let occurrences = bytes
+            .windows(t_hash.len())
+            .filter(|w| *w == t_hash.as_bytes())
+            .count();
+        assert_eq!(occurrences, 1);
+        let listed_size = serialize(&out_points).unwrap().len();
+        assert!(bytes.len() < listed_size / 3);
+
+        let decoded = decode_out_points(&bytes).unwrap();
+        assert_eq!(decoded, [&[other][..], &out_points].concat());
+        assert_eq!(decode_out_points(&encode_out_points(&[])), Ok(Vec::new()));
+    }
+
+    #[test]
+    /// Checks that malformed outpoint groups are rejected
+    fn test_out_points_decode_errors() {
+        let group =
+            |t_hash: &[u8], indices: &[u8]| [encode_push(t_hash), encode_push(indices)].concat();
+
+        // Repeated indices, unterminated and non-minimal varints, and overflowing indices
+        assert_eq!(
+            decode_out_points(&group(b"g0", &[2, 0])),
+            Err(CompactTxError::Indices)
+        );
+        assert_eq!(
+            decode_out_points(&group(b"g0", &[0x80])),
+            Err(CompactTxError::Indices)
+        );
+        assert_eq!(
+            decode_out_points(&group(b"g0", &[0x81, 0])),
+            Err(CompactTxError::Indices)
+        );
+        assert_eq!(
+            decode_out_points(&group(b"g0", &[])),
+            Err(CompactTxError::Indices)
+        );
+        let max = group(b"g0", &[0xfe, 0xff, 0xff, 0xff, 0x0f, 1]);
+        assert_eq!(decode_out_points(&max), Err(CompactTxError::Indices));
+        assert_eq!(
+            decode_out_points(&group(b"g0", &[0xfe, 0xff, 0xff, 0xff, 0x1f])),
+            Err(CompactTxError::Indices)
+        );
+        assert_eq!(
+            decode_out_points(&max[..max.len() - 1]),
+            Err(CompactTxError::Field(ScriptError::TruncatedPush))
+        );
+        assert_eq!(
+            decode_out_points(&group(&[0xff], &[0])),
+            Err(CompactTxError::TxHash)
+        );
+
+        let extremes = [i32::MIN, -1, 0, i32::MAX].map(|n| OutPoint::new("g0".to_owned(), n));
+        assert_eq!(
+            decode_out_points(&encode_out_points(&extremes)),
+            Ok(extremes.to_vec())
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use crate::primitives::asset::Asset;
+use crate::utils::transaction_utils::is_address;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 /// The expectation to be met in a specific DRUID transaction
 #[derive(Default, Clone, Debug, Ord, Eq, PartialEq, Serialize, Deserialize, PartialOrd)]
@@ -9,6 +11,66 @@ pub struct DruidExpectation {
     pub asset: Asset,
 }
 
+/// Terms a participant attaches to one of its DRUID expectations
+///
+/// Terms are agreed between the participants and checked when matching DRUID
+/// transactions, but are not carried by the transactions themselves, so attaching
+/// them leaves the encoding and hash of the transactions unchanged
+///
+/// `max_locktime` is the latest block height the satisfying output may be locked to.
+/// `output_constraint` further restricts the transaction holding the satisfying output
+#[derive(Default, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ExpectationTerms {
+    pub max_locktime: Option<u64>,
+    pub output_constraint: Option<OutputConstraint>,
+}
+
+/// Restriction on the other outputs of a transaction meeting a DRUID expectation
+///
+/// `from` only identifies the inputs of the paying transaction, so on its own an
+/// expectation cannot stop a sender controlling those inputs from paying the expected
+/// output while routing more of the same asset to third parties in the same
+/// transaction, e.g. siphoning further items of the traded genesis hash to an address
+/// of their choosing. A constraint lets the receiver rule such transactions out.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum OutputConstraint {
+    /// Every output of the paying transaction holding the expected asset's class, tokens
+    /// or items of the same genesis hash, pays either `to` or the `change` address
+    ExclusiveAssetClass { change: Option<String> },
+}
+
+impl DruidExpectation {
+    /// Checks that `from` and `to` are addresses, rather than transaction hashes
+    /// or other strings
+    pub fn validate(&self) -> Result<(), DruidExpectationError> {
+        if !is_address(&self.from) {
+            return Err(DruidExpectationError::FromNotAddress(self.from.clone()));
+        }
+        if !is_address(&self.to) {
+            return Err(DruidExpectationError::ToNotAddress(self.to.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// Reasons a DRUID expectation is malformed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DruidExpectationError {
+    FromNotAddress(String),
+    ToNotAddress(String),
+}
+
+impl fmt::Display for DruidExpectationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::FromNotAddress(from) => write!(f, "Expectation from {from} is not an address"),
+            Self::ToNotAddress(to) => write!(f, "Expectation to {to} is not an address"),
+        }
+    }
+}
+
+impl std::error::Error for DruidExpectationError {}
+
 /// A structure to hold DDE-specific content in a transaction
 ///
 /// `druid`                 - DRUID to match on
@@ -1,4 +1,5 @@
 pub mod asset;
 pub mod block;
+pub mod compact;
 pub mod druid;
 pub mod transaction;
@@ -8,16 +8,25 @@ use crate::primitives::{
 use crate::script::lang::Script;
 use crate::script::{OpCodes, StackEntry};
 use crate::utils::is_valid_amount;
+use crate::utils::transaction_utils::{
+    address_type, construct_tx_in_signable_hash, generate_prefixed_hex, is_tx_hash, AddressKind,
+};
 use bincode::serialize;
 use bytes::Bytes;
+use rand::rngs::OsRng;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 use std::fmt;
+use std::str::FromStr;
+use tracing::warn;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum GenesisTxHashSpec {
     Create,
     Default,
-    //TODO: Eventually custom?
+    Existing(String),
+    FromOutPoint(OutPoint),
 }
 
 impl GenesisTxHashSpec {
@@ -25,6 +34,36 @@ impl GenesisTxHashSpec {
         match self {
             GenesisTxHashSpec::Create => None, /* Unique DRS transaction hash will be assigned */
             GenesisTxHashSpec::Default => Some(ITEM_DEFAULT_DRS_TX_HASH.to_string()),
+            GenesisTxHashSpec::Existing(hash) => Some(hash.clone()),
+            GenesisTxHashSpec::FromOutPoint(out_point) => {
+                Some(construct_tx_in_signable_hash(out_point))
+            }
+        }
+    }
+
+    /// Creates an `Existing` spec holding a new genesis hash drawn from `rng`, for items whose
+    /// genesis hash must be known before a create transaction exists. The hash has the format
+    /// of a transaction hash: `TX_PREPEND` followed by 31 lowercase hex characters, carrying
+    /// 124 bits of entropy
+    ///
+    /// ### Arguments
+    ///
+    /// * `rng`  - Source of randomness
+    pub fn create_with_rng(rng: &mut impl RngCore) -> Self {
+        GenesisTxHashSpec::Existing(generate_prefixed_hex(TX_PREPEND, TX_HASH_LENGTH, rng))
+    }
+
+    /// Creates an `Existing` spec holding a new genesis hash drawn from OS randomness.
+    /// See `create_with_rng` for its format
+    pub fn create_random() -> Self {
+        Self::create_with_rng(&mut OsRng)
+    }
+
+    /// Returns whether an `Existing` genesis hash has the format of a transaction hash
+    pub fn is_well_formed(&self) -> bool {
+        match self {
+            GenesisTxHashSpec::Existing(hash) => is_tx_hash(hash),
+            _ => true,
         }
     }
 }
@@ -38,6 +77,64 @@ pub struct TxConstructor {
     pub address_version: Option<u64>,
 }
 
+/// Position of an input in `Transaction::inputs`
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct InputIndex(pub u32);
+
+/// Position of an output in `Transaction::outputs`, or in `Transaction::fees` for fee outputs
+#[derive(
+    Clone, Copy, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct OutputIndex(pub u32);
+
+impl InputIndex {
+    /// Index of the input at position `idx`, such as given by `enumerate`
+    ///
+    /// ### Arguments
+    ///
+    /// * `idx`  - Position of the input
+    pub fn from_position(idx: usize) -> Self {
+        Self(u32::try_from(idx).unwrap_or(u32::MAX))
+    }
+
+    /// Position of the input, for indexing slices
+    pub fn position(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl OutputIndex {
+    /// Index of the output at position `idx`, such as given by `enumerate`
+    ///
+    /// ### Arguments
+    ///
+    /// * `idx`  - Position of the output
+    pub fn from_position(idx: usize) -> Self {
+        Self(u32::try_from(idx).unwrap_or(u32::MAX))
+    }
+
+    /// Position of the output, for indexing slices
+    pub fn position(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for InputIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for OutputIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 /// An outpoint - a combination of a transaction hash and an index n into its vout
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct OutPoint {
@@ -45,9 +142,42 @@ pub struct OutPoint {
     pub n: i32,
 }
 
+/// Formats an outpoint as its canonical `"{t_hash}:{n}"` string.
+///
+/// **NOTE:** This is distinct from the signable string used for signing inputs
 impl fmt::Display for OutPoint {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "t_hash:{}-n:{}", self.t_hash, self.n)
+        write!(f, "{}:{}", self.t_hash, self.n)
+    }
+}
+
+/// Error parsing an outpoint from its canonical string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutPointParseError {
+    MissingSeparator,
+    InvalidIndex,
+    InvalidTxHash(String),
+}
+
+impl fmt::Display for OutPointParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingSeparator => write!(f, "OutPoint is missing the ':' separator"),
+            Self::InvalidIndex => write!(f, "OutPoint index is not a valid number"),
+            Self::InvalidTxHash(t_hash) => write!(f, "OutPoint {t_hash} is not a transaction hash"),
+        }
+    }
+}
+
+impl std::error::Error for OutPointParseError {}
+
+impl FromStr for OutPoint {
+    type Err = OutPointParseError;
+
+    /// Parses an outpoint from its canonical `"{t_hash}:{n}"` string, within the hash
+    /// namespace grace period. See `OutPoint::parse`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OutPoint::parse(s, true)
     }
 }
 
@@ -56,6 +186,40 @@ impl OutPoint {
     pub fn new(t_hash: String, n: i32) -> OutPoint {
         OutPoint { t_hash, n }
     }
+
+    /// Creates a new outpoint instance, failing if `t_hash` isn't a transaction hash
+    pub fn try_new(t_hash: String, n: i32) -> Result<OutPoint, OutPointParseError> {
+        if !is_tx_hash(&t_hash) {
+            return Err(OutPointParseError::InvalidTxHash(t_hash));
+        }
+        Ok(OutPoint { t_hash, n })
+    }
+
+    /// Parses an outpoint from its canonical `"{t_hash}:{n}"` string. Within the hash
+    /// namespace grace period, a `t_hash` which isn't a transaction hash is only logged
+    ///
+    /// ### Arguments
+    ///
+    /// * `s`                   - Outpoint string
+    /// * `hash_namespace_grace` - Whether a malformed `t_hash` is accepted
+    pub fn parse(s: &str, hash_namespace_grace: bool) -> Result<OutPoint, OutPointParseError> {
+        let (t_hash, n) = s
+            .rsplit_once(':')
+            .ok_or(OutPointParseError::MissingSeparator)?;
+        let n = n.parse().map_err(|_| OutPointParseError::InvalidIndex)?;
+        match OutPoint::try_new(t_hash.to_owned(), n) {
+            Err(e) if hash_namespace_grace => {
+                warn!("{e}");
+                Ok(OutPoint::new(t_hash.to_owned(), n))
+            }
+            result => result,
+        }
+    }
+
+    /// Index of the output this outpoint refers to, if `n` isn't negative
+    pub fn output_index(&self) -> Option<OutputIndex> {
+        u32::try_from(self.n).ok().map(OutputIndex)
+    }
 }
 
 impl Default for OutPoint {
@@ -161,21 +325,52 @@ impl TxOut {
         match asset {
             Asset::Token(amount) => TxOut::new_token_amount(to_address, amount, locktime),
             Asset::Item(item) => TxOut::new_item_amount(to_address, item, locktime),
-            _ => panic!("Cannot create TxOut for asset of type {:?}", asset),
+            asset @ Asset::TokenWithMetadata { .. } => TxOut {
+                value: asset,
+                locktime: locktime.unwrap_or(ZERO as u64),
+                script_public_key: Some(to_address),
+            },
         }
     }
 
+    /// Returns whether both outputs pay the same value to the same script, whatever their
+    /// locktimes. Use this rather than `==` when matching outputs against each other
+    ///
+    /// ### Arguments
+    ///
+    /// * `other`  - Output to match against
+    pub fn matches_ignoring_locktime(&self, other: &TxOut) -> bool {
+        self.value == other.value && self.script_public_key == other.script_public_key
+    }
+
     /// Returns whether current tx_out is a P2SH
     pub fn is_p2sh_tx_out(&self) -> bool {
         if let Some(pk) = &self.script_public_key {
-            let pk_bytes = pk.as_bytes();
-            return pk_bytes[0] == P2SH_PREPEND;
+            return address_type(pk) == AddressKind::P2SH;
         }
 
         false
     }
 }
 
+/// Reasons a transaction fails to meet the minimum structural requirements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxStructureError {
+    NoInputs,
+    NoOutputs,
+}
+
+impl fmt::Display for TxStructureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoInputs => write!(f, "Transaction has no inputs"),
+            Self::NoOutputs => write!(f, "Transaction has no outputs besides fees"),
+        }
+    }
+}
+
+impl std::error::Error for TxStructureError {}
+
 /// The basic transaction that is broadcasted on the network and contained in
 /// blocks. A transaction can contain multiple inputs and outputs.
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -205,22 +400,57 @@ impl Transaction {
         }
     }
 
+    /// Checks the minimum structure of the transaction: its inputs must reach an output
+    /// other than a fee, and it must have an input (create and coinbase transactions
+    /// carry their create script as one)
+    pub fn validate_structure(&self) -> Result<(), TxStructureError> {
+        if self.outputs.is_empty() {
+            return Err(TxStructureError::NoOutputs);
+        }
+        if self.inputs.is_empty() {
+            return Err(TxStructureError::NoInputs);
+        }
+        Ok(())
+    }
+
     /// Get the total transaction size in bytes
     pub fn get_total_size(&self) -> usize {
-        let bytes = match serialize(self) {
-            Ok(bytes) => bytes,
-            Err(_) => vec![],
-        };
-        bytes.len()
+        serialize(self).unwrap_or_default().len()
+    }
+
+    /// Gets an input, or `None` if `index` is out of range
+    ///
+    /// ### Arguments
+    ///
+    /// * `index`  - Index of the input
+    pub fn input(&self, index: InputIndex) -> Option<&TxIn> {
+        self.inputs.get(index.position())
+    }
+
+    /// Gets an output, or `None` if `index` is out of range
+    ///
+    /// ### Arguments
+    ///
+    /// * `index`  - Index of the output
+    pub fn output(&self, index: OutputIndex) -> Option<&TxOut> {
+        self.outputs.get(index.position())
+    }
+
+    /// Gets a fee output, or `None` if `index` is out of range
+    ///
+    /// ### Arguments
+    ///
+    /// * `index`  - Index of the fee output
+    pub fn fee_output(&self, index: OutputIndex) -> Option<&TxOut> {
+        self.fees.get(index.position())
     }
 
     /// Gets the create asset assigned to this transaction, if it exists
     fn get_create_asset(&self) -> Option<&Asset> {
-        let is_create = self.inputs.len() == 1
-            && self.inputs[0].previous_out.is_none()
-            && self.outputs.len() == 1;
-
-        is_create.then(|| &self.outputs[0].value)
+        match (self.inputs.as_slice(), self.outputs.as_slice()) {
+            ([tx_in], [tx_out]) if tx_in.previous_out.is_none() => Some(&tx_out.value),
+            _ => None,
+        }
     }
 
     /// Returns whether current transaction is a coinbase tx
@@ -237,17 +467,272 @@ impl Transaction {
             .unwrap_or_default()
     }
 
+    /// Gets the DRUID of this transaction, if it is part of a DDE transaction
+    pub fn druid(&self) -> Option<&str> {
+        self.druid_info.as_ref().map(|v| v.druid.as_str())
+    }
+
+    /// Returns whether current transaction is part of a DDE transaction
+    pub fn has_druid(&self) -> bool {
+        self.druid_info.is_some()
+    }
+
+    /// Gets the number of DDE participants, if this is a DDE transaction
+    pub fn participants(&self) -> Option<usize> {
+        self.druid_info.as_ref().map(|v| v.participants)
+    }
+
+    /// Gets the DDE expectations, if this is a DDE transaction
+    pub fn expectations(&self) -> Option<&[DruidExpectation]> {
+        self.druid_info.as_ref().map(|v| v.expectations.as_slice())
+    }
+
+    /// Sets the DDE values of this transaction, replacing any existing ones
+    ///
+    /// ### Arguments
+    ///
+    /// * `druid`           - DRUID to match on
+    /// * `participants`    - Participants in trade
+    /// * `expectations`    - Expectations to be met by the other participants
+    pub fn set_druid_info(
+        &mut self,
+        druid: String,
+        participants: usize,
+        expectations: Vec<DruidExpectation>,
+    ) -> &mut Self {
+        self.druid_info = Some(DdeValues {
+            druid,
+            participants,
+            expectations,
+            genesis_hash: None,
+        });
+        self
+    }
+
+    /// Gets the addresses paid by this transaction, outputs first then fees,
+    /// skipping any without a `script_public_key`
+    pub fn output_addresses(&self) -> Vec<&str> {
+        self.outputs
+            .iter()
+            .chain(&self.fees)
+            .filter_map(|tx_out| tx_out.script_public_key.as_deref())
+            .collect()
+    }
+
     /// Returns whether current transaction is a P2SH tx
     pub fn is_p2sh_tx(&self) -> bool {
-        if self.outputs.len() != 1 {
-            return false;
+        match self.outputs.as_slice() {
+            [tx_out] => tx_out
+                .script_public_key
+                .as_ref()
+                .is_some_and(|pk| pk.as_bytes().first() == Some(&P2SH_PREPEND)),
+            _ => false,
         }
+    }
+}
 
-        if let Some(pk) = &self.outputs[0].script_public_key {
-            let pk_bytes = pk.as_bytes();
-            return pk_bytes[0] == P2SH_PREPEND;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_utils::test_druid;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::collections::BTreeSet;
 
-        false
+    #[test]
+    /// Checks generated genesis hashes are pinned for a seed, well formed and distinct
+    fn test_genesis_create_with_rng() {
+        let genesis_hash =
+            |spec: GenesisTxHashSpec| (spec.is_well_formed(), spec.get_genesis_hash().unwrap());
+        let seeded = GenesisTxHashSpec::create_with_rng(&mut StdRng::seed_from_u64(0));
+        assert_eq!(
+            genesis_hash(seeded),
+            (true, "g7f6f2ccdb23f2abb7b69278e947c01c".to_owned())
+        );
+
+        let (well_formed, hash) = genesis_hash(GenesisTxHashSpec::create_random());
+        assert!(well_formed && is_tx_hash(&hash));
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let hashes: BTreeSet<_> = (0..100_000)
+            .map(|_| genesis_hash(GenesisTxHashSpec::create_with_rng(&mut rng)).1)
+            .collect();
+        assert_eq!(hashes.len(), 100_000);
+    }
+
+    #[test]
+    /// Checks that outputs differing only in locktime match, but aren't equal
+    fn test_tx_out_matches_ignoring_locktime() {
+        let tx_out = TxOut::new_token_amount("address".to_owned(), TokenAmount(10), None);
+        let locked = TxOut {
+            locktime: 1_000,
+            ..tx_out.clone()
+        };
+        assert!(tx_out.matches_ignoring_locktime(&locked));
+        assert_ne!(tx_out, locked);
+
+        let other_value = TxOut {
+            value: Asset::token_u64(11),
+            ..tx_out.clone()
+        };
+        let other_address = TxOut {
+            script_public_key: Some("other".to_owned()),
+            ..tx_out.clone()
+        };
+        assert!(!tx_out.matches_ignoring_locktime(&other_value));
+        assert!(!tx_out.matches_ignoring_locktime(&other_address));
+    }
+
+    #[test]
+    /// Checks that outpoints round-trip through their canonical string
+    fn test_out_point_string_round_trip() {
+        let out_point = OutPoint::new("g48dda5bbe9171a6656206ec56c595c5".to_owned(), 3);
+        let out_point_str = out_point.to_string();
+
+        assert_eq!(out_point_str, "g48dda5bbe9171a6656206ec56c595c5:3");
+        assert_eq!(out_point_str.parse::<OutPoint>(), Ok(out_point));
+        assert_eq!(
+            ":-1".parse::<OutPoint>(),
+            Ok(OutPoint::new(String::new(), -1))
+        );
+    }
+
+    #[test]
+    /// Checks that malformed outpoint strings are rejected
+    fn test_out_point_parse_malformed() {
+        assert_eq!(
+            "g48dda5bbe9171a6656206ec56c595c5".parse::<OutPoint>(),
+            Err(OutPointParseError::MissingSeparator)
+        );
+        assert_eq!(
+            "g48dda5bbe9171a6656206ec56c595c5:one".parse::<OutPoint>(),
+            Err(OutPointParseError::InvalidIndex)
+        );
+        assert_eq!(
+            "g48dda5bbe9171a6656206ec56c595c5:".parse::<OutPoint>(),
+            Err(OutPointParseError::InvalidIndex)
+        );
+    }
+
+    #[test]
+    /// Checks that outpoints not naming a transaction hash are only rejected outside the
+    /// hash namespace grace period
+    fn test_out_point_parse_hash_namespace_grace() {
+        let address = "a".repeat(64);
+        let out_point_str = format!("{address}:0");
+
+        assert_eq!(
+            OutPoint::parse(&out_point_str, true),
+            Ok(OutPoint::new(address.clone(), 0))
+        );
+        assert_eq!(
+            OutPoint::parse(&out_point_str, false),
+            Err(OutPointParseError::InvalidTxHash(address))
+        );
+        assert_eq!(
+            OutPoint::parse("g48dda5bbe9171a6656206ec56c595c5:1", false),
+            Ok(OutPoint::new(
+                "g48dda5bbe9171a6656206ec56c595c5".to_owned(),
+                1
+            ))
+        );
+    }
+
+    #[test]
+    /// Checks that output addresses include fees and skip outputs without an address
+    fn test_output_addresses() {
+        let tx_out =
+            |address: &str| TxOut::new_token_amount(address.to_owned(), TokenAmount(10), None);
+        let mut tx = Transaction::new();
+        tx.outputs = vec![tx_out("out_1"), tx_out("out_2")];
+        tx.fees = vec![tx_out("fee")];
+        assert_eq!(tx.output_addresses(), vec!["out_1", "out_2", "fee"]);
+
+        tx.outputs.insert(1, TxOut::new());
+        assert_eq!(tx.outputs[1].script_public_key, None);
+        assert_eq!(tx.output_addresses(), vec!["out_1", "out_2", "fee"]);
+        assert!(Transaction::new().output_addresses().is_empty());
+    }
+
+    #[test]
+    /// Checks that DDE accessors return `None` for a non-DDE transaction
+    fn test_druid_accessors_non_dde_tx() {
+        let tx = Transaction::new();
+
+        assert_eq!(tx.druid(), None);
+        assert!(!tx.has_druid());
+        assert_eq!(tx.participants(), None);
+        assert_eq!(tx.expectations(), None);
+    }
+
+    #[test]
+    /// Checks that DDE accessors return the values set on a DDE transaction
+    fn test_druid_accessors_dde_tx() {
+        let expectations = vec![DruidExpectation {
+            from: "from_addr".to_owned(),
+            to: "to_addr".to_owned(),
+            asset: Asset::token_u64(10),
+        }];
+
+        let mut tx = Transaction::new();
+        tx.set_druid_info(test_druid(), 2, expectations.clone());
+
+        assert_eq!(tx.druid(), Some(test_druid().as_str()));
+        assert!(tx.has_druid());
+        assert_eq!(tx.participants(), Some(2));
+        assert_eq!(tx.expectations(), Some(expectations.as_slice()));
+    }
+
+    #[test]
+    /// Checks that the checked accessors return `None` out of range, including on
+    /// transactions without any input or output
+    fn test_indexed_accessors() {
+        let empty = Transaction::new();
+        assert_eq!(empty.input(InputIndex(0)), None);
+        assert_eq!(empty.output(OutputIndex(0)), None);
+        assert_eq!(empty.fee_output(OutputIndex(0)), None);
+        assert!(!empty.is_create_tx());
+        assert!(!empty.is_p2sh_tx());
+
+        let mut tx = Transaction::new();
+        tx.inputs = vec![TxIn::new()];
+        tx.outputs = vec![TxOut::new_token_amount(String::new(), TokenAmount(1), None)];
+        tx.fees = vec![TxOut::new_token_amount(String::new(), TokenAmount(2), None)];
+        assert_eq!(tx.input(InputIndex(0)), Some(&tx.inputs[0]));
+        assert_eq!(tx.output(OutputIndex(0)), Some(&tx.outputs[0]));
+        assert_eq!(tx.fee_output(OutputIndex(0)), Some(&tx.fees[0]));
+        assert_eq!(tx.input(InputIndex(1)), None);
+        assert_eq!(tx.output(OutputIndex(u32::MAX)), None);
+        assert_eq!(tx.fee_output(OutputIndex(1)), None);
+        assert!(!tx.is_p2sh_tx());
+
+        assert_eq!(InputIndex::from_position(3).position(), 3);
+        assert_eq!(
+            OutputIndex::from_position(usize::MAX),
+            OutputIndex(u32::MAX)
+        );
+        assert_eq!(
+            OutPoint::new("g0".to_owned(), 2).output_index(),
+            Some(OutputIndex(2))
+        );
+        assert_eq!(OutPoint::new("g0".to_owned(), -1).output_index(), None);
+    }
+
+    #[test]
+    /// Checks that typed indices serialize as the plain integers they wrap
+    fn test_indices_serialize_as_integers() {
+        assert_eq!(
+            serialize(&InputIndex(7)).unwrap(),
+            serialize(&7u32).unwrap()
+        );
+        assert_eq!(
+            serialize(&OutputIndex(300)).unwrap(),
+            serialize(&300u32).unwrap()
+        );
+        assert_eq!(serde_json::to_string(&OutputIndex(5)).unwrap(), "5");
+        assert_eq!(
+            serde_json::from_str::<InputIndex>("9").unwrap(),
+            InputIndex(9)
+        );
+        assert_eq!(OutputIndex(5).to_string(), "5");
     }
 }
@@ -0,0 +1,180 @@
+use crate::constants::*;
+use crate::crypto::sha3_256;
+use crate::script::lang::{entry_bytes, Stack};
+use crate::script::{OpCodes, StackEntry};
+use bincode::serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Fuel charged for executing script entries. Nodes must agree on the table, since it
+/// decides which scripts fit a fuel budget: changing `Default` is consensus-affecting.
+/// Nodes may compare `checksum` to detect mismatched tables
+///
+/// * `base_op_cost`        - Fuel charged for an opcode not listed in `op_costs`
+/// * `op_costs`            - Fuel charged for specific opcodes, in place of `base_op_cost`
+/// * `push_cost`           - Fuel charged for pushing a data entry
+/// * `per_byte_cost`       - Fuel charged per byte of the top stack item read by a splice
+///   or hash opcode
+/// * `sig_verify_cost`     - Fuel charged by `OP_CHECKSIG` and `OP_CHECKSIGVERIFY`
+/// * `multisig_key_cost`   - Fuel charged per public key checked by `OP_CHECKMULTISIG`
+///   and `OP_CHECKMULTISIGVERIFY`
+#[derive(Clone, Debug, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ScriptCostTable {
+    pub base_op_cost: u64,
+    pub op_costs: BTreeMap<OpCodes, u64>,
+    pub push_cost: u64,
+    pub per_byte_cost: u64,
+    pub sig_verify_cost: u64,
+    pub multisig_key_cost: u64,
+}
+
+impl Default for ScriptCostTable {
+    /// The consensus cost table
+    fn default() -> Self {
+        let hash_ops = vec![
+            OpCodes::OP_SHA3,
+            OpCodes::OP_HASH256,
+            OpCodes::OP_HASH256_V0,
+            OpCodes::OP_HASH256_TEMP,
+        ];
+        Self {
+            base_op_cost: SCRIPT_BASE_OP_COST,
+            op_costs: hash_ops
+                .into_iter()
+                .map(|op| (op, SCRIPT_HASH_OP_COST))
+                .collect(),
+            push_cost: SCRIPT_PUSH_COST,
+            per_byte_cost: SCRIPT_PER_BYTE_COST,
+            sig_verify_cost: SCRIPT_SIG_VERIFY_COST,
+            multisig_key_cost: SCRIPT_MULTISIG_KEY_COST,
+        }
+    }
+}
+
+impl ScriptCostTable {
+    /// Returns the hex encoded SHA3 hash of the table's canonical serialization
+    pub fn checksum(&self) -> String {
+        let bytes = serialize(self).unwrap_or_default();
+        hex::encode(sha3_256::digest(&bytes))
+    }
+
+    /// Returns the fuel charged for pushing a data entry
+    pub fn push(&self) -> u64 {
+        self.push_cost
+    }
+
+    /// Returns the fuel charged for executing an opcode on a stack, before it executes
+    ///
+    /// ### Arguments
+    ///
+    /// * `op`     - Opcode being executed
+    /// * `stack`  - Stack the opcode executes on
+    pub fn op(&self, op: &OpCodes, stack: &Stack) -> u64 {
+        let base = self.op_costs.get(op).copied().unwrap_or(self.base_op_cost);
+        let top = stack.main_stack().last();
+        let extra = match op {
+            OpCodes::OP_CAT
+            | OpCodes::OP_SUBSTR
+            | OpCodes::OP_LEFT
+            | OpCodes::OP_RIGHT
+            | OpCodes::OP_SHA3
+            | OpCodes::OP_HASH256
+            | OpCodes::OP_HASH256_V0
+            | OpCodes::OP_HASH256_TEMP => {
+                let bytes = top.map_or(0, entry_bytes) as u64;
+                self.per_byte_cost.saturating_mul(bytes)
+            }
+            OpCodes::OP_CHECKSIG | OpCodes::OP_CHECKSIGVERIFY => self.sig_verify_cost,
+            OpCodes::OP_CHECKMULTISIG | OpCodes::OP_CHECKMULTISIGVERIFY => match top {
+                Some(StackEntry::Num(n)) => {
+                    let keys = (*n).min(MAX_PUB_KEYS_PER_MULTISIG) as u64;
+                    self.multisig_key_cost.saturating_mul(keys)
+                }
+                _ => 0,
+            },
+            _ => 0,
+        };
+        base.saturating_add(extra)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign_ed25519 as sign;
+    use crate::script::lang::{ExecutionContext, Script};
+
+    /// Fuel used by a script, which must execute successfully
+    fn fuel(script: &Script, costs: ScriptCostTable) -> u64 {
+        let context = ExecutionContext {
+            costs,
+            ..Default::default()
+        };
+        let (result, report) = script.evaluate_with_report(&context);
+        assert!(result.is_ok(), "{:?}", result);
+        report.fuel
+    }
+
+    #[test]
+    /// Checks that the consensus table has a stable checksum which any change alters
+    fn test_checksum() {
+        let default = ScriptCostTable::default();
+        assert_eq!(default.checksum(), ScriptCostTable::default().checksum());
+        assert_eq!(
+            default.checksum(),
+            "b095d63b393209813ab0919736238d8710020a8ca1f0cc73498094bd38d2da9f"
+        );
+
+        let mut custom = default.clone();
+        custom.op_costs.insert(OpCodes::OP_DUP, 2);
+        assert_ne!(custom.checksum(), default.checksum());
+
+        let json = serde_json::to_string(&custom).unwrap();
+        let decoded: ScriptCostTable = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, custom);
+        assert_eq!(decoded.checksum(), custom.checksum());
+    }
+
+    #[test]
+    /// Checks fuel against values computed by hand from the consensus table
+    fn test_fuel() {
+        let (pk, sk) = sign::gen_keypair();
+        let check_data = hex::encode([0; 32]);
+        let signature = sign::sign_detached(check_data.as_bytes(), &sk);
+        let costs = ScriptCostTable::default;
+
+        // 3 pushes and 2 opcodes
+        let add = Script::from(vec![
+            StackEntry::Num(1),
+            StackEntry::Num(2),
+            StackEntry::Op(OpCodes::OP_ADD),
+            StackEntry::Num(3),
+            StackEntry::Op(OpCodes::OP_NUMEQUAL),
+        ]);
+        assert_eq!(fuel(&add, costs()), 5);
+
+        // Hashing 64 hex characters: push, then 10 + 64 for OP_SHA3
+        let hash = Script::from(vec![
+            StackEntry::Bytes(check_data.clone()),
+            StackEntry::Op(OpCodes::OP_SHA3),
+        ]);
+        assert_eq!(fuel(&hash, costs()), 75);
+
+        // P2PKH: 4 pushes, OP_DUP, OP_HASH256 over a public key, OP_EQUALVERIFY and
+        // OP_CHECKSIG: 4 + 1 + (10 + 32) + 1 + (1 + 100)
+        let p2pkh = Script::pay2pkh(check_data.clone(), signature, pk, None);
+        assert_eq!(fuel(&p2pkh, costs()), 149);
+
+        // 1-of-2 multisig: 6 pushes and OP_CHECKMULTISIG over 2 keys: 6 + (1 + 200)
+        let (pk2, _) = sign::gen_keypair();
+        let multisig =
+            Script::multisig_validation(1, 2, check_data, vec![signature], vec![pk, pk2]);
+        assert_eq!(fuel(&multisig, costs()), 207);
+
+        // Changing one entry changes the fuel of scripts using it, and no others
+        let mut custom = costs();
+        custom.op_costs.insert(OpCodes::OP_ADD, 7);
+        assert_eq!(fuel(&add, custom.clone()), 11);
+        assert_eq!(fuel(&hash, custom), 75);
+    }
+}
@@ -5,17 +5,20 @@ use crate::crypto::sign_ed25519 as sign;
 use crate::crypto::sign_ed25519::{PublicKey, Signature};
 use crate::primitives::asset::{Asset, TokenAmount};
 use crate::primitives::transaction::*;
-use crate::script::lang::{ConditionStack, Script, Stack};
+use crate::script::lang::{
+    ConditionStack, ExecutionContext, Script, ScriptError, Stack, TxContext,
+};
 use crate::script::{OpCodes, StackEntry};
 use crate::utils::error_utils::*;
 use crate::utils::transaction_utils::{
-    construct_address, construct_address_temp, construct_address_v0,
+    construct_address, construct_address_temp, construct_address_v0, decode_lower_hex,
 };
 use bincode::de;
 use bincode::serialize;
 use bytes::Bytes;
 use hex::encode;
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use tracing::{debug, error, info, trace};
 use tracing_subscriber::field::debug;
 
@@ -92,7 +95,8 @@ pub fn op_notif(stack: &mut Stack, cond_stack: &mut ConditionStack) -> bool {
     true
 }
 
-/// OP_ELSE: Executes the next block of instructions if the previous OP_IF or OP_NOTIF was not executed
+/// OP_ELSE: Executes the next block of instructions if the previous OP_IF or OP_NOTIF was not executed.
+/// Once `duplicate_else_is_rejected`, each OP_IF or OP_NOTIF block may only have one OP_ELSE
 ///
 /// ### Arguments
 ///
@@ -100,12 +104,17 @@ pub fn op_notif(stack: &mut Stack, cond_stack: &mut ConditionStack) -> bool {
 pub fn op_else(cond_stack: &mut ConditionStack) -> bool {
     let (op, desc) = (OPELSE, OPELSE_DESC);
     trace(op, desc);
-    if cond_stack.is_empty() {
-        error_empty_condition(op);
-        return false;
+    match cond_stack.toggle() {
+        Ok(()) => true,
+        Err(ScriptError::DuplicateElse) => {
+            error_duplicate_else(op);
+            false
+        }
+        Err(_) => {
+            error_empty_condition(op);
+            false
+        }
     }
-    cond_stack.toggle();
-    true
 }
 
 /// OP_ENDIF: Ends an OP_IF or OP_NOTIF block
@@ -116,12 +125,13 @@ pub fn op_else(cond_stack: &mut ConditionStack) -> bool {
 pub fn op_endif(cond_stack: &mut ConditionStack) -> bool {
     let (op, desc) = (OPENDIF, OPENDIF_DESC);
     trace(op, desc);
-    if cond_stack.is_empty() {
-        error_empty_condition(op);
-        return false;
+    match cond_stack.pop() {
+        Ok(()) => true,
+        Err(_) => {
+            error_empty_condition(op);
+            false
+        }
     }
-    cond_stack.pop();
-    true
 }
 
 /// OP_VERIFY: Removes the top item from the stack and ends execution with an error if it is ZERO
@@ -178,13 +188,12 @@ pub fn op_toaltstack(stack: &mut Stack) -> bool {
     let (op, desc) = (OPTOALTSTACK, OPTOALTSTACK_DESC);
     trace(op, desc);
     match stack.pop() {
-        Some(x) => stack.alt_stack.push(x),
+        Some(x) => stack.push_alt(x),
         _ => {
             error_num_items(op);
-            return false;
+            false
         }
-    };
-    true
+    }
 }
 
 /// OP_FROMALTSTACK: Moves the top item from the alt stack to the top of the main stack
@@ -197,14 +206,13 @@ pub fn op_toaltstack(stack: &mut Stack) -> bool {
 pub fn op_fromaltstack(stack: &mut Stack) -> bool {
     let (op, desc) = (OPFROMALTSTACK, OPFROMALTSTACK_DESC);
     trace(op, desc);
-    match stack.alt_stack.pop() {
+    match stack.pop_alt() {
         Some(x) => stack.push(x),
         _ => {
             error_num_items(op);
-            return false;
+            false
         }
-    };
-    true
+    }
 }
 
 /// OP_2DROP: Removes the top two items from the stack
@@ -222,7 +230,8 @@ pub fn op_2drop(stack: &mut Stack) -> bool {
         error_num_items(op);
         return false;
     }
-    stack.main_stack.drain(len - TWO..);
+    stack.pop();
+    stack.pop();
     true
 }
 
@@ -242,8 +251,7 @@ pub fn op_2dup(stack: &mut Stack) -> bool {
         return false;
     }
     let last_two = stack.main_stack[len - TWO..].to_vec();
-    stack.main_stack.extend_from_slice(&last_two);
-    true
+    last_two.into_iter().all(|x| stack.push(x))
 }
 
 /// OP_3DUP: Duplicates the top three items on the stack
@@ -262,8 +270,7 @@ pub fn op_3dup(stack: &mut Stack) -> bool {
         return false;
     }
     let last_three = stack.main_stack[len - THREE..].to_vec();
-    stack.main_stack.extend_from_slice(&last_three);
-    true
+    last_three.into_iter().all(|x| stack.push(x))
 }
 
 /// OP_2OVER: Copies the second-to-top pair of items to the top of the stack
@@ -282,8 +289,7 @@ pub fn op_2over(stack: &mut Stack) -> bool {
         return false;
     }
     let items = stack.main_stack[len - FOUR..len - TWO].to_vec();
-    stack.main_stack.extend_from_slice(&items);
-    true
+    items.into_iter().all(|x| stack.push(x))
 }
 
 /// OP_2ROT: Moves the third-to-top pair of items to the top of the stack
@@ -341,7 +347,7 @@ pub fn op_ifdup(stack: &mut Stack) -> bool {
     match stack.last() {
         Some(x) => {
             if x != StackEntry::Num(ZERO) {
-                stack.push(x);
+                return stack.push(x);
             }
         }
         _ => {
@@ -399,10 +405,9 @@ pub fn op_dup(stack: &mut Stack) -> bool {
         Some(x) => stack.push(x),
         _ => {
             error_num_items(op);
-            return false;
+            false
         }
-    };
-    true
+    }
 }
 
 /// OP_NIP: Removes the second-to-top item from the stack
@@ -420,7 +425,7 @@ pub fn op_nip(stack: &mut Stack) -> bool {
         error_num_items(op);
         return false;
     }
-    stack.main_stack.remove(len - TWO);
+    stack.remove(len - TWO);
     true
 }
 
@@ -499,8 +504,7 @@ pub fn op_roll(stack: &mut Stack) -> bool {
         error_item_index(op);
         return false;
     }
-    let x = stack.main_stack[len - ONE - n].clone();
-    stack.main_stack.remove(len - ONE - n);
+    let x = stack.remove(len - ONE - n);
     stack.push(x)
 }
 
@@ -559,8 +563,7 @@ pub fn op_tuck(stack: &mut Stack) -> bool {
         return false;
     }
     let x2 = stack.main_stack[len - ONE].clone();
-    stack.main_stack.insert(len - TWO, x2);
-    true
+    stack.insert(len - TWO, x2)
 }
 
 /*---- SPLICE OPS ----*/
@@ -597,7 +600,7 @@ pub fn op_cat(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    if s1.len() + s2.len() > MAX_SCRIPT_ITEM_SIZE as usize {
+    if s1.len() + s2.len() > stack.max_item_size() {
         error_item_size(op);
         return false;
     }
@@ -652,15 +655,14 @@ pub fn op_substr(stack: &mut Stack) -> bool {
         error_item_index(op);
         return false;
     }
-    if n2 > s.len() {
-        error_item_index(op);
-        return false;
-    }
-    if n1 + n2 > s.len() {
-        error_item_index(op);
-        return false;
-    }
-    let substr = s[n1..n1 + n2].to_string();
+    let end = match n1.checked_add(n2) {
+        Some(end) if end <= s.len() => end,
+        _ => {
+            error_slice_bounds(op);
+            return false;
+        }
+    };
+    let substr = s[n1..end].to_string();
     stack.push(StackEntry::Bytes(substr))
 }
 
@@ -901,6 +903,54 @@ pub fn op_xor(stack: &mut Stack) -> bool {
     stack.push(StackEntry::Num(n1 ^ n2))
 }
 
+/// Checks that the top two items on the stack can be compared by OP_EQUAL in strict mode,
+/// which rejects comparing a `Num` with `Bytes` instead of treating them as unequal.
+/// Missing items are left for the opcode itself to report
+///
+/// Example: equal_types_match([Num(5), Bytes("05")]) -> false
+///          equal_types_match([Bytes("05"), Bytes("05")]) -> true
+///
+/// ### Arguments
+///
+/// * `stack`  - reference to the stack
+/// * `op`     - opcode about to compare the items
+pub(crate) fn equal_types_match(stack: &Stack, op: &OpCodes) -> bool {
+    let len = stack.main_stack.len();
+    let mismatched = matches!(
+        stack.main_stack.get(len.saturating_sub(2)..),
+        Some([StackEntry::Num(_), StackEntry::Bytes(_)])
+            | Some([StackEntry::Bytes(_), StackEntry::Num(_)])
+    );
+    if mismatched {
+        error_item_type(&op.to_string());
+    }
+    !mismatched
+}
+
+/// Checks that the `Bytes` compared with a `Num` by `OP_LOOSEEQUAL` or `OP_LOOSEEQUALVERIFY`
+/// are canonical hex, which fails the script instead of comparing as unequal.
+/// Missing items and other types are left for the opcode itself to handle
+///
+/// Example: loose_hex_is_canonical([Num(255), Bytes("ff")]) -> true
+///          loose_hex_is_canonical([Num(255), Bytes("FF")]) -> false
+///
+/// ### Arguments
+///
+/// * `stack`  - reference to the stack
+/// * `op`     - opcode about to compare the items
+pub(crate) fn loose_hex_is_canonical(stack: &Stack, op: &OpCodes) -> bool {
+    let len = stack.main_stack.len();
+    let canonical = match stack.main_stack.get(len.saturating_sub(2)..) {
+        Some([StackEntry::Num(_), StackEntry::Bytes(b)])
+        | Some([StackEntry::Bytes(b), StackEntry::Num(_)]) => decode_lower_hex(b).is_ok(),
+        _ => true,
+    };
+    if !canonical {
+        error_invalid_hex(&op.to_string());
+    }
+    canonical
+}
+
 /// OP_EQUAL: Substitutes the top two items on the stack with ONE if they are equal, with ZERO otherwise.
 ///
 /// Example: OP_EQUAL([x1, x2]) -> [1] if x1 == x2
@@ -965,6 +1015,73 @@ pub fn op_equalverify(stack: &mut Stack) -> bool {
     true
 }
 
+/// OP_LOOSEEQUAL: Substitutes the top two items on the stack with ONE if they are equal, with ZERO otherwise.
+///
+/// Unlike OP_EQUAL, a bytes item is also considered equal to a number item if its hex
+/// content decodes to that number.
+///
+/// Example: OP_LOOSEEQUAL([x1, x2]) -> [1] if x1 ~= x2
+///          OP_LOOSEEQUAL([x1, x2]) -> [0] if x1 !~= x2
+///
+/// ### Arguments
+///
+/// * `stack`  - mutable reference to the stack
+pub fn op_looseequal(stack: &mut Stack) -> bool {
+    let (op, desc) = (OPLOOSEEQUAL, OPLOOSEEQUAL_DESC);
+    trace(op, desc);
+    let x2 = match stack.pop() {
+        Some(x) => x,
+        _ => {
+            error_num_items(op);
+            return false;
+        }
+    };
+    let x1 = match stack.pop() {
+        Some(x) => x,
+        _ => {
+            error_num_items(op);
+            return false;
+        }
+    };
+    if x1.loose_eq(&x2) {
+        stack.push(StackEntry::Num(ONE))
+    } else {
+        stack.push(StackEntry::Num(ZERO))
+    }
+}
+
+/// OP_LOOSEEQUALVERIFY: Computes OP_LOOSEEQUAL and OP_VERIFY in sequence
+///
+/// Example: OP_LOOSEEQUALVERIFY([x1, x2]) -> []   if x1 ~= x2
+///          OP_LOOSEEQUALVERIFY([x1, x2]) -> fail if x1 !~= x2
+///
+/// ### Arguments
+///
+/// * `stack`  - mutable reference to the stack
+pub fn op_looseequalverify(stack: &mut Stack) -> bool {
+    let (op, desc) = (OPLOOSEEQUALVERIFY, OPLOOSEEQUALVERIFY_DESC);
+    trace(op, desc);
+    let x2 = match stack.pop() {
+        Some(x) => x,
+        _ => {
+            error_num_items(op);
+            return false;
+        }
+    };
+    let x1 = match stack.pop() {
+        Some(x) => x,
+        _ => {
+            error_num_items(op);
+            return false;
+        }
+    };
+    if !x1.loose_eq(&x2) {
+        error_not_equal_items(op);
+        return false;
+    }
+    true
+}
+
 /*---- ARITHMETIC OPS ----*/
 
 /// OP_1ADD: Adds ONE to the number on top of the stack
@@ -1057,7 +1174,40 @@ pub fn op_2mul(stack: &mut Stack) -> bool {
     }
 }
 
-/// OP_2DIV: Divides by TWO the number on top of the stack
+/// Division rule shared by OP_2DIV, OP_DIV and OP_MOD: the quotient is truncated
+/// toward zero, never rounded toward negative infinity as an arithmetic shift would be.
+/// Returns `None` on division by zero or overflow.
+///
+/// Example: script_div(-3, 2) -> Some(-1)
+///          script_div(3, -2) -> Some(-1)
+///          script_div(-3, -2) -> Some(1)
+///
+/// ### Arguments
+///
+/// * `n1`  - dividend
+/// * `n2`  - divisor
+pub(crate) fn script_div(n1: i128, n2: i128) -> Option<i128> {
+    n1.checked_div(n2)
+}
+
+/// Remainder matching `script_div`: it takes the sign of the dividend, so that
+/// `n1 == script_div(n1, n2) * n2 + script_rem(n1, n2)`.
+/// Returns `None` on division by zero or overflow.
+///
+/// Example: script_rem(-3, 2) -> Some(-1)
+///          script_rem(3, -2) -> Some(1)
+///          script_rem(-3, -2) -> Some(-1)
+///
+/// ### Arguments
+///
+/// * `n1`  - dividend
+/// * `n2`  - divisor
+pub(crate) fn script_rem(n1: i128, n2: i128) -> Option<i128> {
+    n1.checked_rem(n2)
+}
+
+/// OP_2DIV: Divides by TWO the number on top of the stack, truncating toward zero
+/// as `script_div` does
 ///
 /// Example: OP_2DIV([n]) -> [n/2]
 ///
@@ -1078,7 +1228,10 @@ pub fn op_2div(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    stack.push(StackEntry::Num(n / TWO))
+    match script_div(n as i128, TWO as i128) {
+        Some(n) => stack.push(StackEntry::Num(n as usize)),
+        None => false,
+    }
 }
 
 /// OP_NOT: Substitutes the number on top of the stack with ONE if it is equal to ZERO, with ZERO otherwise
@@ -1149,30 +1302,22 @@ pub fn op_0notequal(stack: &mut Stack) -> bool {
 pub fn op_add(stack: &mut Stack) -> bool {
     let (op, desc) = (OPADD, OPADD_DESC);
     trace(op, desc);
-    let n2 = match stack.pop() {
-        Some(StackEntry::Num(n)) => n,
-        Some(_) => {
-            error_item_type(op);
-            return false;
-        }
-        _ => {
+    let guard = match stack.require::<2>() {
+        Ok(guard) => guard,
+        Err(_) => {
             error_num_items(op);
             return false;
         }
     };
-    let n1 = match stack.pop() {
-        Some(StackEntry::Num(n)) => n,
-        Some(_) => {
+    let [n1, n2] = match guard.nums() {
+        Ok(nums) => nums,
+        Err(_) => {
             error_item_type(op);
             return false;
         }
-        _ => {
-            error_num_items(op);
-            return false;
-        }
     };
     match n1.checked_add(n2) {
-        Some(n) => stack.push(StackEntry::Num(n)),
+        Some(n) => guard.replace(StackEntry::Num(n)),
         _ => {
             error_overflow(op);
             false
@@ -1262,7 +1407,8 @@ pub fn op_mul(stack: &mut Stack) -> bool {
     }
 }
 
-/// OP_DIV: Divides the second-to-top number by the number on top of the stack
+/// OP_DIV: Divides the second-to-top number by the number on top of the stack,
+/// truncating toward zero as `script_div` does
 ///
 /// Example: OP_DIV([n1, n2]) -> [n1/n2]
 ///
@@ -1294,8 +1440,8 @@ pub fn op_div(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    match n1.checked_div(n2) {
-        Some(n) => stack.push(StackEntry::Num(n)),
+    match script_div(n1 as i128, n2 as i128) {
+        Some(n) => stack.push(StackEntry::Num(n as usize)),
         _ => {
             error_div_zero(op);
             false
@@ -1303,7 +1449,8 @@ pub fn op_div(stack: &mut Stack) -> bool {
     }
 }
 
-/// OP_MOD: Computes the remainder of the division of the second-to-top number by the number on top of the stack
+/// OP_MOD: Computes the remainder of the division of the second-to-top number by the number on top of the stack,
+/// taking the sign of the dividend as `script_rem` does
 ///
 /// Example: OP_MOD([n1, n2]) -> [n1%n2]
 ///
@@ -1335,8 +1482,8 @@ pub fn op_mod(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    match n1.checked_rem(n2) {
-        Some(n) => stack.push(StackEntry::Num(n)),
+    match script_rem(n1 as i128, n2 as i128) {
+        Some(n) => stack.push(StackEntry::Num(n as usize)),
         _ => {
             error_div_zero(op);
             false
@@ -1863,49 +2010,35 @@ pub fn op_max(stack: &mut Stack) -> bool {
 /// Example: OP_WITHIN([n1, n2, n3]) -> [1] if n1 >= n2 and n1 < n3
 ///          OP_WITHIN([n1, n2, n3]) -> [0] if n1 < n2 or n1 >= n3
 ///
+/// The range is half-open, including its lower bound but not its upper bound:
+///          OP_WITHIN([1, 1, 3]) -> [1]
+///          OP_WITHIN([3, 1, 3]) -> [0]
+///          OP_WITHIN([1, 1, 1]) -> [0]
+///
 /// ### Arguments
 ///
 /// * `stack`  - mutable reference to the stack
 pub fn op_within(stack: &mut Stack) -> bool {
     let (op, desc) = (OPWITHIN, OPWITHIN_DESC);
     trace(op, desc);
-    let n3 = match stack.pop() {
-        Some(StackEntry::Num(n)) => n,
-        Some(_) => {
-            error_item_type(op);
-            return false;
-        }
-        _ => {
+    let guard = match stack.require::<3>() {
+        Ok(guard) => guard,
+        Err(_) => {
             error_num_items(op);
             return false;
         }
     };
-    let n2 = match stack.pop() {
-        Some(StackEntry::Num(n)) => n,
-        Some(_) => {
-            error_item_type(op);
-            return false;
-        }
-        _ => {
-            error_num_items(op);
-            return false;
-        }
-    };
-    let n1 = match stack.pop() {
-        Some(StackEntry::Num(n)) => n,
-        Some(_) => {
+    let [n1, n2, n3] = match guard.nums() {
+        Ok(nums) => nums,
+        Err(_) => {
             error_item_type(op);
             return false;
         }
-        _ => {
-            error_num_items(op);
-            return false;
-        }
     };
     if n1 >= n2 && n1 < n3 {
-        stack.push(StackEntry::Num(ONE))
+        guard.replace(StackEntry::Num(ONE))
     } else {
-        stack.push(StackEntry::Num(ZERO))
+        guard.replace(StackEntry::Num(ZERO))
     }
 }
 
@@ -1942,6 +2075,10 @@ pub fn op_sha3(stack: &mut Stack) -> bool {
 ///
 /// Example: OP_HASH256([pk]) -> [addr]
 ///
+/// Info: The address is pushed as its lowercase hex string, exactly as returned by
+/// `construct_address`, not as the raw digest bytes. P2PKH scripts push the payee
+/// address in the same hex form, so OP_EQUALVERIFY compares strings of the same encoding
+///
 /// ### Arguments
 ///
 /// * `stack`  - mutable reference to the stack
@@ -1959,6 +2096,7 @@ pub fn op_hash256(stack: &mut Stack) -> bool {
             return false;
         }
     };
+    // Hex encoded address, matching `StackEntry::Bytes(address)` in P2PKH scripts
     let addr = construct_address(&pk);
     stack.push(StackEntry::Bytes(addr))
 }
@@ -1974,6 +2112,7 @@ pub fn op_hash256(stack: &mut Stack) -> bool {
 /// ### Arguments
 ///
 /// * `stack`  - mutable reference to the stack
+#[doc(hidden)]
 pub fn op_hash256_v0(stack: &mut Stack) -> bool {
     let (op, desc) = (OPHASH256V0, OPHASH256V0_DESC);
     trace(op, desc);
@@ -2003,6 +2142,7 @@ pub fn op_hash256_v0(stack: &mut Stack) -> bool {
 /// ### Arguments
 ///
 /// * `stack`  - mutable reference to the stack
+#[doc(hidden)]
 pub fn op_hash256_temp(stack: &mut Stack) -> bool {
     let (op, desc) = (OPHASH256TEMP, OPHASH256TEMP_DESC);
     trace(op, desc);
@@ -2026,11 +2166,14 @@ pub fn op_hash256_temp(stack: &mut Stack) -> bool {
 /// Example: OP_CHECKSIG([msg, sig, pk]) -> [1] if Verify(sig, msg, pk) == 1
 ///          OP_CHECKSIG([msg, sig, pk]) -> [0] if Verify(sig, msg, pk) == 0
 ///
-/// Info: It allows signature verification on arbitrary messsages, not only transactions
+/// Info: It allows signature verification on arbitrary messsages, not only transactions.
+///       Verification is done by `sign::verify_detached`, which only handles public data
+///       and so can't leak a secret key through its timing
 ///
 /// ### Arguments
 ///
 /// * `stack`  - mutable reference to the stack
+#[must_use]
 pub fn op_checksig(stack: &mut Stack) -> bool {
     let (op, desc) = (OPCHECKSIG, OPCHECKSIG_DESC);
     trace(op, desc);
@@ -2067,6 +2210,8 @@ pub fn op_checksig(stack: &mut Stack) -> bool {
             return false;
         }
     };
+    // Hex encoding the signature and verifying against the hex text of the legacy message
+    // format only touch public stack items, so their timing reveals nothing secret
     trace!("Signature: {:?}", hex::encode(sig));
     if (!sign::verify_detached(&sig, msg.as_bytes(), &pk)) {
         trace!("Signature verification failed");
@@ -2121,6 +2266,8 @@ pub fn op_checksigverify(stack: &mut Stack) -> bool {
             return false;
         }
     };
+    // Hex encoding the signature and verifying against the hex text of the legacy message
+    // format only touch public stack items, so their timing reveals nothing secret
     trace!("Signature: {:?}", hex::encode(sig));
     if (!sign::verify_detached(&sig, msg.as_bytes(), &pk)) {
         trace!("Signature verification failed");
@@ -2136,11 +2283,14 @@ pub fn op_checksigverify(stack: &mut Stack) -> bool {
 ///          OP_CHECKMULTISIG([msg, sig1, sig2, m, pk1, pk2, pk3, n]) -> [0] if Verify(sig1, sig2, msg, pk1, pk2, pk3) == 0
 ///
 /// Info: It allows multi-signature verification on arbitrary messsages, not only transactions
-///       Ordering of signatures and public keys is not relevant
+///       Ordering of signatures and public keys is not relevant.
+///       The number of verifications depends on which keys match, but keys and
+///       signatures are public, so this reveals nothing secret
 ///
 /// ### Arguments
 ///
 /// * `stack`  - mutable reference to the stack
+#[must_use]
 pub fn op_checkmultisig(stack: &mut Stack) -> bool {
     let (op, desc) = (OPCHECKMULTISIG, OPCHECKMULTISIG_DESC);
     trace(op, desc);
@@ -2155,20 +2305,24 @@ pub fn op_checkmultisig(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    if n > MAX_PUB_KEYS_PER_MULTISIG as usize {
+    if n > MAX_PUB_KEYS_PER_MULTISIG {
         error_num_pubkeys(op);
         return false;
     }
-    let mut pks = Vec::new();
-    while let Some(StackEntry::PubKey(_)) = stack.last() {
-        if let Some(StackEntry::PubKey(pk)) = stack.pop() {
-            pks.push(pk);
+    let mut pks = Vec::with_capacity(n);
+    for _ in 0..n {
+        match stack.pop() {
+            Some(StackEntry::PubKey(pk)) => pks.push(pk),
+            Some(_) => {
+                error_item_type(op);
+                return false;
+            }
+            _ => {
+                error_num_pubkeys(op);
+                return false;
+            }
         }
     }
-    if pks.len() != n {
-        error_num_pubkeys(op);
-        return false;
-    }
     let m = match stack.pop() {
         Some(StackEntry::Num(n)) => n,
         Some(_) => {
@@ -2184,16 +2338,20 @@ pub fn op_checkmultisig(stack: &mut Stack) -> bool {
         error_num_signatures(op);
         return false;
     }
-    let mut sigs = Vec::new();
-    while let Some(StackEntry::Signature(_)) = stack.last() {
-        if let Some(StackEntry::Signature(sig)) = stack.pop() {
-            sigs.push(sig);
+    let mut sigs = Vec::with_capacity(m);
+    for _ in 0..m {
+        match stack.pop() {
+            Some(StackEntry::Signature(sig)) => sigs.push(sig),
+            Some(_) => {
+                error_item_type(op);
+                return false;
+            }
+            _ => {
+                error_num_signatures(op);
+                return false;
+            }
         }
     }
-    if sigs.len() != m {
-        error_num_signatures(op);
-        return false;
-    }
     let msg = match stack.pop() {
         Some(StackEntry::Bytes(s)) => s,
         Some(_) => {
@@ -2234,20 +2392,24 @@ pub fn op_checkmultisigverify(stack: &mut Stack) -> bool {
             return false;
         }
     };
-    if n > MAX_PUB_KEYS_PER_MULTISIG as usize {
+    if n > MAX_PUB_KEYS_PER_MULTISIG {
         error_num_pubkeys(op);
         return false;
     }
-    let mut pks = Vec::new();
-    while let Some(StackEntry::PubKey(_)) = stack.last() {
-        if let Some(StackEntry::PubKey(pk)) = stack.pop() {
-            pks.push(pk);
+    let mut pks = Vec::with_capacity(n);
+    for _ in 0..n {
+        match stack.pop() {
+            Some(StackEntry::PubKey(pk)) => pks.push(pk),
+            Some(_) => {
+                error_item_type(op);
+                return false;
+            }
+            _ => {
+                error_num_pubkeys(op);
+                return false;
+            }
         }
     }
-    if pks.len() != n {
-        error_num_pubkeys(op);
-        return false;
-    }
     let m = match stack.pop() {
         Some(StackEntry::Num(n)) => n,
         Some(_) => {
@@ -2263,16 +2425,20 @@ pub fn op_checkmultisigverify(stack: &mut Stack) -> bool {
         error_num_signatures(op);
         return false;
     }
-    let mut sigs = Vec::new();
-    while let Some(StackEntry::Signature(_)) = stack.last() {
-        if let Some(StackEntry::Signature(sig)) = stack.pop() {
-            sigs.push(sig);
+    let mut sigs = Vec::with_capacity(m);
+    for _ in 0..m {
+        match stack.pop() {
+            Some(StackEntry::Signature(sig)) => sigs.push(sig),
+            Some(_) => {
+                error_item_type(op);
+                return false;
+            }
+            _ => {
+                error_num_signatures(op);
+                return false;
+            }
         }
     }
-    if sigs.len() != m {
-        error_num_signatures(op);
-        return false;
-    }
     let msg = match stack.pop() {
         Some(StackEntry::Bytes(s)) => s,
         Some(_) => {
@@ -2312,3 +2478,122 @@ fn verify_multisig(sigs: &[Signature], msg: &String, pks: &mut Vec<PublicKey>) -
     }
     num_valid_sigs == sigs.len()
 }
+
+/*---- INTROSPECTION OPS ----*/
+
+/// Checks that the output index on top of the stack is in range of the spending
+/// transaction's outputs and holds tokens, as `OP_TXOUTVALUE` requires.
+/// Missing items and other types are left for the opcode itself to report
+///
+/// Example: output_is_token([0], [Token(5)]) -> Ok(())
+///          output_is_token([1], [Token(5)]) -> Err(ItemIndex(1))
+///
+/// ### Arguments
+///
+/// * `stack`          - reference to the stack
+/// * `output_values`  - values of the outputs of the spending transaction
+pub(crate) fn output_is_token(stack: &Stack, output_values: &[Asset]) -> Result<(), ScriptError> {
+    let op = OPTXOUTVALUE;
+    let index = match stack.last() {
+        Some(StackEntry::Num(index)) => index,
+        _ => return Ok(()),
+    };
+    match output_values.get(index) {
+        Some(Asset::Item(_) | Asset::TokenWithMetadata { .. }) => {
+            error_output_not_token(op);
+            Err(ScriptError::OutputNotToken(index))
+        }
+        Some(_) => Ok(()),
+        None => {
+            error_item_index(op);
+            Err(ScriptError::OutputIndex(index))
+        }
+    }
+}
+
+/// Spending transaction of the context a script is executed in, as required by the
+/// introspection opcodes
+///
+/// ### Arguments
+///
+/// * `context`  - Execution context of the script
+/// * `op`       - Introspection opcode about to be executed
+pub(crate) fn tx_context<'a>(
+    context: &'a ExecutionContext,
+    op: &OpCodes,
+) -> Result<&'a TxContext, ScriptError> {
+    context.tx.as_ref().ok_or_else(|| {
+        error_no_tx_context(&op.to_string());
+        ScriptError::NoTxContext
+    })
+}
+
+/// OP_TXINCOUNT: Pushes the number of inputs of the spending transaction onto the stack
+///
+/// Example: OP_TXINCOUNT([]) -> [1] for a transaction with one input
+///
+/// ### Arguments
+///
+/// * `stack`        - mutable reference to the stack
+/// * `input_count`  - number of inputs of the spending transaction
+pub fn op_txincount(stack: &mut Stack, input_count: usize) -> bool {
+    let (op, desc) = (OPTXINCOUNT, OPTXINCOUNT_DESC);
+    trace(op, desc);
+    stack.push(StackEntry::Num(input_count))
+}
+
+/// OP_TXOUTPUTCOUNT: Pushes the number of outputs of the spending transaction onto the stack
+///
+/// Example: OP_TXOUTPUTCOUNT([]) -> [2] for a transaction with two outputs
+///
+/// ### Arguments
+///
+/// * `stack`          - mutable reference to the stack
+/// * `output_values`  - values of the outputs of the spending transaction
+pub fn op_txoutputcount(stack: &mut Stack, output_values: &[Asset]) -> bool {
+    let (op, desc) = (OPTXOUTPUTCOUNT, OPTXOUTPUTCOUNT_DESC);
+    trace(op, desc);
+    stack.push(StackEntry::Num(output_values.len()))
+}
+
+/// OP_TXOUTVALUE: Substitutes the output index on top of the stack with the token amount
+/// of that output of the spending transaction
+///
+/// Example: OP_TXOUTVALUE([0]) -> [5] for a transaction whose first output holds 5 tokens
+///
+/// ### Arguments
+///
+/// * `stack`          - mutable reference to the stack
+/// * `output_values`  - values of the outputs of the spending transaction
+pub fn op_txoutvalue(stack: &mut Stack, output_values: &[Asset]) -> bool {
+    let (op, desc) = (OPTXOUTVALUE, OPTXOUTVALUE_DESC);
+    trace(op, desc);
+    let value = match stack.pop() {
+        Some(StackEntry::Num(index)) => match output_values.get(index) {
+            Some(Asset::Item(_) | Asset::TokenWithMetadata { .. }) => {
+                error_output_not_token(op);
+                return false;
+            }
+            Some(value) => value.token_amount(),
+            None => {
+                error_item_index(op);
+                return false;
+            }
+        },
+        Some(_) => {
+            error_item_type(op);
+            return false;
+        }
+        _ => {
+            error_num_items(op);
+            return false;
+        }
+    };
+    match usize::try_from(value.0) {
+        Ok(amount) => stack.push(StackEntry::Num(amount)),
+        Err(_) => {
+            error_overflow(op);
+            false
+        }
+    }
+}
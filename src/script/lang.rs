@@ -4,21 +4,296 @@ use crate::crypto::sha3_256;
 use crate::crypto::sign_ed25519::{
     PublicKey, Signature, ED25519_PUBLIC_KEY_LEN, ED25519_SIGNATURE_LEN,
 };
+use crate::primitives::asset::{Asset, TokenAmount};
+use crate::primitives::transaction::Transaction;
+use crate::script::cost::ScriptCostTable;
 use crate::script::interface_ops::*;
-use crate::script::{OpCodes, StackEntry};
+use crate::script::{duplicate_else_is_rejected, opcode_is_activated, OpCodes, StackEntry};
 use crate::utils::error_utils::*;
 use crate::utils::transaction_utils::{construct_address, construct_address_for};
 use bincode::serialize;
 use bytes::Bytes;
 use hex::encode;
 use serde::{Deserialize, Serialize};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::iter::FromIterator;
 use tracing::{error, warn};
 
+/// Limits applied while executing a script. Carried alongside the script rather than
+/// read from constants, so that a new transaction version can change them
+///
+/// * `max_script_item_size`  - Maximum number of bytes in a single stack item
+/// * `max_script_size`       - Maximum number of bytes in a whole script
+/// * `recursion_depth`       - Number of scripts currently being executed, nested in one another
+/// * `max_recursion_depth`   - Maximum number of scripts that may be nested in one another
+/// * `block_height`          - Height of the block the script runs in, for soft fork activation
+/// * `strict_equal`          - Whether comparing `Num` with `Bytes` in `OP_EQUAL` fails the script
+/// * `tx`                    - Spending transaction, as read by the introspection opcodes,
+///   if the script is executed for one
+/// * `costs`                 - Fuel charged for each executed entry
+#[derive(Clone, Debug, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionContext {
+    pub max_script_item_size: usize,
+    pub max_script_size: usize,
+    pub recursion_depth: usize,
+    pub max_recursion_depth: usize,
+    pub block_height: Option<u64>,
+    #[serde(default)]
+    pub strict_equal: bool,
+    #[serde(default)]
+    pub tx: Option<TxContext>,
+    #[serde(default)]
+    pub costs: ScriptCostTable,
+}
+
+impl Default for ExecutionContext {
+    fn default() -> Self {
+        Self {
+            max_script_item_size: MAX_SCRIPT_ITEM_SIZE,
+            max_script_size: MAX_SCRIPT_SIZE,
+            recursion_depth: ZERO,
+            max_recursion_depth: MAX_SCRIPT_RECURSION_DEPTH,
+            block_height: None,
+            strict_equal: false,
+            tx: None,
+            costs: ScriptCostTable::default(),
+        }
+    }
+}
+
+impl ExecutionContext {
+    /// Returns the context for executing a script nested within the current one
+    pub fn nested(&self) -> Result<Self, ScriptError> {
+        let recursion_depth = self.recursion_depth + ONE;
+        if recursion_depth > self.max_recursion_depth {
+            error_max_recursion_depth();
+            return Err(ScriptError::RecursionDepthExceeded(recursion_depth));
+        }
+        Ok(Self {
+            recursion_depth,
+            ..self.clone()
+        })
+    }
+}
+
+/// Parts of the spending transaction the introspection opcodes read
+///
+/// * `input_count`     - Number of inputs
+/// * `output_values`   - Values of the outputs, in order
+#[derive(Clone, Debug, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TxContext {
+    pub input_count: usize,
+    pub output_values: Vec<Asset>,
+}
+
+impl TxContext {
+    /// Creates the context of a spending transaction
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx`  - Transaction spending the script
+    pub fn new(tx: &Transaction) -> Self {
+        Self {
+            input_count: tx.inputs.len(),
+            output_values: tx.outputs.iter().map(|o| o.value.clone()).collect(),
+        }
+    }
+}
+
+/// Checks `Script::with_output_constraint` can make on the spending transaction's outputs
+///
+/// * `Count`           - Number of outputs
+/// * `ValueEquals`     - Index of an output and the exact token amount it must hold
+/// * `ValueAtLeast`    - Index of an output and the least token amount it must hold
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TxOutConstraint {
+    Count(usize),
+    ValueEquals(usize, TokenAmount),
+    ValueAtLeast(usize, TokenAmount),
+}
+
+/// Expectations a create script is interpreted against
+///
+/// * `asset_hash`      - Hash of the asset the create transaction creates
+/// * `block_number`    - Block number the script must commit to, if known
+/// * `current_height`  - Current height, which the block number must be within
+///   `CREATE_BLOCK_WINDOW` blocks of, if known
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CreateContext {
+    pub asset_hash: String,
+    pub block_number: Option<u64>,
+    pub current_height: Option<u64>,
+}
+
+/// Checks the block number a create script commits to, as done when constructing and
+/// interpreting it: it must be within `CREATE_BLOCK_WINDOW` blocks of `current_height`.
+/// Returns the block number as the `Num` to push
+///
+/// ### Arguments
+///
+/// * `block_number`    - Block number the create script commits to
+/// * `current_height`  - Current height of the chain
+pub fn check_create_block_number(
+    block_number: u64,
+    current_height: u64,
+) -> Result<usize, ScriptError> {
+    if block_number.abs_diff(current_height) > CREATE_BLOCK_WINDOW {
+        return Err(ScriptError::BlockNumberOutsideWindow(
+            block_number,
+            current_height,
+        ));
+    }
+    block_number_as_num(block_number, usize::BITS)
+}
+
+/// Converts a block number to a `Num` held in `bits` bits, failing rather than truncating
+///
+/// ### Arguments
+///
+/// * `block_number`    - Block number to convert
+/// * `bits`            - Width of the `Num`, `usize::BITS` outside of tests
+pub(crate) fn block_number_as_num(block_number: u64, bits: u32) -> Result<usize, ScriptError> {
+    let too_large = ScriptError::BlockNumberTooLarge(block_number);
+    if bits < u64::BITS && block_number >> bits != 0 {
+        return Err(too_large);
+    }
+    usize::try_from(block_number).map_err(|_| too_large)
+}
+
+/// What a successfully interpreted create script commits to,
+/// for the caller to cross-check
+///
+/// * `pub_key`         - Public key whose signature authorized the creation
+/// * `asset_hash`      - Hash of the asset that was signed
+/// * `block_number`    - Block number the script commits to
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CreateClaims {
+    pub pub_key: PublicKey,
+    pub asset_hash: String,
+    pub block_number: u64,
+}
+
+/// Peak stack usage of a script execution, measured after each entry is executed,
+/// for keeping scripts within `MAX_STACK_SIZE`, and the fuel it used
+///
+/// * `peak_main_depth`     - Most items held by the main stack at once
+/// * `peak_alt_depth`      - Most items held by the alt stack at once
+/// * `peak_combined_depth` - Most items held by both stacks together at once,
+///   which may be below the sum of the other two peaks
+/// * `fuel`                - Fuel charged by the context's cost table for the
+///   entries executed, skipped entries being free
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ResourceReport {
+    pub peak_main_depth: usize,
+    pub peak_alt_depth: usize,
+    pub peak_combined_depth: usize,
+    pub fuel: u64,
+}
+
+impl ResourceReport {
+    /// Updates the peaks with the current depths of `stack`
+    ///
+    /// ### Arguments
+    ///
+    /// * `stack`  - Stack being executed on
+    fn observe(&mut self, stack: &Stack) {
+        let (main, alt) = (stack.main_stack.len(), stack.alt_stack.len());
+        self.peak_main_depth = self.peak_main_depth.max(main);
+        self.peak_alt_depth = self.peak_alt_depth.max(alt);
+        self.peak_combined_depth = self.peak_combined_depth.max(main + alt);
+    }
+
+    /// Adds fuel used by an entry
+    ///
+    /// ### Arguments
+    ///
+    /// * `fuel`  - Fuel charged for the entry
+    fn charge(&mut self, fuel: u64) {
+        self.fuel = self.fuel.saturating_add(fuel);
+    }
+}
+
+/// Errors preventing a script from being executed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+    RecursionDepthExceeded(usize),
+    NotCreateScript,
+    OpcodeNotAllowed(OpCodes),
+    BlockNumberMismatch(u64),
+    AssetHashNotSigned,
+    ExecutionFailed,
+    ScriptTooLarge(usize),
+    TooManyOps(usize),
+    ItemTooLarge(usize),
+    NonMinimalPush,
+    TruncatedPush,
+    NotAPush(u8),
+    EmptyCondition,
+    DuplicateElse,
+    ItemType,
+    InvalidHex,
+    StackUnderflow(usize),
+    BlockNumberOutsideWindow(u64, u64),
+    BlockNumberTooLarge(u64),
+    NoTxContext,
+    OutputIndex(usize),
+    OutputNotToken(usize),
+    StackMemory(usize),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::RecursionDepthExceeded(depth) => {
+                write!(f, "{ERROR_MAX_RECURSION_DEPTH}: {depth}")
+            }
+            Self::NotCreateScript => {
+                write!(f, "Script doesn't start with OP_CREATE and a block number")
+            }
+            Self::OpcodeNotAllowed(op) => write!(f, "{op} is not allowed in a create script"),
+            Self::BlockNumberMismatch(n) => write!(f, "Script commits to wrong block number {n}"),
+            Self::AssetHashNotSigned => write!(f, "Asset hash is not signed in the script"),
+            Self::ExecutionFailed => write!(f, "Script execution failed"),
+            Self::ScriptTooLarge(len) => write!(f, "{ERROR_MAX_SCRIPT_SIZE}: {len}"),
+            Self::TooManyOps(count) => write!(f, "{ERROR_MAX_OPS_SCRIPT}: {count}"),
+            Self::ItemTooLarge(len) => write!(f, "{ERROR_ITEM_SIZE}: {len}"),
+            Self::NonMinimalPush => write!(f, "Data push doesn't use the smallest encoding"),
+            Self::TruncatedPush => write!(f, "Data push is longer than the script"),
+            Self::NotAPush(op) => write!(f, "Byte {op:#04x} doesn't start a data push"),
+            Self::EmptyCondition => write!(f, "{ERROR_EMPTY_CONDITION}"),
+            Self::DuplicateElse => write!(f, "{ERROR_DUPLICATE_ELSE}"),
+            Self::ItemType => write!(f, "{ERROR_ITEM_TYPE}"),
+            Self::InvalidHex => write!(f, "{ERROR_INVALID_HEX}"),
+            Self::StackUnderflow(n) => write!(f, "{ERROR_NUM_ITEMS}: {n} required"),
+            Self::BlockNumberOutsideWindow(n, height) => write!(
+                f,
+                "Block number {n} is more than {CREATE_BLOCK_WINDOW} blocks from height {height}"
+            ),
+            Self::BlockNumberTooLarge(n) => write!(f, "Block number {n} is too large for a Num"),
+            Self::NoTxContext => write!(f, "{ERROR_NO_TX_CONTEXT}"),
+            Self::OutputIndex(n) => write!(f, "Output index {n} is out of bound"),
+            Self::OutputNotToken(n) => write!(f, "Output {n}: {ERROR_OUTPUT_NOT_TOKEN}"),
+            Self::StackMemory(bytes) => write!(f, "{ERROR_MAX_STACK_BYTES}: {bytes}"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
 /// Stack for script execution
+///
+/// Entries should be added and removed through the `Stack` methods,
+/// which keep `total_bytes` up to date across the main and alt stacks,
+/// and record in `refused_bytes` the memory a push refused for `MAX_STACK_BYTES` needed
 #[derive(Clone, Debug, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Stack {
-    pub main_stack: Vec<StackEntry>,
-    pub alt_stack: Vec<StackEntry>,
+    pub(crate) main_stack: Vec<StackEntry>,
+    pub(crate) alt_stack: Vec<StackEntry>,
+    total_bytes: usize,
+    max_item_size: usize,
+    #[serde(skip)]
+    refused_bytes: Option<usize>,
 }
 
 impl Default for Stack {
@@ -31,23 +306,90 @@ impl Stack {
     /// Creates a new stack
     pub fn new() -> Self {
         Self {
-            main_stack: Vec::with_capacity(MAX_STACK_SIZE as usize),
-            alt_stack: Vec::with_capacity(MAX_STACK_SIZE as usize),
+            main_stack: Vec::with_capacity(MAX_STACK_SIZE),
+            alt_stack: Vec::with_capacity(MAX_STACK_SIZE),
+            total_bytes: ZERO,
+            max_item_size: MAX_SCRIPT_ITEM_SIZE,
+            refused_bytes: None,
         }
     }
 
+    /// Creates a new stack enforcing the item size limit of `context`
+    ///
+    /// ### Arguments
+    ///
+    /// * `context`  - Execution context holding the limits
+    pub fn with_context(context: &ExecutionContext) -> Self {
+        Self {
+            max_item_size: context.max_script_item_size,
+            ..Self::new()
+        }
+    }
+
+    /// Returns the maximum number of bytes a single item may hold
+    pub fn max_item_size(&self) -> usize {
+        self.max_item_size
+    }
+
     /// Checks if the stack is valid
     pub fn is_valid(&self) -> bool {
-        if self.main_stack.len() + self.alt_stack.len() > MAX_STACK_SIZE as usize {
+        if self.main_stack.len() + self.alt_stack.len() > MAX_STACK_SIZE {
             error_max_stack_size();
             return false;
         }
+        if self.total_bytes > MAX_STACK_BYTES {
+            error_max_stack_bytes();
+            return false;
+        }
         true
     }
 
+    /// Returns the entries of the main stack, from bottom to top
+    pub fn main_stack(&self) -> &[StackEntry] {
+        &self.main_stack
+    }
+
+    /// Returns the entries of the alt stack, from bottom to top
+    pub fn alt_stack(&self) -> &[StackEntry] {
+        &self.alt_stack
+    }
+
+    /// Returns the number of bytes held by the main and alt stacks together
+    pub fn memory_usage(&self) -> usize {
+        self.total_bytes
+    }
+
+    /// Returns the error of the stack running out of memory: a push refused for
+    /// exceeding `MAX_STACK_BYTES`, or more bytes held than it allows
+    pub fn memory_error(&self) -> Option<ScriptError> {
+        self.refused_bytes
+            .or((self.total_bytes > MAX_STACK_BYTES).then_some(self.total_bytes))
+            .map(ScriptError::StackMemory)
+    }
+
     /// Pops the top item from the stack
     pub fn pop(&mut self) -> Option<StackEntry> {
-        self.main_stack.pop()
+        let entry = self.main_stack.pop()?;
+        self.total_bytes -= entry_bytes(&entry);
+        Some(entry)
+    }
+
+    /// Removes the item at `index` from the stack
+    ///
+    /// ### Arguments
+    ///
+    /// * `index`  - Index of the item, counted from the bottom of the stack
+    pub fn remove(&mut self, index: usize) -> StackEntry {
+        let entry = self.main_stack.remove(index);
+        self.total_bytes -= entry_bytes(&entry);
+        entry
+    }
+
+    /// Pops the top item from the alt stack
+    pub fn pop_alt(&mut self) -> Option<StackEntry> {
+        let entry = self.alt_stack.pop()?;
+        self.total_bytes -= entry_bytes(&entry);
+        Some(entry)
     }
 
     /// Returns the top item on the stack
@@ -62,37 +404,188 @@ impl Stack {
 
     /// Pushes a new entry onto the stack
     pub fn push(&mut self, stack_entry: StackEntry) -> bool {
-        match stack_entry.clone() {
+        let len = self.main_stack.len();
+        self.insert(len, stack_entry)
+    }
+
+    /// Inserts a new entry into the stack at `index`
+    ///
+    /// ### Arguments
+    ///
+    /// * `index`        - Index to insert at, counted from the bottom of the stack
+    /// * `stack_entry`  - Entry to insert
+    pub fn insert(&mut self, index: usize, stack_entry: StackEntry) -> bool {
+        if !self.reserve_bytes(&stack_entry) {
+            return false;
+        }
+        self.main_stack.insert(index, stack_entry);
+        true
+    }
+
+    /// Pushes a new entry onto the alt stack
+    ///
+    /// ### Arguments
+    ///
+    /// * `stack_entry`  - Entry to push
+    pub fn push_alt(&mut self, stack_entry: StackEntry) -> bool {
+        if !self.reserve_bytes(&stack_entry) {
+            return false;
+        }
+        self.alt_stack.push(stack_entry);
+        true
+    }
+
+    /// Takes exclusive access to the top `N` items of the stack, failing without
+    /// touching the stack if it holds fewer
+    pub fn require<const N: usize>(&mut self) -> Result<StackGuard<'_, N>, ScriptError> {
+        if self.main_stack.len() < N {
+            return Err(ScriptError::StackUnderflow(N));
+        }
+        Ok(StackGuard { stack: self })
+    }
+
+    /// Checks whether an entry may be added to the stack, accounting for its bytes if so
+    ///
+    /// ### Arguments
+    ///
+    /// * `stack_entry`  - Entry to be added
+    fn reserve_bytes(&mut self, stack_entry: &StackEntry) -> bool {
+        match stack_entry {
             StackEntry::Op(_) => {
                 return false;
             }
-            StackEntry::Bytes(s) => {
-                if s.len() > MAX_SCRIPT_ITEM_SIZE as usize {
-                    return false;
-                }
+            StackEntry::Bytes(s) if s.len() > self.max_item_size => {
+                return false;
             }
             _ => (),
         }
-        self.main_stack.push(stack_entry);
+        let total_bytes = self.total_bytes + entry_bytes(stack_entry);
+        if total_bytes > MAX_STACK_BYTES {
+            error_max_stack_bytes();
+            self.refused_bytes = Some(total_bytes);
+            return false;
+        }
+        self.total_bytes = total_bytes;
         true
     }
 }
 
+/// Exclusive access to the top `N` items of a stack, which are known to exist.
+/// Obtained with `Stack::require`, so ops can read all their operands before
+/// mutating the stack, and replace them in one step
+#[derive(Debug)]
+pub struct StackGuard<'a, const N: usize> {
+    stack: &'a mut Stack,
+}
+
+impl<'a, const N: usize> StackGuard<'a, N> {
+    /// Returns the top `N` items, from bottom to top
+    pub fn items(&self) -> &[StackEntry; N] {
+        let start = self.stack.main_stack.len() - N;
+        self.stack.main_stack[start..].try_into().unwrap()
+    }
+
+    /// Returns the top `N` items as numbers, from bottom to top.
+    /// Fails if any of them isn't a number
+    pub fn nums(&self) -> Result<[usize; N], ScriptError> {
+        let mut nums = [ZERO; N];
+        for (n, entry) in nums.iter_mut().zip(self.items()) {
+            match entry {
+                StackEntry::Num(v) => *n = *v,
+                _ => return Err(ScriptError::ItemType),
+            }
+        }
+        Ok(nums)
+    }
+
+    /// Replaces the top `N` items with `stack_entry`. If `stack_entry` can't be
+    /// pushed, the stack is left as it was
+    ///
+    /// ### Arguments
+    ///
+    /// * `stack_entry`  - Entry to push in place of the items
+    pub fn replace(self, stack_entry: StackEntry) -> bool {
+        let start = self.stack.main_stack.len() - N;
+        let items = self.stack.main_stack.split_off(start);
+        self.stack.total_bytes -= items.iter().map(entry_bytes).sum::<usize>();
+        if self.stack.push(stack_entry) {
+            return true;
+        }
+        self.stack.total_bytes += items.iter().map(entry_bytes).sum::<usize>();
+        self.stack.main_stack.extend(items);
+        false
+    }
+}
+
+/// Number of bytes an entry occupies on the stack
+///
+/// ### Arguments
+///
+/// * `stack_entry`  - Entry to measure
+pub(crate) fn entry_bytes(stack_entry: &StackEntry) -> usize {
+    match stack_entry {
+        StackEntry::Op(_) | StackEntry::Num(_) => usize::BITS as usize / EIGHT,
+        StackEntry::Signature(_) => ED25519_SIGNATURE_LEN,
+        StackEntry::PubKey(_) => ED25519_PUBLIC_KEY_LEN,
+        StackEntry::Bytes(s) => s.len(),
+    }
+}
+
 impl From<Vec<StackEntry>> for Stack {
     /// Creates a new stack with a pre-filled main stack
     fn from(stack: Vec<StackEntry>) -> Self {
         Stack {
+            total_bytes: stack.iter().map(entry_bytes).sum(),
             main_stack: stack,
-            alt_stack: Vec::with_capacity(MAX_STACK_SIZE as usize),
+            alt_stack: Vec::with_capacity(MAX_STACK_SIZE),
+            max_item_size: MAX_SCRIPT_ITEM_SIZE,
+            refused_bytes: None,
         }
     }
 }
 
-/// Stack for conditionals
+/// Stack for conditionals, tracking which `OP_IF`/`OP_NOTIF` blocks are open and
+/// whether the entries currently being interpreted should be executed.
+///
+/// Each frame is an open conditional block, recording whether its `OP_ELSE` has been
+/// seen. Only the position of the first false frame is kept, as a block is executed
+/// only if all frames are true. Custom control flow opcodes can rely on this contract:
+///
+/// * `push` opens a block and `pop` closes the innermost one
+/// * `toggle` flips the innermost block, at most once per block once
+///   `duplicate_else_is_rejected`, and any number of times before
+/// * `all_true` is whether entries should currently be executed
+/// * `first_false_pos` is the index, counted from the outermost block, of the first
+///   false block, or `None` if all are true. Blocks nested inside a false block
+///   can't become true until it is closed or toggled
+/// * a script must leave the stack empty, with every block closed
+///
+/// ### Example
+///
+/// Following `1 OP_IF 0 OP_IF <a> OP_ELSE <b> OP_ENDIF OP_ENDIF`:
+///
+/// ```
+/// use tw_chain::script::lang::ConditionStack;
+///
+/// let mut cond_stack = ConditionStack::new();
+/// cond_stack.push(true);
+/// cond_stack.push(false);
+/// assert!(!cond_stack.all_true()); // <a> is skipped
+/// assert_eq!(cond_stack.first_false_pos(), Some(1));
+///
+/// cond_stack.toggle().unwrap();
+/// assert!(cond_stack.all_true()); // <b> is executed
+/// assert!(cond_stack.toggle().is_err()); // a second OP_ELSE is rejected
+///
+/// cond_stack.pop().unwrap();
+/// cond_stack.pop().unwrap();
+/// assert!(cond_stack.is_empty());
+/// ```
 #[derive(Clone, Debug, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ConditionStack {
-    pub size: usize,
-    pub first_false_pos: Option<usize>,
+    first_false_pos: Option<usize>,
+    else_seen: Vec<bool>,
+    reject_duplicate_else: bool,
 }
 
 impl Default for ConditionStack {
@@ -102,54 +595,85 @@ impl Default for ConditionStack {
 }
 
 impl ConditionStack {
-    /// Creates a new stack for conditionals
+    /// Creates a new stack for conditionals, rejecting repeated `OP_ELSE`
     pub fn new() -> Self {
+        Self::at_height(None)
+    }
+
+    /// Creates a new stack for conditionals executed at a block height, only rejecting
+    /// repeated `OP_ELSE` once `duplicate_else_is_rejected` at that height
+    ///
+    /// ### Arguments
+    ///
+    /// * `block_height`  - Height of the block the script is executed in, if any
+    pub fn at_height(block_height: Option<u64>) -> Self {
         Self {
-            size: ZERO,
             first_false_pos: None,
+            else_seen: Vec::new(),
+            reject_duplicate_else: duplicate_else_is_rejected(block_height),
         }
     }
 
-    /// Checks if all values are true
+    /// Checks if all values are true, so that entries should be executed
     pub fn all_true(&self) -> bool {
         self.first_false_pos.is_none()
     }
 
     /// Checks if the condition stack is empty
     pub fn is_empty(&self) -> bool {
-        self.size == ZERO
+        self.else_seen.is_empty()
+    }
+
+    /// Returns the number of open conditional blocks
+    pub fn depth(&self) -> usize {
+        self.else_seen.len()
     }
 
-    /// Pushes a new value onto the condition stack
+    /// Returns the position of the outermost false block, if any
+    pub fn first_false_pos(&self) -> Option<usize> {
+        self.first_false_pos
+    }
+
+    /// Opens a conditional block
+    ///
+    /// ### Arguments
+    ///
+    /// * `cond`  - Whether the block is executed before any `OP_ELSE`
     pub fn push(&mut self, cond: bool) {
         if self.first_false_pos.is_none() && !cond {
-            self.first_false_pos = Some(self.size);
+            self.first_false_pos = Some(self.depth());
         }
-        self.size += ONE;
+        self.else_seen.push(false);
     }
 
-    /// Pops the top value from the condition stack
-    pub fn pop(&mut self) {
-        self.size -= ONE;
-        if let Some(pos) = self.first_false_pos {
-            if pos == self.size {
-                self.first_false_pos.take();
-            }
+    /// Closes the innermost conditional block, for its `OP_ENDIF`.
+    /// Fails if the stack is empty
+    pub fn pop(&mut self) -> Result<(), ScriptError> {
+        self.else_seen.pop().ok_or(ScriptError::EmptyCondition)?;
+        if self.first_false_pos == Some(self.depth()) {
+            self.first_false_pos = None;
         }
+        Ok(())
     }
 
-    /// Toggles the top value on the condition stack
-    pub fn toggle(&mut self) {
+    /// Toggles the innermost conditional block, for its `OP_ELSE`.
+    /// Fails if the stack is empty or, when rejecting repeated `OP_ELSE`, the block
+    /// already had its `OP_ELSE`
+    pub fn toggle(&mut self) -> Result<(), ScriptError> {
+        let top = self
+            .depth()
+            .checked_sub(ONE)
+            .ok_or(ScriptError::EmptyCondition)?;
+        if self.reject_duplicate_else && self.else_seen[top] {
+            return Err(ScriptError::DuplicateElse);
+        }
+        self.else_seen[top] = true;
         match self.first_false_pos {
-            Some(pos) => {
-                if pos == self.size - ONE {
-                    self.first_false_pos = None;
-                }
-            }
-            _ => {
-                self.first_false_pos = Some(self.size - ONE);
-            }
+            Some(pos) if pos == top => self.first_false_pos = None,
+            Some(_) => (),
+            None => self.first_false_pos = Some(top),
         }
+        Ok(())
     }
 }
 
@@ -172,49 +696,174 @@ impl Script {
         Self { stack: Vec::new() }
     }
 
-    /// Checks if a script is valid
+    /// Checks a script against the default limits, returning the first limit it breaks
+    pub fn verify(&self) -> Result<(), ScriptError> {
+        self.verify_in_context(&ExecutionContext::default())
+    }
+
+    /// Checks a script against the limits of `context`, returning the first limit it breaks
+    ///
+    /// ### Arguments
+    ///
+    /// * `context`  - Execution context holding the limits
+    pub fn verify_in_context(&self, context: &ExecutionContext) -> Result<(), ScriptError> {
+        let mut size = ScriptSize::default();
+        self.stack
+            .iter()
+            .try_for_each(|entry| size.add(entry, context))
+    }
+
+    /// Checks if a script is valid under the default limits
     pub fn is_valid(&self) -> bool {
-        let mut len = ZERO; // script length in bytes
-        let mut ops_count = ZERO; // number of opcodes in script
-        for entry in &self.stack {
-            match entry {
-                StackEntry::Op(_) => {
-                    len += ONE;
-                    ops_count += ONE;
-                }
-                StackEntry::Signature(_) => len += ED25519_SIGNATURE_LEN,
-                StackEntry::PubKey(_) => len += ED25519_PUBLIC_KEY_LEN,
-                StackEntry::Bytes(s) => len += s.len(),
-                StackEntry::Num(_) => len += usize::BITS as usize / EIGHT,
-            };
-        }
-        if len > MAX_SCRIPT_SIZE as usize {
-            error_max_script_size();
-            return false;
+        self.verify().is_ok()
+    }
+
+    /// Checks if a script is valid under the limits of `context`
+    ///
+    /// ### Arguments
+    ///
+    /// * `context`  - Execution context holding the limits
+    pub fn is_valid_in_context(&self, context: &ExecutionContext) -> bool {
+        self.verify_in_context(context).is_ok()
+    }
+
+    /// Constructs a script from fallible entries, such as those of a streaming decoder,
+    /// under the default limits. Stops at the first error or limit violation, without
+    /// consuming the rest of `iter`
+    ///
+    /// ### Arguments
+    ///
+    /// * `iter`  - Entries of the script
+    pub fn try_from_iter(
+        iter: impl IntoIterator<Item = Result<StackEntry, ScriptError>>,
+    ) -> Result<Script, ScriptError> {
+        let mut builder = ScriptBuilder::new();
+        for entry in iter {
+            builder.push(entry?)?;
         }
-        if ops_count > MAX_OPS_PER_SCRIPT as usize {
-            error_max_ops_script();
-            return false;
+        Ok(builder.build())
+    }
+
+    /// Constructs a script from one of the standard templates. Templates filled with
+    /// oversized data are still returned as is, to be rejected when validated
+    ///
+    /// ### Arguments
+    ///
+    /// * `stack`  - Entries of the script
+    fn from_template(stack: Vec<StackEntry>) -> Script {
+        match Script::try_from_iter(stack.iter().cloned().map(Ok)) {
+            Ok(script) => script,
+            Err(e) => {
+                warn!("Constructed script exceeds limits: {e}");
+                Script::from(stack)
+            }
         }
-        true
     }
 
-    /// Interprets and executes a script
+    /// Interprets and executes a script under the default limits
     pub fn interpret(&self) -> bool {
-        if !self.is_valid() {
-            return false;
+        self.interpret_full().is_ok()
+    }
+
+    /// Interprets and executes a script under the default limits, returning
+    /// why it failed. Clones the script; see `execute`
+    pub fn interpret_full(&self) -> Result<(), ScriptError> {
+        self.clone().execute()
+    }
+
+    /// Interprets and executes a script under the default limits, consuming it.
+    ///
+    /// Entries are moved onto the stack rather than cloned, so this is preferred
+    /// over `interpret` and `interpret_full` when the script isn't used afterwards
+    pub fn execute(self) -> Result<(), ScriptError> {
+        let context = ExecutionContext::default();
+        self.verify_in_context(&context)?;
+        let stack = Self::run(self.stack, &context, &mut ResourceReport::default())?;
+        match stack.is_last_non_zero() {
+            true => Ok(()),
+            false => Err(ScriptError::ExecutionFailed),
         }
-        let mut stack = Stack::new();
-        let mut cond_stack = ConditionStack::new();
+    }
+
+    /// Executes a script within the script being executed under `context`,
+    /// such as a P2SH redeem script, failing if nesting gets too deep
+    ///
+    /// ### Arguments
+    ///
+    /// * `context`  - Execution context of the enclosing script
+    pub fn execute_in_context(&self, context: &ExecutionContext) -> Result<bool, ScriptError> {
+        let nested = context.nested()?;
+        Ok(self.interpret_in_context(&nested))
+    }
+
+    /// Interprets and executes a script under the limits of `context`
+    ///
+    /// ### Arguments
+    ///
+    /// * `context`  - Execution context holding the limits
+    pub fn interpret_in_context(&self, context: &ExecutionContext) -> bool {
+        self.evaluate(context)
+            .is_ok_and(|stack| stack.is_last_non_zero())
+    }
+
+    /// Executes a script under the limits of `context`, returning the stack it leaves.
+    /// Unlike `interpret_in_context`, the top item of the final stack isn't checked.
+    /// Scripts breaking the limits fail with the error of `verify_in_context` before
+    /// any entry is executed
+    ///
+    /// ### Arguments
+    ///
+    /// * `context`  - Execution context holding the limits
+    pub fn evaluate(&self, context: &ExecutionContext) -> Result<Stack, ScriptError> {
+        self.evaluate_with_report(context).0
+    }
+
+    /// Executes a script under the limits of `context` as `evaluate` does, also reporting
+    /// its peak stack usage and the fuel it used. Scripts failing verification report no usage
+    ///
+    /// ### Arguments
+    ///
+    /// * `context`  - Execution context holding the limits
+    pub fn evaluate_with_report(
+        &self,
+        context: &ExecutionContext,
+    ) -> (Result<Stack, ScriptError>, ResourceReport) {
+        let mut report = ResourceReport::default();
+        let result = self
+            .verify_in_context(context)
+            .and_then(|_| Self::run(self.stack.iter().cloned(), context, &mut report));
+        (result, report)
+    }
+
+    /// Executes already verified script entries under the limits of `context`,
+    /// returning the stack they leave
+    ///
+    /// ### Arguments
+    ///
+    /// * `entries`  - Entries of the script, in order
+    /// * `context`  - Execution context holding the limits
+    /// * `report`   - Report to record peak stack usage and fuel in
+    fn run(
+        entries: impl IntoIterator<Item = StackEntry>,
+        context: &ExecutionContext,
+        report: &mut ResourceReport,
+    ) -> Result<Stack, ScriptError> {
+        let mut stack = Stack::with_context(context);
+        let mut cond_stack = ConditionStack::at_height(context.block_height);
         let mut test_for_return = true;
-        for stack_entry in &self.stack {
-            match stack_entry.clone() {
+        for stack_entry in entries {
+            match stack_entry {
                 /*---- OPCODE ----*/
                 StackEntry::Op(op) => {
                     if !cond_stack.all_true() && !op.is_conditional() {
                         // skip opcode if latest condition check failed
                         continue;
                     }
+                    let op = match context.block_height {
+                        Some(height) if !opcode_is_activated(&op, height) => OpCodes::OP_NOP,
+                        _ => op,
+                    };
+                    report.charge(context.costs.op(&op, &stack));
                     match op {
                         // constants
                         OpCodes::OP_0 => test_for_return &= stack.push(StackEntry::Num(ZERO)),
@@ -275,8 +924,22 @@ impl Script {
                         OpCodes::OP_AND => test_for_return &= op_and(&mut stack),
                         OpCodes::OP_OR => test_for_return &= op_or(&mut stack),
                         OpCodes::OP_XOR => test_for_return &= op_xor(&mut stack),
+                        OpCodes::OP_EQUAL | OpCodes::OP_EQUALVERIFY
+                            if context.strict_equal && !equal_types_match(&stack, &op) =>
+                        {
+                            return Err(ScriptError::ItemType)
+                        }
                         OpCodes::OP_EQUAL => test_for_return &= op_equal(&mut stack),
                         OpCodes::OP_EQUALVERIFY => test_for_return &= op_equalverify(&mut stack),
+                        OpCodes::OP_LOOSEEQUAL | OpCodes::OP_LOOSEEQUALVERIFY
+                            if !loose_hex_is_canonical(&stack, &op) =>
+                        {
+                            return Err(ScriptError::InvalidHex)
+                        }
+                        OpCodes::OP_LOOSEEQUAL => test_for_return &= op_looseequal(&mut stack),
+                        OpCodes::OP_LOOSEEQUALVERIFY => {
+                            test_for_return &= op_looseequalverify(&mut stack)
+                        }
                         // arithmetic
                         OpCodes::OP_1ADD => test_for_return &= op_1add(&mut stack),
                         OpCodes::OP_1SUB => test_for_return &= op_1sub(&mut stack),
@@ -326,25 +989,218 @@ impl Script {
                         }
                         // smart data
                         OpCodes::OP_CREATE => (),
+                        // introspection
+                        OpCodes::OP_TXINCOUNT => {
+                            let tx = tx_context(context, &op)?;
+                            test_for_return &= op_txincount(&mut stack, tx.input_count)
+                        }
+                        OpCodes::OP_TXOUTPUTCOUNT => {
+                            let tx = tx_context(context, &op)?;
+                            test_for_return &= op_txoutputcount(&mut stack, &tx.output_values)
+                        }
+                        OpCodes::OP_TXOUTVALUE => {
+                            let tx = tx_context(context, &op)?;
+                            output_is_token(&stack, &tx.output_values)?;
+                            test_for_return &= op_txoutvalue(&mut stack, &tx.output_values)
+                        }
                         // reserved
                         _ => (),
                     }
                 }
                 /*---- SIGNATURE | PUBKEY | NUM | BYTES ----*/
-                StackEntry::Signature(_)
+                entry @ (StackEntry::Signature(_)
                 | StackEntry::PubKey(_)
                 | StackEntry::Num(_)
-                | StackEntry::Bytes(_) => {
+                | StackEntry::Bytes(_)) => {
                     if cond_stack.all_true() {
-                        test_for_return &= stack.push(stack_entry.clone())
+                        report.charge(context.costs.push());
+                        test_for_return &= stack.push(entry)
                     }
                 }
             }
+            report.observe(&stack);
             if !test_for_return || !stack.is_valid() {
-                return false;
+                return Err(stack.memory_error().unwrap_or(ScriptError::ExecutionFailed));
+            }
+        }
+        if !cond_stack.is_empty() {
+            return Err(ScriptError::ExecutionFailed);
+        }
+        Ok(stack)
+    }
+
+    /// Interprets a create script in a constrained mode where only data pushes
+    /// and the opcodes of `OpCodes::is_create_allowed` may be used.
+    ///
+    /// The script must start with `OP_CREATE` followed by the block number, and
+    /// successfully check a signature over the expected asset hash
+    ///
+    /// ### Arguments
+    ///
+    /// * `context`  - Asset hash and block number the script must commit to
+    pub fn interpret_create(&self, context: &CreateContext) -> Result<CreateClaims, ScriptError> {
+        let block_number = match self.stack.as_slice() {
+            [StackEntry::Op(OpCodes::OP_CREATE), StackEntry::Num(n), ..] => *n as u64,
+            _ => return Err(ScriptError::NotCreateScript),
+        };
+        if context.block_number.is_some_and(|n| n != block_number) {
+            return Err(ScriptError::BlockNumberMismatch(block_number));
+        }
+        if let Some(current_height) = context.current_height {
+            check_create_block_number(block_number, current_height)?;
+        }
+        for entry in &self.stack {
+            if let StackEntry::Op(op) = entry {
+                if !op.is_create_allowed() {
+                    return Err(ScriptError::OpcodeNotAllowed(op.clone()));
+                }
+            }
+        }
+        self.verify()?;
+
+        let mut stack = Stack::new();
+        let mut pub_key = None;
+        for stack_entry in &self.stack {
+            let is_success = match stack_entry {
+                StackEntry::Op(OpCodes::OP_CREATE) => true,
+                StackEntry::Op(OpCodes::OP_DROP) => op_drop(&mut stack),
+                StackEntry::Op(OpCodes::OP_SHA3) => op_sha3(&mut stack),
+                StackEntry::Op(OpCodes::OP_HASH256) => op_hash256(&mut stack),
+                StackEntry::Op(op @ (OpCodes::OP_CHECKSIG | OpCodes::OP_CHECKSIGVERIFY)) => {
+                    // Claim the key of the first valid signature over the asset hash
+                    let signed = match stack.main_stack.as_slice() {
+                        [.., StackEntry::Bytes(msg), StackEntry::Signature(_), StackEntry::PubKey(pk)] => {
+                            (msg == &context.asset_hash).then_some(*pk)
+                        }
+                        _ => None,
+                    };
+                    let is_success = if op == &OpCodes::OP_CHECKSIG {
+                        op_checksig(&mut stack)
+                            && stack.main_stack.last() == Some(&StackEntry::Num(ONE))
+                    } else {
+                        op_checksigverify(&mut stack)
+                    };
+                    if is_success && pub_key.is_none() {
+                        pub_key = signed;
+                    }
+                    is_success
+                }
+                StackEntry::Op(op) => return Err(ScriptError::OpcodeNotAllowed(op.clone())),
+                _ => stack.push(stack_entry.clone()),
+            };
+            if !is_success || !stack.is_valid() {
+                return Err(stack.memory_error().unwrap_or(ScriptError::ExecutionFailed));
+            }
+        }
+        if !stack.is_last_non_zero() {
+            return Err(ScriptError::ExecutionFailed);
+        }
+
+        match pub_key {
+            Some(pub_key) => Ok(CreateClaims {
+                pub_key,
+                asset_hash: context.asset_hash.clone(),
+                block_number,
+            }),
+            None => Err(ScriptError::AssetHashNotSigned),
+        }
+    }
+
+    /// Returns an equivalent script with redundant opcode sequences removed.
+    ///
+    /// Only rewrites that provably preserve the result of `interpret` are applied:
+    /// * `OP_NOP` is dropped
+    /// * `OP_DUP OP_DROP` is collapsed when directly preceded by a data push
+    /// * `OP_NOT OP_NOT` is collapsed when directly preceded by an entry leaving `0` or `1`
+    ///
+    /// Scripts that fail `is_valid` are returned unchanged, as removing entries could make them valid.
+    /// `OP_DUP OP_DROP` is only collapsed when the script is too short to ever overflow the stack,
+    /// since the removed `OP_DUP` could otherwise be the push that exceeds `MAX_STACK_SIZE`
+    /// or `MAX_STACK_BYTES`
+    pub fn optimize(&self) -> Script {
+        if !self.is_valid() {
+            return self.clone();
+        }
+
+        // No single entry can grow the stack by more than three items of at most
+        // `MAX_SCRIPT_ITEM_SIZE` bytes each
+        let max_bytes: usize = self
+            .stack
+            .iter()
+            .map(|entry| match entry {
+                StackEntry::Op(_) => THREE * MAX_SCRIPT_ITEM_SIZE,
+                _ => entry_bytes(entry),
+            })
+            .sum();
+        let can_overflow = self.stack.len() * THREE > MAX_STACK_SIZE || max_bytes > MAX_STACK_BYTES;
+        let mut optimized: Vec<StackEntry> = Vec::with_capacity(self.stack.len());
+
+        for entry in &self.stack {
+            if entry == &StackEntry::Op(OpCodes::OP_NOP) {
+                continue;
             }
+            optimized.push(entry.clone());
+
+            let len = optimized.len();
+            let redundant = match &optimized[len.saturating_sub(THREE)..] {
+                [prev, StackEntry::Op(OpCodes::OP_DUP), StackEntry::Op(OpCodes::OP_DROP)] => {
+                    !can_overflow && is_data_push(prev)
+                }
+                [prev, StackEntry::Op(OpCodes::OP_NOT), StackEntry::Op(OpCodes::OP_NOT)] => {
+                    leaves_bool(prev)
+                }
+                _ => false,
+            };
+            if redundant {
+                optimized.truncate(len - TWO);
+            }
+        }
+
+        Script::from(optimized)
+    }
+
+    /// Returns the canonical form of a script, in which functionally identical scripts are equal:
+    /// * `OP_NOP` is dropped
+    /// * `OP_0` to `OP_16` are replaced by the `Num` entry they push
+    ///
+    /// Data pushes need no rewriting, as entries hold the pushed data rather than its encoding,
+    /// and non-minimal encodings are already rejected by `wire::decode_push`.
+    /// Scripts that fail `is_valid` are returned unchanged. A `Num` entry is larger than
+    /// a constant opcode, so constants are kept as opcodes if replacing them would exceed
+    /// `MAX_SCRIPT_SIZE`
+    pub fn normalized(&self) -> Script {
+        if !self.is_valid() {
+            return self.clone();
+        }
+        let stripped = self
+            .stack
+            .iter()
+            .filter(|entry| *entry != &StackEntry::Op(OpCodes::OP_NOP));
+        let folded: Script = stripped
+            .clone()
+            .map(|entry| match constant_value(Some(entry)) {
+                Some(n) => StackEntry::Num(n),
+                None => entry.clone(),
+            })
+            .collect();
+        if folded.is_valid() {
+            folded
+        } else {
+            stripped.cloned().collect()
         }
-        test_for_return && stack.is_last_non_zero() && cond_stack.is_empty()
+    }
+
+    /// Checks whether two scripts are functionally identical under the default limits,
+    /// even if encoded differently, by comparing their `normalized` forms.
+    ///
+    /// This is a policy and tooling notion, for deduplication and matching.
+    /// Consensus always compares the exact entries of scripts
+    ///
+    /// ### Arguments
+    ///
+    /// * `other`  - Script to compare against
+    pub fn semantic_eq(&self, other: &Script) -> bool {
+        self == other || self.normalized() == other.normalized()
     }
 
     /// Constructs a new script for coinbase
@@ -357,30 +1213,33 @@ impl Script {
         Self { stack }
     }
 
-    /// Constructs a new script for an asset creation
+    /// Constructs a new script for an asset creation. Fails if the block number
+    /// wouldn't be accepted at `current_height`, see `check_create_block_number`
     ///
     /// ### Arguments
     ///
     /// * `block_number`    - The block time
+    /// * `current_height`  - Current height of the chain
     /// * `asset_hash`      - The hash of the asset
     /// * `signature`       - The signature of the asset contents
     /// * `pub_key`         - The public key used in creating the signed content
     pub fn new_create_asset(
         block_number: u64,
+        current_height: u64,
         asset_hash: String,
         signature: Signature,
         pub_key: PublicKey,
-    ) -> Self {
-        let stack = vec![
-            StackEntry::Op(OpCodes::OP_CREATE),
-            StackEntry::Num(block_number as usize),
+    ) -> Result<Self, ScriptError> {
+        let block_number = check_create_block_number(block_number, current_height)?;
+        let rest = Script::from(vec![
+            StackEntry::Num(block_number),
             StackEntry::Op(OpCodes::OP_DROP),
             StackEntry::Bytes(asset_hash),
             StackEntry::Signature(signature),
             StackEntry::PubKey(pub_key),
             StackEntry::Op(OpCodes::OP_CHECKSIG),
-        ];
-        Self { stack }
+        ]);
+        Ok(Script::prefix(StackEntry::Op(OpCodes::OP_CREATE), rest))
     }
 
     /// Constructs a pay to public key hash script
@@ -401,17 +1260,179 @@ impl Script {
             Some(NETWORK_VERSION_TEMP) => OpCodes::OP_HASH256_TEMP,
             _ => OpCodes::OP_HASH256,
         };
-        let stack = vec![
+        let unlocking = Script::from_template(vec![
             StackEntry::Bytes(check_data),
             StackEntry::Signature(signature),
             StackEntry::PubKey(pub_key),
+        ]);
+        let address = construct_address_for(&pub_key, address_version);
+        let locking = Script::p2pkh_locking_with(op_hash_256, address);
+        Script::concat(unlocking, locking)
+    }
+
+    /// Constructs the locking side of a pay to public key hash script,
+    /// spendable by a `pay2pkh` script for the same address
+    ///
+    /// ### Arguments
+    ///
+    /// * `address`     - Address of the payee
+    pub fn p2pkh_locking(address: &str) -> Self {
+        Script::p2pkh_locking_with(OpCodes::OP_HASH256, address.to_owned())
+    }
+
+    /// Constructs a pay to public key hash locking script hashing with `op_hash_256`
+    ///
+    /// ### Arguments
+    ///
+    /// * `op_hash_256` - Hash opcode matching the address version
+    /// * `address`     - Address of the payee
+    fn p2pkh_locking_with(op_hash_256: OpCodes, address: String) -> Self {
+        Script::from_template(vec![
             StackEntry::Op(OpCodes::OP_DUP),
             StackEntry::Op(op_hash_256),
-            StackEntry::Bytes(construct_address_for(&pub_key, address_version)),
+            StackEntry::Bytes(address),
             StackEntry::Op(OpCodes::OP_EQUALVERIFY),
             StackEntry::Op(OpCodes::OP_CHECKSIG),
-        ];
-        Self { stack }
+        ])
+    }
+
+    /// Constructs a dual signature (2-of-2) pay to public key hash script, spending
+    /// an output paid to `construct_dual_address` of both keys. Each signature is checked
+    /// against the key in the same position:
+    ///
+    /// `<check_data> OP_DUP <sig_a> <pk_a> OP_CHECKSIGVERIFY <sig_b> <pk_b> OP_CHECKSIG`
+    ///
+    /// ### Arguments
+    ///
+    /// * `check_data`  - Check data both parties signed
+    /// * `sig_a`       - Signature of the first party
+    /// * `sig_b`       - Signature of the second party
+    /// * `pk_a`        - Public key of the first party
+    /// * `pk_b`        - Public key of the second party
+    pub fn pay2pkh_dual(
+        check_data: String,
+        sig_a: Signature,
+        sig_b: Signature,
+        pk_a: PublicKey,
+        pk_b: PublicKey,
+    ) -> Self {
+        Script::from_template(vec![
+            StackEntry::Bytes(check_data),
+            StackEntry::Op(OpCodes::OP_DUP),
+            StackEntry::Signature(sig_a),
+            StackEntry::PubKey(pk_a),
+            StackEntry::Op(OpCodes::OP_CHECKSIGVERIFY),
+            StackEntry::Signature(sig_b),
+            StackEntry::PubKey(pk_b),
+            StackEntry::Op(OpCodes::OP_CHECKSIG),
+        ])
+    }
+
+    /// Constructs an escrow locking script, paid to at `construct_escrow_address`.
+    /// The escrow is settled by the buyer and seller together or, from `timeout_block`,
+    /// by the arbiter along with either of them:
+    ///
+    /// `OP_IF 2 <buyer> <seller> 2 OP_CHECKMULTISIG OP_ELSE <timeout_block> OP_DROP
+    /// 2 OP_PICK OP_SWAP <arbiter> OP_CHECKSIGVERIFY 1 <buyer> <seller> 2 OP_CHECKMULTISIG OP_ENDIF`
+    ///
+    /// No opcode reads the block height, so the timeout is enforced when validating
+    /// the transaction spending the escrow. Spending scripts are one of the escrow
+    /// unlocking scripts followed by this one
+    ///
+    /// ### Arguments
+    ///
+    /// * `buyer_pk`        - Public key of the buyer
+    /// * `seller_pk`       - Public key of the seller
+    /// * `arbiter_pk`      - Public key of the arbiter
+    /// * `timeout_block`   - Block height from which the arbiter can settle the escrow
+    pub fn new_escrow(
+        buyer_pk: PublicKey,
+        seller_pk: PublicKey,
+        arbiter_pk: PublicKey,
+        timeout_block: u64,
+    ) -> Self {
+        Script::from_template(vec![
+            StackEntry::Op(OpCodes::OP_IF),
+            StackEntry::Num(2),
+            StackEntry::PubKey(buyer_pk),
+            StackEntry::PubKey(seller_pk),
+            StackEntry::Num(2),
+            StackEntry::Op(OpCodes::OP_CHECKMULTISIG),
+            StackEntry::Op(OpCodes::OP_ELSE),
+            StackEntry::Num(timeout_block as usize),
+            StackEntry::Op(OpCodes::OP_DROP),
+            StackEntry::Num(2),
+            StackEntry::Op(OpCodes::OP_PICK),
+            StackEntry::Op(OpCodes::OP_SWAP),
+            StackEntry::PubKey(arbiter_pk),
+            StackEntry::Op(OpCodes::OP_CHECKSIGVERIFY),
+            StackEntry::Num(1),
+            StackEntry::PubKey(buyer_pk),
+            StackEntry::PubKey(seller_pk),
+            StackEntry::Num(2),
+            StackEntry::Op(OpCodes::OP_CHECKMULTISIG),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+        ])
+    }
+
+    /// Constructs the unlocking script releasing an escrow with the signatures of
+    /// both the buyer and the seller
+    ///
+    /// ### Arguments
+    ///
+    /// * `check_data`  - Check data both parties signed
+    /// * `buyer_sig`   - Signature of the buyer
+    /// * `seller_sig`  - Signature of the seller
+    pub fn escrow_release(check_data: String, buyer_sig: Signature, seller_sig: Signature) -> Self {
+        Script::escrow_unlock(check_data, buyer_sig, seller_sig, 1)
+    }
+
+    /// Constructs the unlocking script refunding an escrow to the buyer, with the
+    /// signatures of the buyer and the arbiter. Only valid from the escrow timeout
+    ///
+    /// ### Arguments
+    ///
+    /// * `check_data`  - Check data both parties signed
+    /// * `buyer_sig`   - Signature of the buyer
+    /// * `arbiter_sig` - Signature of the arbiter
+    pub fn escrow_refund_via_arbiter(
+        check_data: String,
+        buyer_sig: Signature,
+        arbiter_sig: Signature,
+    ) -> Self {
+        Script::escrow_unlock(check_data, buyer_sig, arbiter_sig, 0)
+    }
+
+    /// Constructs the unlocking script paying an escrow to the seller, with the
+    /// signatures of the seller and the arbiter. Only valid from the escrow timeout
+    ///
+    /// ### Arguments
+    ///
+    /// * `check_data`  - Check data both parties signed
+    /// * `seller_sig`  - Signature of the seller
+    /// * `arbiter_sig` - Signature of the arbiter
+    pub fn escrow_seller_claim_via_arbiter(
+        check_data: String,
+        seller_sig: Signature,
+        arbiter_sig: Signature,
+    ) -> Self {
+        Script::escrow_unlock(check_data, seller_sig, arbiter_sig, 0)
+    }
+
+    /// `<check_data> <sig_a> <sig_b> <branch>`, where `branch` selects the
+    /// `OP_IF` (1) or `OP_ELSE` (0) branch of the escrow script
+    fn escrow_unlock(
+        check_data: String,
+        sig_a: Signature,
+        sig_b: Signature,
+        branch: usize,
+    ) -> Self {
+        Script::from_template(vec![
+            StackEntry::Bytes(check_data),
+            StackEntry::Signature(sig_a),
+            StackEntry::Signature(sig_b),
+            StackEntry::Num(branch),
+        ])
     }
 
     /// Constructs one part of a multiparty transaction script
@@ -422,13 +1443,12 @@ impl Script {
     /// * `pub_key`     - Public key of this party
     /// * `signature`   - Signature of this party
     pub fn member_multisig(check_data: String, pub_key: PublicKey, signature: Signature) -> Self {
-        let stack = vec![
+        Script::from_template(vec![
             StackEntry::Bytes(check_data),
             StackEntry::Signature(signature),
             StackEntry::PubKey(pub_key),
             StackEntry::Op(OpCodes::OP_CHECKSIG),
-        ];
-        Self { stack }
+        ])
     }
 
     /// Constructs a multisig locking script
@@ -444,7 +1464,7 @@ impl Script {
         stack.append(&mut pub_keys.iter().map(|e| StackEntry::PubKey(*e)).collect());
         stack.push(StackEntry::Num(n));
         stack.push(StackEntry::Op(OpCodes::OP_CHECKMULTISIG));
-        Self { stack }
+        Script::from_template(stack)
     }
 
     /// Constructs a multisig unlocking script
@@ -461,7 +1481,7 @@ impl Script {
                 .map(|e| StackEntry::Signature(*e))
                 .collect(),
         );
-        Self { stack }
+        Script::from_template(stack)
     }
 
     /// Constructs a multisig validation script
@@ -480,23 +1500,456 @@ impl Script {
         pub_keys: Vec<PublicKey>,
     ) -> Self {
         let mut stack = vec![StackEntry::Bytes(check_data)];
-        stack.append(
-            &mut signatures
-                .iter()
-                .map(|e| StackEntry::Signature(*e))
-                .collect(),
-        );
+        stack.extend(signatures.iter().map(|e| StackEntry::Signature(*e)));
         stack.push(StackEntry::Num(m));
-        stack.append(&mut pub_keys.iter().map(|e| StackEntry::PubKey(*e)).collect());
+        stack.extend(pub_keys.iter().map(|e| StackEntry::PubKey(*e)));
         stack.push(StackEntry::Num(n));
         stack.push(StackEntry::Op(OpCodes::OP_CHECKMULTISIG));
-        Self { stack }
+        Script::from_template(stack)
+    }
+
+    /// Iterates over the opcodes of the script in order, skipping data pushes
+    pub fn ops(&self) -> impl Iterator<Item = OpCodes> + '_ {
+        self.stack.iter().filter_map(|entry| match entry {
+            StackEntry::Op(op) => Some(op.clone()),
+            _ => None,
+        })
+    }
+
+    /// Checks whether the script holds an opcode anywhere, including untaken branches
+    ///
+    /// ### Arguments
+    ///
+    /// * `op`  - Opcode to look for
+    pub fn contains_op(&self, op: OpCodes) -> bool {
+        self.ops().any(|script_op| script_op == op)
     }
+
+    /// Iterates over the entries that execute, skipping those in conditional branches
+    /// that cannot be taken. See `ScriptActiveIter` for how conditions are resolved
+    pub fn active_iter(&self) -> ScriptActiveIter<'_> {
+        ScriptActiveIter {
+            entries: self.stack.iter(),
+            previous: None,
+            branches: Vec::new(),
+        }
+    }
+
+    /// Counts the opcodes that execute, skipping those in conditional branches
+    /// that cannot be taken
+    pub fn count_active_ops(&self) -> usize {
+        self.active_iter()
+            .filter(|entry| matches!(entry, StackEntry::Op(_)))
+            .count()
+    }
+
+    /// Returns the keys able to satisfy the script, if it follows one of the standard
+    /// templates. P2SH scripts are redeem scripts themselves, so this applies to them as is
+    pub fn required_signers(&self) -> SignerRequirements {
+        signer_requirements(&self.stack)
+    }
+
+    /// Prepends a check of the spending transaction's outputs to a script, failing the
+    /// script unless the check passes:
+    ///
+    /// * `Count(n)`:               `OP_TXOUTPUTCOUNT <n> OP_NUMEQUALVERIFY`
+    /// * `ValueEquals(i, a)`:      `<i> OP_TXOUTVALUE <a> OP_NUMEQUALVERIFY`
+    /// * `ValueAtLeast(i, a)`:     `<i> OP_TXOUTVALUE <a> OP_GREATERTHANOREQUAL OP_VERIFY`
+    ///
+    /// ### Arguments
+    ///
+    /// * `constraint`  - Check to prepend
+    pub fn with_output_constraint(self, constraint: TxOutConstraint) -> Self {
+        let num = |amount: TokenAmount| StackEntry::Num(amount.0 as usize);
+        let check = match constraint {
+            TxOutConstraint::Count(count) => vec![
+                StackEntry::Op(OpCodes::OP_TXOUTPUTCOUNT),
+                StackEntry::Num(count),
+                StackEntry::Op(OpCodes::OP_NUMEQUALVERIFY),
+            ],
+            TxOutConstraint::ValueEquals(index, amount) => vec![
+                StackEntry::Num(index),
+                StackEntry::Op(OpCodes::OP_TXOUTVALUE),
+                num(amount),
+                StackEntry::Op(OpCodes::OP_NUMEQUALVERIFY),
+            ],
+            TxOutConstraint::ValueAtLeast(index, amount) => vec![
+                StackEntry::Num(index),
+                StackEntry::Op(OpCodes::OP_TXOUTVALUE),
+                num(amount),
+                StackEntry::Op(OpCodes::OP_GREATERTHANOREQUAL),
+                StackEntry::Op(OpCodes::OP_VERIFY),
+            ],
+        };
+        Script::concat(Script::from_template(check), self)
+    }
+
+    /// Appends entries to the end of the script
+    ///
+    /// ### Arguments
+    ///
+    /// * `iter`  - Entries to append
+    pub fn extend(&mut self, iter: impl IntoIterator<Item = StackEntry>) {
+        self.stack.extend(iter);
+    }
+
+    /// Chains two scripts into one, with the entries of `a` followed by those of `b`
+    ///
+    /// ### Arguments
+    ///
+    /// * `a`  - First script
+    /// * `b`  - Script to append to `a`
+    pub fn concat(mut a: Script, b: Script) -> Script {
+        a.extend(b.stack);
+        a
+    }
+
+    /// Prepends a single entry to a script
+    ///
+    /// ### Arguments
+    ///
+    /// * `entry`  - Entry to prepend
+    /// * `rest`   - Script to follow `entry`
+    pub fn prefix(entry: StackEntry, rest: Script) -> Script {
+        Script::concat(Script::from(vec![entry]), rest)
+    }
+}
+
+/// Iterator over the executing entries of a script, starting from an empty condition stack
+///
+/// The condition of an `OP_IF` or `OP_NOTIF` is known when the entry executing right before
+/// it is a constant number. Other conditions depend on the stack at run time, so both of
+/// their branches are treated as active. Conditional opcodes are yielded whenever the block
+/// they belong to is active, as the interpreter always processes them there
+pub struct ScriptActiveIter<'a> {
+    entries: std::slice::Iter<'a, StackEntry>,
+    previous: Option<&'a StackEntry>,
+    branches: Vec<Option<bool>>,
+}
+
+impl ScriptActiveIter<'_> {
+    /// Checks whether no enclosing branch is known not to be taken
+    fn is_active(&self) -> bool {
+        self.branches.iter().all(|branch| *branch != Some(false))
+    }
+}
+
+impl<'a> Iterator for ScriptActiveIter<'a> {
+    type Item = &'a StackEntry;
+
+    fn next(&mut self) -> Option<&'a StackEntry> {
+        while let Some(entry) = self.entries.next() {
+            let active = match entry {
+                StackEntry::Op(op @ (OpCodes::OP_IF | OpCodes::OP_NOTIF)) => {
+                    let active = self.is_active();
+                    let cond = match active {
+                        true => constant_value(self.previous)
+                            .map(|n| (n != ZERO) == (*op == OpCodes::OP_IF)),
+                        false => Some(false),
+                    };
+                    self.branches.push(cond);
+                    active
+                }
+                StackEntry::Op(OpCodes::OP_ELSE) => {
+                    let top = self.branches.pop();
+                    let active = self.is_active();
+                    self.branches.extend(top.map(|cond| cond.map(|c| !c)));
+                    active
+                }
+                StackEntry::Op(OpCodes::OP_ENDIF) => {
+                    self.branches.pop();
+                    self.is_active()
+                }
+                _ => self.is_active(),
+            };
+            if active {
+                self.previous = Some(entry);
+                return Some(entry);
+            }
+        }
+        None
+    }
+}
+
+/// Returns the number an entry pushes if it is a constant
+///
+/// ### Arguments
+///
+/// * `entry`  - Entry to check
+fn constant_value(entry: Option<&StackEntry>) -> Option<usize> {
+    match entry? {
+        StackEntry::Num(n) => Some(*n),
+        StackEntry::Op(op) if (OpCodes::OP_0..=OpCodes::OP_16).contains(op) => {
+            Some(op.clone() as usize)
+        }
+        _ => None,
+    }
+}
+
+/// Key able to provide a signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signer {
+    PubKey(PublicKey),
+    Address(String),
+}
+
+/// Signatures needed to satisfy a script
+///
+/// * `Single`      - One signature by the given key
+/// * `Threshold`   - `m` signatures by distinct keys of `keys`
+/// * `Branched`    - The requirements of any one branch of a conditional, in script order
+/// * `Unknown`     - The script follows no standard template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignerRequirements {
+    Single(Signer),
+    Threshold { m: usize, keys: Vec<PublicKey> },
+    Branched(Vec<SignerRequirements>),
+    Unknown,
+}
+
+/// Detects the signer requirements of a standard template
+///
+/// ### Arguments
+///
+/// * `stack`  - Entries of the script, or of one branch of it
+fn signer_requirements(stack: &[StackEntry]) -> SignerRequirements {
+    use OpCodes::{OP_CHECKMULTISIG, OP_CHECKSIG, OP_CREATE, OP_DROP, OP_DUP, OP_EQUALVERIFY};
+    use StackEntry::{Bytes, Num, Op, PubKey};
+
+    if let Some(branches) = split_branches(stack) {
+        return SignerRequirements::Branched(
+            branches.into_iter().map(signer_requirements).collect(),
+        );
+    }
+
+    // Create scripts commit to their block number before the signature check
+    let stack = match stack {
+        [Op(OP_CREATE), Num(_), Op(OP_DROP), rest @ ..] => rest,
+        _ => stack,
+    };
+    let data_pushes = |entries: &[StackEntry]| entries.iter().all(is_data_push);
+
+    match stack {
+        // `Script::p2pkh_locking`, optionally preceded by its unlocking data
+        [data @ .., Op(OP_DUP), Op(hash), Bytes(addr), Op(OP_EQUALVERIFY), Op(OP_CHECKSIG)]
+            if is_hash256(hash) && data_pushes(data) =>
+        {
+            SignerRequirements::Single(Signer::Address(addr.clone()))
+        }
+        // `Script::member_multisig` and `Script::new_create_asset`
+        [data @ .., PubKey(pk), Op(OP_CHECKSIG)] if data_pushes(data) => {
+            SignerRequirements::Single(Signer::PubKey(*pk))
+        }
+        // `Script::multisig_lock` and `Script::multisig_validation`
+        [rest @ .., Num(n), Op(OP_CHECKMULTISIG)] => {
+            let keys: Vec<PublicKey> = rest
+                .iter()
+                .rev()
+                .map_while(|entry| match entry {
+                    PubKey(pk) => Some(*pk),
+                    _ => None,
+                })
+                .collect();
+            let split = rest.len().checked_sub(keys.len() + ONE);
+            match split.map(|i| rest.split_at(i)) {
+                Some((data, [Num(m), ..])) if keys.len() == *n && m <= n && data_pushes(data) => {
+                    SignerRequirements::Threshold {
+                        m: *m,
+                        keys: keys.into_iter().rev().collect(),
+                    }
+                }
+                _ => SignerRequirements::Unknown,
+            }
+        }
+        _ => SignerRequirements::Unknown,
+    }
+}
+
+/// Checks whether an opcode constructs an address from a public key, for any address version
+///
+/// ### Arguments
+///
+/// * `op`  - Opcode to check
+fn is_hash256(op: &OpCodes) -> bool {
+    matches!(
+        op,
+        OpCodes::OP_HASH256 | OpCodes::OP_HASH256_V0 | OpCodes::OP_HASH256_TEMP
+    )
+}
+
+/// Splits a script of the form `<data> OP_IF <a> OP_ELSE <b> OP_ENDIF` into its two
+/// branches, in script order. `OP_NOTIF` is split the same way
+///
+/// ### Arguments
+///
+/// * `stack`  - Entries of the script
+fn split_branches(stack: &[StackEntry]) -> Option<Vec<&[StackEntry]>> {
+    let start = stack.iter().position(|e| !is_data_push(e))?;
+    if !matches!(
+        stack[start],
+        StackEntry::Op(OpCodes::OP_IF | OpCodes::OP_NOTIF)
+    ) {
+        return None;
+    }
+
+    let mut depth = ZERO;
+    let mut else_index = None;
+    for (i, entry) in stack.iter().enumerate().skip(start + ONE) {
+        match entry {
+            StackEntry::Op(OpCodes::OP_IF | OpCodes::OP_NOTIF) => depth += ONE,
+            // A second `OP_ELSE` makes the branches non-standard
+            StackEntry::Op(OpCodes::OP_ELSE) if depth == ZERO && else_index.is_some() => {
+                return None
+            }
+            StackEntry::Op(OpCodes::OP_ELSE) if depth == ZERO => else_index = Some(i),
+            StackEntry::Op(OpCodes::OP_ENDIF) if depth == ZERO => {
+                // The conditional must close the script and have both branches
+                let else_index = else_index.filter(|_| i == stack.len() - ONE)?;
+                return Some(vec![
+                    &stack[start + ONE..else_index],
+                    &stack[else_index + ONE..i],
+                ]);
+            }
+            StackEntry::Op(OpCodes::OP_ENDIF) => depth -= ONE,
+            _ => {}
+        }
+    }
+    None
+}
+
+impl FromIterator<StackEntry> for Script {
+    /// Creates a new script from an iterator of entries
+    fn from_iter<I: IntoIterator<Item = StackEntry>>(iter: I) -> Self {
+        Script {
+            stack: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Checks whether an entry always pushes exactly one item when executed
+///
+/// ### Arguments
+///
+/// * `entry`  - Entry to check
+fn is_data_push(entry: &StackEntry) -> bool {
+    match entry {
+        StackEntry::Bytes(s) => s.len() <= MAX_SCRIPT_ITEM_SIZE,
+        StackEntry::Signature(_) | StackEntry::PubKey(_) | StackEntry::Num(_) => true,
+        StackEntry::Op(op) => (OpCodes::OP_0..=OpCodes::OP_16).contains(op),
+    }
+}
+
+/// Checks whether an entry, if it succeeds, leaves `Num(0)` or `Num(1)` on top of the stack
+///
+/// ### Arguments
+///
+/// * `entry`  - Entry to check
+fn leaves_bool(entry: &StackEntry) -> bool {
+    matches!(
+        entry,
+        StackEntry::Num(ZERO | ONE)
+            | StackEntry::Op(
+                OpCodes::OP_0 | OpCodes::OP_1 | OpCodes::OP_NOT | OpCodes::OP_0NOTEQUAL
+            )
+    )
 }
 
 impl From<Vec<StackEntry>> for Script {
-    /// Creates a new script with a pre-filled stack
+    /// Creates a new script with a pre-filled stack, without checking any limits.
+    /// Use `Script::try_from_iter` or `ScriptBuilder` for checked construction
     fn from(s: Vec<StackEntry>) -> Self {
         Script { stack: s }
     }
 }
+
+/// Running size of a script, checked against the limits as each entry is added.
+/// This is the single place script size and opcode count limits are enforced
+#[derive(Clone, Debug, Default)]
+struct ScriptSize {
+    len: usize,
+    ops_count: usize,
+}
+
+impl ScriptSize {
+    /// Adds an entry to the running size
+    ///
+    /// ### Arguments
+    ///
+    /// * `entry`    - Entry to add
+    /// * `context`  - Execution context holding the limits
+    fn add(&mut self, entry: &StackEntry, context: &ExecutionContext) -> Result<(), ScriptError> {
+        self.len += match entry {
+            StackEntry::Op(_) => ONE,
+            StackEntry::Signature(_) => ED25519_SIGNATURE_LEN,
+            StackEntry::PubKey(_) => ED25519_PUBLIC_KEY_LEN,
+            StackEntry::Bytes(s) => s.len(),
+            StackEntry::Num(_) => usize::BITS as usize / EIGHT,
+        };
+        if let StackEntry::Op(_) = entry {
+            self.ops_count += ONE;
+        }
+        if self.len > context.max_script_size {
+            error_max_script_size();
+            return Err(ScriptError::ScriptTooLarge(self.len));
+        }
+        if self.ops_count > MAX_OPS_PER_SCRIPT {
+            error_max_ops_script();
+            return Err(ScriptError::TooManyOps(self.ops_count));
+        }
+        Ok(())
+    }
+}
+
+/// Builds a script entry by entry, rejecting each entry that would break the limits
+/// of the execution context.
+///
+/// On top of the checks of `Script::is_valid`, data pushes larger than
+/// `max_script_item_size` are rejected, as they could never be pushed when executed
+#[derive(Clone, Debug, Default)]
+pub struct ScriptBuilder {
+    stack: Vec<StackEntry>,
+    size: ScriptSize,
+    context: ExecutionContext,
+}
+
+impl ScriptBuilder {
+    /// Creates a builder enforcing the default limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a builder enforcing the limits of `context`
+    ///
+    /// ### Arguments
+    ///
+    /// * `context`  - Execution context holding the limits
+    pub fn with_context(context: &ExecutionContext) -> Self {
+        Self {
+            context: context.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// Appends an entry, failing without appending it if a limit would be broken
+    ///
+    /// ### Arguments
+    ///
+    /// * `entry`  - Entry to append
+    pub fn push(&mut self, entry: StackEntry) -> Result<&mut Self, ScriptError> {
+        if let StackEntry::Bytes(s) = &entry {
+            if s.len() > self.context.max_script_item_size {
+                return Err(ScriptError::ItemTooLarge(s.len()));
+            }
+        }
+        let mut size = self.size.clone();
+        size.add(&entry, &self.context)?;
+        self.size = size;
+        self.stack.push(entry);
+        Ok(self)
+    }
+
+    /// Returns the script built so far
+    pub fn build(self) -> Script {
+        Script::from(self.stack)
+    }
+}
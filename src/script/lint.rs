@@ -0,0 +1,396 @@
+use crate::constants::LINT_MULTISIG_KEYS_THRESHOLD;
+use crate::script::lang::Script;
+use crate::script::{OpCodes, StackEntry};
+use std::fmt;
+
+/// Stable codes for lint findings, which wallets may use to suppress specific lints.
+///
+/// * `L001` - `UnreachableBranch`: a conditional whose condition is a constant push
+/// * `L002` - `RedundantSequence`: an opcode sequence with no effect (`OP_DUP OP_DROP`, `OP_NOT OP_NOT`)
+/// * `L003` - `DeprecatedOpcode`: an opcode kept only for legacy address versions
+/// * `L004` - `ReservedOpcode`: an opcode reserved for future upgrades
+/// * `L005` - `OversizedMultisig`: a multisig whose public key count makes it costly to validate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintCode {
+    UnreachableBranch,
+    RedundantSequence,
+    DeprecatedOpcode,
+    ReservedOpcode,
+    OversizedMultisig,
+}
+
+impl LintCode {
+    /// Returns the stable string code of the lint
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LintCode::UnreachableBranch => "L001",
+            LintCode::RedundantSequence => "L002",
+            LintCode::DeprecatedOpcode => "L003",
+            LintCode::ReservedOpcode => "L004",
+            LintCode::OversizedMultisig => "L005",
+        }
+    }
+}
+
+impl fmt::Display for LintCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Severity of a lint finding. No finding affects consensus validity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LintSeverity {
+    Info,
+    Warning,
+}
+
+/// A single non-consensus finding about a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    pub code: LintCode,
+    pub severity: LintSeverity,
+    pub index: usize,
+    pub message: String,
+    pub suggestion: String,
+}
+
+impl LintFinding {
+    fn new(
+        code: LintCode,
+        severity: LintSeverity,
+        index: usize,
+        message: String,
+        suggestion: &str,
+    ) -> Self {
+        Self {
+            code,
+            severity,
+            index,
+            message,
+            suggestion: suggestion.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at {}: {} ({})",
+            self.code, self.index, self.message, self.suggestion
+        )
+    }
+}
+
+/// Lints a script before broadcast, returning findings ordered by stack index.
+///
+/// Findings are advisory only and never make a script invalid.
+/// Push encodings are not linted, as pushes are typed `StackEntry` values rather than raw bytes
+///
+/// ### Arguments
+///
+/// * `script`  - Script to lint
+pub fn lint(script: &Script) -> Vec<LintFinding> {
+    let stack = &script.stack;
+    let mut findings = Vec::new();
+
+    for (index, entry) in stack.iter().enumerate() {
+        let next = stack.get(index + 1);
+
+        if let Some(finding) = lint_unreachable_branch(stack, index) {
+            findings.push(finding);
+        }
+
+        let op = match entry {
+            StackEntry::Op(op) => op,
+            _ => continue,
+        };
+
+        match (op, next) {
+            (OpCodes::OP_DUP, Some(StackEntry::Op(next @ OpCodes::OP_DROP)))
+            | (OpCodes::OP_NOT, Some(StackEntry::Op(next @ OpCodes::OP_NOT))) => {
+                findings.push(LintFinding::new(
+                    LintCode::RedundantSequence,
+                    LintSeverity::Info,
+                    index,
+                    format!("{op} followed by {next} has no effect"),
+                    "Remove both opcodes",
+                ))
+            }
+            _ => {}
+        }
+
+        match op {
+            OpCodes::OP_HASH256_V0 | OpCodes::OP_HASH256_TEMP => findings.push(LintFinding::new(
+                LintCode::DeprecatedOpcode,
+                LintSeverity::Warning,
+                index,
+                format!("{op} only exists for legacy address versions"),
+                "Use OP_HASH256 with a current address",
+            )),
+            OpCodes::OP_NOP1
+            | OpCodes::OP_NOP2
+            | OpCodes::OP_NOP3
+            | OpCodes::OP_NOP4
+            | OpCodes::OP_NOP5
+            | OpCodes::OP_NOP6
+            | OpCodes::OP_NOP7
+            | OpCodes::OP_NOP8
+            | OpCodes::OP_NOP9
+            | OpCodes::OP_NOP10 => findings.push(LintFinding::new(
+                LintCode::ReservedOpcode,
+                LintSeverity::Warning,
+                index,
+                format!("{op} is reserved and may change meaning in a future upgrade"),
+                "Remove the opcode",
+            )),
+            OpCodes::OP_CHECKMULTISIG | OpCodes::OP_CHECKMULTISIGVERIFY => {
+                if let Some(StackEntry::Num(n)) = index.checked_sub(1).map(|i| &stack[i]) {
+                    if *n > LINT_MULTISIG_KEYS_THRESHOLD {
+                        findings.push(LintFinding::new(
+                            LintCode::OversizedMultisig,
+                            LintSeverity::Info,
+                            index,
+                            format!("{op} with n={n} may require up to {n} signature checks"),
+                            "Reduce the number of public keys",
+                        ))
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    findings
+}
+
+/// Checks whether the conditional at `index` has a branch that can never execute,
+/// because its condition is a constant pushed directly before it
+///
+/// ### Arguments
+///
+/// * `stack`   - Script entries
+/// * `index`   - Index of the entry to check
+fn lint_unreachable_branch(stack: &[StackEntry], index: usize) -> Option<LintFinding> {
+    let op = match &stack[index] {
+        StackEntry::Op(op @ (OpCodes::OP_IF | OpCodes::OP_NOTIF)) => op,
+        _ => return None,
+    };
+    let condition = constant_condition(&stack[index.checked_sub(1)?])?;
+    let takes_if_branch = condition == (*op == OpCodes::OP_IF);
+
+    let (unreachable, suggestion) = if takes_if_branch {
+        find_matching_else(stack, index)?;
+        (
+            "OP_ELSE".to_string(),
+            "Remove the condition and the OP_ELSE branch",
+        )
+    } else {
+        (
+            op.to_string(),
+            "Remove the condition and the unreachable branch",
+        )
+    };
+
+    Some(LintFinding::new(
+        LintCode::UnreachableBranch,
+        LintSeverity::Warning,
+        index,
+        format!("{op} condition is constant, so its {unreachable} branch never executes"),
+        suggestion,
+    ))
+}
+
+/// Folds a constant push into the boolean it evaluates to as a condition
+///
+/// ### Arguments
+///
+/// * `entry`   - Entry to fold
+fn constant_condition(entry: &StackEntry) -> Option<bool> {
+    match entry {
+        StackEntry::Num(n) => Some(*n != 0),
+        StackEntry::Op(OpCodes::OP_0) => Some(false),
+        StackEntry::Op(
+            OpCodes::OP_1
+            | OpCodes::OP_2
+            | OpCodes::OP_3
+            | OpCodes::OP_4
+            | OpCodes::OP_5
+            | OpCodes::OP_6
+            | OpCodes::OP_7
+            | OpCodes::OP_8
+            | OpCodes::OP_9
+            | OpCodes::OP_10
+            | OpCodes::OP_11
+            | OpCodes::OP_12
+            | OpCodes::OP_13
+            | OpCodes::OP_14
+            | OpCodes::OP_15
+            | OpCodes::OP_16,
+        ) => Some(true),
+        _ => None,
+    }
+}
+
+/// Finds the `OP_ELSE` at the same nesting depth as the conditional at `index`
+///
+/// ### Arguments
+///
+/// * `stack`   - Script entries
+/// * `index`   - Index of the opening conditional
+fn find_matching_else(stack: &[StackEntry], index: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, entry) in stack.iter().enumerate().skip(index + 1) {
+        match entry {
+            StackEntry::Op(OpCodes::OP_IF | OpCodes::OP_NOTIF) => depth += 1,
+            StackEntry::Op(OpCodes::OP_ENDIF) if depth == 0 => return None,
+            StackEntry::Op(OpCodes::OP_ENDIF) => depth -= 1,
+            StackEntry::Op(OpCodes::OP_ELSE) if depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign_ed25519 as sign;
+
+    fn codes(stack: Vec<StackEntry>) -> Vec<(LintCode, usize)> {
+        lint(&Script::from(stack))
+            .into_iter()
+            .map(|f| (f.code, f.index))
+            .collect()
+    }
+
+    #[test]
+    /// Checks that constant conditions are reported as unreachable branches
+    fn test_lint_unreachable_branch() {
+        let if_false = vec![
+            StackEntry::Op(OpCodes::OP_0),
+            StackEntry::Op(OpCodes::OP_IF),
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+        ];
+        let if_true_else = vec![
+            StackEntry::Num(1),
+            StackEntry::Op(OpCodes::OP_IF),
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_ELSE),
+            StackEntry::Op(OpCodes::OP_2),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+        ];
+        let if_true = vec![
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_IF),
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+        ];
+        let if_dynamic = vec![
+            StackEntry::Op(OpCodes::OP_DEPTH),
+            StackEntry::Op(OpCodes::OP_IF),
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_ELSE),
+            StackEntry::Op(OpCodes::OP_2),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+        ];
+
+        assert_eq!(codes(if_false), vec![(LintCode::UnreachableBranch, 1)]);
+        assert_eq!(codes(if_true_else), vec![(LintCode::UnreachableBranch, 1)]);
+        assert_eq!(codes(if_true), vec![]);
+        assert_eq!(codes(if_dynamic), vec![]);
+    }
+
+    #[test]
+    /// Checks that opcode pairs without effect are reported
+    fn test_lint_redundant_sequence() {
+        let dup_drop = vec![
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_DUP),
+            StackEntry::Op(OpCodes::OP_DROP),
+        ];
+        let double_not = vec![
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_NOT),
+            StackEntry::Op(OpCodes::OP_NOT),
+        ];
+        let dup_add = vec![
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_DUP),
+            StackEntry::Op(OpCodes::OP_ADD),
+        ];
+
+        assert_eq!(codes(dup_drop), vec![(LintCode::RedundantSequence, 1)]);
+        assert_eq!(codes(double_not), vec![(LintCode::RedundantSequence, 1)]);
+        assert_eq!(codes(dup_add), vec![]);
+    }
+
+    #[test]
+    /// Checks that deprecated opcodes are reported
+    fn test_lint_deprecated_opcode() {
+        let deprecated = vec![
+            StackEntry::Bytes("a".to_string()),
+            StackEntry::Op(OpCodes::OP_HASH256_V0),
+        ];
+        let current = vec![
+            StackEntry::Bytes("a".to_string()),
+            StackEntry::Op(OpCodes::OP_HASH256),
+        ];
+
+        assert_eq!(codes(deprecated), vec![(LintCode::DeprecatedOpcode, 1)]);
+        assert_eq!(codes(current), vec![]);
+    }
+
+    #[test]
+    /// Checks that reserved opcodes are reported
+    fn test_lint_reserved_opcode() {
+        let reserved = vec![
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_NOP4),
+        ];
+        let nop = vec![
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_NOP),
+        ];
+
+        assert_eq!(codes(reserved), vec![(LintCode::ReservedOpcode, 1)]);
+        assert_eq!(codes(nop), vec![]);
+    }
+
+    #[test]
+    /// Checks that multisigs above the key threshold are reported
+    fn test_lint_oversized_multisig() {
+        let keys = |n| (0..n).map(|_| sign::gen_keypair().0).collect::<Vec<_>>();
+        let n = LINT_MULTISIG_KEYS_THRESHOLD + 1;
+        let oversized = Script::multisig_lock(1, n, "data".to_string(), keys(n));
+        let small = Script::multisig_lock(1, 2, "data".to_string(), keys(2));
+
+        assert_eq!(
+            codes(oversized.stack),
+            vec![(LintCode::OversizedMultisig, n + 3)]
+        );
+        assert_eq!(codes(small.stack), vec![]);
+    }
+
+    #[test]
+    /// Checks that the standard script templates lint clean
+    fn test_lint_standard_templates() {
+        let (pk, sk) = sign::gen_keypair();
+        let sig = sign::sign_detached(b"data", &sk);
+        let templates = vec![
+            Script::new_for_coinbase(0),
+            Script::new_create_asset(0, 0, "data".to_string(), sig, pk).unwrap(),
+            Script::pay2pkh("data".to_string(), sig, pk, None),
+            Script::member_multisig("data".to_string(), pk, sig),
+            Script::multisig_lock(1, 2, "data".to_string(), vec![pk, pk]),
+            Script::multisig_unlock("data".to_string(), vec![sig]),
+            Script::multisig_validation(1, 2, "data".to_string(), vec![sig], vec![pk, pk]),
+        ];
+
+        for script in templates {
+            assert_eq!(lint(&script), vec![], "{:?}", script.stack);
+        }
+    }
+}
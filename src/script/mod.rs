@@ -1,9 +1,16 @@
 #![allow(unused)]
+pub mod cost;
 pub mod interface_ops;
 pub mod lang;
+pub mod lint;
+pub mod wire;
 
+use crate::constants::{DUPLICATE_ELSE_FORK_HEIGHT, SOFT_FORK_HEIGHTS};
 use crate::crypto::sign_ed25519::{PublicKey, Signature};
+use crate::crypto::CryptoError;
+use crate::utils::transaction_utils::decode_lower_hex;
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 use std::fmt;
 
 /// Stack entry enum
@@ -16,9 +23,63 @@ pub enum StackEntry {
     Bytes(String),
 }
 
+impl StackEntry {
+    /// Creates a `PubKey` entry from raw public key bytes
+    ///
+    /// ### Arguments
+    ///
+    /// * `bytes`  - Public key bytes, `ED25519_PUBLIC_KEY_LEN` long
+    pub fn from_pubkey_bytes(bytes: &[u8]) -> Result<StackEntry, CryptoError> {
+        PublicKey::try_from(bytes).map(StackEntry::PubKey)
+    }
+
+    /// Creates a `Signature` entry from raw signature bytes
+    ///
+    /// ### Arguments
+    ///
+    /// * `bytes`  - Signature bytes, `ED25519_SIGNATURE_LEN` long
+    pub fn from_signature_bytes(bytes: &[u8]) -> Result<StackEntry, CryptoError> {
+        Signature::try_from(bytes).map(StackEntry::Signature)
+    }
+
+    /// Checks whether two entries are equal, additionally considering a `Bytes` entry
+    /// equal to a `Num` entry when its hex content decodes to the same big-endian number.
+    ///
+    /// Example: `Bytes("ff")`, `Bytes("00ff")` and `Num(255)` are all loosely equal
+    ///
+    /// ### Arguments
+    ///
+    /// * `other`  - Entry to compare against
+    pub fn loose_eq(&self, other: &StackEntry) -> bool {
+        match (self, other) {
+            (StackEntry::Num(n), StackEntry::Bytes(b))
+            | (StackEntry::Bytes(b), StackEntry::Num(n)) => hex_as_num(b) == Some(*n),
+            _ => self == other,
+        }
+    }
+}
+
+/// Decodes a canonical hex string as a big-endian number, if it fits in a `usize`
+///
+/// ### Arguments
+///
+/// * `s`  - Hex string to decode
+fn hex_as_num(s: &str) -> Option<usize> {
+    let bytes = decode_lower_hex(s).ok()?;
+    let significant: Vec<u8> = bytes.into_iter().skip_while(|b| *b == 0).collect();
+    if significant.len() > std::mem::size_of::<usize>() {
+        return None;
+    }
+    Some(
+        significant
+            .iter()
+            .fold(0, |acc, b| (acc << 8) | *b as usize),
+    )
+}
+
 /// Opcodes enum
 #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum OpCodes {
     // constants
     OP_0 = 0x00,
@@ -127,6 +188,13 @@ pub enum OpCodes {
     OP_NOP8 = 0xb7,
     OP_NOP9 = 0xb8,
     OP_NOP10 = 0xb9,
+    // introspection, declared last so the serialized form of earlier opcodes is unchanged
+    OP_TXOUTPUTCOUNT = 0xa8,
+    OP_TXOUTVALUE = 0xa9,
+    OP_TXINCOUNT = 0xaa,
+    // loose equality, declared last for the same reason
+    OP_LOOSEEQUAL = 0x66,
+    OP_LOOSEEQUALVERIFY = 0x67,
 }
 
 impl OpCodes {
@@ -137,6 +205,69 @@ impl OpCodes {
             OpCodes::OP_IF | OpCodes::OP_NOTIF | OpCodes::OP_ELSE | OpCodes::OP_ENDIF
         )
     }
+
+    /// Returns true if the opcode may be used in a create script
+    pub fn is_create_allowed(&self) -> bool {
+        matches!(
+            self,
+            OpCodes::OP_CREATE
+                | OpCodes::OP_DROP
+                | OpCodes::OP_CHECKSIG
+                | OpCodes::OP_CHECKSIGVERIFY
+                | OpCodes::OP_SHA3
+                | OpCodes::OP_HASH256
+        )
+    }
+}
+
+/// Returns whether an opcode is active at a block height, according to `SOFT_FORK_HEIGHTS`
+///
+/// ### Arguments
+///
+/// * `op`              - Opcode to check
+/// * `block_height`    - Height of the block the script is executed in
+pub fn opcode_is_activated(op: &OpCodes, block_height: u64) -> bool {
+    opcode_is_activated_in(SOFT_FORK_HEIGHTS, op, block_height)
+}
+
+/// Returns whether an opcode is active at a block height, according to `heights`
+///
+/// ### Arguments
+///
+/// * `heights`         - Activation heights of soft-forked opcodes
+/// * `op`              - Opcode to check
+/// * `block_height`    - Height of the block the script is executed in
+fn opcode_is_activated_in(
+    heights: &[(OpCodes, Option<u64>)],
+    op: &OpCodes,
+    block_height: u64,
+) -> bool {
+    match heights.iter().find(|(fork_op, _)| fork_op == op) {
+        Some((_, Some(height))) => block_height >= *height,
+        _ => true,
+    }
+}
+
+/// Returns whether a repeated `OP_ELSE` fails the script at a block height, according to
+/// `DUPLICATE_ELSE_FORK_HEIGHT`. As with soft-forked opcodes, scripts executed outside
+/// of a block follow the new rule
+///
+/// ### Arguments
+///
+/// * `block_height`    - Height of the block the script is executed in, if any
+pub fn duplicate_else_is_rejected(block_height: Option<u64>) -> bool {
+    duplicate_else_is_rejected_in(DUPLICATE_ELSE_FORK_HEIGHT, block_height)
+}
+
+/// Returns whether a repeated `OP_ELSE` fails the script at a block height, once
+/// `fork_height` is reached
+///
+/// ### Arguments
+///
+/// * `fork_height`     - Activation height of the rule
+/// * `block_height`    - Height of the block the script is executed in, if any
+fn duplicate_else_is_rejected_in(fork_height: u64, block_height: Option<u64>) -> bool {
+    block_height.is_none_or(|height| height >= fork_height)
 }
 
 /// Allows for string casting
@@ -145,3 +276,61 @@ impl fmt::Display for OpCodes {
         write!(f, "{self:?}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Checks that opcodes serialize as their baseline variant index, so that adding
+    /// an opcode can't change the encoding and hash of existing scripts
+    fn test_opcode_encoding_is_stable() {
+        let expected = [
+            (OpCodes::OP_0, 0u32),
+            (OpCodes::OP_EQUALVERIFY, 53),
+            (OpCodes::OP_1ADD, 54),
+            (OpCodes::OP_HASH256, 80),
+            (OpCodes::OP_CHECKSIG, 83),
+            (OpCodes::OP_CREATE, 87),
+            (OpCodes::OP_NOP10, 97),
+        ];
+
+        for (op, index) in expected {
+            let bytes = bincode::serialize(&op).unwrap();
+            assert_eq!(bytes, index.to_le_bytes(), "{}", op);
+        }
+    }
+
+    #[test]
+    /// Checks that opcodes activate exactly at their soft fork height
+    fn test_opcode_is_activated() {
+        let heights = [(OpCodes::OP_NOP1, Some(100)), (OpCodes::OP_NOP2, None)];
+
+        assert!(!opcode_is_activated_in(&heights, &OpCodes::OP_NOP1, 0));
+        assert!(!opcode_is_activated_in(&heights, &OpCodes::OP_NOP1, 99));
+        assert!(opcode_is_activated_in(&heights, &OpCodes::OP_NOP1, 100));
+        assert!(opcode_is_activated_in(
+            &heights,
+            &OpCodes::OP_NOP1,
+            u64::MAX
+        ));
+        assert!(opcode_is_activated_in(&heights, &OpCodes::OP_NOP2, 0));
+        assert!(opcode_is_activated_in(&heights, &OpCodes::OP_ADD, 0));
+
+        assert!(opcode_is_activated(&OpCodes::OP_HASH256_V0, 0));
+        assert!(opcode_is_activated(&OpCodes::OP_NOP10, 0));
+    }
+
+    #[test]
+    /// Checks that repeated `OP_ELSE` is only rejected from its fork height, or outside of
+    /// a block
+    fn test_duplicate_else_is_rejected() {
+        assert!(!duplicate_else_is_rejected_in(100, Some(0)));
+        assert!(!duplicate_else_is_rejected_in(100, Some(99)));
+        assert!(duplicate_else_is_rejected_in(100, Some(100)));
+        assert!(duplicate_else_is_rejected_in(100, None));
+
+        assert!(!duplicate_else_is_rejected(Some(0)));
+        assert!(duplicate_else_is_rejected(None));
+    }
+}
@@ -0,0 +1,243 @@
+use crate::script::lang::{Script, ScriptError};
+use crate::script::StackEntry;
+use std::convert::TryFrom;
+
+// Data pushes use opcode bytes above every `OpCodes` value, so the two never collide
+
+/// First opcode of the small push range, pushing 0 bytes
+pub const OP_PUSH_SMALL: u8 = 0xc0;
+/// Largest push whose length is encoded in the opcode itself
+pub const MAX_SMALL_PUSH: usize = 59;
+/// Push with a 1-byte length prefix
+pub const OP_PUSHDATA1: u8 = 0xfc;
+/// Push with a 2-byte little-endian length prefix
+pub const OP_PUSHDATA2: u8 = 0xfd;
+/// Push with a 4-byte little-endian length prefix
+pub const OP_PUSHDATA4: u8 = 0xfe;
+
+// Data entries are pushed with a leading tag byte giving their type, so that entries of
+// different types never share an encoding
+
+/// Tag of a pushed `Signature` entry, followed by its 64 bytes
+pub const ENTRY_TAG_SIGNATURE: u8 = 0x00;
+/// Tag of a pushed `PubKey` entry, followed by its 32 bytes
+pub const ENTRY_TAG_PUBKEY: u8 = 0x01;
+/// Tag of a pushed `Num` entry, followed by its value as 8 little-endian bytes
+pub const ENTRY_TAG_NUM: u8 = 0x02;
+/// Tag of a pushed `Bytes` entry, followed by the UTF-8 bytes of its text
+pub const ENTRY_TAG_BYTES: u8 = 0x03;
+
+/// Checks whether a byte starts a data push
+///
+/// ### Arguments
+///
+/// * `byte`  - First byte of an encoded entry
+pub fn is_push(byte: u8) -> bool {
+    (OP_PUSH_SMALL..=OP_PUSHDATA4).contains(&byte)
+}
+
+/// Encodes a data push with the smallest length prefix that fits
+///
+/// * up to `MAX_SMALL_PUSH` bytes: `OP_PUSH_SMALL + len`, then the data
+/// * up to `u8::MAX` bytes: `OP_PUSHDATA1`, 1 length byte, then the data
+/// * up to `u16::MAX` bytes: `OP_PUSHDATA2`, 2 length bytes, then the data
+/// * otherwise: `OP_PUSHDATA4`, 4 length bytes, then the data
+///
+/// ### Arguments
+///
+/// * `data`  - Bytes to push, at most `u32::MAX` long
+pub fn encode_push(data: &[u8]) -> Vec<u8> {
+    let len = data.len();
+    let mut encoded = Vec::with_capacity(len + 5);
+    if len <= MAX_SMALL_PUSH {
+        encoded.push(OP_PUSH_SMALL + len as u8);
+    } else if let Ok(len) = u8::try_from(len) {
+        encoded.push(OP_PUSHDATA1);
+        encoded.push(len);
+    } else if let Ok(len) = u16::try_from(len) {
+        encoded.push(OP_PUSHDATA2);
+        encoded.extend_from_slice(&len.to_le_bytes());
+    } else {
+        encoded.push(OP_PUSHDATA4);
+        encoded.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+    encoded.extend_from_slice(data);
+    encoded
+}
+
+/// Encodes a single script entry: an opcode as its byte, which is always below
+/// `OP_PUSH_SMALL`, and any other entry as one push of its tag and content
+///
+/// ### Arguments
+///
+/// * `entry`  - Entry to encode
+pub fn encode_entry(entry: &StackEntry) -> Vec<u8> {
+    let tagged = |tag: u8, content: &[u8]| encode_push(&[&[tag][..], content].concat());
+    match entry {
+        StackEntry::Op(op) => vec![op.clone() as u8],
+        StackEntry::Signature(signature) => tagged(ENTRY_TAG_SIGNATURE, signature.as_ref()),
+        StackEntry::PubKey(pub_key) => tagged(ENTRY_TAG_PUBKEY, pub_key.as_ref()),
+        StackEntry::Num(n) => tagged(ENTRY_TAG_NUM, &(*n as u64).to_le_bytes()),
+        StackEntry::Bytes(text) => tagged(ENTRY_TAG_BYTES, text.as_bytes()),
+    }
+}
+
+impl Script {
+    /// Encodes the script in the wire format: its entries, each encoded by
+    /// `encode_entry`, one after another. Unlike bincode, the encoding is defined here
+    /// and doesn't depend on how the entry types are laid out
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.stack.iter().flat_map(encode_entry).collect()
+    }
+}
+
+/// Decodes a data push from the start of `bytes`, returning the pushed data and the
+/// remaining bytes. Pushes not using the smallest encoding are rejected, so every
+/// script has exactly one encoding
+///
+/// ### Arguments
+///
+/// * `bytes`  - Encoded script, starting with a data push
+pub fn decode_push(bytes: &[u8]) -> Result<(&[u8], &[u8]), ScriptError> {
+    let (&op, rest) = bytes.split_first().ok_or(ScriptError::TruncatedPush)?;
+    let (len, min_len, rest) = match op {
+        OP_PUSHDATA1 => {
+            let (len, rest) = split_len::<1>(rest)?;
+            (len, MAX_SMALL_PUSH + 1, rest)
+        }
+        OP_PUSHDATA2 => {
+            let (len, rest) = split_len::<2>(rest)?;
+            (len, u8::MAX as usize + 1, rest)
+        }
+        OP_PUSHDATA4 => {
+            let (len, rest) = split_len::<4>(rest)?;
+            (len, u16::MAX as usize + 1, rest)
+        }
+        op if is_push(op) => ((op - OP_PUSH_SMALL) as usize, 0, rest),
+        op => return Err(ScriptError::NotAPush(op)),
+    };
+
+    if len < min_len {
+        return Err(ScriptError::NonMinimalPush);
+    }
+    if rest.len() < len {
+        return Err(ScriptError::TruncatedPush);
+    }
+    Ok(rest.split_at(len))
+}
+
+/// Splits an `N`-byte little-endian length prefix from the start of `bytes`
+///
+/// ### Arguments
+///
+/// * `bytes`  - Bytes starting with the length prefix
+fn split_len<const N: usize>(bytes: &[u8]) -> Result<(usize, &[u8]), ScriptError> {
+    if bytes.len() < N {
+        return Err(ScriptError::TruncatedPush);
+    }
+    let (prefix, rest) = bytes.split_at(N);
+    let len = prefix
+        .iter()
+        .rev()
+        .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+    Ok((len, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign_ed25519 as sign;
+    use crate::script::OpCodes;
+
+    #[test]
+    /// Checks that pushes use the smallest encoding and round-trip
+    fn test_encode_push() {
+        let cases = [
+            (0, vec![OP_PUSH_SMALL]),
+            (5, vec![OP_PUSH_SMALL + 5]),
+            (MAX_SMALL_PUSH, vec![OP_PUSH_SMALL + MAX_SMALL_PUSH as u8]),
+            (MAX_SMALL_PUSH + 1, vec![OP_PUSHDATA1, 60]),
+            (255, vec![OP_PUSHDATA1, 0xff]),
+            (256, vec![OP_PUSHDATA2, 0x00, 0x01]),
+            (65535, vec![OP_PUSHDATA2, 0xff, 0xff]),
+            (65536, vec![OP_PUSHDATA4, 0x00, 0x00, 0x01, 0x00]),
+        ];
+
+        for (len, prefix) in cases {
+            let data = vec![7u8; len];
+            let encoded = encode_push(&data);
+            assert_eq!(&encoded[..prefix.len()], &prefix[..], "len {}", len);
+            assert_eq!(encoded.len(), prefix.len() + len);
+
+            let mut with_rest = encoded.clone();
+            with_rest.push(0x01);
+            assert_eq!(
+                decode_push(&with_rest),
+                Ok((&data[..], &[0x01u8][..])),
+                "len {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    /// Checks that non-minimal and malformed pushes are rejected
+    fn test_decode_push_errors() {
+        let data = [1u8, 2, 3, 4, 5];
+        let pushdata4 = [&[OP_PUSHDATA4, 5, 0, 0, 0][..], &data].concat();
+        let pushdata1 = [&[OP_PUSHDATA1, 5][..], &data].concat();
+
+        assert_eq!(decode_push(&pushdata4), Err(ScriptError::NonMinimalPush));
+        assert_eq!(decode_push(&pushdata1), Err(ScriptError::NonMinimalPush));
+        assert_eq!(
+            decode_push(&[OP_PUSHDATA2, 0xff, 0x00]),
+            Err(ScriptError::NonMinimalPush)
+        );
+        assert_eq!(
+            decode_push(&[OP_PUSH_SMALL + 5, 1, 2]),
+            Err(ScriptError::TruncatedPush)
+        );
+        assert_eq!(
+            decode_push(&[OP_PUSHDATA2, 0x00]),
+            Err(ScriptError::TruncatedPush)
+        );
+        assert_eq!(decode_push(&[]), Err(ScriptError::TruncatedPush));
+        assert_eq!(decode_push(&[0x01]), Err(ScriptError::NotAPush(0x01)));
+        assert!(!is_push(0xff));
+    }
+
+    #[test]
+    /// Checks the wire encoding of each entry type, and that entries with the same
+    /// content but different types are encoded differently
+    fn test_script_to_bytes() {
+        let (pk, sk) = sign::gen_keypair();
+        let signature = sign::sign_detached(b"data", &sk);
+        let script = Script::from(vec![
+            StackEntry::Num(1),
+            StackEntry::Bytes("ab".to_owned()),
+            StackEntry::PubKey(pk),
+            StackEntry::Signature(signature),
+            StackEntry::Op(OpCodes::OP_CHECKSIG),
+        ]);
+        let expected = [
+            &[OP_PUSH_SMALL + 9, ENTRY_TAG_NUM, 1, 0, 0, 0, 0, 0, 0, 0][..],
+            &[OP_PUSH_SMALL + 3, ENTRY_TAG_BYTES, b'a', b'b'],
+            &[OP_PUSH_SMALL + 33, ENTRY_TAG_PUBKEY],
+            pk.as_ref(),
+            &[OP_PUSHDATA1, 65, ENTRY_TAG_SIGNATURE],
+            signature.as_ref(),
+            &[OpCodes::OP_CHECKSIG as u8],
+        ]
+        .concat();
+        assert_eq!(script.to_bytes(), expected);
+
+        let num = Script::from(vec![StackEntry::Num(0x6261)]);
+        let bytes = Script::from(vec![StackEntry::Bytes("ab".to_owned())]);
+        let op = Script::from(vec![StackEntry::Op(OpCodes::OP_1)]);
+        assert_ne!(num.to_bytes(), bytes.to_bytes());
+        assert_ne!(
+            op.to_bytes(),
+            Script::from(vec![StackEntry::Num(1)]).to_bytes()
+        );
+    }
+}
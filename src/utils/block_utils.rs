@@ -0,0 +1,553 @@
+//! Assembly of block templates from mempool transactions.
+//!
+//! Transactions are selected greedily by fee rate. A transaction spending the output
+//! of another mempool transaction waits until that parent has been selected, so
+//! parents always come before their children in the template. Each selected
+//! transaction is validated against, then applied to, a working copy of the UTXO set,
+//! which keeps conflicting transactions out of the same template.
+
+use crate::constants::MAX_PUB_KEYS_PER_MULTISIG;
+use crate::primitives::asset::TokenAmount;
+use crate::primitives::transaction::Transaction;
+use crate::script::lang::Script;
+use crate::script::{OpCodes, StackEntry};
+use crate::utils::fee_utils::tx_fee;
+use crate::utils::script_utils::TxValidationError;
+use crate::utils::transaction_utils::construct_tx_hash;
+use crate::utils::utxo_set::{UtxoSet, UtxoSetError};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Limits a block's transactions must fit in
+///
+/// * `max_size`    - Maximum total serialized size of the transactions, in bytes
+/// * `max_sig_ops` - Maximum total signature operations of the transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockLimits {
+    pub max_size: usize,
+    pub max_sig_ops: usize,
+}
+
+/// Reasons a mempool transaction was left out of a block template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExclusionReason {
+    Invalid(TxValidationError),
+    Unapplicable(UtxoSetError),
+    MissingParent(String),
+    ExceedsSize,
+    ExceedsSigOps,
+}
+
+impl fmt::Display for ExclusionReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Invalid(e) => write!(f, "Invalid transaction: {e}"),
+            Self::Unapplicable(e) => write!(f, "Can't be applied: {e}"),
+            Self::MissingParent(tx_hash) => {
+                write!(f, "Parent transaction {tx_hash} was not selected")
+            }
+            Self::ExceedsSize => write!(f, "Doesn't fit in the remaining block size"),
+            Self::ExceedsSigOps => {
+                write!(f, "Doesn't fit in the remaining signature operations")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExclusionReason {}
+
+/// Reasons the transactions of a block aren't valid together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockTxError {
+    Invalid(usize, TxValidationError),
+    Unapplicable(usize, UtxoSetError),
+    ExceedsSize(usize),
+    ExceedsSigOps(usize),
+}
+
+impl fmt::Display for BlockTxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Invalid(idx, e) => write!(f, "Transaction {idx}: {e}"),
+            Self::Unapplicable(idx, e) => write!(f, "Transaction {idx}: {e}"),
+            Self::ExceedsSize(idx) => {
+                write!(f, "Transaction {idx} exceeds the block size limit")
+            }
+            Self::ExceedsSigOps(idx) => {
+                write!(
+                    f,
+                    "Transaction {idx} exceeds the block signature operation limit"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for BlockTxError {}
+
+/// Transactions selected for a block, in the order they must appear in it
+///
+/// * `transactions`    - Selected transactions, parents before children
+/// * `total_fees`      - Sum of the `Token` fees of the selected transactions
+/// * `total_size`      - Sum of the serialized sizes of the selected transactions
+/// * `total_sig_ops`   - Sum of the signature operations of the selected transactions
+/// * `excluded`        - Mempool transactions left out, by hash, with the reason why
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockTemplate {
+    pub transactions: Vec<Transaction>,
+    pub total_fees: TokenAmount,
+    pub total_size: usize,
+    pub total_sig_ops: usize,
+    pub excluded: BTreeMap<String, ExclusionReason>,
+}
+
+/// Counts the signature operations of a transaction's input scripts. A multisig check
+/// counts as many operations as it has public keys, or the maximum number of keys when
+/// the count isn't pushed right before it
+///
+/// ### Arguments
+///
+/// * `tx`  - Transaction to count the signature operations of
+pub fn tx_sig_ops(tx: &Transaction) -> usize {
+    tx.inputs
+        .iter()
+        .map(|tx_in| script_sig_ops(&tx_in.script_signature))
+        .sum()
+}
+
+/// Counts the signature operations of a script
+///
+/// ### Arguments
+///
+/// * `script`  - Script to count the signature operations of
+fn script_sig_ops(script: &Script) -> usize {
+    let mut previous: Option<&StackEntry> = None;
+    let mut count = 0;
+    for entry in &script.stack {
+        count += match entry {
+            StackEntry::Op(OpCodes::OP_CHECKSIG | OpCodes::OP_CHECKSIGVERIFY) => 1,
+            StackEntry::Op(OpCodes::OP_CHECKMULTISIG | OpCodes::OP_CHECKMULTISIGVERIFY) => {
+                match previous {
+                    Some(StackEntry::Num(n)) => (*n).min(MAX_PUB_KEYS_PER_MULTISIG),
+                    _ => MAX_PUB_KEYS_PER_MULTISIG,
+                }
+            }
+            _ => 0,
+        };
+        previous = Some(entry);
+    }
+    count
+}
+
+/// Compares the fee rates of two transactions without rounding, highest first
+///
+/// ### Arguments
+///
+/// * `a`   - Fee and size of the first transaction
+/// * `b`   - Fee and size of the second transaction
+fn cmp_fee_rate_desc(a: (TokenAmount, usize), b: (TokenAmount, usize)) -> Ordering {
+    let a_rate = a.0 .0 as u128 * b.1 as u128;
+    let b_rate = b.0 .0 as u128 * a.1 as u128;
+    b_rate.cmp(&a_rate)
+}
+
+/// Selects mempool transactions into a block template fitting `limits`.
+///
+/// The transaction with the highest fee rate among those whose mempool parents have all
+/// been selected is considered next, until none is left. Transactions which don't fit the
+/// remaining limits, aren't valid against the outputs selected so far, or have a parent
+/// which was left out, are reported in `excluded`. The template always passes
+/// `validate_block_transactions` for the same limits, UTXO set and block number
+///
+/// ### Arguments
+///
+/// * `mempool`         - Candidate transactions, in any order
+/// * `limits`          - Limits the template must fit in
+/// * `utxo`            - UTXO set the block builds on
+/// * `block_number`    - Number of the block being assembled
+pub fn assemble_block_template(
+    mempool: &[Transaction],
+    limits: &BlockLimits,
+    utxo: &UtxoSet,
+    block_number: u64,
+) -> BlockTemplate {
+    let hashes: Vec<String> = mempool.iter().map(construct_tx_hash).collect();
+    let sizes: Vec<usize> = mempool.iter().map(Transaction::get_total_size).collect();
+    let fees: Vec<TokenAmount> = mempool.iter().map(tx_fee).collect();
+    let in_mempool: BTreeSet<&str> = hashes.iter().map(String::as_str).collect();
+
+    let mut pending: Vec<usize> = (0..mempool.len()).collect();
+    pending.sort_by(|&a, &b| {
+        cmp_fee_rate_desc((fees[a], sizes[a]), (fees[b], sizes[b])).then(a.cmp(&b))
+    });
+
+    let mut working = utxo.clone();
+    let mut selected = BTreeSet::new();
+    let mut template = BlockTemplate::default();
+
+    // Parents of a transaction in the mempool which haven't been selected yet
+    let unselected_parents = |idx: usize, selected: &BTreeSet<String>| -> Vec<String> {
+        mempool[idx]
+            .inputs
+            .iter()
+            .filter_map(|tx_in| tx_in.previous_out.as_ref())
+            .map(|out_point| &out_point.t_hash)
+            .filter(|t_hash| **t_hash != hashes[idx] && in_mempool.contains(t_hash.as_str()))
+            .filter(|t_hash| !selected.contains(*t_hash))
+            .cloned()
+            .collect()
+    };
+
+    loop {
+        // Highest fee rate transaction not waiting on a pending parent
+        let next = pending.iter().position(|&idx| {
+            unselected_parents(idx, &selected)
+                .iter()
+                .all(|parent| template.excluded.contains_key(parent))
+        });
+        let position = match next {
+            Some(position) => position,
+            None => break,
+        };
+        let idx = pending.remove(position);
+        let tx = &mempool[idx];
+        let tx_hash = hashes[idx].clone();
+        let sig_ops = tx_sig_ops(tx);
+
+        let exclusion = if let Some(parent) = unselected_parents(idx, &selected).pop() {
+            Some(ExclusionReason::MissingParent(parent))
+        } else if template.total_size + sizes[idx] > limits.max_size {
+            Some(ExclusionReason::ExceedsSize)
+        } else if template.total_sig_ops + sig_ops > limits.max_sig_ops {
+            Some(ExclusionReason::ExceedsSigOps)
+        } else if let Err(e) = working.validate(tx, block_number) {
+            Some(ExclusionReason::Invalid(e))
+        } else if let Err(e) = working.apply_transaction(tx, block_number) {
+            Some(ExclusionReason::Unapplicable(e))
+        } else {
+            None
+        };
+
+        match exclusion {
+            Some(reason) => {
+                template.excluded.entry(tx_hash).or_insert(reason);
+            }
+            None => {
+                template.transactions.push(tx.clone());
+                template.total_fees += fees[idx];
+                template.total_size += sizes[idx];
+                template.total_sig_ops += sig_ops;
+                selected.insert(tx_hash);
+            }
+        }
+    }
+
+    // Only transactions waiting on each other can be left, which hashing rules out
+    for idx in pending {
+        let parent = unselected_parents(idx, &selected).pop().unwrap_or_default();
+        let reason = ExclusionReason::MissingParent(parent);
+        template
+            .excluded
+            .entry(hashes[idx].clone())
+            .or_insert(reason);
+    }
+
+    template
+}
+
+/// Checks that the transactions of a block fit `limits` and are valid in order, each
+/// against the outputs left by the ones before it
+///
+/// ### Arguments
+///
+/// * `txs`             - Transactions of the block, in block order
+/// * `limits`          - Limits the transactions must fit in
+/// * `utxo`            - UTXO set the block builds on
+/// * `block_number`    - Number of the block holding `txs`
+pub fn validate_block_transactions(
+    txs: &[Transaction],
+    limits: &BlockLimits,
+    utxo: &UtxoSet,
+    block_number: u64,
+) -> Result<(), BlockTxError> {
+    let mut working = utxo.clone();
+    let mut total_size = 0;
+    let mut total_sig_ops = 0;
+
+    for (idx, tx) in txs.iter().enumerate() {
+        total_size += tx.get_total_size();
+        if total_size > limits.max_size {
+            return Err(BlockTxError::ExceedsSize(idx));
+        }
+
+        total_sig_ops += tx_sig_ops(tx);
+        if total_sig_ops > limits.max_sig_ops {
+            return Err(BlockTxError::ExceedsSigOps(idx));
+        }
+
+        working
+            .validate(tx, block_number)
+            .map_err(|e| BlockTxError::Invalid(idx, e))?;
+        working
+            .apply_transaction(tx, block_number)
+            .map_err(|e| BlockTxError::Unapplicable(idx, e))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign_ed25519::{self as sign, PublicKey, SecretKey};
+    use crate::primitives::asset::Asset;
+    use crate::primitives::transaction::{OutPoint, TxIn, TxOut};
+    use crate::utils::script_utils::InputError;
+    use crate::utils::transaction_utils::{construct_address, construct_payment_tx, ReceiverInfo};
+
+    const NO_LIMITS: BlockLimits = BlockLimits {
+        max_size: usize::MAX,
+        max_sig_ops: usize::MAX,
+    };
+
+    /// Set holding one output per amount owned by `owner`, from a made up transaction hash
+    fn funded_set(owner: &PublicKey, amounts: &[u64]) -> (UtxoSet, Vec<OutPoint>) {
+        let out_points: Vec<_> = (0..amounts.len())
+            .map(|n| OutPoint::new("g0".to_owned(), n as i32))
+            .collect();
+        let utxo = out_points
+            .iter()
+            .zip(amounts)
+            .map(|(o, a)| {
+                let address = construct_address(owner);
+                (
+                    o.clone(),
+                    TxOut::new_token_amount(address, TokenAmount(*a), None),
+                )
+            })
+            .collect();
+        (UtxoSet::new(utxo, 10), out_points)
+    }
+
+    /// Signed transaction paying `amount` from `previous_out` back to `owner`, less `fee`
+    fn pay(
+        owner: &(PublicKey, SecretKey),
+        previous_out: &OutPoint,
+        amount: u64,
+        fee: u64,
+    ) -> Transaction {
+        let key_material = BTreeMap::from([(previous_out.clone(), owner.clone())]);
+        let tx_ins = vec![TxIn::new_from_input(previous_out.clone(), Script::new())];
+        let address = construct_address(&owner.0);
+        let receiver = ReceiverInfo {
+            address: address.clone(),
+            asset: Asset::token_u64(amount - fee),
+        };
+        let fee = ReceiverInfo {
+            address,
+            asset: Asset::token_u64(fee),
+        };
+        construct_payment_tx(tx_ins, receiver, Some(fee), 0, &key_material).unwrap()
+    }
+
+    /// Outpoint of the single output of `tx`
+    fn output_of(tx: &Transaction) -> OutPoint {
+        OutPoint::new(construct_tx_hash(tx), 0)
+    }
+
+    /// Assembles a template, checking that it validates against the same inputs
+    fn assemble(mempool: &[Transaction], limits: &BlockLimits, utxo: &UtxoSet) -> BlockTemplate {
+        let template = assemble_block_template(mempool, limits, utxo, 1);
+        let validation = validate_block_transactions(&template.transactions, limits, utxo, 1);
+        assert_eq!(validation, Ok(()));
+        assert_eq!(
+            template.transactions.len() + template.excluded.len(),
+            mempool.len()
+        );
+        template
+    }
+
+    #[test]
+    /// Checks that transactions are selected by fee rate, with chains ordered parents first
+    fn test_assemble_dependency_order() {
+        let owner = sign::gen_keypair();
+        let (utxo, out_points) = funded_set(&owner.0, &[1000, 1000]);
+        let grandparent = pay(&owner, &out_points[0], 1000, 1);
+        let parent = pay(&owner, &output_of(&grandparent), 999, 2);
+        let child = pay(&owner, &output_of(&parent), 997, 50);
+        let unrelated = pay(&owner, &out_points[1], 1000, 10);
+
+        let mempool = [
+            child.clone(),
+            unrelated.clone(),
+            parent.clone(),
+            grandparent.clone(),
+        ];
+        let template = assemble(&mempool, &NO_LIMITS, &utxo);
+
+        assert_eq!(
+            template.transactions,
+            vec![unrelated, grandparent, parent, child]
+        );
+        assert_eq!(template.total_fees, TokenAmount(63));
+        assert_eq!(
+            template.total_size,
+            mempool
+                .iter()
+                .map(Transaction::get_total_size)
+                .sum::<usize>()
+        );
+        assert_eq!(template.total_sig_ops, 4);
+        assert!(template.excluded.is_empty());
+    }
+
+    #[test]
+    /// Checks that a child is never selected without its parent, whatever its fee
+    fn test_assemble_child_without_parent() {
+        let owner = sign::gen_keypair();
+        let (utxo, out_points) = funded_set(&owner.0, &[1000, 1000]);
+        let parent = pay(&owner, &out_points[0], 1000, 1);
+        let child = pay(&owner, &output_of(&parent), 999, 500);
+        let filler = pay(&owner, &out_points[1], 1000, 2);
+
+        // The parent doesn't fit once the better paying filler is selected
+        let limits = BlockLimits {
+            max_size: filler.get_total_size() + parent.get_total_size() - 1,
+            ..NO_LIMITS
+        };
+        let mempool = [child.clone(), parent.clone(), filler.clone()];
+        let template = assemble(&mempool, &limits, &utxo);
+
+        assert_eq!(template.transactions, vec![filler]);
+        assert_eq!(
+            template.excluded,
+            BTreeMap::from([
+                (construct_tx_hash(&parent), ExclusionReason::ExceedsSize),
+                (
+                    construct_tx_hash(&child),
+                    ExclusionReason::MissingParent(construct_tx_hash(&parent))
+                ),
+            ])
+        );
+
+        // A child whose parent isn't in the mempool is checked against the UTXO set alone
+        let template = assemble(std::slice::from_ref(&child), &NO_LIMITS, &utxo);
+        assert!(template.transactions.is_empty());
+        assert_eq!(
+            template.excluded[&construct_tx_hash(&child)],
+            ExclusionReason::Invalid(TxValidationError::Input(
+                0,
+                InputError::NotInUtxo(output_of(&parent))
+            ))
+        );
+    }
+
+    #[test]
+    /// Checks that size and signature operation limits are respected at the boundary
+    fn test_assemble_limits_boundary() {
+        let owner = sign::gen_keypair();
+        let (utxo, out_points) = funded_set(&owner.0, &[1000, 1000]);
+        let first = pay(&owner, &out_points[0], 1000, 20);
+        let second = pay(&owner, &out_points[1], 1000, 10);
+        let mempool = [first.clone(), second.clone()];
+        let both_size = first.get_total_size() + second.get_total_size();
+
+        let limits = BlockLimits {
+            max_size: both_size,
+            ..NO_LIMITS
+        };
+        let template = assemble(&mempool, &limits, &utxo);
+        assert_eq!(template.transactions, mempool.to_vec());
+        assert_eq!(template.total_size, both_size);
+
+        let limits = BlockLimits {
+            max_size: both_size - 1,
+            ..NO_LIMITS
+        };
+        let template = assemble(&mempool, &limits, &utxo);
+        assert_eq!(template.transactions, vec![first.clone()]);
+        assert_eq!(
+            template.excluded[&construct_tx_hash(&second)],
+            ExclusionReason::ExceedsSize
+        );
+
+        let limits = BlockLimits {
+            max_sig_ops: 2,
+            ..NO_LIMITS
+        };
+        let template = assemble(&mempool, &limits, &utxo);
+        assert_eq!(template.transactions.len(), 2);
+        assert_eq!(template.total_sig_ops, 2);
+
+        let limits = BlockLimits {
+            max_sig_ops: 1,
+            ..NO_LIMITS
+        };
+        let template = assemble(&mempool, &limits, &utxo);
+        assert_eq!(template.transactions, vec![first]);
+        assert_eq!(
+            template.excluded[&construct_tx_hash(&second)],
+            ExclusionReason::ExceedsSigOps
+        );
+        assert_eq!(
+            validate_block_transactions(&mempool, &limits, &utxo, 1),
+            Err(BlockTxError::ExceedsSigOps(1))
+        );
+    }
+
+    #[test]
+    /// Checks that only the better paying of two conflicting transactions is selected
+    fn test_assemble_conflicts() {
+        let owner = sign::gen_keypair();
+        let (utxo, out_points) = funded_set(&owner.0, &[1000]);
+        let low = pay(&owner, &out_points[0], 1000, 5);
+        let high = pay(&owner, &out_points[0], 1000, 30);
+        let low_child = pay(&owner, &output_of(&low), 995, 100);
+
+        let mempool = [low.clone(), low_child.clone(), high.clone()];
+        let template = assemble(&mempool, &NO_LIMITS, &utxo);
+
+        assert_eq!(template.transactions, vec![high.clone()]);
+        assert_eq!(
+            template.excluded[&construct_tx_hash(&low)],
+            ExclusionReason::Invalid(TxValidationError::Input(
+                0,
+                InputError::SpentBy {
+                    out_point: out_points[0].clone(),
+                    tx_hash: construct_tx_hash(&high),
+                }
+            ))
+        );
+        assert_eq!(
+            template.excluded[&construct_tx_hash(&low_child)],
+            ExclusionReason::MissingParent(construct_tx_hash(&low))
+        );
+        assert!(matches!(
+            validate_block_transactions(&[high, low], &NO_LIMITS, &utxo, 1),
+            Err(BlockTxError::Invalid(1, _))
+        ));
+    }
+
+    #[test]
+    /// Checks signature operation counts of input scripts
+    fn test_tx_sig_ops() {
+        let owner = sign::gen_keypair();
+        let (_, out_points) = funded_set(&owner.0, &[1000]);
+        assert_eq!(tx_sig_ops(&pay(&owner, &out_points[0], 1000, 1)), 1);
+
+        let multisig = |n: Option<usize>| {
+            let mut stack: Vec<_> = n.map(StackEntry::Num).into_iter().collect();
+            stack.push(StackEntry::Op(OpCodes::OP_CHECKMULTISIG));
+            let mut tx = Transaction::new();
+            tx.inputs = vec![TxIn::new_from_input(out_points[0].clone(), stack.into())];
+            tx
+        };
+        assert_eq!(tx_sig_ops(&multisig(Some(3))), 3);
+        assert_eq!(
+            tx_sig_ops(&multisig(Some(MAX_PUB_KEYS_PER_MULTISIG + 1))),
+            MAX_PUB_KEYS_PER_MULTISIG
+        );
+        assert_eq!(tx_sig_ops(&multisig(None)), MAX_PUB_KEYS_PER_MULTISIG);
+    }
+}
@@ -0,0 +1,593 @@
+use crate::constants::{
+    METADATA_COMMITMENT_DOMAIN, METADATA_COMMITMENT_PREFIX, UTXO_LEAF_DOMAIN, UTXO_NODE_DOMAIN,
+};
+use crate::crypto::sha3_256;
+use crate::primitives::asset::{Asset, ItemAsset};
+use crate::primitives::transaction::{OutPoint, Transaction, TxOut};
+use crate::utils::transaction_utils::{construct_tx_hash, decode_lower_hex, UtxoMap};
+use bincode::serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fmt;
+
+/// Commitment to private item metadata, stored on chain in place of the plaintext
+#[derive(Deserialize, Serialize, Debug, Copy, Clone, Eq, PartialEq, PartialOrd, Ord)]
+pub struct MetadataCommitment(pub [u8; 32]);
+
+impl MetadataCommitment {
+    /// Parses a commitment from the `metadata` field of an item,
+    /// returning `None` if the metadata is plaintext
+    ///
+    /// ### Arguments
+    ///
+    /// * `metadata` - Item metadata
+    pub fn from_metadata(metadata: &str) -> Option<Self> {
+        let hash = metadata.strip_prefix(METADATA_COMMITMENT_PREFIX)?;
+        let bytes = decode_lower_hex(hash).ok()?;
+        Some(Self(bytes.try_into().ok()?))
+    }
+
+    /// Serializes the commitment for the `metadata` field of an item
+    pub fn to_metadata(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for MetadataCommitment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{METADATA_COMMITMENT_PREFIX}{}", hex::encode(self.0))
+    }
+}
+
+/// Off-chain reveal of committed item metadata, verifiable by anyone
+/// holding the create transaction
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct MetadataRevealProof {
+    pub genesis_hash: String,
+    pub plaintext: Vec<u8>,
+    pub salt: [u8; 32],
+    pub commitment: MetadataCommitment,
+}
+
+impl MetadataRevealProof {
+    /// Checks that the proof is about the item created by `create_tx`, with the genesis
+    /// hash and commitment it holds, and that the revealed plaintext and salt match the
+    /// commitment
+    ///
+    /// ### Arguments
+    ///
+    /// * `create_tx`   - Item create transaction holding the commitment
+    pub fn verify(&self, create_tx: &Transaction) -> bool {
+        created_item_commitment(create_tx)
+            .is_some_and(|created| created == (self.genesis_hash.clone(), self.commitment))
+            && verify_metadata_commitment(&self.plaintext, &self.salt, &self.commitment)
+    }
+}
+
+/// Commits to item metadata, hashing it with a salt so that the plaintext
+/// can't be guessed from the commitment
+///
+/// ### Arguments
+///
+/// * `plaintext`   - Private metadata
+/// * `salt`        - Random salt, kept alongside the plaintext for the reveal
+pub fn commit_metadata(plaintext: &[u8], salt: &[u8; 32]) -> MetadataCommitment {
+    let parts = vec![METADATA_COMMITMENT_DOMAIN, &salt[..], plaintext];
+    MetadataCommitment(sha3_256::digest_all(parts.into_iter()).into())
+}
+
+/// Verifies that item metadata and salt match a commitment
+///
+/// ### Arguments
+///
+/// * `plaintext`   - Revealed metadata
+/// * `salt`        - Revealed salt
+/// * `commitment`  - Commitment to check against
+pub fn verify_metadata_commitment(
+    plaintext: &[u8],
+    salt: &[u8; 32],
+    commitment: &MetadataCommitment,
+) -> bool {
+    commit_metadata(plaintext, salt) == *commitment
+}
+
+/// Gets the metadata commitment of an item, if its metadata is committed
+///
+/// ### Arguments
+///
+/// * `item`    - Item asset
+pub fn item_metadata_commitment(item: &ItemAsset) -> Option<MetadataCommitment> {
+    MetadataCommitment::from_metadata(item.metadata.as_ref()?)
+}
+
+/// Gets the genesis hash and metadata commitment of the item created by `create_tx`,
+/// if its metadata is committed. Items without a genesis hash take the hash of their
+/// create transaction
+///
+/// ### Arguments
+///
+/// * `create_tx`   - Item create transaction
+fn created_item_commitment(create_tx: &Transaction) -> Option<(String, MetadataCommitment)> {
+    create_tx
+        .outputs
+        .iter()
+        .find_map(|tx_out| match &tx_out.value {
+            Asset::Item(item) => {
+                let commitment = item_metadata_commitment(item)?;
+                let genesis_hash = match &item.genesis_hash {
+                    Some(genesis_hash) => genesis_hash.clone(),
+                    None => construct_tx_hash(create_tx),
+                };
+                Some((genesis_hash, commitment))
+            }
+            _ => None,
+        })
+}
+
+/// Builds a reveal proof for the item created by `create_tx`,
+/// returning `None` if it holds no commitment matching `plaintext` and `salt`
+///
+/// ### Arguments
+///
+/// * `create_tx`       - Item create transaction holding the commitment
+/// * `plaintext`       - Private metadata
+/// * `salt`            - Salt used for the commitment
+pub fn construct_metadata_reveal_proof(
+    create_tx: &Transaction,
+    plaintext: Vec<u8>,
+    salt: [u8; 32],
+) -> Option<MetadataRevealProof> {
+    let (genesis_hash, commitment) = created_item_commitment(create_tx)?;
+    let proof = MetadataRevealProof {
+        genesis_hash,
+        plaintext,
+        salt,
+        commitment,
+    };
+    proof.verify(create_tx).then_some(proof)
+}
+
+/// Merkle tree over the entries of a UTXO set, in outpoint order, committing to the whole
+/// set with a single root. Entries can be proven against the root with a `UtxoProof`
+/// holding one hash per level of the tree
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UtxoCommitmentTree {
+    out_points: Vec<OutPoint>,
+    levels: Vec<Vec<[u8; 32]>>,
+}
+
+impl UtxoCommitmentTree {
+    /// Builds the tree of a UTXO set
+    ///
+    /// ### Arguments
+    ///
+    /// * `utxo`    - UTXO set to commit to
+    pub fn new(utxo: &UtxoMap) -> Self {
+        let out_points = utxo.keys().cloned().collect();
+        let mut levels = vec![utxo
+            .iter()
+            .map(|(out_point, tx_out)| utxo_leaf_hash(out_point, tx_out))
+            .collect::<Vec<_>>()];
+        while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+            // An unpaired last node is promoted to the next level as is
+            let next = level
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => utxo_node_hash(left, right),
+                    _ => pair[0],
+                })
+                .collect();
+            levels.push(next);
+        }
+        Self { out_points, levels }
+    }
+
+    /// Hex encoded root of the tree. The root of an empty set is all zeros
+    pub fn root(&self) -> String {
+        let root = self.levels.last().and_then(|level| level.first());
+        hex::encode(root.copied().unwrap_or_default())
+    }
+
+    /// Builds the proof that an outpoint is in the set, if it is
+    ///
+    /// ### Arguments
+    ///
+    /// * `out_point`   - Outpoint to prove
+    pub fn prove(&self, out_point: &OutPoint) -> Option<UtxoProof> {
+        let index = self.out_points.binary_search(out_point).ok()?;
+        let siblings = self
+            .levels
+            .iter()
+            .enumerate()
+            .filter_map(|(height, level)| level.get((index >> height) ^ 1).copied())
+            .collect();
+        Some(UtxoProof {
+            index,
+            leaf_count: self.out_points.len(),
+            siblings,
+        })
+    }
+}
+
+/// Proof that an entry is part of a committed UTXO set
+///
+/// * `index`       - Position of the entry in the set, in outpoint order
+/// * `leaf_count`  - Number of entries in the set
+/// * `siblings`    - Hashes paired with the entry's branch, from the leaves up
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct UtxoProof {
+    pub index: usize,
+    pub leaf_count: usize,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+/// Commits to a UTXO set, returning the hex encoded root of its `UtxoCommitmentTree`
+///
+/// ### Arguments
+///
+/// * `utxo`    - UTXO set to commit to
+pub fn construct_utxo_commitment(utxo: &UtxoMap) -> String {
+    UtxoCommitmentTree::new(utxo).root()
+}
+
+/// Verifies that an entry is part of the UTXO set committed to by `root`
+///
+/// ### Arguments
+///
+/// * `root`        - Hex encoded commitment to the UTXO set
+/// * `out_point`   - Outpoint of the entry
+/// * `tx_out`      - Output of the entry
+/// * `proof`       - Proof of the entry against `root`
+pub fn verify_utxo_proof(
+    root: &str,
+    out_point: &OutPoint,
+    tx_out: &TxOut,
+    proof: &UtxoProof,
+) -> bool {
+    if proof.index >= proof.leaf_count {
+        return false;
+    }
+    let mut siblings = proof.siblings.iter();
+    let (mut index, mut count) = (proof.index, proof.leaf_count);
+    let mut hash = utxo_leaf_hash(out_point, tx_out);
+    while count > 1 {
+        if index % 2 == 1 || index + 1 < count {
+            let sibling = match siblings.next() {
+                Some(sibling) => sibling,
+                None => return false,
+            };
+            hash = if index % 2 == 1 {
+                utxo_node_hash(sibling, &hash)
+            } else {
+                utxo_node_hash(&hash, sibling)
+            };
+        }
+        index /= 2;
+        count = count.div_ceil(2);
+    }
+    siblings.next().is_none() && hex::encode(hash) == root
+}
+
+/// Hashes an entry of a UTXO set into a leaf of its commitment
+///
+/// ### Arguments
+///
+/// * `out_point`   - Outpoint of the entry
+/// * `tx_out`      - Output of the entry
+fn utxo_leaf_hash(out_point: &OutPoint, tx_out: &TxOut) -> [u8; 32] {
+    let entry = serialize(&(out_point, tx_out)).unwrap_or_default();
+    let parts = vec![UTXO_LEAF_DOMAIN, &entry];
+    sha3_256::digest_all(parts.into_iter()).into()
+}
+
+/// Hashes two nodes of a UTXO set commitment into their parent
+///
+/// ### Arguments
+///
+/// * `left`    - Left child
+/// * `right`   - Right child
+fn utxo_node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let parts = vec![UTXO_NODE_DOMAIN, &left[..], &right[..]];
+    sha3_256::digest_all(parts.into_iter()).into()
+}
+
+/// A proof that doesn't verify against the committed UTXO set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidUtxoProof {
+    pub out_point: OutPoint,
+}
+
+impl fmt::Display for InvalidUtxoProof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Outpoint {} isn't proven to be in the committed UTXO set",
+            self.out_point
+        )
+    }
+}
+
+impl std::error::Error for InvalidUtxoProof {}
+
+/// UTXO entries proven against a committed UTXO set, allowing light nodes to validate
+/// transactions without holding the full set. `get` can be passed as the `is_in_utxo`
+/// of `tx_is_valid`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ProofBackedUtxoProvider {
+    root: String,
+    entries: UtxoMap,
+}
+
+impl ProofBackedUtxoProvider {
+    /// Creates a provider from entries and their proofs, failing on the first proof
+    /// that doesn't verify against `root`
+    ///
+    /// ### Arguments
+    ///
+    /// * `root`    - Hex encoded commitment to the UTXO set
+    /// * `proofs`  - Entries of the set with their proofs
+    pub fn from_proofs(
+        root: String,
+        proofs: BTreeMap<OutPoint, (TxOut, UtxoProof)>,
+    ) -> Result<Self, InvalidUtxoProof> {
+        let mut entries = UtxoMap::new();
+        for (out_point, (tx_out, proof)) in proofs {
+            if !verify_utxo_proof(&root, &out_point, &tx_out, &proof) {
+                return Err(InvalidUtxoProof { out_point });
+            }
+            entries.insert(out_point, tx_out);
+        }
+        Ok(Self { root, entries })
+    }
+
+    /// Hex encoded commitment the entries are proven against
+    pub fn root(&self) -> &str {
+        &self.root
+    }
+
+    /// Gets the proven output of an outpoint
+    ///
+    /// ### Arguments
+    ///
+    /// * `out_point`   - Outpoint to look up
+    pub fn get(&self, out_point: &OutPoint) -> Option<&TxOut> {
+        self.entries.get(out_point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::MAX_METADATA_BYTES;
+    use crate::crypto::sign_ed25519 as sign;
+    use crate::primitives::asset::TokenAmount;
+    use crate::primitives::transaction::GenesisTxHashSpec;
+    use crate::utils::script_utils::{tx_has_valid_create_script, tx_is_valid};
+    use crate::utils::test_utils::generate_tx_with_ins_and_outs_assets;
+    use crate::utils::transaction_utils::{construct_item_create_tx_committed, construct_tx_hash};
+
+    const SALT: [u8; 32] = [7; 32];
+
+    #[test]
+    /// Checks that a commitment verifies only against its own plaintext and salt
+    fn test_commit_verify_metadata() {
+        let plaintext = b"serial: 0042, owner: ACME";
+        let commitment = commit_metadata(plaintext, &SALT);
+
+        assert!(verify_metadata_commitment(plaintext, &SALT, &commitment));
+        assert!(!verify_metadata_commitment(
+            b"serial: 0043, owner: ACME",
+            &SALT,
+            &commitment
+        ));
+        assert!(!verify_metadata_commitment(
+            plaintext,
+            &[8; 32],
+            &commitment
+        ));
+        assert_eq!(
+            MetadataCommitment::from_metadata(&commitment.to_metadata()),
+            Some(commitment)
+        );
+    }
+
+    #[test]
+    /// Checks that plaintext metadata isn't mistaken for a commitment
+    fn test_metadata_commitment_from_metadata() {
+        let hash = hex::encode([1; 32]);
+
+        assert!(MetadataCommitment::from_metadata(&format!("commit:{hash}")).is_some());
+        assert!(MetadataCommitment::from_metadata(&hash).is_none());
+        assert!(MetadataCommitment::from_metadata("commit:").is_none());
+        assert!(MetadataCommitment::from_metadata(&format!("commit:{hash}00")).is_none());
+        assert!(MetadataCommitment::from_metadata("commit:not hex").is_none());
+        let upper = hash.replace('1', "A");
+        assert!(MetadataCommitment::from_metadata(&format!("commit:{upper}")).is_none());
+    }
+
+    #[test]
+    /// Checks that the on-chain commitment size doesn't depend on the plaintext size
+    fn test_metadata_commitment_constant_size() {
+        let sizes: Vec<usize> = vec![0, 1, 100, MAX_METADATA_BYTES * 10]
+            .into_iter()
+            .map(|len| commit_metadata(&vec![0; len], &SALT).to_metadata().len())
+            .collect();
+
+        assert!(sizes.iter().all(|size| *size == sizes[0]));
+        assert!(sizes[0] < MAX_METADATA_BYTES);
+    }
+
+    #[test]
+    /// Checks that a committed item create transaction is valid and its metadata revealable
+    fn test_construct_item_create_tx_committed() {
+        let (pk, sk) = sign::gen_keypair();
+        let plaintext = vec![1; MAX_METADATA_BYTES * 2];
+        let tx = construct_item_create_tx_committed(
+            0,
+            0,
+            pk,
+            &sk,
+            1,
+            GenesisTxHashSpec::Create,
+            None,
+            &plaintext,
+            &SALT,
+        )
+        .unwrap();
+
+        assert!(tx.is_create_tx());
+        assert!(tx_has_valid_create_script(
+            &tx.inputs[0].script_signature,
+            &tx.outputs[0].value
+        ));
+
+        let proof = construct_metadata_reveal_proof(&tx, plaintext.clone(), SALT).unwrap();
+        assert!(proof.verify(&tx));
+        assert_eq!(proof.genesis_hash, construct_tx_hash(&tx));
+        assert_eq!(proof.commitment, commit_metadata(&plaintext, &SALT));
+        assert!(construct_metadata_reveal_proof(&tx, plaintext, [8; 32]).is_none());
+
+        // A proof claiming another item's genesis hash doesn't verify
+        let forged = MetadataRevealProof {
+            genesis_hash: construct_tx_hash(&Transaction::new()),
+            ..proof.clone()
+        };
+        assert!(!forged.verify(&tx));
+
+        // Nor does a proof checked against another create transaction
+        let other_tx = construct_item_create_tx_committed(
+            0,
+            0,
+            pk,
+            &sk,
+            1,
+            GenesisTxHashSpec::Default,
+            None,
+            &proof.plaintext,
+            &SALT,
+        )
+        .unwrap();
+        assert!(!proof.verify(&other_tx));
+        let other_proof = construct_metadata_reveal_proof(&other_tx, proof.plaintext, SALT);
+        assert_eq!(
+            other_proof.map(|proof| proof.genesis_hash),
+            GenesisTxHashSpec::Default.get_genesis_hash()
+        );
+    }
+
+    /// Builds a UTXO set of `len` token outputs
+    fn utxo_set(len: usize) -> UtxoMap {
+        (0..len)
+            .map(|n| {
+                let out_point = OutPoint::new(format!("g{n:031x}"), n as i32 % 3);
+                let tx_out =
+                    TxOut::new_token_amount(format!("{n:064x}"), TokenAmount(n as u64), None);
+                (out_point, tx_out)
+            })
+            .collect()
+    }
+
+    #[test]
+    /// Checks that every entry of a UTXO set is proven with a logarithmic proof
+    fn test_utxo_proofs_verify() {
+        for len in [1, 2, 3, 7, 1000] {
+            let utxo = utxo_set(len);
+            let tree = UtxoCommitmentTree::new(&utxo);
+            let root = construct_utxo_commitment(&utxo);
+            assert_eq!(tree.root(), root);
+
+            for (out_point, tx_out) in &utxo {
+                let proof = tree.prove(out_point).unwrap();
+                assert!(proof.siblings.len() <= len.next_power_of_two().trailing_zeros() as usize);
+                assert!(verify_utxo_proof(&root, out_point, tx_out, &proof));
+            }
+        }
+
+        let empty = UtxoCommitmentTree::new(&UtxoMap::new());
+        assert_eq!(empty.root(), hex::encode([0; 32]));
+        assert_eq!(empty.prove(&OutPoint::new("g0".to_owned(), 0)), None);
+    }
+
+    #[test]
+    /// Checks that proofs fail for tampered entries, proofs and roots
+    fn test_utxo_proofs_tampered() {
+        let utxo = utxo_set(1000);
+        let tree = UtxoCommitmentTree::new(&utxo);
+        let root = tree.root();
+        let (out_point, tx_out) = utxo.iter().nth(500).unwrap();
+        let proof = tree.prove(out_point).unwrap();
+        assert!(verify_utxo_proof(&root, out_point, tx_out, &proof));
+
+        let mut tampered_out = tx_out.clone();
+        tampered_out.value = Asset::token_u64(1_000_000);
+        assert!(!verify_utxo_proof(&root, out_point, &tampered_out, &proof));
+        let mut locked_out = tx_out.clone();
+        locked_out.locktime = 10;
+        assert!(!verify_utxo_proof(&root, out_point, &locked_out, &proof));
+
+        let (other_point, _) = utxo.iter().nth(501).unwrap();
+        assert!(!verify_utxo_proof(&root, other_point, tx_out, &proof));
+
+        let other_root = construct_utxo_commitment(&utxo_set(999));
+        assert!(!verify_utxo_proof(&other_root, out_point, tx_out, &proof));
+
+        let wrong_index = UtxoProof {
+            index: 501,
+            ..proof.clone()
+        };
+        let short = UtxoProof {
+            siblings: proof.siblings[1..].to_vec(),
+            ..proof.clone()
+        };
+        let long = UtxoProof {
+            siblings: [proof.siblings.clone(), vec![[0; 32]]].concat(),
+            ..proof.clone()
+        };
+        let out_of_range = UtxoProof {
+            index: 1000,
+            ..proof.clone()
+        };
+        for bad_proof in [wrong_index, short, long, out_of_range] {
+            assert!(!verify_utxo_proof(&root, out_point, tx_out, &bad_proof));
+        }
+    }
+
+    #[test]
+    /// Checks that a transaction validates against UTXOs proven by a light client
+    fn test_tx_is_valid_with_proven_utxo() {
+        let (tx_utxo, tx) = generate_tx_with_ins_and_outs_assets(
+            &[
+                (5, None, None),
+                (3, None, None),
+                (1, Some("genesis_a"), None),
+            ],
+            &[(8, None), (1, Some("genesis_a"))],
+        );
+        let mut utxo = utxo_set(1000);
+        utxo.extend(tx_utxo.clone());
+        let tree = UtxoCommitmentTree::new(&utxo);
+
+        let proofs: BTreeMap<_, _> = tx_utxo
+            .iter()
+            .map(|(out_point, tx_out)| {
+                let proof = tree.prove(out_point).unwrap();
+                (out_point.clone(), (tx_out.clone(), proof))
+            })
+            .collect();
+        let provider = ProofBackedUtxoProvider::from_proofs(tree.root(), proofs.clone()).unwrap();
+        assert_eq!(provider.root(), tree.root());
+        assert!(tx.inputs.len() > 1);
+        assert!(tx_is_valid(&tx, 0, |v| provider.get(v)).is_ok());
+
+        // Entries whose proofs don't verify are rejected
+        let mut tampered = proofs;
+        let (out_point, (tx_out, _)) = tampered.iter_mut().next().unwrap();
+        tx_out.value = Asset::token_u64(100);
+        let out_point = out_point.clone();
+        assert_eq!(
+            ProofBackedUtxoProvider::from_proofs(tree.root(), tampered),
+            Err(InvalidUtxoProof { out_point })
+        );
+    }
+}
@@ -1,12 +1,47 @@
-use tracing::info;
+use tracing::{info, warn};
+
+use crate::constants::{DRUID_LENGTH, DRUID_PREPEND, MIN_DDE_PARTICIPANTS};
 
 use crate::primitives::asset::Asset;
-use crate::primitives::druid::DruidExpectation;
+use crate::primitives::druid::{DruidExpectation, ExpectationTerms, OutputConstraint};
 use crate::primitives::transaction::Transaction;
-use crate::utils::transaction_utils::construct_tx_ins_address;
-use std::collections::BTreeSet;
+use crate::utils::script_utils::ValidationContext;
+use crate::utils::transaction_utils::{
+    construct_tx_ins_address_at_version, generate_prefixed_hex, is_lower_hex,
+};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::iter::Extend;
 
+/// Generates a DRUID from `rng`: `DRUID_PREPEND` followed by 31 lowercase hex
+/// characters, `DRUID_LENGTH` characters in total and carrying 124 bits of entropy
+///
+/// ### Arguments
+///
+/// * `rng`  - Source of randomness
+pub fn generate_druid(rng: &mut impl RngCore) -> String {
+    generate_prefixed_hex(DRUID_PREPEND, DRUID_LENGTH, rng)
+}
+
+/// Generates a DRUID from OS randomness. See `generate_druid` for its format
+pub fn random_druid() -> String {
+    generate_druid(&mut OsRng)
+}
+
+/// Checks whether a string has the format of a generated DRUID
+///
+/// ### Arguments
+///
+/// * `s`  - String to check
+pub fn is_druid(s: &str) -> bool {
+    s.len() == DRUID_LENGTH && s.as_bytes()[0] == DRUID_PREPEND && is_lower_hex(&s[1..])
+}
+
+/// Source of a DRUID transaction output: input address, output address and asset
+type TxSource<'a> = (String, &'a String, &'a Asset);
+
 /// Verifies that all DDE transaction expectations are met for DRUID-matching transactions
 ///
 /// ### Arguments
@@ -16,14 +51,94 @@ use std::iter::Extend;
 pub fn druid_expectations_are_met<'a>(
     druid: &str,
     transactions: impl Iterator<Item = &'a Transaction>,
+) -> bool {
+    let context = ValidationContext::default();
+    expectations_are_met(druid, transactions, &BTreeMap::new(), None, &context)
+}
+
+/// Verifies that all DDE transaction expectations are met for DRUID-matching transactions,
+/// also requiring outputs meeting expectations to be spendable at the current block
+///
+/// ### Arguments
+///
+/// * `druid`                   - DRUID to match all transactions on
+/// * `transactions`            - Transactions to verify
+/// * `current_block_number`    - Current block number
+pub fn druid_expectations_are_met_strict<'a>(
+    druid: &str,
+    transactions: impl Iterator<Item = &'a Transaction>,
+    current_block_number: u64,
+) -> bool {
+    let context = ValidationContext::default();
+    expectations_are_met(
+        druid,
+        transactions,
+        &BTreeMap::new(),
+        Some(current_block_number),
+        &context,
+    )
+}
+
+/// Verifies that all DDE transaction expectations are met for DRUID-matching transactions,
+/// under the terms the participants agreed for them
+///
+/// ### Arguments
+///
+/// * `druid`           - DRUID to match all transactions on
+/// * `transactions`    - Transactions to verify
+/// * `terms`           - Terms agreed for expectations. Expectations without any have none
+pub fn druid_expectations_are_met_with_terms<'a>(
+    druid: &str,
+    transactions: impl Iterator<Item = &'a Transaction>,
+    terms: &BTreeMap<DruidExpectation, ExpectationTerms>,
+) -> bool {
+    let context = ValidationContext::default();
+    druid_expectations_are_met_in_context(druid, transactions, terms, &context)
+}
+
+/// Verifies DDE transaction expectations as `druid_expectations_are_met_with_terms` does,
+/// under the policy of `context`
+///
+/// ### Arguments
+///
+/// * `druid`           - DRUID to match all transactions on
+/// * `transactions`    - Transactions to verify
+/// * `terms`           - Terms agreed for expectations. Expectations without any have none
+/// * `context`         - Validation policy to apply
+pub fn druid_expectations_are_met_in_context<'a>(
+    druid: &str,
+    transactions: impl Iterator<Item = &'a Transaction>,
+    terms: &BTreeMap<DruidExpectation, ExpectationTerms>,
+    context: &ValidationContext,
+) -> bool {
+    expectations_are_met(druid, transactions, terms, None, context)
+}
+
+/// Verifies DDE transaction expectations, with outputs locked beyond the `max_locktime`
+/// of an expectation's terms, or `default_max_locktime` if it has none, not meeting it
+///
+/// ### Arguments
+///
+/// * `druid`                   - DRUID to match all transactions on
+/// * `transactions`            - Transactions to verify
+/// * `terms`                   - Terms agreed for expectations
+/// * `default_max_locktime`    - Locktime limit for expectations without their own
+/// * `context`                 - Validation policy to apply
+fn expectations_are_met<'a>(
+    druid: &str,
+    transactions: impl Iterator<Item = &'a Transaction>,
+    terms: &BTreeMap<DruidExpectation, ExpectationTerms>,
+    default_max_locktime: Option<u64>,
+    context: &ValidationContext,
 ) -> bool {
     let mut expects = BTreeSet::new();
-    let mut tx_source = BTreeSet::new();
+    let mut tx_source = BTreeMap::new();
+    let mut druid_txs = Vec::new();
 
     for tx in transactions {
         info!("");
         if let Some(druid_info) = &tx.druid_info {
-            let ins = construct_tx_ins_address(&tx.inputs);
+            let ins = construct_tx_ins_address_at_version(&tx.inputs, tx.version);
 
             // Ensure match with passed DRUID
             if druid_info.druid == druid {
@@ -32,28 +147,175 @@ pub fn druid_expectations_are_met<'a>(
 
                 info!("Expectations: {:?}", expects);
 
+                // Keep the earliest unlocking output for each source
                 for out in &tx.outputs {
                     if let Some(pk) = &out.script_public_key {
-                        tx_source.insert((ins.clone(), pk, &out.value));
+                        tx_source
+                            .entry((ins.clone(), pk, &out.value))
+                            .and_modify(|l: &mut u64| *l = (*l).min(out.locktime))
+                            .or_insert(out.locktime);
                     }
                 }
                 info!("Tx Source: {:?}", tx_source);
+                druid_txs.push((ins, tx));
             }
         }
         info!("");
     }
 
-    expects.iter().all(|e| expectation_met(e, &tx_source))
+    if !participants_are_met(&druid_txs) {
+        return false;
+    }
+
+    // Malformed expectations are only logged during the hash namespace grace period
+    for e in &expects {
+        if let Err(err) = e.validate() {
+            if !context.hash_namespace_grace {
+                return false;
+            }
+            warn!("{err}");
+        }
+    }
+
+    let no_terms = ExpectationTerms::default();
+    expects.iter().all(|e| {
+        let terms = terms.get(*e).unwrap_or(&no_terms);
+        let max_locktime = terms.max_locktime.or(default_max_locktime);
+        expectation_met(e, &tx_source, max_locktime) && output_constraint_met(e, terms, &druid_txs)
+    })
 }
 
-/// Predicate for expected transaction presence in the transaction set
+/// Checks that every DRUID transaction declares the same number of participants, at least
+/// `MIN_DDE_PARTICIPANTS`, and that each participant sent its transaction
+///
+/// ### Arguments
+///
+/// * `druid_txs`   - DRUID-matching transactions, with the address of their inputs
+fn participants_are_met(druid_txs: &[(String, &Transaction)]) -> bool {
+    let count = druid_txs.len();
+    if count < MIN_DDE_PARTICIPANTS {
+        warn!("DDE needs {MIN_DDE_PARTICIPANTS} participants, {count} transactions found");
+        return false;
+    }
+    druid_txs.iter().all(|(_, tx)| {
+        let participants = tx.druid_info.as_ref().map_or(0, |info| info.participants);
+        if participants != count {
+            warn!("DDE declares {participants} participants, {count} transactions found");
+            return false;
+        }
+        true
+    })
+}
+
+/// Checks the output constraint of an expectation's terms, if any, against every DRUID
+/// transaction from its sender holding an output that meets it
 ///
 /// ### Arguments
 ///
 /// * `e`           - The expectation to check on
-/// * `tx_source`   - The source transaction source to match against
-fn expectation_met(e: &DruidExpectation, tx_source: &BTreeSet<(String, &String, &Asset)>) -> bool {
-    tx_source.get(&(e.from.clone(), &e.to, &e.asset)).is_some()
+/// * `terms`       - Terms agreed for the expectation
+/// * `druid_txs`   - DRUID-matching transactions, with the address of their inputs
+fn output_constraint_met(
+    e: &DruidExpectation,
+    terms: &ExpectationTerms,
+    druid_txs: &[(String, &Transaction)],
+) -> bool {
+    druid_txs
+        .iter()
+        .filter(|(ins, tx)| *ins == e.from && pays_expectation(e, tx))
+        .all(|(_, tx)| match check_output_constraint(e, terms, tx) {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("{err}");
+                false
+            }
+        })
+}
+
+/// Whether a transaction holds an output paying the expected asset to the expected address
+fn pays_expectation(e: &DruidExpectation, tx: &Transaction) -> bool {
+    tx.outputs
+        .iter()
+        .any(|out| out.script_public_key.as_ref() == Some(&e.to) && out.value == e.asset)
+}
+
+/// Reasons a transaction breaks the output constraint of an expectation it pays
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputConstraintError {
+    ThirdPartyOutput {
+        index: usize,
+        address: Option<String>,
+    },
+}
+
+impl fmt::Display for OutputConstraintError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ThirdPartyOutput { index, address } => write!(
+                f,
+                "Output {index} pays the expected asset class to third party {address:?}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OutputConstraintError {}
+
+/// Checks a transaction paying an expectation against the output constraint of the
+/// expectation's terms. Terms without a constraint accept any transaction
+///
+/// ### Arguments
+///
+/// * `e`       - The expectation whose constraint to check
+/// * `terms`   - Terms agreed for the expectation
+/// * `tx`      - Transaction holding the output meeting the expectation
+pub fn check_output_constraint(
+    e: &DruidExpectation,
+    terms: &ExpectationTerms,
+    tx: &Transaction,
+) -> Result<(), OutputConstraintError> {
+    match &terms.output_constraint {
+        None => Ok(()),
+        Some(OutputConstraint::ExclusiveAssetClass { change }) => {
+            for (index, out) in tx.outputs.iter().enumerate() {
+                let address = out.script_public_key.as_ref();
+                let allowed = address == Some(&e.to) || address == change.as_ref();
+                if !allowed && is_same_asset_class(&out.value, &e.asset) {
+                    return Err(OutputConstraintError::ThirdPartyOutput {
+                        index,
+                        address: address.cloned(),
+                    });
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Whether two assets are of the same class: both tokens, or items of the same genesis hash
+fn is_same_asset_class(a: &Asset, b: &Asset) -> bool {
+    match (a, b) {
+        (Asset::Item(a), Asset::Item(b)) => a.genesis_hash == b.genesis_hash,
+        _ => !a.is_item() && !b.is_item(),
+    }
+}
+
+/// Predicate for expected transaction presence in the transaction set
+///
+/// ### Arguments
+///
+/// * `e`               - The expectation to check on
+/// * `tx_source`       - The source transactions, with their locktime, to match against
+/// * `max_locktime`    - Latest block height the satisfying output may be locked to
+fn expectation_met(
+    e: &DruidExpectation,
+    tx_source: &BTreeMap<TxSource, u64>,
+    max_locktime: Option<u64>,
+) -> bool {
+    match tx_source.get(&(e.from.clone(), &e.to, &e.asset)) {
+        Some(locktime) => max_locktime.is_none_or(|max| *locktime <= max),
+        None => false,
+    }
 }
 
 #[cfg(test)]
@@ -62,16 +324,20 @@ mod tests {
     use std::vec;
 
     use super::*;
-    use crate::crypto::sign_ed25519::{self as sign};
+    use crate::crypto::sign_ed25519::{self as sign, PublicKey, Signature};
     use crate::primitives::asset::{Asset, ItemAsset, TokenAmount};
-    use crate::primitives::druid::{DdeValues, DruidExpectation};
+    use crate::primitives::druid::{
+        DdeValues, DruidExpectation, DruidExpectationError, OutputConstraint,
+    };
     use crate::primitives::transaction::*;
+    use crate::script::lang::Script;
+    use crate::utils::test_utils::{generate_unsigned_tx_ins, test_druid};
     use crate::utils::transaction_utils::*;
 
     /// Util function to create valid DDE asset tx's
     fn create_dde_txs() -> Vec<Transaction> {
-        let druid = "VALUE".to_owned();
-        let tx_input = construct_payment_tx_ins(vec![]);
+        let druid = test_druid();
+        let tx_input = generate_unsigned_tx_ins();
         let from_addr = construct_tx_ins_address(&tx_input);
 
         let (pk, sk) = sign::gen_keypair();
@@ -132,7 +398,8 @@ mod tests {
             vec![token_tx_out],
             None,
             &key_material,
-        );
+        )
+        .unwrap();
 
         let bob_druid_info = DdeValues {
             druid: druid.clone(),
@@ -146,7 +413,8 @@ mod tests {
             vec![data_tx_out],
             None,
             &key_material,
-        );
+        )
+        .unwrap();
 
         vec![alice_tx, bob_tx]
     }
@@ -157,9 +425,9 @@ mod tests {
         //
         let amount = TokenAmount(33);
         let payment = TokenAmount(11);
-        let druid = "VALUE".to_owned();
+        let druid = test_druid();
 
-        let tx_input = construct_payment_tx_ins(vec![]);
+        let tx_input = generate_unsigned_tx_ins();
         let from_addr = construct_tx_ins_address(&tx_input);
 
         let alice_addr = "1111".to_owned();
@@ -173,7 +441,7 @@ mod tests {
         let send_tx = {
             let tx_ins = {
                 // constructors with enough money for amount and excess, caller responsibility.
-                construct_payment_tx_ins(vec![])
+                generate_unsigned_tx_ins()
             };
             let excess_tx_out =
                 TxOut::new_token_amount(sender_address_excess, amount - payment, None);
@@ -206,7 +474,8 @@ mod tests {
                 0,
                 druid_info,
                 &key_material,
-            );
+            )
+            .unwrap();
 
             tx.outputs.push(excess_tx_out);
 
@@ -216,8 +485,7 @@ mod tests {
         let recv_tx = {
             let tx_ins = {
                 // constructors with enough money for amount and excess, caller responsibility.
-                let tx_ins_constructor = vec![];
-                construct_payment_tx_ins(tx_ins_constructor)
+                generate_unsigned_tx_ins()
             };
             let expectation = DruidExpectation {
                 from: from_addr,
@@ -242,16 +510,84 @@ mod tests {
                 druid_info,
                 &key_material,
             )
+            .unwrap()
         };
 
         (send_tx, recv_tx)
     }
 
+    #[test]
+    /// Checks generated DRUIDs are pinned for a seed, well formed and distinct
+    fn test_generate_druid() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        assert_eq!(
+            generate_druid(&mut StdRng::seed_from_u64(0)),
+            "D7f6f2ccdb23f2abb7b69278e947c01c".to_owned()
+        );
+        assert_eq!(test_druid(), test_druid());
+        assert!(is_druid(&test_druid()));
+        assert!(is_druid(&random_druid()));
+        assert_ne!(random_druid(), random_druid());
+
+        assert!(!is_druid("VALUE"));
+        assert!(!is_druid(&test_druid()[1..]));
+        assert!(!is_druid(&test_druid().to_uppercase()));
+        assert!(!is_druid(&construct_tx_hash(&Transaction::new())));
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let druids: BTreeSet<_> = (0..100_000).map(|_| generate_druid(&mut rng)).collect();
+        assert_eq!(druids.len(), 100_000);
+    }
+
     #[test]
     /// Checks that matching DDE transactions are verified as such by DDE verifier
     fn should_pass_matching_dde_tx_valid() {
         let txs = create_dde_txs();
-        assert!(druid_expectations_are_met("VALUE", txs.iter()));
+        assert!(druid_expectations_are_met(&test_druid(), txs.iter()));
+    }
+
+    #[test]
+    /// Checks that DDE transactions must declare at least two participants, as many as
+    /// there are transactions
+    fn should_fail_dde_tx_participants_mismatch() {
+        let with_participants = |participants: &[usize]| {
+            let mut txs = create_dde_txs();
+            txs.truncate(participants.len());
+            for (tx, n) in txs.iter_mut().zip(participants) {
+                tx.druid_info.as_mut().unwrap().participants = *n;
+            }
+            druid_expectations_are_met(&test_druid(), txs.iter())
+        };
+
+        assert!(with_participants(&[2, 2]));
+        assert!(!with_participants(&[0, 0]));
+        assert!(!with_participants(&[1, 1]));
+        assert!(!with_participants(&[3, 3]));
+        assert!(!with_participants(&[2, 3]));
+        assert!(!with_participants(&[1]));
+        assert!(!with_participants(&[]));
+    }
+
+    #[test]
+    /// Checks that DDE `from` addresses are matched against the stable input address
+    /// from `STABLE_TX_INS_ADDRESS_VERSION` onwards
+    fn should_match_dde_from_address_at_version() {
+        use crate::constants::STABLE_TX_INS_ADDRESS_VERSION;
+
+        let mut txs = create_dde_txs();
+        for tx in &mut txs {
+            tx.version = STABLE_TX_INS_ADDRESS_VERSION;
+        }
+        assert!(!druid_expectations_are_met(&test_druid(), txs.iter()));
+
+        for tx in &mut txs {
+            let from = construct_tx_ins_address_stable(&tx.inputs);
+            let mut expects = tx.expectations().unwrap().to_vec();
+            expects.iter_mut().for_each(|e| e.from = from.clone());
+            tx.set_druid_info(test_druid(), 2, expects);
+        }
+        assert!(druid_expectations_are_met(&test_druid(), txs.iter()));
     }
 
     #[test]
@@ -261,22 +597,15 @@ mod tests {
         let mut change_tx = txs.pop().unwrap();
         let orig_tx = txs[0].clone();
 
-        let druid_info = change_tx.druid_info.clone();
-        let mut expects = druid_info.unwrap().expectations;
+        let mut expects = change_tx.expectations().unwrap().to_vec();
         expects[0].to = "60764505679457".to_string();
 
         // New druid info
-        let nm_druid_info = DdeValues {
-            druid: "VALUE".to_owned(),
-            participants: 2,
-            expectations: expects,
-            genesis_hash: None,
-        };
-        change_tx.druid_info = Some(nm_druid_info);
+        change_tx.set_druid_info(test_druid(), 2, expects);
 
         assert!(!druid_expectations_are_met(
-            "VALUE",
-            vec![orig_tx, change_tx].iter()
+            &test_druid(),
+            [orig_tx, change_tx].iter()
         ));
     }
 
@@ -285,8 +614,194 @@ mod tests {
     fn should_pass_matching_rb_payment_valid() {
         let (send_tx, recv_tx) = create_rb_payment_txs();
         assert!(druid_expectations_are_met(
-            "VALUE",
-            vec![send_tx, recv_tx].iter()
+            &test_druid(),
+            [send_tx, recv_tx].iter()
+        ));
+    }
+
+    #[test]
+    /// Checks that expectations met by outputs locked beyond the `max_locktime` of their
+    /// terms fail, without the terms changing the transactions
+    fn should_fail_rb_payment_locktime_exceeds_max() {
+        let (mut send_tx, recv_tx) = create_rb_payment_txs();
+        let expectation = recv_tx.druid_info.as_ref().unwrap().expectations[0].clone();
+        let terms = BTreeMap::from([(
+            expectation,
+            ExpectationTerms {
+                max_locktime: Some(100),
+                ..Default::default()
+            },
+        )]);
+
+        assert!(druid_expectations_are_met_with_terms(
+            &test_druid(),
+            [send_tx.clone(), recv_tx.clone()].iter(),
+            &terms
+        ));
+
+        send_tx.outputs[0].locktime = 1_000_000;
+        assert!(druid_expectations_are_met(
+            &test_druid(),
+            [send_tx.clone(), recv_tx.clone()].iter()
+        ));
+        assert!(!druid_expectations_are_met_with_terms(
+            &test_druid(),
+            [send_tx, recv_tx].iter(),
+            &terms
+        ));
+    }
+
+    #[test]
+    /// Checks that strict verification requires outputs to be spendable at the current block
+    fn should_fail_rb_payment_locked_strict() {
+        let (mut send_tx, recv_tx) = create_rb_payment_txs();
+        send_tx.outputs[0].locktime = 1_000_000;
+        let txs = [send_tx, recv_tx];
+
+        assert!(druid_expectations_are_met(&test_druid(), txs.iter()));
+        assert!(druid_expectations_are_met_strict(
+            &test_druid(),
+            txs.iter(),
+            1_000_000
+        ));
+        assert!(!druid_expectations_are_met_strict(
+            &test_druid(),
+            txs.iter(),
+            0
+        ));
+    }
+
+    #[test]
+    /// Checks that DDE transactions encoded before expectation terms existed keep their
+    /// bincode encoding and hash
+    fn should_pass_rb_payment_baseline_encoding() {
+        let encoded = concat!(
+            "010000000000000001200000000000000067303030303030303030303030303030303030",
+            "30303030303030303030303030000000000800000000000000040000000a000000000000",
+            "00636865636b5f6461746101000000400000000000000002020202020202020202020202",
+            "020202020202020202020202020202020202020202020202020202020202020202020202",
+            "020202020202020202020202020202020000002000000000000000010101010101010101",
+            "010101010101010101010101010101010101010101010100000000230000000000000050",
+            "000000040000004000000000000000306263646430646636613265313037383465313234",
+            "313534316539316363623839373534316166373133653332376639353535323331643134",
+            "31633939363434000000003500000000000000530000000100000000000000000000000a",
+            "000000000000000000000000000000014000000000000000306263646430646636613265",
+            "313037383465313234313534316539316363623839373534316166373133653332376639",
+            "353535323331643134316339393634340600000000000000000000000000000001200000",
+            "000000000044303030303030303030303030303030303030303030303030303030303030",
+            "300200000000000000010000000000000040000000000000006161616161616161616161",
+            "616161616161616161616161616161616161616161616161616161616161616161616161",
+            "616161616161616161616161616161616140000000000000006262626262626262626262",
+            "626262626262626262626262626262626262626262626262626262626262626262626262",
+            "626262626262626262626262626262626201000000010000000000000001200000000000",
+            "000067313131313131313131313131313131313131313131313131313131313131310000",
+        );
+        let pk = PublicKey::from_slice(&[1; 32]).unwrap();
+        let sig = Signature::from_slice(&[2; 64]).unwrap();
+        let mut tx = Transaction::new();
+        let out_point = OutPoint::new("g0000000000000000000000000000000".to_owned(), 0);
+        tx.inputs.push(TxIn::new_from_input(
+            out_point,
+            Script::pay2pkh("check_data".to_owned(), sig, pk, None),
+        ));
+        tx.outputs.push(TxOut::new_token_amount(
+            construct_address(&pk),
+            TokenAmount(10),
+            None,
+        ));
+        tx.druid_info = Some(DdeValues {
+            druid: "D0000000000000000000000000000000".to_owned(),
+            participants: 2,
+            expectations: vec![DruidExpectation {
+                from: "a".repeat(64),
+                to: "b".repeat(64),
+                asset: Asset::item(1, Some("g1111111111111111111111111111111".to_owned()), None),
+            }],
+            genesis_hash: None,
+        });
+
+        let decoded: Transaction = bincode::deserialize(&hex::decode(encoded).unwrap()).unwrap();
+        assert_eq!(decoded, tx);
+        assert_eq!(hex::encode(bincode::serialize(&tx).unwrap()), encoded);
+        assert_eq!(construct_tx_hash(&tx), "gce3bff92981936791a670d10d3f6bed");
+    }
+
+    #[test]
+    /// Checks that a counterpart transaction siphoning items of the expected genesis hash
+    /// to a third party only fails expectations whose terms constrain its outputs
+    fn should_fail_rb_payment_siphoning_items_under_constraint() {
+        let (send_tx, mut recv_tx) = create_rb_payment_txs();
+        let siphoned = Asset::item(5, Some("genesis_hash".to_owned()), None);
+        recv_tx.outputs.push(TxOut {
+            value: siphoned,
+            script_public_key: Some("third_party".to_owned()),
+            ..Default::default()
+        });
+
+        // Expectations without terms only require the expected output
+        assert!(druid_expectations_are_met(
+            &test_druid(),
+            [send_tx.clone(), recv_tx.clone()].iter()
+        ));
+
+        let expectation = send_tx.druid_info.as_ref().unwrap().expectations[0].clone();
+        let constrained = ExpectationTerms {
+            output_constraint: Some(OutputConstraint::ExclusiveAssetClass { change: None }),
+            ..Default::default()
+        };
+        let terms = BTreeMap::from([(expectation.clone(), constrained.clone())]);
+        assert_eq!(
+            check_output_constraint(&expectation, &constrained, &recv_tx),
+            Err(OutputConstraintError::ThirdPartyOutput {
+                index: 1,
+                address: Some("third_party".to_owned()),
+            })
+        );
+        assert!(!druid_expectations_are_met_with_terms(
+            &test_druid(),
+            [send_tx.clone(), recv_tx.clone()].iter(),
+            &terms
+        ));
+
+        // Items of other genesis hashes are not constrained
+        recv_tx.outputs[1].value = Asset::item(5, Some("other_genesis_hash".to_owned()), None);
+        assert_eq!(
+            check_output_constraint(&expectation, &constrained, &recv_tx),
+            Ok(())
+        );
+        assert!(druid_expectations_are_met_with_terms(
+            &test_druid(),
+            [send_tx, recv_tx].iter(),
+            &terms
+        ));
+    }
+
+    #[test]
+    /// Checks that constrained expectations accept outputs of the asset class paying change
+    fn should_pass_rb_payment_constraint_with_change() {
+        let (send_tx, recv_tx) = create_rb_payment_txs();
+        let expectation = recv_tx.druid_info.as_ref().unwrap().expectations[0].clone();
+        let constrain = |change: Option<&str>| {
+            let terms = ExpectationTerms {
+                output_constraint: Some(OutputConstraint::ExclusiveAssetClass {
+                    change: change.map(str::to_owned),
+                }),
+                ..Default::default()
+            };
+            BTreeMap::from([(expectation.clone(), terms)])
+        };
+
+        // The sender keeps its excess tokens in a second output
+        assert!(!druid_expectations_are_met_with_terms(
+            &test_druid(),
+            [send_tx.clone(), recv_tx.clone()].iter(),
+            &constrain(None)
+        ));
+
+        assert!(druid_expectations_are_met_with_terms(
+            &test_druid(),
+            [send_tx, recv_tx].iter(),
+            &constrain(Some("11112"))
         ));
     }
 
@@ -301,8 +816,8 @@ mod tests {
 
         // Non-matching druid
         assert!(!druid_expectations_are_met(
-            "VALUE",
-            vec![send_tx, recv_tx].iter()
+            &test_druid(),
+            [send_tx, recv_tx].iter()
         ));
     }
 
@@ -314,8 +829,8 @@ mod tests {
 
         // Non-matching address expectation
         assert!(!druid_expectations_are_met(
-            "VALUE",
-            vec![send_tx, recv_tx].iter()
+            &test_druid(),
+            [send_tx, recv_tx].iter()
         ));
     }
 
@@ -327,8 +842,8 @@ mod tests {
 
         // Non-matching address expectation
         assert!(!druid_expectations_are_met(
-            "VALUE",
-            vec![send_tx, recv_tx].iter()
+            &test_druid(),
+            [send_tx, recv_tx].iter()
         ));
     }
 
@@ -340,8 +855,60 @@ mod tests {
 
         // Non-matching address expectation
         assert!(!druid_expectations_are_met(
-            "VALUE",
-            vec![send_tx, recv_tx].iter()
+            &test_druid(),
+            [send_tx, recv_tx].iter()
+        ));
+    }
+
+    #[test]
+    /// Checks that expectations must name addresses rather than transaction hashes
+    fn test_druid_expectation_validate() {
+        let (pk, _) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let tx_hash = construct_tx_hash(&Transaction::new());
+        let expectation = |from: &str, to: &str| DruidExpectation {
+            from: from.to_owned(),
+            to: to.to_owned(),
+            asset: Asset::token_u64(1),
+        };
+
+        assert_eq!(expectation(&address, &address).validate(), Ok(()));
+        assert_eq!(
+            expectation(&address, &tx_hash).validate(),
+            Err(DruidExpectationError::ToNotAddress(tx_hash.clone()))
+        );
+        assert_eq!(
+            expectation(&tx_hash, &address).validate(),
+            Err(DruidExpectationError::FromNotAddress(tx_hash))
+        );
+    }
+
+    #[test]
+    /// Checks that malformed expectations are only rejected outside the hash namespace
+    /// grace period
+    fn test_druid_expectations_hash_namespace_grace() {
+        // The expectations pay to short test addresses rather than well-formed ones
+        let (send_tx, recv_tx) = create_rb_payment_txs();
+        let expectations = &recv_tx.druid_info.as_ref().unwrap().expectations;
+        assert!(expectations.iter().any(|e| e.validate().is_err()));
+        let met = |hash_namespace_grace| {
+            let context = ValidationContext {
+                hash_namespace_grace,
+                ..Default::default()
+            };
+            druid_expectations_are_met_in_context(
+                &test_druid(),
+                [send_tx.clone(), recv_tx.clone()].iter(),
+                &BTreeMap::new(),
+                &context,
+            )
+        };
+
+        assert!(met(true));
+        assert!(!met(false));
+        assert!(druid_expectations_are_met(
+            &test_druid(),
+            [send_tx, recv_tx].iter()
         ));
     }
 }
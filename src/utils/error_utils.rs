@@ -15,6 +15,10 @@ pub fn error_empty_condition(op: &str) {
     error!("{op}: {ERROR_EMPTY_CONDITION}")
 }
 
+pub fn error_duplicate_else(op: &str) {
+    error!("{op}: {ERROR_DUPLICATE_ELSE}")
+}
+
 pub fn error_verify(op: &str) {
     error!("{op}: {ERROR_VERIFY}")
 }
@@ -31,10 +35,18 @@ pub fn error_item_type(op: &str) {
     error!("{op}: {ERROR_ITEM_TYPE}")
 }
 
+pub fn error_invalid_hex(op: &str) {
+    error!("{op}: {ERROR_INVALID_HEX}")
+}
+
 pub fn error_item_index(op: &str) {
     error!("{op}: {ERROR_ITEM_INDEX}")
 }
 
+pub fn error_slice_bounds(op: &str) {
+    error!("{op}: {ERROR_SLICE_BOUNDS}")
+}
+
 pub fn error_item_size(op: &str) {
     error!("{op}: {ERROR_ITEM_SIZE}")
 }
@@ -67,6 +79,14 @@ pub fn error_num_signatures(op: &str) {
     error!("{op}: {ERROR_NUM_SIGNATURES}")
 }
 
+pub fn error_no_tx_context(op: &str) {
+    error!("{op}: {ERROR_NO_TX_CONTEXT}")
+}
+
+pub fn error_output_not_token(op: &str) {
+    error!("{op}: {ERROR_OUTPUT_NOT_TOKEN}")
+}
+
 // script
 
 pub fn error_max_script_size() {
@@ -77,6 +97,14 @@ pub fn error_max_stack_size() {
     error!("{ERROR_MAX_STACK_SIZE}")
 }
 
+pub fn error_max_stack_bytes() {
+    error!("{ERROR_MAX_STACK_BYTES}")
+}
+
+pub fn error_max_recursion_depth() {
+    error!("{ERROR_MAX_RECURSION_DEPTH}")
+}
+
 pub fn error_max_ops_script() {
     error!("{ERROR_MAX_OPS_SCRIPT}")
 }
@@ -0,0 +1,195 @@
+use crate::primitives::asset::TokenAmount;
+use crate::primitives::transaction::Transaction;
+use std::collections::VecDeque;
+
+/// Default number of recent blocks tracked by a `FeeEstimator`
+pub const DEFAULT_FEE_ESTIMATOR_BLOCKS: usize = 100;
+
+/// Gets the total `Token` fee paid by a transaction
+///
+/// ### Arguments
+///
+/// * `tx`  - Transaction to get the fee for
+pub fn tx_fee(tx: &Transaction) -> TokenAmount {
+    tx.fees.iter().map(|fee| fee.value.token_amount()).sum()
+}
+
+/// Computes the fee rate of a transaction, in `Token` units per byte
+///
+/// ### Arguments
+///
+/// * `tx`  - Transaction to compute the fee rate for
+pub fn fee_rate(tx: &Transaction) -> TokenAmount {
+    tx_fee(tx) / tx.get_total_size() as u64
+}
+
+/// Estimates a fee rate from the fee rates paid in recently seen blocks.
+///
+/// Each block contributes the median fee rate of its fee-paying transactions.
+/// Until a block has been added, the configured default rate is returned.
+#[derive(Debug, Clone)]
+pub struct FeeEstimator {
+    max_blocks: usize,
+    default_rate: TokenAmount,
+    block_rates: VecDeque<TokenAmount>,
+}
+
+impl FeeEstimator {
+    /// Creates a new fee estimator
+    ///
+    /// ### Arguments
+    ///
+    /// * `max_blocks`      - Number of most recent blocks to estimate from
+    /// * `default_rate`    - Fee rate to return when no block data is available
+    pub fn new(max_blocks: usize, default_rate: TokenAmount) -> Self {
+        Self {
+            max_blocks,
+            default_rate,
+            block_rates: VecDeque::with_capacity(max_blocks),
+        }
+    }
+
+    /// Adds the transactions of a block to the estimator. Coinbase and create
+    /// transactions, as well as transactions without fees, are ignored.
+    ///
+    /// ### Arguments
+    ///
+    /// * `txs` - Transactions contained in the block
+    pub fn add_block<'a>(&mut self, txs: impl IntoIterator<Item = &'a Transaction>) {
+        let mut rates: Vec<TokenAmount> = txs
+            .into_iter()
+            .filter(|tx| !tx.is_coinbase() && !tx.is_create_tx() && !tx.fees.is_empty())
+            .map(fee_rate)
+            .collect();
+
+        if rates.is_empty() || self.max_blocks == 0 {
+            return;
+        }
+
+        rates.sort_unstable();
+        self.block_rates.push_back(rates[rates.len() / 2]);
+
+        if self.block_rates.len() > self.max_blocks {
+            self.block_rates.pop_front();
+        }
+    }
+
+    /// Estimates the fee rate needed for a transaction to be included within
+    /// `target_blocks` blocks.
+    ///
+    /// The estimate is taken at the `50 + 50 / target_blocks` percentile of the
+    /// tracked block rates: the highest rate seen for a target of one block,
+    /// tending to the median rate as the target grows.
+    ///
+    /// ### Arguments
+    ///
+    /// * `target_blocks`   - Number of blocks within which to be included
+    pub fn estimate(&self, target_blocks: usize) -> TokenAmount {
+        if self.block_rates.is_empty() {
+            return self.default_rate;
+        }
+
+        let mut rates: Vec<TokenAmount> = self.block_rates.iter().copied().collect();
+        rates.sort_unstable();
+
+        let percentile = 50 + 50 / target_blocks.max(1);
+        rates[(rates.len() - 1) * percentile / 100]
+    }
+
+    /// Number of blocks currently tracked by the estimator
+    pub fn num_blocks(&self) -> usize {
+        self.block_rates.len()
+    }
+}
+
+impl Default for FeeEstimator {
+    fn default() -> Self {
+        Self::new(DEFAULT_FEE_ESTIMATOR_BLOCKS, TokenAmount(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::asset::Asset;
+    use crate::primitives::transaction::{OutPoint, TxIn, TxOut};
+    use crate::script::lang::Script;
+
+    /// Creates a transaction paying exactly `rate` tokens per byte in fees
+    fn tx_with_fee_rate(rate: u64) -> Transaction {
+        let address = hex::encode([0; 32]);
+        let mut tx = Transaction::new();
+        tx.inputs.push(TxIn::new_from_input(
+            OutPoint::new("tx_hash".to_owned(), 0),
+            Script::new(),
+        ));
+        tx.outputs.push(TxOut::new_token_amount(
+            address.clone(),
+            TokenAmount(1),
+            None,
+        ));
+        tx.fees
+            .push(TxOut::new_token_amount(address, TokenAmount(0), None));
+
+        // Fee amounts are fixed-width, so the size doesn't change with the fee
+        let size = tx.get_total_size() as u64;
+        tx.fees[0].value = Asset::Token(TokenAmount(rate * size));
+        tx
+    }
+
+    #[test]
+    /// Checks that the fee rate divides the fee by the serialized size
+    fn test_fee_rate() {
+        let tx = tx_with_fee_rate(7);
+
+        assert_eq!(fee_rate(&tx), TokenAmount(7));
+        assert_eq!(fee_rate(&Transaction::new()), TokenAmount(0));
+    }
+
+    #[test]
+    /// Checks that the default rate is returned before any block is added
+    fn test_estimate_cold_start() {
+        let mut estimator = FeeEstimator::new(10, TokenAmount(42));
+        assert_eq!(estimator.estimate(1), TokenAmount(42));
+
+        // Blocks without fee-paying transactions provide no data
+        estimator.add_block(&[Transaction::new()]);
+        assert_eq!(estimator.num_blocks(), 0);
+        assert_eq!(estimator.estimate(1), TokenAmount(42));
+    }
+
+    #[test]
+    /// Checks that estimates match the expected percentile of block rates
+    fn test_estimate_percentiles() {
+        let mut estimator = FeeEstimator::new(10, TokenAmount(0));
+
+        // Block medians of 1, 2, ..., 9
+        for rate in 1..10 {
+            let txs = [
+                tx_with_fee_rate(rate - 1),
+                tx_with_fee_rate(rate),
+                tx_with_fee_rate(rate + 100),
+            ];
+            estimator.add_block(&txs);
+        }
+
+        assert_eq!(estimator.num_blocks(), 9);
+        assert_eq!(estimator.estimate(1), TokenAmount(9));
+        assert_eq!(estimator.estimate(2), TokenAmount(7));
+        assert_eq!(estimator.estimate(100), TokenAmount(5));
+    }
+
+    #[test]
+    /// Checks that only the most recent blocks are used for estimation
+    fn test_estimate_drops_old_blocks() {
+        let mut estimator = FeeEstimator::new(2, TokenAmount(0));
+
+        for rate in [50, 3, 5] {
+            estimator.add_block(&[tx_with_fee_rate(rate)]);
+        }
+
+        assert_eq!(estimator.num_blocks(), 2);
+        assert_eq!(estimator.estimate(1), TokenAmount(5));
+        assert_eq!(estimator.estimate(100), TokenAmount(3));
+    }
+}
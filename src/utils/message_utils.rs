@@ -0,0 +1,122 @@
+use crate::constants::MESSAGE_SIGNING_DOMAIN;
+use crate::crypto::sign_ed25519::{self as sign, PublicKey, SecretKey, Signature};
+use crate::utils::transaction_utils::construct_address;
+use serde::{Deserialize, Serialize};
+
+/// Message signed outside of any transaction, proving control of an address
+#[derive(Deserialize, Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct SignedMessage {
+    pub message: Vec<u8>,
+    pub signature: Signature,
+    pub public_key: PublicKey,
+}
+
+/// Bytes signed for `message`. Prefixed with `MESSAGE_SIGNING_DOMAIN`, so they can't
+/// be the hex hash signed for a transaction, nor a transaction signature be replayed
+/// as a signed message
+///
+/// ### Arguments
+///
+/// * `message` - Message to sign
+fn message_preimage(message: &[u8]) -> Vec<u8> {
+    [MESSAGE_SIGNING_DOMAIN, message].concat()
+}
+
+/// Signs a message with the key of an address
+///
+/// ### Arguments
+///
+/// * `message`     - Message to sign
+/// * `public_key`  - Public key to verify the signature with
+/// * `secret_key`  - Corresponding secret key
+pub fn sign_message(
+    message: &[u8],
+    public_key: PublicKey,
+    secret_key: &SecretKey,
+) -> SignedMessage {
+    SignedMessage {
+        message: message.to_vec(),
+        signature: sign::sign_detached(&message_preimage(message), secret_key),
+        public_key,
+    }
+}
+
+/// Checks that a message was signed by the key of `address`
+///
+/// ### Arguments
+///
+/// * `signed`  - Signed message
+/// * `address` - Address the message should be signed for
+pub fn verify_message(signed: &SignedMessage, address: &str) -> bool {
+    construct_address(&signed.public_key) == address
+        && sign::verify_detached(
+            &signed.signature,
+            &message_preimage(&signed.message),
+            &signed.public_key,
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::NETWORK_VERSION;
+    use crate::primitives::asset::TokenAmount;
+    use crate::primitives::transaction::{OutPoint, TxIn, TxOut};
+    use crate::script::lang::Script;
+    use crate::utils::transaction_utils::SignableMessage;
+
+    #[test]
+    /// Checks that signed messages only verify for the signing address
+    fn test_verify_message() {
+        let (pk, sk) = sign::gen_keypair();
+        let (other_pk, _) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let signed = sign_message(b"hello", pk, &sk);
+
+        assert!(verify_message(&signed, &address));
+        assert!(!verify_message(&signed, &construct_address(&other_pk)));
+
+        let tampered = SignedMessage {
+            message: b"hellp".to_vec(),
+            ..signed.clone()
+        };
+        assert!(!verify_message(&tampered, &address));
+
+        // The key must match the signature, not only the address
+        let wrong_key = SignedMessage {
+            public_key: other_pk,
+            ..signed
+        };
+        assert!(!verify_message(&wrong_key, &construct_address(&other_pk)));
+    }
+
+    #[test]
+    /// Checks that transaction signatures and signed messages can't be swapped
+    fn test_verify_message_rejects_tx_signature() {
+        let (pk, sk) = sign::gen_keypair();
+        let address = construct_address(&pk);
+
+        let tx_in = TxIn::new_from_input(OutPoint::new("g0".to_owned(), 0), Script::new());
+        let tx_outs = vec![TxOut::new_token_amount(
+            address.clone(),
+            TokenAmount(1),
+            None,
+        )];
+        let message = SignableMessage::tx_in(&tx_in, &tx_outs);
+        let version = NETWORK_VERSION as usize;
+        let tx_signed = SignedMessage {
+            message: message.hash_at_version(version).into_bytes(),
+            signature: message.sign_at_version(version, &sk),
+            public_key: pk,
+        };
+        assert!(!verify_message(&tx_signed, &address));
+
+        let signed = sign_message(message.hash_at_version(version).as_bytes(), pk, &sk);
+        assert!(verify_message(&signed, &address));
+        assert!(!sign::verify_detached(
+            &signed.signature,
+            message.hash_at_version(version).as_bytes(),
+            &pk
+        ));
+    }
+}
@@ -1,15 +1,23 @@
 use std::collections::BTreeMap;
 
-use crate::constants::{D_DISPLAY_PLACES, TOTAL_TOKENS};
+use crate::constants::TOTAL_TOKENS;
 use crate::primitives::asset::TokenAmount;
 
 // ------- MODS ------- //
 
+pub mod block_utils;
+pub mod commitment_utils;
 pub mod druid_utils;
-pub mod error_utils;
+pub(crate) mod error_utils;
+pub mod fee_utils;
+pub mod message_utils;
+pub mod query_utils;
 pub mod script_utils;
+#[doc(hidden)]
 pub mod test_utils;
 pub mod transaction_utils;
+pub mod utxo_set;
+pub mod validation_cache;
 
 // ------- FUNCTIONS ------- //
 
@@ -26,10 +34,11 @@ pub fn is_valid_amount(_value: &TokenAmount) -> bool {
 /// ### Arguments
 ///
 /// * `value`   - Value to format for display
+#[doc(hidden)]
+#[deprecated(note = "Use `TokenAmount::to_display_string` instead")]
 pub fn format_for_display(value: &u64) -> String {
     if value < &TOTAL_TOKENS {
-        let value_f64 = *value as f64;
-        return (value_f64 / D_DISPLAY_PLACES).to_string();
+        return TokenAmount(*value).to_display_string();
     }
 
     "Value out of bounds".to_string()
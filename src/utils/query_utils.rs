@@ -0,0 +1,385 @@
+//! Queries over chain data for explorers and wallets.
+//!
+//! An `ItemRegistry` indexes every item line, keyed by genesis hash, from the create
+//! transactions that started them. It can be built from a full history with
+//! `build_item_registry`, then kept up to date with `ItemRegistry::apply_transaction`.
+
+use crate::crypto::sign_ed25519::PublicKey;
+use crate::primitives::asset::Asset;
+use crate::primitives::transaction::Transaction;
+use crate::script::lang::Script;
+use crate::script::{OpCodes, StackEntry};
+use crate::utils::transaction_utils::{
+    construct_address, construct_p2sh_address, construct_p2sh_address_v2, UtxoMap,
+};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// An item line, as started by its create transaction
+///
+/// * `creator_address`     - Address of the public key signing the create script
+/// * `created_in_tx`       - Hash of the create transaction
+/// * `total_minted`        - Amount of the item minted by the create transaction
+/// * `metadata`            - Metadata of the item, or its commitment if kept private
+/// * `created_at_block`    - Block number committed to by the create script, if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemLine {
+    pub creator_address: String,
+    pub created_in_tx: String,
+    pub total_minted: u64,
+    pub metadata: Option<String>,
+    pub created_at_block: Option<u64>,
+}
+
+/// Reasons a create transaction can't be added to an `ItemRegistry`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemRegistryError {
+    DuplicateGenesisHash {
+        genesis_hash: String,
+        created_in_tx: String,
+        duplicate_tx: String,
+    },
+    NoCreatorKey(String),
+}
+
+impl fmt::Display for ItemRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::DuplicateGenesisHash {
+                genesis_hash,
+                created_in_tx,
+                duplicate_tx,
+            } => write!(
+                f,
+                "Genesis hash {genesis_hash} of {duplicate_tx} was already created in {created_in_tx}"
+            ),
+            Self::NoCreatorKey(tx_hash) => {
+                write!(f, "Create script of {tx_hash} holds no public key")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ItemRegistryError {}
+
+/// Item lines by genesis hash, along with the create transactions which couldn't be
+/// added to them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ItemRegistry {
+    lines: BTreeMap<String, ItemLine>,
+    errors: Vec<ItemRegistryError>,
+}
+
+impl ItemRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Item lines, by genesis hash
+    pub fn lines(&self) -> &BTreeMap<String, ItemLine> {
+        &self.lines
+    }
+
+    /// Gets the item line started with `genesis_hash`
+    ///
+    /// ### Arguments
+    ///
+    /// * `genesis_hash`    - Genesis hash of the item line
+    pub fn get(&self, genesis_hash: &str) -> Option<&ItemLine> {
+        self.lines.get(genesis_hash)
+    }
+
+    /// Create transactions left out of the registry while it was built
+    pub fn errors(&self) -> &[ItemRegistryError] {
+        &self.errors
+    }
+
+    /// Adds the item line started by a transaction, if it is an item create transaction.
+    /// Other transactions leave the registry unchanged, as does a create transaction
+    /// reusing the genesis hash of an existing line
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx_hash` - Hash of the transaction
+    /// * `tx`      - Transaction to add
+    pub fn apply_transaction(
+        &mut self,
+        tx_hash: &str,
+        tx: &Transaction,
+    ) -> Result<(), ItemRegistryError> {
+        if !tx.is_create_tx() {
+            return Ok(());
+        }
+        let item = match &tx.outputs[0].value {
+            Asset::Item(item) => item,
+            _ => return Ok(()),
+        };
+
+        // Items created without a genesis hash take the hash of their create transaction
+        let genesis_hash = item.genesis_hash.as_deref().unwrap_or(tx_hash);
+        if let Some(line) = self.lines.get(genesis_hash) {
+            return Err(ItemRegistryError::DuplicateGenesisHash {
+                genesis_hash: genesis_hash.to_owned(),
+                created_in_tx: line.created_in_tx.clone(),
+                duplicate_tx: tx_hash.to_owned(),
+            });
+        }
+
+        let script = &tx.inputs[0].script_signature;
+        let creator_address = match create_script_key(script) {
+            Some(public_key) => construct_address(public_key),
+            None => return Err(ItemRegistryError::NoCreatorKey(tx_hash.to_owned())),
+        };
+        let line = ItemLine {
+            creator_address,
+            created_in_tx: tx_hash.to_owned(),
+            total_minted: item.amount,
+            metadata: item.metadata.clone(),
+            created_at_block: create_script_block(script),
+        };
+        self.lines.insert(genesis_hash.to_owned(), line);
+        Ok(())
+    }
+
+    /// Computes the unspent amount of an item line, leaving out amounts burnt by
+    /// sending them to the P2SH address of an `OP_BURN` script
+    ///
+    /// ### Arguments
+    ///
+    /// * `genesis_hash`    - Genesis hash of the item line
+    /// * `utxo`            - Unspent outputs to sum over
+    pub fn circulating_amount(&self, genesis_hash: &str, utxo: &UtxoMap) -> u64 {
+        let burn_script = Script::from(vec![StackEntry::Op(OpCodes::OP_BURN)]);
+        let mut burn_addresses = vec![construct_p2sh_address(&burn_script)];
+        burn_addresses.extend(construct_p2sh_address_v2(&burn_script));
+
+        utxo.iter()
+            .filter(|(_, tx_out)| {
+                let address = tx_out.script_public_key.as_ref();
+                !address.is_some_and(|address| burn_addresses.contains(address))
+            })
+            .filter_map(|(out_point, tx_out)| match &tx_out.value {
+                Asset::Item(item) => {
+                    let hash = item.genesis_hash.as_ref().unwrap_or(&out_point.t_hash);
+                    (hash == genesis_hash).then_some(item.amount)
+                }
+                _ => None,
+            })
+            .sum()
+    }
+}
+
+/// Builds the registry of item lines started by the create transactions of a history.
+/// Create transactions which can't be added are reported in `ItemRegistry::errors`
+///
+/// ### Arguments
+///
+/// * `txs` - Transactions with their hashes, in chain order
+pub fn build_item_registry<'a>(
+    txs: impl Iterator<Item = (&'a String, &'a Transaction)>,
+) -> ItemRegistry {
+    let mut registry = ItemRegistry::new();
+    for (tx_hash, tx) in txs {
+        if let Err(e) = registry.apply_transaction(tx_hash, tx) {
+            registry.errors.push(e);
+        }
+    }
+    registry
+}
+
+/// Gets the public key signing a create script
+///
+/// ### Arguments
+///
+/// * `script`  - Create script
+fn create_script_key(script: &Script) -> Option<&PublicKey> {
+    script.stack.iter().find_map(|entry| match entry {
+        StackEntry::PubKey(public_key) => Some(public_key),
+        _ => None,
+    })
+}
+
+/// Gets the block number a create script commits to, pushed right after `OP_CREATE`
+///
+/// ### Arguments
+///
+/// * `script`  - Create script
+fn create_script_block(script: &Script) -> Option<u64> {
+    let mut entries = script.stack.iter();
+    entries.find(|entry| **entry == StackEntry::Op(OpCodes::OP_CREATE))?;
+    match entries.next()? {
+        StackEntry::Num(block_number) => Some(*block_number as u64),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign_ed25519::{self as sign, SecretKey};
+    use crate::primitives::asset::TokenAmount;
+    use crate::primitives::transaction::{GenesisTxHashSpec, OutPoint, TxIn, TxOut};
+    use crate::utils::transaction_utils::{
+        construct_item_create_tx, construct_p2sh_tx, construct_payment_tx, construct_tx_hash,
+        ReceiverInfo,
+    };
+
+    /// Item create transaction signed by `creator`, with its hash
+    fn create(
+        creator: &(PublicKey, SecretKey),
+        block_num: u64,
+        amount: u64,
+        spec: GenesisTxHashSpec,
+        metadata: Option<&str>,
+    ) -> (String, Transaction) {
+        let metadata = metadata.map(str::to_owned);
+        let (pk, sk) = creator;
+        let tx =
+            construct_item_create_tx(block_num, block_num, *pk, sk, amount, spec, None, metadata)
+                .unwrap();
+        (construct_tx_hash(&tx), tx)
+    }
+
+    /// Applies a transaction to a UTXO map, returning its hash
+    fn apply(utxo: &mut UtxoMap, tx: &Transaction) -> String {
+        let tx_hash = construct_tx_hash(tx);
+        for tx_in in &tx.inputs {
+            if let Some(out_point) = &tx_in.previous_out {
+                utxo.remove(out_point);
+            }
+        }
+        for (n, tx_out) in tx.outputs.iter().enumerate() {
+            utxo.insert(OutPoint::new(tx_hash.clone(), n as i32), tx_out.clone());
+        }
+        tx_hash
+    }
+
+    #[test]
+    /// Checks that item lines are indexed from create transactions only
+    fn test_build_item_registry() {
+        let alice = sign::gen_keypair();
+        let bob = sign::gen_keypair();
+        let existing = "g7f6f2ccdb23f2abb7b69278e947c01c".to_owned();
+        let (hash_a, tx_a) = create(&alice, 3, 10, GenesisTxHashSpec::Create, Some("{}"));
+        let (hash_b, tx_b) = create(
+            &bob,
+            7,
+            25,
+            GenesisTxHashSpec::Existing(existing.clone()),
+            None,
+        );
+
+        // Coinbase and payment transactions don't start item lines
+        let coinbase = Transaction {
+            inputs: vec![TxIn::new_from_script(Script::new_for_coinbase(0))],
+            outputs: vec![TxOut::new_token_amount(
+                construct_address(&alice.0),
+                TokenAmount(5),
+                None,
+            )],
+            ..Default::default()
+        };
+        let out_point = OutPoint::new(hash_a.clone(), 0);
+        let key_material = BTreeMap::from([(out_point.clone(), alice.clone())]);
+        let receiver = ReceiverInfo {
+            address: construct_address(&bob.0),
+            asset: tx_a.outputs[0].value.clone(),
+        };
+        let tx_ins = vec![TxIn::new_from_input(out_point, Script::new())];
+        let payment = construct_payment_tx(tx_ins, receiver, None, 0, &key_material).unwrap();
+        let history = [
+            (construct_tx_hash(&coinbase), coinbase),
+            (hash_a.clone(), tx_a),
+            (construct_tx_hash(&payment), payment),
+            (hash_b.clone(), tx_b),
+        ];
+
+        let registry = build_item_registry(history.iter().map(|(h, tx)| (h, tx)));
+        assert!(registry.errors().is_empty());
+        assert_eq!(registry.lines().len(), 2);
+        assert_eq!(
+            registry.get(&hash_a),
+            Some(&ItemLine {
+                creator_address: construct_address(&alice.0),
+                created_in_tx: hash_a.clone(),
+                total_minted: 10,
+                metadata: Some("{}".to_owned()),
+                created_at_block: Some(3),
+            })
+        );
+        assert_eq!(
+            registry.get(&existing),
+            Some(&ItemLine {
+                creator_address: construct_address(&bob.0),
+                created_in_tx: hash_b,
+                total_minted: 25,
+                metadata: None,
+                created_at_block: Some(7),
+            })
+        );
+
+        // Applying the same history one transaction at a time gives the same registry
+        let mut incremental = ItemRegistry::new();
+        for (tx_hash, tx) in &history {
+            incremental.apply_transaction(tx_hash, tx).unwrap();
+        }
+        assert_eq!(incremental, registry);
+    }
+
+    #[test]
+    /// Checks that burnt items are left out of the circulating amount
+    fn test_circulating_amount() {
+        let alice = sign::gen_keypair();
+        let (hash_a, tx_a) = create(&alice, 0, 10, GenesisTxHashSpec::Create, None);
+        let (hash_b, tx_b) = create(&alice, 0, 4, GenesisTxHashSpec::Create, None);
+        let mut registry = ItemRegistry::new();
+        let mut utxo = UtxoMap::new();
+        for (tx_hash, tx) in [(&hash_a, &tx_a), (&hash_b, &tx_b)] {
+            registry.apply_transaction(tx_hash, tx).unwrap();
+            apply(&mut utxo, tx);
+        }
+        assert_eq!(registry.circulating_amount(&hash_a, &utxo), 10);
+        assert_eq!(registry.circulating_amount(&hash_b, &utxo), 4);
+
+        // Burn the first item by sending it to the P2SH address of an `OP_BURN` script
+        let out_point = OutPoint::new(hash_a.clone(), 0);
+        let burn_script = Script::from(vec![StackEntry::Op(OpCodes::OP_BURN)]);
+        let key_material = BTreeMap::from([(out_point.clone(), alice.clone())]);
+        let item = Asset::item(10, Some(hash_a.clone()), None);
+        let tx_ins = vec![TxIn::new_from_input(out_point, Script::new())];
+        let burn_tx =
+            construct_p2sh_tx(tx_ins, None, &burn_script, item, 0, &key_material).unwrap();
+        apply(&mut utxo, &burn_tx);
+
+        assert_eq!(registry.circulating_amount(&hash_a, &utxo), 0);
+        assert_eq!(registry.circulating_amount(&hash_b, &utxo), 4);
+        assert_eq!(registry.get(&hash_a).unwrap().total_minted, 10);
+    }
+
+    #[test]
+    /// Checks that a create transaction reusing a genesis hash is reported, keeping the
+    /// first line
+    fn test_duplicate_genesis_hash() {
+        let alice = sign::gen_keypair();
+        let bob = sign::gen_keypair();
+        let genesis_hash = "g7f6f2ccdb23f2abb7b69278e947c01c".to_owned();
+        let spec = || GenesisTxHashSpec::Existing(genesis_hash.clone());
+        let (hash_a, tx_a) = create(&alice, 0, 10, spec(), None);
+        let (hash_b, tx_b) = create(&bob, 1, 1000, spec(), None);
+
+        let history = [(&hash_a, &tx_a), (&hash_b, &tx_b)];
+        let registry = build_item_registry(history.iter().copied());
+        let duplicate = ItemRegistryError::DuplicateGenesisHash {
+            genesis_hash: genesis_hash.clone(),
+            created_in_tx: hash_a.clone(),
+            duplicate_tx: hash_b.clone(),
+        };
+        assert_eq!(registry.errors(), std::slice::from_ref(&duplicate));
+        assert_eq!(registry.get(&genesis_hash).unwrap().created_in_tx, hash_a);
+        assert_eq!(registry.get(&genesis_hash).unwrap().total_minted, 10);
+
+        let mut registry = registry;
+        assert_eq!(registry.apply_transaction(&hash_b, &tx_b), Err(duplicate));
+    }
+}
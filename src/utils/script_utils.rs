@@ -4,29 +4,60 @@ use crate::crypto::sha3_256;
 use crate::crypto::sign_ed25519::{
     self as sign, PublicKey, Signature, ED25519_PUBLIC_KEY_LEN, ED25519_SIGNATURE_LEN,
 };
-use crate::primitives::asset::{Asset, AssetValues, ItemAsset, TokenAmount};
-use crate::primitives::druid::DruidExpectation;
+use crate::primitives::asset::{
+    Asset, AssetDiff, AssetError, AssetValues, ItemAsset, TokenAmount, TokenMetadata,
+};
+use crate::primitives::druid::{DruidExpectation, DruidExpectationError};
 use crate::primitives::transaction::*;
 use crate::script::interface_ops::*;
-use crate::script::lang::{ConditionStack, Script, Stack};
+use crate::script::lang::{
+    ConditionStack, CreateClaims, CreateContext, ExecutionContext, Script, ScriptError, Stack,
+    TxContext,
+};
 use crate::script::{OpCodes, StackEntry};
+use crate::utils::commitment_utils::MetadataCommitment;
 use crate::utils::error_utils::*;
 use crate::utils::transaction_utils::{
-    construct_address, construct_tx_hash, construct_tx_in_out_signable_hash,
-    construct_tx_in_signable_asset_hash, construct_tx_in_signable_hash,
+    address_type, construct_address, construct_dual_address, construct_tx_hash,
+    construct_tx_in_out_signable_hash, construct_tx_in_signable_asset_hash,
+    construct_tx_in_signable_hash, is_address, is_genesis_hash, AddressKind, CreateTxError,
+    SignableMessage,
 };
 use bincode::serialize;
 use bytes::Bytes;
 use hex::encode;
 use ring::error;
 use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::thread::current;
 use tracing::{debug, error, info, trace};
 
-use super::transaction_utils::construct_p2sh_address;
+use super::transaction_utils::{construct_p2sh_address, is_p2sh_address_of};
+
+/// Policy applied while validating transactions, which nodes may tighten as the
+/// network migrates. The default accepts everything that has ever been valid
+///
+/// * `allow_legacy_addresses`  - Whether outputs may pay to V0 length addresses
+/// * `hash_namespace_grace`    - Whether transaction hashes and addresses in the wrong
+///   place or a non-canonical form are only logged rather than rejected, while existing
+///   data migrates
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ValidationContext {
+    pub allow_legacy_addresses: bool,
+    pub hash_namespace_grace: bool,
+}
+
+impl Default for ValidationContext {
+    fn default() -> Self {
+        Self {
+            allow_legacy_addresses: true,
+            hash_namespace_grace: true,
+        }
+    }
+}
 
-/// Verifies that all incoming transactions are allowed to be spent. Returns false if a single
-/// transaction doesn't verify
+/// Verifies that all incoming transactions are allowed to be spent, returning the first
+/// reason the transaction is invalid
 ///
 /// TODO: Currently assumes p2pkh and p2sh, abstract to all tx types
 ///
@@ -39,165 +70,609 @@ pub fn tx_is_valid<'a>(
     tx: &Transaction,
     current_block_number: u64,
     is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut> + 'a,
-) -> (bool, String) {
+) -> Result<(), TxValidationError> {
+    let context = ValidationContext::default();
+    tx_is_valid_in_context(tx, current_block_number, is_in_utxo, &context)
+}
+
+/// Verifies a transaction as `tx_is_valid` does, under the policy of `context`
+///
+/// ### Arguments
+///
+/// * `tx`                   - Transaction to verify
+/// * `current_block_number` - Current block number
+/// * `is_in_utxo`           - Function to check if a `TxOut` is in the UTXO set
+/// * `context`              - Validation policy to apply
+pub fn tx_is_valid_in_context<'a>(
+    tx: &Transaction,
+    current_block_number: u64,
+    is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut> + 'a,
+    context: &ValidationContext,
+) -> Result<(), TxValidationError> {
     let mut tx_ins_spent: AssetValues = Default::default();
 
-    // `Item` assets MUST have an a DRS value associated with them when they are getting on-spent
+    // Check the minimum structure, including that spent inputs go to outputs rather than only fees
+    if let Err(e) = tx.validate_structure() {
+        error!("TRANSACTION STRUCTURE IS INVALID: {e}");
+        return Err(TxValidationError::Structure(e));
+    }
+
+    for (idx, tx_in) in tx.inputs.iter().enumerate() {
+        let verified = resolve_input(tx_in, &is_in_utxo).and_then(|(out_point, tx_out)| {
+            verify_input(tx, tx_in, tx_out, current_block_number)?;
+            Ok(tx_out.value.clone().with_fixed_hash(out_point))
+        });
+        match verified {
+            Ok(asset) => tx_ins_spent.update_add(&asset),
+            Err(e) => {
+                error!("INVALID INPUT {idx}: {e}");
+                return Err(TxValidationError::Input(idx, e));
+            }
+        }
+    }
+
+    tx_outs_are_valid_in_context(&tx.outputs, &tx.fees, tx_ins_spent, context)
+}
 
-    debug!("tx: {:?}", tx.outputs);
-    if tx.outputs.iter().any(|out| {
-        debug!("out is item: {:?}", out.value.is_item());
-        debug!("out has drs: {:?}", out.value.get_genesis_hash().is_none());
-        debug!("out has metadata: {:?}", out.value.get_metadata().is_some());
+/// Reasons a single transaction input can fail validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputError {
+    NoPreviousOut,
+    NotInUtxo(OutPoint),
+    SpentBy {
+        out_point: OutPoint,
+        tx_hash: String,
+    },
+    LocktimeNotMet {
+        locktime: u64,
+        current_block: u64,
+    },
+    NoPublicKey,
+    InvalidScript,
+}
 
-        (out.value.is_item()
-            && (out.value.get_genesis_hash().is_none() || out.value.get_metadata().is_some()))
-    }) {
-        error!("ON-SPENDING NEEDS EMPTY METADATA AND NON-EMPTY DRS SPECIFICATION");
-        return (
-            false,
-            "On-spending items needs empty metadata and non-empty genesis hash".to_string(),
-        );
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NoPreviousOut => write!(f, "Transaction doesn't contain previous outpoint"),
+            Self::NotInUtxo(out_point) => write!(f, "UTXO doesn't contain {out_point}"),
+            Self::SpentBy { out_point, tx_hash } => {
+                write!(f, "UTXO {out_point} already spent by {tx_hash}")
+            }
+            Self::LocktimeNotMet {
+                locktime,
+                current_block,
+            } => write!(
+                f,
+                "Locktime {locktime} not expired at block {current_block}"
+            ),
+            Self::NoPublicKey => write!(f, "Previous outpoint has no public key"),
+            Self::InvalidScript => write!(f, "Invalid signature or script structure"),
+        }
     }
+}
 
-    // Check that inputs and outputs even exist
-    if tx.inputs.is_empty() || tx.outputs.is_empty() {
-        error!("TRANSACTION HAS NO INPUTS OR OUTPUTS");
-        return (false, "Transaction has no inputs or outputs".to_string());
+/// Finds the UTXO spent by a transaction input
+///
+/// ### Arguments
+///
+/// * `tx_in`       - Input to resolve
+/// * `is_in_utxo`  - Function to check if a `TxOut` is in the UTXO set
+pub fn resolve_input<'a, 'b>(
+    tx_in: &'b TxIn,
+    is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut>,
+) -> Result<(&'b OutPoint, &'a TxOut), InputError> {
+    let out_point = tx_in
+        .previous_out
+        .as_ref()
+        .ok_or(InputError::NoPreviousOut)?;
+    let tx_out = is_in_utxo(out_point).ok_or_else(|| InputError::NotInUtxo(out_point.clone()))?;
+    Ok((out_point, tx_out))
+}
+
+/// Verifies that a transaction input may spend the UTXO it resolved to,
+/// checking its locktime and unlocking script
+///
+/// ### Arguments
+///
+/// * `tx`                   - Transaction holding the input
+/// * `tx_in`                - Input to verify
+/// * `tx_out`               - UTXO spent by the input
+/// * `current_block_number` - Current block number
+pub fn verify_input(
+    tx: &Transaction,
+    tx_in: &TxIn,
+    tx_out: &TxOut,
+    current_block_number: u64,
+) -> Result<(), InputError> {
+    if tx_out.locktime > current_block_number {
+        return Err(InputError::LocktimeNotMet {
+            locktime: tx_out.locktime,
+            current_block: current_block_number,
+        });
+    }
+
+    let pk = tx_out
+        .script_public_key
+        .as_ref()
+        .ok_or(InputError::NoPublicKey)?;
+    let full_tx_hash = SignableMessage::tx_in(tx_in, &tx.outputs).hash_at_version(tx.version);
+    debug!("full_tx_hash: {:?}", full_tx_hash);
+
+    // Check will need to include other signature types here
+    let is_valid = match address_type(pk) {
+        AddressKind::P2PKH => {
+            tx_has_valid_p2pkh_sig(&tx_in.script_signature, &full_tx_hash, pk)
+                || tx_has_valid_dual_p2pkh_sig(&tx_in.script_signature, &full_tx_hash, pk)
+        }
+        AddressKind::P2SH => {
+            let context = ExecutionContext {
+                block_height: Some(current_block_number),
+                tx: Some(TxContext::new(tx)),
+                ..Default::default()
+            };
+            let script = &tx_in.script_signature;
+            tx_has_valid_p2sh_script_in_context(script, pk, tx.version, &context)
+                || tx_has_valid_escrow_script(
+                    &tx_in.script_signature,
+                    &full_tx_hash,
+                    pk,
+                    tx.version,
+                    current_block_number,
+                )
+        }
+    };
+    match is_valid {
+        true => Ok(()),
+        false => Err(InputError::InvalidScript),
     }
+}
 
-    for tx_in in &tx.inputs {
-        let full_tx_hash = construct_tx_in_out_signable_hash(tx_in, &tx.outputs);
-        println!("full_tx_hash: {:?}", full_tx_hash);
+/// Part of a transaction a validation finding applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Transaction,
+    Input(InputIndex),
+    Output(OutputKind, OutputIndex),
+}
 
-        // Ensure the transaction is in the `UTXO` set
-        let tx_out_point = match tx_in.previous_out.as_ref() {
-            Some(v) => v,
-            None => {
-                error!("TRANSACTION DOESN'T CONTAIN PREVIOUS OUTPOINT");
-                return (
-                    false,
-                    "Transaction doesn't contain previous outpoint".to_string(),
-                );
-            }
-        };
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Transaction => write!(f, "Transaction"),
+            Self::Input(idx) => write!(f, "Input {idx}"),
+            Self::Output(kind, idx) => write!(f, "{kind} {idx}"),
+        }
+    }
+}
 
-        let tx_out = if let Some(tx_out) = is_in_utxo(tx_out_point) {
-            tx_out
-        } else {
-            error!("UTXO DOESN'T CONTAIN THIS TX");
-            return (
-                false,
-                "UTXO doesn't contain this transaction, or the locktime has not expired"
-                    .to_string(),
-            );
-        };
+/// Reasons a transaction can fail validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxValidationError {
+    Structure(TxStructureError),
+    Input(usize, InputError),
+    Output(OutputError),
+    Unbalanced {
+        inputs: Box<AssetValues>,
+        outputs: Box<AssetValues>,
+        diff: Box<AssetDiff>,
+    },
+    Druid(DruidExpectationError),
+}
+
+impl TxValidationError {
+    /// Error for outputs not spending exactly what the inputs provide
+    ///
+    /// ### Arguments
+    ///
+    /// * `inputs`  - Total spendable from the inputs
+    /// * `outputs` - Total spent by the outputs and fees
+    pub fn unbalanced(inputs: AssetValues, outputs: AssetValues) -> Self {
+        let diff = Box::new(inputs.diff(&outputs));
+        Self::Unbalanced {
+            inputs: Box::new(inputs),
+            outputs: Box::new(outputs),
+            diff,
+        }
+    }
+}
 
-        // Check locktime
-        if tx_out.locktime > current_block_number {
-            error!("LOCKTIME NOT MET");
-            return (false, "Locktime not expired".to_string());
+impl fmt::Display for TxValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Structure(e) => write!(f, "{e}"),
+            Self::Input(idx, e) => write!(f, "Input {idx}: {e}"),
+            Self::Output(e) => write!(f, "{e}"),
+            Self::Unbalanced { diff, .. } => {
+                write!(f, "TxOuts spent don't match TxIns spent: {diff}")
+            }
+            Self::Druid(e) => write!(f, "{e}"),
         }
+    }
+}
+
+impl std::error::Error for TxValidationError {}
+
+/// Every problem found in a transaction by `diagnose_tx`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxDiagnostics {
+    pub findings: Vec<(Location, TxValidationError)>,
+}
+
+impl TxDiagnostics {
+    /// Whether no problem was found
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    fn push(&mut self, location: Location, error: TxValidationError) {
+        self.findings.push((location, error));
+    }
+}
+
+/// Runs every independent check of `tx_is_valid` without stopping at the first failure,
+/// to help wallet developers fix all problems with a transaction at once.
+///
+/// This is a debugging aid only and must never replace `tx_is_valid` when accepting
+/// transactions. The balance is only checked once every input has been resolved,
+/// as it can't be known otherwise
+///
+/// ### Arguments
+///
+/// * `tx`                   - Transaction to diagnose
+/// * `current_block_number` - Current block number
+/// * `is_in_utxo`           - Function to check if a `TxOut` is in the UTXO set
+pub fn diagnose_tx<'a>(
+    tx: &Transaction,
+    current_block_number: u64,
+    is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut> + 'a,
+) -> TxDiagnostics {
+    let mut diagnostics = TxDiagnostics::default();
 
-        // At this point `TxIn` will be valid
-        let tx_out_pk = tx_out.script_public_key.as_ref();
-        let tx_out_hash = construct_tx_in_signable_hash(tx_out_point);
-        let full_tx_hash = construct_tx_in_out_signable_hash(tx_in, &tx.outputs);
+    if let Err(e) = tx.validate_structure() {
+        diagnostics.push(Location::Transaction, TxValidationError::Structure(e));
+    }
 
-        debug!("full_tx_hash: {:?}", full_tx_hash);
+    let expectations = tx.druid_info.iter().flat_map(|d| &d.expectations);
+    for e in expectations.filter_map(|e| e.validate().err()) {
+        diagnostics.push(Location::Transaction, TxValidationError::Druid(e));
+    }
 
-        if let Some(pk) = tx_out_pk {
-            // Check will need to include other signature types here
-            if !tx_has_valid_p2pkh_sig(&tx_in.script_signature, &full_tx_hash, pk)
-                && !tx_has_valid_p2sh_script(&tx_in.script_signature, pk)
-            {
-                error!("INVALID SIGNATURE OR SCRIPT TYPE");
-                return (false, "Invalid signature or script structure".to_string());
+    let mut tx_ins_spent: AssetValues = Default::default();
+    let mut all_resolved = true;
+    for (idx, tx_in) in tx.inputs.iter().enumerate() {
+        let location = Location::Input(InputIndex::from_position(idx));
+        match resolve_input(tx_in, &is_in_utxo) {
+            Ok((out_point, tx_out)) => {
+                tx_ins_spent.update_add(&tx_out.value.clone().with_fixed_hash(out_point));
+                if let Err(e) = verify_input(tx, tx_in, tx_out, current_block_number) {
+                    diagnostics.push(location, TxValidationError::Input(idx, e));
+                }
+            }
+            Err(e) => {
+                all_resolved = false;
+                diagnostics.push(location, TxValidationError::Input(idx, e));
             }
-        } else {
-            return (false, "Previous outpoint has no public key".to_string());
         }
+    }
 
-        let asset = tx_out.value.clone().with_fixed_hash(tx_out_point);
-        tx_ins_spent.update_add(&asset);
+    let mut tx_outs_spent: AssetValues = Default::default();
+    let mut items_remaining = tx_ins_spent.items.clone();
+    let all_outs = all_outputs(&tx.outputs, &tx.fees);
+    let context = ValidationContext::default();
+    for (idx, (kind, tx_out)) in all_outs {
+        if let Err(e) = validate_output(idx, tx_out, kind, &mut items_remaining, &context) {
+            let location = Location::Output(kind, OutputIndex::from_position(idx));
+            diagnostics.push(location, TxValidationError::Output(e));
+        }
+        tx_outs_spent.update_add(&tx_out.value);
     }
 
-    debug!(
-        "txs are valid: {:?}",
-        tx_outs_are_valid(&tx.outputs, &tx.fees, tx_ins_spent.clone())
-    );
+    if all_resolved && !tx_outs_spent.is_equal(&tx_ins_spent) {
+        let unbalanced = TxValidationError::unbalanced(tx_ins_spent, tx_outs_spent);
+        diagnostics.push(Location::Transaction, unbalanced);
+    }
 
-    tx_outs_are_valid(&tx.outputs, &tx.fees, tx_ins_spent)
+    diagnostics
 }
 
-/// Verifies that the outgoing `TxOut`s are valid. Returns false if a single
-/// transaction doesn't verify.
+/// Kind of a transaction output, determining which validation rules apply to it
+///
+/// * `Normal`  - A regular output in `Transaction::outputs`
+/// * `Fee`     - A fee output in `Transaction::fees`, which must be `Token`-only without locktime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputKind {
+    Normal,
+    Fee,
+}
+
+impl fmt::Display for OutputKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputKind::Normal => write!(f, "Output"),
+            OutputKind::Fee => write!(f, "Fee"),
+        }
+    }
+}
+
+/// Reasons a single transaction output can fail validation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputError {
+    InvalidAddressLength(OutputKind, usize, String),
+    MalformedAddress(OutputKind, usize, String),
+    FeeNotToken(usize),
+    FeeHasLocktime(usize),
+    ItemOnSpendInvalid(OutputKind, usize),
+    TokenClassOnSpendInvalid(OutputKind, usize),
+    UnknownGenesisHash(OutputKind, usize, String),
+    InsufficientItems(OutputKind, usize, String),
+    InvalidItem(OutputKind, usize, AssetError),
+    LegacyAddress(OutputKind, usize, String),
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidAddressLength(kind, idx, address) => {
+                write!(f, "Address {address} in {kind} {idx} has invalid length")
+            }
+            Self::MalformedAddress(kind, idx, address) => {
+                write!(f, "Address {address} in {kind} {idx} is not lowercase hex")
+            }
+            Self::FeeNotToken(idx) => write!(f, "Fee {idx} is not a token amount"),
+            Self::FeeHasLocktime(idx) => write!(f, "Fee {idx} has a locktime"),
+            Self::ItemOnSpendInvalid(..) => write!(
+                f,
+                "On-spending items needs empty metadata and non-empty genesis hash"
+            ),
+            Self::TokenClassOnSpendInvalid(..) => {
+                write!(
+                    f,
+                    "On-spending token class amounts needs non-empty genesis hash"
+                )
+            }
+            Self::UnknownGenesisHash(kind, idx, genesis_hash) => write!(
+                f,
+                "{kind} {idx} references genesis hash {genesis_hash} not present in inputs"
+            ),
+            Self::InsufficientItems(kind, idx, genesis_hash) => write!(
+                f,
+                "{kind} {idx} spends more items of genesis hash {genesis_hash} than inputs provide"
+            ),
+            Self::InvalidItem(kind, idx, e) => write!(f, "{kind} {idx}: {e}"),
+            Self::LegacyAddress(kind, idx, address) => {
+                write!(f, "Address {address} in {kind} {idx} has a legacy format")
+            }
+        }
+    }
+}
+
+/// Verifies that the outgoing `TxOut`s are valid, returning the first
+/// problem found
 ///
 /// TODO: Abstract to data assets
 ///
 /// ### Arguments
 ///
 /// * `tx_outs`      - `TxOut`s to verify
+/// * `fees`         - Fee `TxOut`s to verify
 /// * `tx_ins_spent` - Total amount spendable from `TxIn`s
 pub fn tx_outs_are_valid(
     tx_outs: &[TxOut],
     fees: &[TxOut],
     tx_ins_spent: AssetValues,
-) -> (bool, String) {
+) -> Result<(), TxValidationError> {
+    let context = ValidationContext::default();
+    tx_outs_are_valid_in_context(tx_outs, fees, tx_ins_spent, &context)
+}
+
+/// Checks outputs and fees as `tx_outs_are_valid` does, under the policy of `context`
+///
+/// ### Arguments
+///
+/// * `tx_outs`         - Outputs of the transaction
+/// * `fees`            - Fees of the transaction
+/// * `tx_ins_spent`    - Assets spent by the inputs
+/// * `context`         - Validation policy to apply
+pub fn tx_outs_are_valid_in_context(
+    tx_outs: &[TxOut],
+    fees: &[TxOut],
+    tx_ins_spent: AssetValues,
+    context: &ValidationContext,
+) -> Result<(), TxValidationError> {
     let mut tx_outs_spent: AssetValues = Default::default();
 
-    for tx_out in tx_outs {
-        // Addresses must have valid length
-        if let Some(addr) = &tx_out.script_public_key {
-            if !address_has_valid_length(addr) {
-                trace!("Address has invalid length");
-                return (false, "Address in output has invalid length".to_string());
-            }
+    // Item amounts provided by the inputs that have not yet been claimed by an output
+    let mut items_remaining = tx_ins_spent.items.clone();
+
+    for (idx, (kind, tx_out)) in all_outputs(tx_outs, fees) {
+        if let Err(e) = validate_output(idx, tx_out, kind, &mut items_remaining, context) {
+            error!("INVALID {kind} {idx}: {e}");
+            return Err(TxValidationError::Output(e));
         }
 
         tx_outs_spent.update_add(&tx_out.value);
     }
 
-    // Check fees as well
-    for fee in fees {
-        // Addresses must have valid length
-        if let Some(addr) = &fee.script_public_key {
-            if !address_has_valid_length(addr) {
-                trace!("Address has invalid length");
-                return (false, "Address in fee has invalid length".to_string());
+    // Ensure that the `TxIn`s correlate with the `TxOut`s
+    match tx_outs_spent.is_equal(&tx_ins_spent) {
+        true => Ok(()),
+        false => {
+            let e = TxValidationError::unbalanced(tx_ins_spent, tx_outs_spent);
+            error!("INVALID TRANSACTION: {e}");
+            Err(e)
+        }
+    }
+}
+
+/// Iterates over the outputs then the fees of a transaction, each with its kind
+/// and its index within its own collection
+///
+/// ### Arguments
+///
+/// * `tx_outs` - Outputs of the transaction
+/// * `fees`    - Fees of the transaction
+fn all_outputs<'a>(
+    tx_outs: &'a [TxOut],
+    fees: &'a [TxOut],
+) -> impl Iterator<Item = (usize, (OutputKind, &'a TxOut))> {
+    tx_outs
+        .iter()
+        .map(|out| (OutputKind::Normal, out))
+        .enumerate()
+        .chain(fees.iter().map(|fee| (OutputKind::Fee, fee)).enumerate())
+}
+
+/// Validates a single on-spending transaction output. All per-output rules live here,
+/// with `kind` selecting the rules that only apply to fees.
+///
+/// Item outputs are claimed against `items_remaining`, so this must be called once for
+/// every output of the transaction.
+///
+/// ### Arguments
+///
+/// * `index`           - Index of the output within its collection
+/// * `tx_out`          - Output to validate
+/// * `kind`            - Kind of the output
+/// * `items_remaining` - Unclaimed input item amounts, keyed by `genesis_hash`
+/// * `context`         - Validation policy to apply
+pub fn validate_output(
+    index: usize,
+    tx_out: &TxOut,
+    kind: OutputKind,
+    items_remaining: &mut BTreeMap<String, u64>,
+    context: &ValidationContext,
+) -> Result<(), OutputError> {
+    // Addresses must have valid length
+    if let Some(addr) = &tx_out.script_public_key {
+        if !address_has_valid_length(addr) {
+            trace!("Address has invalid length");
+            return Err(OutputError::InvalidAddressLength(kind, index, addr.clone()));
+        }
+        // Non-canonical addresses are only logged during the hash namespace grace period
+        if !is_address(addr) {
+            if !context.hash_namespace_grace {
+                return Err(OutputError::MalformedAddress(kind, index, addr.clone()));
             }
+            trace!("Address {addr} is not canonical hex");
+        }
+        if !context.allow_legacy_addresses && addr.len() == V0_ADDRESS_LENGTH * 2 {
+            trace!("Address is of a legacy format");
+            return Err(OutputError::LegacyAddress(kind, index, addr.clone()));
         }
+    }
 
-        tx_outs_spent.update_add(&fee.value);
+    if kind == OutputKind::Fee {
+        if !tx_out.value.is_token() {
+            return Err(OutputError::FeeNotToken(index));
+        }
+        if tx_out.locktime != 0 {
+            return Err(OutputError::FeeHasLocktime(index));
+        }
     }
 
-    // Ensure that the `TxIn`s correlate with the `TxOut`s
-    match tx_outs_spent.is_equal(&tx_ins_spent) {
-        true => (true, "".to_string()),
-        false => {
-            error!("TXOUTS SPENT DOESN'T MATCH TXINS SPENT");
-            (false, "TxOuts spent don't match TxIns spent".to_string())
+    // Token class amounts are balanced against inputs of the same `genesis_hash`
+    let item = match &tx_out.value {
+        Asset::Item(item) => item,
+        Asset::TokenWithMetadata {
+            genesis_hash: None, ..
+        } => return Err(OutputError::TokenClassOnSpendInvalid(kind, index)),
+        Asset::Token(_) | Asset::TokenWithMetadata { .. } => return Ok(()),
+    };
+    if let Err(e) = item.validate_amount() {
+        return Err(OutputError::InvalidItem(kind, index, e));
+    }
+
+    // `Item` assets MUST have a DRS value and no metadata when they are getting on-spent
+    let genesis_hash = match &item.genesis_hash {
+        Some(genesis_hash) if item.metadata.is_none() => genesis_hash,
+        _ => return Err(OutputError::ItemOnSpendInvalid(kind, index)),
+    };
+
+    // Item outputs must be backed by input items of the same `genesis_hash`
+    match items_remaining.get_mut(genesis_hash) {
+        None => Err(OutputError::UnknownGenesisHash(
+            kind,
+            index,
+            genesis_hash.clone(),
+        )),
+        Some(remaining) if *remaining < item.amount => Err(OutputError::InsufficientItems(
+            kind,
+            index,
+            genesis_hash.clone(),
+        )),
+        Some(remaining) => {
+            *remaining -= item.amount;
+            Ok(())
         }
     }
 }
 
-/// Checks whether a create transaction has a valid input script
+/// Checks whether a create transaction of the current version has a valid input script
 ///
 /// ### Arguments
 ///
 /// * `script`      - Script to validate
 /// * `asset`       - Asset to be created
 pub fn tx_has_valid_create_script(script: &Script, asset: &Asset) -> bool {
-    let mut it = script.stack.iter();
-    let asset_hash = construct_tx_in_signable_asset_hash(asset);
+    tx_has_valid_create_script_at_version(script, asset, NETWORK_VERSION as usize)
+}
+
+/// Checks whether a create transaction has a valid input script. Transactions from
+/// `CREATE_SCRIPT_INTERPRETER_VERSION` onwards are checked with `Script::interpret_create`,
+/// earlier ones against the exact pattern of `Script::new_create_asset`
+///
+/// ### Arguments
+///
+/// * `script`      - Script to validate
+/// * `asset`       - Asset to be created
+/// * `tx_version`  - Version of the create transaction
+pub fn tx_has_valid_create_script_at_version(
+    script: &Script,
+    asset: &Asset,
+    tx_version: usize,
+) -> bool {
+    let asset_hash = SignableMessage::asset(asset).hash_at_version(tx_version);
 
     if let Asset::Item(r) = asset {
-        if !item_has_valid_size(r) {
-            trace!("Item metadata is too large");
+        if let Err(e) = validate_item_metadata(r.metadata.as_deref()) {
+            trace!("{e}");
             return false;
         }
     }
 
+    if let Asset::TokenWithMetadata {
+        amount,
+        metadata,
+        genesis_hash,
+    } = asset
+    {
+        if genesis_hash.is_some() {
+            trace!("Created token class already has a genesis hash");
+            return false;
+        }
+        if !token_has_valid_supply(amount, metadata) {
+            trace!("Token supply is invalid");
+            return false;
+        }
+    }
+
+    if tx_version >= CREATE_SCRIPT_INTERPRETER_VERSION {
+        let context = CreateContext {
+            asset_hash,
+            block_number: None,
+            current_height: None,
+        };
+        return match script.interpret_create(&context) {
+            Ok(claims) => claims.asset_hash == context.asset_hash,
+            Err(e) => {
+                trace!("Invalid script for create: {e}");
+                false
+            }
+        };
+    }
+
+    let mut it = script.stack.iter();
     if let (
         Some(StackEntry::Op(OpCodes::OP_CREATE)),
         Some(StackEntry::Num(_)),
@@ -226,6 +701,38 @@ pub fn tx_has_valid_create_script(script: &Script, asset: &Asset) -> bool {
     false
 }
 
+/// Checks that a transaction is a valid create transaction: a single input without a
+/// previous outpoint, whose create script signs the asset of the single output. The
+/// signed asset doesn't cover an item's genesis hash, so only its shape is checked
+///
+/// ### Arguments
+///
+/// * `tx`  - Transaction to validate
+pub fn validate_create_tx(tx: &Transaction) -> Result<(), CreateTxError> {
+    tx.validate_structure()?;
+
+    let tx_in = match tx.inputs.as_slice() {
+        [tx_in] if tx_in.previous_out.is_none() => tx_in,
+        _ => return Err(CreateTxError::NotCreateInput),
+    };
+    let asset = match tx.outputs.as_slice() {
+        [tx_out] => &tx_out.value,
+        tx_outs => return Err(CreateTxError::OutputCount(tx_outs.len())),
+    };
+    if let Asset::Item(item) = asset {
+        item.validate_amount()?;
+        if let Some(hash) = item.genesis_hash().filter(|hash| !is_genesis_hash(hash)) {
+            return Err(CreateTxError::GenesisHashConflict(hash.to_owned()));
+        }
+        validate_item_metadata(item.metadata.as_deref())?;
+    }
+
+    match tx_has_valid_create_script_at_version(&tx_in.script_signature, asset, tx.version) {
+        true => Ok(()),
+        false => Err(CreateTxError::InvalidScript),
+    }
+}
+
 /// Checks whether a transaction to spend tokens in P2PKH has a valid signature
 ///
 /// ### Arguments
@@ -276,6 +783,55 @@ fn tx_has_valid_p2pkh_sig(script: &Script, outpoint_hash: &str, tx_out_pub_key:
     false
 }
 
+/// Checks whether a transaction to spend tokens in dual signature (2-of-2) P2PKH has
+/// valid signatures from both keys committed to by the previous tx_out address
+///
+/// ### Arguments
+///
+/// * `script`          - Script to validate
+/// * `outpoint_hash`   - Hash of the corresponding outpoint
+/// * `tx_out_pub_key`  - Dual address of the previous tx_out
+fn tx_has_valid_dual_p2pkh_sig(script: &Script, outpoint_hash: &str, tx_out_pub_key: &str) -> bool {
+    let mut it = script.stack.iter();
+
+    if let (
+        Some(StackEntry::Bytes(b)),
+        Some(StackEntry::Op(OpCodes::OP_DUP)),
+        Some(StackEntry::Signature(_)),
+        Some(StackEntry::PubKey(pk_a)),
+        Some(StackEntry::Op(OpCodes::OP_CHECKSIGVERIFY)),
+        Some(StackEntry::Signature(_)),
+        Some(StackEntry::PubKey(pk_b)),
+        Some(StackEntry::Op(OpCodes::OP_CHECKSIG)),
+        None,
+    ) = (
+        it.next(),
+        it.next(),
+        it.next(),
+        it.next(),
+        it.next(),
+        it.next(),
+        it.next(),
+        it.next(),
+        it.next(),
+    ) {
+        if construct_dual_address(pk_a, pk_b) == tx_out_pub_key
+            && b == outpoint_hash
+            && script.interpret()
+        {
+            return true;
+        }
+    }
+
+    trace!(
+        "Invalid dual P2PKH script: {:?} tx_out_pub_key: {}",
+        script.stack,
+        tx_out_pub_key
+    );
+
+    false
+}
+
 /// Checks whether a transaction to spend tokens in P2SH has a valid hash and executing script
 ///
 /// ### Arguments
@@ -283,10 +839,27 @@ fn tx_has_valid_p2pkh_sig(script: &Script, outpoint_hash: &str, tx_out_pub_key:
 /// * `script`          - Script to validate
 /// * `address`         - Address of the P2SH transaction
 pub fn tx_has_valid_p2sh_script(script: &Script, address: &str) -> bool {
-    let p2sh_address = construct_p2sh_address(script);
+    let context = ExecutionContext::default();
+    tx_has_valid_p2sh_script_in_context(script, address, NETWORK_VERSION as usize, &context)
+}
 
-    if p2sh_address == address {
-        return script.interpret();
+/// Checks whether a transaction to spend tokens in P2SH has a valid hash and a script
+/// executing under `context`, which may carry the outputs of the spending transaction
+///
+/// ### Arguments
+///
+/// * `script`          - Script to validate
+/// * `address`         - Address of the P2SH transaction
+/// * `tx_version`      - Version of the spending transaction, selecting the address formats
+/// * `context`         - Execution context of the spending transaction
+pub fn tx_has_valid_p2sh_script_in_context(
+    script: &Script,
+    address: &str,
+    tx_version: usize,
+    context: &ExecutionContext,
+) -> bool {
+    if is_p2sh_address_of(script, address, tx_version) {
+        return script.execute_in_context(context).unwrap_or_default();
     }
 
     trace!(
@@ -298,38 +871,292 @@ pub fn tx_has_valid_p2sh_script(script: &Script, address: &str) -> bool {
     false
 }
 
-/// Checks that a item's metadata conforms to the network size constraint
+/// Checks whether a transaction settling an escrow unlocks a `Script::new_escrow` script
+/// committed to by the previous tx_out address, with signatures over this transaction.
+/// The arbiter can only settle the escrow from its timeout
 ///
 /// ### Arguments
 ///
-/// * `item` - Item to check
-fn item_has_valid_size(item: &ItemAsset) -> bool {
-    if let Some(metadata) = &item.metadata {
-        return metadata.len() <= MAX_METADATA_BYTES;
+/// * `script`                  - Script to validate
+/// * `outpoint_hash`           - Hash of the corresponding outpoint
+/// * `tx_out_pub_key`          - P2SH address of the previous tx_out
+/// * `tx_version`              - Version of the spending transaction
+/// * `current_block_number`    - Current block number
+fn tx_has_valid_escrow_script(
+    script: &Script,
+    outpoint_hash: &str,
+    tx_out_pub_key: &str,
+    tx_version: usize,
+    current_block_number: u64,
+) -> bool {
+    let mut it = script.stack.iter();
+
+    if let (
+        Some(StackEntry::Bytes(b)),
+        Some(StackEntry::Signature(_)),
+        Some(StackEntry::Signature(_)),
+        Some(StackEntry::Num(branch)),
+    ) = (it.next(), it.next(), it.next(), it.next())
+    {
+        let lock = Script::from(it.as_slice().to_vec());
+        let branch_open = |timeout_block| match branch {
+            1 => true,
+            0 => current_block_number >= timeout_block,
+            _ => false,
+        };
+
+        if escrow_timeout(&lock).is_some_and(branch_open)
+            && is_p2sh_address_of(&lock, tx_out_pub_key, tx_version)
+            && b == outpoint_hash
+            && script.interpret()
+        {
+            return true;
+        }
     }
-    true
+
+    trace!(
+        "Invalid escrow script: {:?}, address: {}",
+        script.stack,
+        tx_out_pub_key
+    );
+
+    false
 }
 
-/// Checks that an address has a valid length
+/// Gets the timeout of an escrow script, if it is exactly a `Script::new_escrow` script
 ///
 /// ### Arguments
 ///
-/// * `address` - Address to check
-fn address_has_valid_length(address: &str) -> bool {
-    address.len() == 32 || address.len() == 64
+/// * `script`  - Script to check
+fn escrow_timeout(script: &Script) -> Option<u64> {
+    let entry = |idx: usize| script.stack.get(idx);
+    match (entry(2), entry(3), entry(7), entry(12)) {
+        (
+            Some(StackEntry::PubKey(buyer)),
+            Some(StackEntry::PubKey(seller)),
+            Some(StackEntry::Num(timeout_block)),
+            Some(StackEntry::PubKey(arbiter)),
+        ) => {
+            let timeout_block = *timeout_block as u64;
+            let escrow = Script::new_escrow(*buyer, *seller, *arbiter, timeout_block);
+            (&escrow == script).then_some(timeout_block)
+        }
+        _ => None,
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::vec;
+/// Reasons an item's metadata is rejected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataError {
+    TooLarge(usize),
+    InvalidJson(String),
+    NotAnObject,
+    MissingSchemaVersion,
+    UnknownSchema(u64),
+    MissingField(u64, String),
+}
 
-    use super::*;
-    use crate::constants::ITEM_ACCEPT_VAL;
-    use crate::primitives::asset::Asset;
-    use crate::primitives::druid::DdeValues;
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::TooLarge(len) => write!(f, "Item metadata is too large: {len} bytes"),
+            Self::InvalidJson(e) => write!(f, "Item metadata is not valid JSON: {e}"),
+            Self::NotAnObject => write!(f, "Item metadata is not a JSON object"),
+            Self::MissingSchemaVersion => {
+                write!(f, "Item metadata doesn't declare a numeric schema version")
+            }
+            Self::UnknownSchema(version) => {
+                write!(f, "Item metadata schema version {version} is unknown")
+            }
+            Self::MissingField(version, field) => write!(
+                f,
+                "Item metadata is missing field {field} required by schema version {version}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MetadataError {}
+
+/// Checks that a item's metadata conforms to the network size constraint, the only
+/// check transaction validation makes on metadata
+///
+/// ### Arguments
+///
+/// * `metadata` - Item metadata to check
+pub fn validate_item_metadata(metadata: Option<&str>) -> Result<(), MetadataError> {
+    match metadata {
+        Some(metadata) if metadata.len() > MAX_METADATA_BYTES => {
+            Err(MetadataError::TooLarge(metadata.len()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks item metadata before constructing a transaction holding it: as
+/// `validate_item_metadata` does and, with the `validate-metadata` feature, that plaintext
+/// metadata is valid JSON. Committed metadata is never JSON, so only its size is checked.
+/// The JSON check only restricts the transactions a node builds and is never applied
+/// when validating transactions, so nodes built with and without the feature agree on
+/// which transactions are valid
+///
+/// ### Arguments
+///
+/// * `metadata` - Item metadata to check
+pub fn check_item_metadata_to_construct(metadata: Option<&str>) -> Result<(), MetadataError> {
+    validate_item_metadata(metadata)?;
+
+    #[cfg(feature = "validate-metadata")]
+    if let Some(metadata) = metadata {
+        if MetadataCommitment::from_metadata(metadata).is_none() {
+            if let Err(e) = serde_json::from_str::<serde_json::Value>(metadata) {
+                return Err(MetadataError::InvalidJson(e.to_string()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fields an item metadata document must hold for one version of the metadata format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemMetadataSchema {
+    pub version: u64,
+    pub required_fields: Vec<String>,
+}
+
+impl ItemMetadataSchema {
+    /// Version 1 of the item metadata format
+    pub fn v1() -> Self {
+        Self {
+            version: 1,
+            required_fields: ["name", "description", "image", "url"]
+                .iter()
+                .map(|f| f.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Treatment of metadata declaring a schema version missing from the registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownSchemaPolicy {
+    Reject,
+    PassThrough,
+}
+
+/// Known versions of the item metadata format, by version number
+#[derive(Debug, Clone)]
+pub struct ItemMetadataSchemaRegistry {
+    schemas: BTreeMap<u64, ItemMetadataSchema>,
+    unknown_schema_policy: UnknownSchemaPolicy,
+}
+
+impl ItemMetadataSchemaRegistry {
+    /// Creates an empty registry
+    ///
+    /// ### Arguments
+    ///
+    /// * `unknown_schema_policy` - Treatment of unregistered schema versions
+    pub fn new(unknown_schema_policy: UnknownSchemaPolicy) -> Self {
+        Self {
+            schemas: BTreeMap::new(),
+            unknown_schema_policy,
+        }
+    }
+
+    /// Adds a schema, replacing any previous schema of the same version
+    ///
+    /// ### Arguments
+    ///
+    /// * `schema` - Schema to add
+    pub fn register(&mut self, schema: ItemMetadataSchema) {
+        self.schemas.insert(schema.version, schema);
+    }
+
+    /// Returns the schema of a version, if registered
+    ///
+    /// ### Arguments
+    ///
+    /// * `version` - Schema version
+    pub fn get(&self, version: u64) -> Option<&ItemMetadataSchema> {
+        self.schemas.get(&version)
+    }
+}
+
+/// Checks that item metadata is a JSON object holding every field required by the
+/// schema version it declares in its `"schema"` field
+///
+/// ### Arguments
+///
+/// * `metadata` - Item metadata to check
+/// * `registry` - Known schema versions
+pub fn validate_against_schema(
+    metadata: &str,
+    registry: &ItemMetadataSchemaRegistry,
+) -> Result<(), MetadataError> {
+    let document: serde_json::Value =
+        serde_json::from_str(metadata).map_err(|e| MetadataError::InvalidJson(e.to_string()))?;
+    let fields = document.as_object().ok_or(MetadataError::NotAnObject)?;
+    let version = fields
+        .get(ITEM_METADATA_SCHEMA_FIELD)
+        .and_then(|v| v.as_u64())
+        .ok_or(MetadataError::MissingSchemaVersion)?;
+
+    let schema = match (registry.get(version), registry.unknown_schema_policy) {
+        (Some(schema), _) => schema,
+        (None, UnknownSchemaPolicy::PassThrough) => return Ok(()),
+        (None, UnknownSchemaPolicy::Reject) => return Err(MetadataError::UnknownSchema(version)),
+    };
+    match schema
+        .required_fields
+        .iter()
+        .find(|field| !fields.contains_key(*field))
+    {
+        Some(field) => Err(MetadataError::MissingField(version, field.clone())),
+        None => Ok(()),
+    }
+}
+
+/// Checks that a created token class has a valid total supply,
+/// not exceeding `TOTAL_TOKENS` nor creating more than its total supply
+///
+/// ### Arguments
+///
+/// * `amount`      - Amount of tokens to create
+/// * `metadata`    - Metadata of the token class
+fn token_has_valid_supply(amount: &TokenAmount, metadata: &TokenMetadata) -> bool {
+    metadata.total_supply <= TOTAL_TOKENS && amount.0 <= metadata.total_supply
+}
+
+/// Checks that an address has a valid length
+///
+/// ### Arguments
+///
+/// * `address` - Address to check
+fn address_has_valid_length(address: &str) -> bool {
+    address.len() == 32 || address.len() == 64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use super::*;
+    use crate::constants::ITEM_ACCEPT_VAL;
+    use crate::crypto::CryptoError;
+    use crate::primitives::asset::Asset;
+    use crate::primitives::druid::DdeValues;
     use crate::primitives::transaction::OutPoint;
-    use crate::utils::test_utils::generate_tx_with_ins_and_outs_assets;
+    use crate::script::lang::{
+        block_number_as_num, ResourceReport, ScriptBuilder, Signer, SignerRequirements,
+        TxOutConstraint,
+    };
+    use crate::utils::test_utils::{
+        generate_tx_with_ins_and_outs_assets, generate_unsigned_tx_ins,
+    };
     use crate::utils::transaction_utils::*;
+    use crate::utils::validation_cache::ValidationCache;
 
     /*---- FLOW CONTROL OPS ----*/
 
@@ -359,8 +1186,8 @@ mod tests {
         let mut v: Vec<StackEntry> = vec![];
         op_if(&mut stack, &mut cond_stack);
         assert_eq!(stack.main_stack, v);
-        assert_eq!(cond_stack.size, 1);
-        assert_eq!(cond_stack.first_false_pos, None);
+        assert_eq!(cond_stack.depth(), 1);
+        assert_eq!(cond_stack.first_false_pos(), None);
         /// op_if([0], {0,None}) -> [], {1,0}
         let mut stack = Stack::new();
         stack.push(StackEntry::Num(0));
@@ -368,19 +1195,18 @@ mod tests {
         let mut v: Vec<StackEntry> = vec![];
         op_if(&mut stack, &mut cond_stack);
         assert_eq!(stack.main_stack, v);
-        assert_eq!(cond_stack.size, 1);
-        assert_eq!(cond_stack.first_false_pos, Some(0));
+        assert_eq!(cond_stack.depth(), 1);
+        assert_eq!(cond_stack.first_false_pos(), Some(0));
         /// op_if([1], {1,0}) -> [1], {2,0}
         let mut stack = Stack::new();
         stack.push(StackEntry::Num(1));
         let mut cond_stack = ConditionStack::new();
-        cond_stack.size = 1;
-        cond_stack.first_false_pos = Some(0);
+        cond_stack.push(false);
         let mut v: Vec<StackEntry> = vec![StackEntry::Num(1)];
         op_if(&mut stack, &mut cond_stack);
         assert_eq!(stack.main_stack, v);
-        assert_eq!(cond_stack.size, 2);
-        assert_eq!(cond_stack.first_false_pos, Some(0));
+        assert_eq!(cond_stack.depth(), 2);
+        assert_eq!(cond_stack.first_false_pos(), Some(0));
         /// error item type
         let mut stack = Stack::new();
         stack.push(StackEntry::Bytes(String::new()));
@@ -404,8 +1230,8 @@ mod tests {
         let mut v: Vec<StackEntry> = vec![];
         op_notif(&mut stack, &mut cond_stack);
         assert_eq!(stack.main_stack, v);
-        assert_eq!(cond_stack.size, 1);
-        assert_eq!(cond_stack.first_false_pos, None);
+        assert_eq!(cond_stack.depth(), 1);
+        assert_eq!(cond_stack.first_false_pos(), None);
         /// op_notif([1], {0,None}) -> [], {1,0}
         let mut stack = Stack::new();
         stack.push(StackEntry::Num(1));
@@ -413,19 +1239,18 @@ mod tests {
         let mut v: Vec<StackEntry> = vec![];
         op_notif(&mut stack, &mut cond_stack);
         assert_eq!(stack.main_stack, v);
-        assert_eq!(cond_stack.size, 1);
-        assert_eq!(cond_stack.first_false_pos, Some(0));
+        assert_eq!(cond_stack.depth(), 1);
+        assert_eq!(cond_stack.first_false_pos(), Some(0));
         /// op_notif([0], {1,0}) -> [0], {2,0}
         let mut stack = Stack::new();
         stack.push(StackEntry::Num(0));
         let mut cond_stack = ConditionStack::new();
-        cond_stack.size = 1;
-        cond_stack.first_false_pos = Some(0);
+        cond_stack.push(false);
         let mut v: Vec<StackEntry> = vec![StackEntry::Num(0)];
         op_notif(&mut stack, &mut cond_stack);
         assert_eq!(stack.main_stack, v);
-        assert_eq!(cond_stack.size, 2);
-        assert_eq!(cond_stack.first_false_pos, Some(0));
+        assert_eq!(cond_stack.depth(), 2);
+        assert_eq!(cond_stack.first_false_pos(), Some(0));
         /// error item type
         let mut stack = Stack::new();
         stack.push(StackEntry::Bytes(String::new()));
@@ -444,29 +1269,58 @@ mod tests {
     fn test_else() {
         /// op_else({1,None}) -> {1,0}
         let mut cond_stack = ConditionStack::new();
-        cond_stack.size = 1;
-        cond_stack.first_false_pos = None;
+        cond_stack.push(true);
         op_else(&mut cond_stack);
-        assert_eq!(cond_stack.size, 1);
-        assert_eq!(cond_stack.first_false_pos, Some(0));
+        assert_eq!(cond_stack.depth(), 1);
+        assert_eq!(cond_stack.first_false_pos(), Some(0));
         /// op_else({1,0}) -> {1,None}
         let mut cond_stack = ConditionStack::new();
-        cond_stack.size = 1;
-        cond_stack.first_false_pos = Some(0);
+        cond_stack.push(false);
         op_else(&mut cond_stack);
-        assert_eq!(cond_stack.size, 1);
-        assert_eq!(cond_stack.first_false_pos, None);
+        assert_eq!(cond_stack.depth(), 1);
+        assert_eq!(cond_stack.first_false_pos(), None);
         /// op_else({2,0}) -> {2,0}
         let mut cond_stack = ConditionStack::new();
-        cond_stack.size = 2;
-        cond_stack.first_false_pos = Some(0);
+        cond_stack.push(false);
+        cond_stack.push(true);
         op_else(&mut cond_stack);
-        assert_eq!(cond_stack.size, 2);
-        assert_eq!(cond_stack.first_false_pos, Some(0));
+        assert_eq!(cond_stack.depth(), 2);
+        assert_eq!(cond_stack.first_false_pos(), Some(0));
+        /// op_else(op_else({1,None})) -> fail
+        let mut cond_stack = ConditionStack::new();
+        cond_stack.push(true);
+        assert!(op_else(&mut cond_stack));
+        assert!(!op_else(&mut cond_stack));
+        assert_eq!(cond_stack.toggle(), Err(ScriptError::DuplicateElse));
+        assert_eq!(cond_stack.first_false_pos(), Some(0));
+        /// op_else(op_else({1,None})) -> {1,None} below the fork height
+        let mut cond_stack = ConditionStack::at_height(Some(0));
+        cond_stack.push(true);
+        assert!(op_else(&mut cond_stack));
+        assert!(op_else(&mut cond_stack));
+        assert_eq!(cond_stack.first_false_pos(), None);
+        /// An inner block's OP_ELSE doesn't count towards the outer block's
+        let mut cond_stack = ConditionStack::new();
+        cond_stack.push(true);
+        cond_stack.push(false);
+        assert!(op_else(&mut cond_stack));
+        assert!(op_endif(&mut cond_stack));
+        assert!(op_else(&mut cond_stack));
+        assert_eq!(cond_stack.first_false_pos(), Some(0));
+        assert!(!op_else(&mut cond_stack));
+        /// An outer block's OP_ELSE doesn't count towards a new inner block's
+        let mut cond_stack = ConditionStack::new();
+        cond_stack.push(false);
+        assert!(op_else(&mut cond_stack));
+        cond_stack.push(true);
+        assert!(op_else(&mut cond_stack));
+        assert_eq!(cond_stack.first_false_pos(), Some(1));
+        assert!(!op_else(&mut cond_stack));
         /// empty condition stack
         let mut cond_stack = ConditionStack::new();
         let b = op_else(&mut cond_stack);
-        assert!(!b)
+        assert!(!b);
+        assert_eq!(cond_stack.toggle(), Err(ScriptError::EmptyCondition))
     }
 
     #[test]
@@ -474,31 +1328,79 @@ mod tests {
     fn test_endif() {
         /// op_endif({1,None}) -> {0,None}
         let mut cond_stack = ConditionStack::new();
-        cond_stack.size = 1;
-        cond_stack.first_false_pos = None;
+        cond_stack.push(true);
         op_endif(&mut cond_stack);
-        assert_eq!(cond_stack.size, 0);
-        assert_eq!(cond_stack.first_false_pos, None);
+        assert_eq!(cond_stack.depth(), 0);
+        assert_eq!(cond_stack.first_false_pos(), None);
         /// op_endif({1,0}) -> {0,None}
         let mut cond_stack = ConditionStack::new();
-        cond_stack.size = 1;
-        cond_stack.first_false_pos = Some(0);
+        cond_stack.push(false);
         op_endif(&mut cond_stack);
-        assert_eq!(cond_stack.size, 0);
-        assert_eq!(cond_stack.first_false_pos, None);
+        assert_eq!(cond_stack.depth(), 0);
+        assert_eq!(cond_stack.first_false_pos(), None);
         /// op_endif({2,0}) -> {1,0}
         let mut cond_stack = ConditionStack::new();
-        cond_stack.size = 2;
-        cond_stack.first_false_pos = Some(0);
+        cond_stack.push(false);
+        cond_stack.push(true);
         op_endif(&mut cond_stack);
-        assert_eq!(cond_stack.size, 1);
-        assert_eq!(cond_stack.first_false_pos, Some(0));
+        assert_eq!(cond_stack.depth(), 1);
+        assert_eq!(cond_stack.first_false_pos(), Some(0));
         /// empty condition stack
         let mut cond_stack = ConditionStack::new();
         let b = op_endif(&mut cond_stack);
         assert!(!b)
     }
 
+    #[test]
+    /// Checks the condition stack API directly, as a custom control flow opcode would,
+    /// following `1 OP_IF 0 OP_IF <a> OP_ELSE <b> 1 OP_IF <c> OP_ENDIF OP_ENDIF OP_ELSE <d> OP_ENDIF`
+    fn test_condition_stack_api() {
+        let mut cond_stack = ConditionStack::new();
+        let mut executed = Vec::new();
+        let mut run = |cond_stack: &ConditionStack, entry| {
+            if cond_stack.all_true() {
+                executed.push(entry);
+            }
+        };
+
+        cond_stack.push(true);
+        cond_stack.push(false);
+        assert_eq!(
+            (cond_stack.depth(), cond_stack.first_false_pos()),
+            (2, Some(1))
+        );
+        run(&cond_stack, "a");
+        assert_eq!(cond_stack.toggle(), Ok(()));
+        run(&cond_stack, "b");
+        cond_stack.push(true);
+        assert_eq!(
+            (cond_stack.depth(), cond_stack.first_false_pos()),
+            (3, None)
+        );
+        run(&cond_stack, "c");
+        assert_eq!(cond_stack.pop(), Ok(()));
+        assert_eq!(cond_stack.toggle(), Err(ScriptError::DuplicateElse));
+        assert_eq!(cond_stack.pop(), Ok(()));
+        assert_eq!(cond_stack.toggle(), Ok(()));
+        assert_eq!(
+            (cond_stack.depth(), cond_stack.first_false_pos()),
+            (1, Some(0))
+        );
+
+        // Blocks opened within a false block stay false, whatever their condition
+        cond_stack.push(true);
+        assert_eq!(cond_stack.toggle(), Ok(()));
+        assert!(!cond_stack.all_true());
+        assert_eq!(cond_stack.pop(), Ok(()));
+        run(&cond_stack, "d");
+        assert_eq!(cond_stack.pop(), Ok(()));
+
+        assert!(cond_stack.is_empty() && cond_stack.all_true());
+        assert_eq!(cond_stack.pop(), Err(ScriptError::EmptyCondition));
+        assert_eq!(cond_stack.toggle(), Err(ScriptError::EmptyCondition));
+        assert_eq!(executed, vec!["b", "c"]);
+    }
+
     #[test]
     /// Test OP_VERIFY
     fn test_verify() {
@@ -557,7 +1459,7 @@ mod tests {
     fn test_fromaltstack() {
         /// op_fromaltstack([], [1]) -> [1], []
         let mut stack = Stack::new();
-        stack.alt_stack.push(StackEntry::Num(1));
+        stack.push_alt(StackEntry::Num(1));
         let mut v1: Vec<StackEntry> = vec![StackEntry::Num(1)];
         let mut v2: Vec<StackEntry> = vec![];
         op_fromaltstack(&mut stack);
@@ -1092,6 +1994,14 @@ mod tests {
         stack.push(StackEntry::Num(usize::MAX));
         let b = op_substr(&mut stack);
         assert!(!b);
+        /// op_substr(["hello",4,usize::MAX-2]) -> fail, as 4 + usize::MAX-2 overflows
+        let mut stack = Stack::new();
+        stack.push(StackEntry::Bytes("hello".to_string()));
+        stack.push(StackEntry::Num(4));
+        stack.push(StackEntry::Num(usize::MAX - 2));
+        let b = op_substr(&mut stack);
+        assert!(!b);
+        assert!(stack.main_stack.is_empty());
         /// op_substr(["hello",1,""]) -> fail
         let mut stack = Stack::new();
         stack.push(StackEntry::Bytes("hello".to_string()));
@@ -1332,6 +2242,173 @@ mod tests {
         assert!(!b)
     }
 
+    #[test]
+    /// Checks that strict mode rejects comparing `Num` with `Bytes` in OP_EQUAL
+    fn test_equal_strict() {
+        let strict = ExecutionContext {
+            strict_equal: true,
+            ..Default::default()
+        };
+        let lenient = ExecutionContext::default();
+        let script = |x1: StackEntry, x2: StackEntry, op: OpCodes| {
+            Script::from(vec![x1, x2, StackEntry::Op(op)])
+        };
+        let num = || StackEntry::Num(5);
+        let bytes = || StackEntry::Bytes("05".to_owned());
+
+        // Mismatched types are unequal in lenient mode and an error in strict mode
+        for (x1, x2) in [(num(), bytes()), (bytes(), num())] {
+            let equal = script(x1.clone(), x2.clone(), OpCodes::OP_EQUAL);
+            assert_eq!(
+                equal.evaluate(&lenient).map(|s| s.main_stack),
+                Ok(vec![StackEntry::Num(0)])
+            );
+            assert!(!equal.interpret_in_context(&lenient));
+            assert_eq!(equal.evaluate(&strict), Err(ScriptError::ItemType));
+
+            let verify = script(x1, x2, OpCodes::OP_EQUALVERIFY);
+            assert_eq!(verify.evaluate(&lenient), Err(ScriptError::ExecutionFailed));
+            assert_eq!(verify.evaluate(&strict), Err(ScriptError::ItemType));
+        }
+
+        // Same-type comparisons are unaffected
+        let cases = vec![
+            (num(), num(), 1),
+            (num(), StackEntry::Num(6), 0),
+            (bytes(), bytes(), 1),
+            (bytes(), StackEntry::Bytes("06".to_owned()), 0),
+        ];
+        for (x1, x2, expected) in cases {
+            let equal = script(x1, x2, OpCodes::OP_EQUAL);
+            for context in [&lenient, &strict].iter() {
+                assert_eq!(
+                    equal.evaluate(context).map(|s| s.main_stack),
+                    Ok(vec![StackEntry::Num(expected)])
+                );
+            }
+        }
+
+        // Skipped branches aren't type checked
+        let skipped = Script::from(vec![
+            StackEntry::Num(0),
+            StackEntry::Op(OpCodes::OP_IF),
+            num(),
+            bytes(),
+            StackEntry::Op(OpCodes::OP_EQUAL),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+            StackEntry::Num(1),
+        ]);
+        assert!(skipped.interpret_in_context(&strict));
+    }
+
+    #[test]
+    /// Test OP_LOOSEEQUAL
+    fn test_looseequal() {
+        /// op_looseequal([n, bytes(n)]) -> [1] at byte boundaries
+        for (n, b) in [(0, "00"), (1, "01"), (255, "ff"), (255, "00ff"), (0, "")] {
+            let mut stack = Stack::new();
+            stack.push(StackEntry::Num(n));
+            stack.push(StackEntry::Bytes(b.to_string()));
+            let v: Vec<StackEntry> = vec![StackEntry::Num(1)];
+            op_looseequal(&mut stack);
+            assert_eq!(stack.main_stack, v);
+            /// OP_EQUAL stays strict
+            let mut stack = Stack::new();
+            stack.push(StackEntry::Bytes(b.to_string()));
+            stack.push(StackEntry::Num(n));
+            let v: Vec<StackEntry> = vec![StackEntry::Num(0)];
+            op_equal(&mut stack);
+            assert_eq!(stack.main_stack, v);
+        }
+        /// op_looseequal([bytes(n), n]) -> [0] for mismatching values
+        for (b, n) in [
+            ("01", 0),
+            ("00", 1),
+            ("0100", 255),
+            ("ff", 256),
+            ("hello", 0),
+        ] {
+            let mut stack = Stack::new();
+            stack.push(StackEntry::Bytes(b.to_string()));
+            stack.push(StackEntry::Num(n));
+            let v: Vec<StackEntry> = vec![StackEntry::Num(0)];
+            op_looseequal(&mut stack);
+            assert_eq!(stack.main_stack, v);
+        }
+        /// op_looseequal(["hello","hello"]) -> [1]
+        let mut stack = Stack::new();
+        for i in 1..=2 {
+            stack.push(StackEntry::Bytes("hello".to_string()));
+        }
+        let v: Vec<StackEntry> = vec![StackEntry::Num(1)];
+        op_looseequal(&mut stack);
+        assert_eq!(stack.main_stack, v);
+        /// op_looseequal([1]) -> fail
+        let mut stack = Stack::new();
+        stack.push(StackEntry::Num(1));
+        let b = op_looseequal(&mut stack);
+        assert!(!b)
+    }
+
+    #[test]
+    /// Checks that loosely comparing a `Num` with non-canonical hex fails the script
+    fn test_looseequal_invalid_hex() {
+        let context = ExecutionContext::default();
+        let script = |x1: StackEntry, x2: StackEntry, op: OpCodes| {
+            Script::from(vec![x1, x2, StackEntry::Op(op)])
+        };
+        let num = || StackEntry::Num(255);
+        let bytes = |b: &str| StackEntry::Bytes(b.to_owned());
+
+        for b in ["FF", "00Ff", "f", "zz", "0xff", "ff "] {
+            for (x1, x2) in [(num(), bytes(b)), (bytes(b), num())] {
+                for op in [OpCodes::OP_LOOSEEQUAL, OpCodes::OP_LOOSEEQUALVERIFY] {
+                    let result = script(x1.clone(), x2.clone(), op).evaluate(&context);
+                    assert_eq!(result, Err(ScriptError::InvalidHex), "{}", b);
+                }
+            }
+        }
+
+        // Canonical hex and comparisons without a `Num` are unaffected
+        let cases = [
+            (num(), bytes("ff"), 1),
+            (num(), bytes("00ff"), 1),
+            (num(), bytes("fe"), 0),
+            (bytes("FF"), bytes("FF"), 1),
+            (bytes("FF"), bytes("ff"), 0),
+        ];
+        for (x1, x2, expected) in cases {
+            let equal = script(x1, x2, OpCodes::OP_LOOSEEQUAL);
+            assert_eq!(
+                equal.evaluate(&context).map(|s| s.main_stack),
+                Ok(vec![StackEntry::Num(expected)])
+            );
+        }
+    }
+
+    #[test]
+    /// Test OP_LOOSEEQUALVERIFY
+    fn test_looseequalverify() {
+        /// op_looseequalverify([255, "ff"]) -> []
+        let mut stack = Stack::new();
+        stack.push(StackEntry::Num(255));
+        stack.push(StackEntry::Bytes("ff".to_string()));
+        let v: Vec<StackEntry> = vec![];
+        assert!(op_looseequalverify(&mut stack));
+        assert_eq!(stack.main_stack, v);
+        /// op_looseequalverify([1, "00"]) -> fail
+        let mut stack = Stack::new();
+        stack.push(StackEntry::Num(1));
+        stack.push(StackEntry::Bytes("00".to_string()));
+        let b = op_looseequalverify(&mut stack);
+        assert!(!b);
+        /// op_looseequalverify([1]) -> fail
+        let mut stack = Stack::new();
+        stack.push(StackEntry::Num(1));
+        let b = op_looseequalverify(&mut stack);
+        assert!(!b)
+    }
+
     /*---- ARITHMETIC OPS ----*/
 
     #[test]
@@ -1475,6 +2552,61 @@ mod tests {
         assert!(!b)
     }
 
+    #[test]
+    /// Checks that stack guards are only taken on deep enough stacks, and that ops
+    /// using them leave the stack untouched when they fail
+    fn test_stack_guard() {
+        let entries = vec![StackEntry::Bytes("a".to_owned()), StackEntry::Num(1)];
+        let mut stack = Stack::from(entries.clone());
+
+        assert!(matches!(
+            stack.require::<3>(),
+            Err(ScriptError::StackUnderflow(3))
+        ));
+        assert_eq!(stack.require::<2>().unwrap().items(), &entries[..]);
+        assert_eq!(
+            stack.require::<2>().unwrap().nums(),
+            Err(ScriptError::ItemType)
+        );
+        assert_eq!(stack.require::<1>().unwrap().nums(), Ok([1]));
+        assert_eq!(stack.require::<0>().unwrap().nums(), Ok([]));
+        assert_eq!(stack.main_stack, entries);
+
+        // Underflow and item type failures don't pop any operand
+        for (entries, op) in [
+            (vec![StackEntry::Num(1)], op_add as fn(&mut Stack) -> bool),
+            (
+                vec![StackEntry::Bytes("a".to_owned()), StackEntry::Num(1)],
+                op_add,
+            ),
+            (
+                vec![StackEntry::Num(1), StackEntry::Num(usize::MAX)],
+                op_add,
+            ),
+            (vec![StackEntry::Num(1), StackEntry::Num(2)], op_within),
+            (
+                vec![
+                    StackEntry::Num(1),
+                    StackEntry::Bytes("a".to_owned()),
+                    StackEntry::Num(2),
+                ],
+                op_within,
+            ),
+        ] {
+            let mut stack = Stack::from(entries.clone());
+            assert!(!op(&mut stack));
+            assert_eq!(stack.main_stack, entries);
+        }
+
+        // A replacement that can't be pushed restores the items it replaces
+        let mut stack = Stack::from(entries.clone());
+        let too_large = StackEntry::Bytes("a".repeat(stack.max_item_size() + 1));
+        assert!(!stack.require::<2>().unwrap().replace(too_large));
+        assert_eq!(stack, Stack::from(entries.clone()));
+        assert!(stack.require::<2>().unwrap().replace(StackEntry::Num(2)));
+        assert_eq!(stack, Stack::from(vec![StackEntry::Num(2)]));
+    }
+
     #[test]
     /// Test OP_SUB
     fn test_sub() {
@@ -1570,6 +2702,42 @@ mod tests {
         assert!(!b)
     }
 
+    #[test]
+    /// Checks division truncates toward zero and the remainder takes the dividend's sign
+    fn test_div_rounding() {
+        let cases = [
+            (7, 2, 3, 1),
+            (-7, 2, -3, -1),
+            (7, -2, -3, 1),
+            (-7, -2, 3, -1),
+            (-3, 2, -1, -1),
+            (-1, 2, 0, -1),
+            (6, -3, -2, 0),
+        ];
+        for (n1, n2, quotient, remainder) in cases {
+            assert_eq!(script_div(n1, n2), Some(quotient), "{} / {}", n1, n2);
+            assert_eq!(script_rem(n1, n2), Some(remainder), "{} % {}", n1, n2);
+            assert_eq!(quotient * n2 + remainder, n1);
+        }
+        assert_eq!(script_div(-3, 0), None);
+        assert_eq!(script_rem(-3, 0), None);
+        assert_eq!(script_div(i128::MIN, -1), None);
+        assert_eq!(script_rem(i128::MIN, -1), None);
+
+        // The opcodes apply the same rule to the non-negative numbers scripts hold
+        let mut stack = Stack::new();
+        stack.push(StackEntry::Num(usize::MAX));
+        assert!(op_2div(&mut stack));
+        assert_eq!(stack.main_stack, vec![StackEntry::Num(usize::MAX / 2)]);
+        for (op, expected) in [(op_div as fn(&mut Stack) -> bool, 3), (op_mod, 1)] {
+            let mut stack = Stack::new();
+            stack.push(StackEntry::Num(7));
+            stack.push(StackEntry::Num(2));
+            assert!(op(&mut stack));
+            assert_eq!(stack.main_stack, vec![StackEntry::Num(expected)]);
+        }
+    }
+
     #[test]
     /// Test OP_LSHIFT
     fn test_lshift() {
@@ -1904,6 +3072,29 @@ mod tests {
         let mut v: Vec<StackEntry> = vec![StackEntry::Num(0)];
         op_within(&mut stack);
         assert_eq!(stack.main_stack, v);
+        /// boundaries of the half-open range [n2, n3)
+        let cases = vec![
+            ([1, 1, 3], 1),
+            ([3, 1, 3], 0),
+            ([0, 1, 3], 0),
+            ([usize::MAX - 1, 0, usize::MAX], 1),
+            ([usize::MAX, 0, usize::MAX], 0),
+            ([1, 1, 1], 0),
+            ([usize::MAX, usize::MAX, usize::MAX], 0),
+            ([0, 0, 0], 0),
+        ];
+        for (nums, expected) in cases {
+            let mut stack = Stack::new();
+            for n in nums.iter() {
+                stack.push(StackEntry::Num(*n));
+            }
+            assert!(op_within(&mut stack));
+            assert_eq!(
+                stack.main_stack,
+                vec![StackEntry::Num(expected)],
+                "{nums:?}"
+            );
+        }
         /// op_within([1,2]) -> fail
         let mut stack = Stack::new();
         for i in 1..=2 {
@@ -1913,6 +3104,146 @@ mod tests {
         assert!(!b)
     }
 
+    /*---- INTROSPECTION OPS ----*/
+
+    #[test]
+    /// Test OP_TXINCOUNT
+    fn test_txincount() {
+        /// op_txincount([]) -> [2]
+        let mut stack = Stack::new();
+        assert!(op_txincount(&mut stack, 2));
+        assert_eq!(stack.main_stack, vec![StackEntry::Num(2)]);
+
+        let mut tx = Transaction::new();
+        tx.inputs = vec![TxIn::new(), TxIn::new()];
+        tx.outputs = vec![TxOut::new(); 3];
+        let context = ExecutionContext {
+            tx: Some(TxContext::new(&tx)),
+            ..Default::default()
+        };
+        let counts = Script::from(vec![
+            StackEntry::Op(OpCodes::OP_TXINCOUNT),
+            StackEntry::Op(OpCodes::OP_TXOUTPUTCOUNT),
+        ]);
+        assert_eq!(
+            counts.evaluate(&context).map(|s| s.main_stack),
+            Ok(vec![StackEntry::Num(2), StackEntry::Num(3)])
+        );
+
+        // Standalone scripts have no spending transaction to count
+        assert_eq!(counts.interpret_full(), Err(ScriptError::NoTxContext));
+        assert_eq!(
+            counts.evaluate(&ExecutionContext::default()),
+            Err(ScriptError::NoTxContext)
+        );
+    }
+
+    #[test]
+    /// Test OP_TXOUTPUTCOUNT and OP_TXOUTVALUE
+    fn test_txoutputcount_txoutvalue() {
+        let item = Asset::Item(ItemAsset::new(3, None, None));
+        let values = vec![Asset::token_u64(5), item];
+
+        /// op_txoutputcount([]) -> [2]
+        let mut stack = Stack::new();
+        assert!(op_txoutputcount(&mut stack, &values));
+        assert_eq!(stack.main_stack, vec![StackEntry::Num(2)]);
+        /// op_txoutvalue([0]) -> [5]
+        let mut stack = Stack::from(vec![StackEntry::Num(0)]);
+        assert!(op_txoutvalue(&mut stack, &values));
+        assert_eq!(stack.main_stack, vec![StackEntry::Num(5)]);
+        /// op_txoutvalue([1]) -> fail
+        for entries in [
+            vec![StackEntry::Num(1)],
+            vec![StackEntry::Num(2)],
+            vec![StackEntry::Bytes("00".to_owned())],
+            vec![],
+        ] {
+            let mut stack = Stack::from(entries);
+            assert!(!op_txoutvalue(&mut stack, &values));
+        }
+
+        let context = ExecutionContext {
+            tx: Some(TxContext {
+                input_count: 1,
+                output_values: values,
+            }),
+            ..Default::default()
+        };
+        let script = |index| {
+            Script::from(vec![
+                StackEntry::Num(index),
+                StackEntry::Op(OpCodes::OP_TXOUTVALUE),
+            ])
+        };
+        assert_eq!(
+            script(0).evaluate(&context).map(|s| s.main_stack),
+            Ok(vec![StackEntry::Num(5)])
+        );
+        assert_eq!(
+            script(1).evaluate(&context),
+            Err(ScriptError::OutputNotToken(1))
+        );
+        assert_eq!(
+            script(2).evaluate(&context),
+            Err(ScriptError::OutputIndex(2))
+        );
+
+        // Without a spending transaction, neither opcode can run
+        assert_eq!(script(0).interpret_full(), Err(ScriptError::NoTxContext));
+        let count = Script::from(vec![StackEntry::Op(OpCodes::OP_TXOUTPUTCOUNT)]);
+        assert_eq!(count.interpret_full(), Err(ScriptError::NoTxContext));
+        assert!(!count.interpret());
+
+        // Output constraints, checked before the rest of the script
+        let at_least = |amount| {
+            Script::from(vec![StackEntry::Op(OpCodes::OP_1)])
+                .with_output_constraint(TxOutConstraint::ValueAtLeast(0, TokenAmount(amount)))
+        };
+        assert!(at_least(5).interpret_in_context(&context));
+        assert!(!at_least(6).interpret_in_context(&context));
+        let count = |count| {
+            Script::from(vec![StackEntry::Op(OpCodes::OP_1)])
+                .with_output_constraint(TxOutConstraint::Count(count))
+        };
+        assert!(count(2).interpret_in_context(&context));
+        assert!(!count(1).interpret_in_context(&context));
+    }
+
+    #[test]
+    /// Checks that a P2SH vault requiring a single output of an exact amount can only be
+    /// spent by transactions meeting both constraints
+    fn test_output_constraint_vault() {
+        let amount = TokenAmount(100);
+        let vault = Script::from(vec![StackEntry::Op(OpCodes::OP_1)])
+            .with_output_constraint(TxOutConstraint::ValueEquals(0, amount))
+            .with_output_constraint(TxOutConstraint::Count(1));
+        let vault_out = OutPoint::new("g0".to_owned(), 0);
+        let utxo = BTreeMap::from([(
+            vault_out.clone(),
+            TxOut {
+                value: Asset::Token(amount),
+                script_public_key: Some(construct_p2sh_address(&vault)),
+                ..Default::default()
+            },
+        )]);
+
+        let spend = |amounts: &[u64]| {
+            let mut tx = Transaction::new();
+            tx.inputs = vec![TxIn::new_from_input(vault_out.clone(), vault.clone())];
+            tx.outputs = amounts
+                .iter()
+                .map(|a| TxOut::new_token_amount(hex::encode([1; 32]), TokenAmount(*a), None))
+                .collect();
+            tx_is_valid(&tx, 0, |out_point| utxo.get(out_point))
+        };
+        let invalid_script = Err(TxValidationError::Input(0, InputError::InvalidScript));
+
+        assert_eq!(spend(&[100]), Ok(()));
+        assert_eq!(spend(&[60, 40]), invalid_script);
+        assert_eq!(spend(&[90]), invalid_script);
+    }
+
     /*---- CRYPTO OPS ----*/
 
     #[test]
@@ -1970,6 +3301,44 @@ mod tests {
         assert!(!b)
     }
 
+    #[test]
+    /// Checks OP_HASH256 pushes the hex address that P2PKH locking scripts compare against
+    fn test_hash256_address_format() {
+        let (pk, sk) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let mut stack = Stack::new();
+        stack.push(StackEntry::PubKey(pk));
+        assert!(op_hash256(&mut stack));
+
+        let pushed = match stack.pop() {
+            Some(StackEntry::Bytes(pushed)) => pushed,
+            entry => panic!("unexpected entry {:?}", entry),
+        };
+        assert_eq!(pushed, address);
+        assert_eq!(hex::decode(&pushed).unwrap(), construct_address_raw(&pk));
+        assert_eq!(
+            Script::p2pkh_locking(&address).stack[2],
+            StackEntry::Bytes(pushed)
+        );
+
+        // A public key differing in one byte hashes to another address
+        let mut corrupted = pk.as_ref().to_vec();
+        corrupted[0] ^= 1;
+        let corrupted = StackEntry::from_pubkey_bytes(&corrupted).unwrap();
+        let mut stack = Stack::new();
+        stack.push(corrupted.clone());
+        assert!(op_hash256(&mut stack));
+        assert_ne!(stack.pop(), Some(StackEntry::Bytes(address.clone())));
+
+        let signature = sign::sign_detached(b"data", &sk);
+        let unlocking = Script::from(vec![
+            StackEntry::Bytes("data".to_owned()),
+            StackEntry::Signature(signature),
+            corrupted,
+        ]);
+        assert!(!Script::concat(unlocking, Script::p2pkh_locking(&address)).interpret());
+    }
+
     #[test]
     /// Test OP_HASH256_V0
     fn test_hash256_v0() {
@@ -2195,7 +3564,7 @@ mod tests {
         /// too many pubkeys
         /// op_checkmultisig([MAX_PUB_KEYS_PER_MULTISIG+1]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Num(MAX_PUB_KEYS_PER_MULTISIG as usize + ONE));
+        stack.push(StackEntry::Num(MAX_PUB_KEYS_PER_MULTISIG + ONE));
         let b = op_checkmultisig(&mut stack);
         assert!(!b);
         /// not enough pubkeys
@@ -2227,6 +3596,36 @@ mod tests {
         stack.push(StackEntry::Num(3));
         let b = op_checkmultisig(&mut stack);
         assert!(!b);
+        /// non-pubkey among the pubkeys
+        /// op_checkmultisig([msg,sig1,sig2,2,pk1,pk2,1,pk3,3]) -> fail
+        let msg = hex::encode(vec![0, 0, 0]);
+        let mut stack = Stack::new();
+        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::Signature(sig1));
+        stack.push(StackEntry::Signature(sig2));
+        stack.push(StackEntry::Num(2));
+        stack.push(StackEntry::PubKey(pk1));
+        stack.push(StackEntry::PubKey(pk2));
+        stack.push(StackEntry::Num(1));
+        stack.push(StackEntry::PubKey(pk3));
+        stack.push(StackEntry::Num(3));
+        let b = op_checkmultisig(&mut stack);
+        assert!(!b);
+        /// non-signature among the signatures
+        /// op_checkmultisig([msg,sig1,1,sig2,2,pk1,pk2,pk3,3]) -> fail
+        let msg = hex::encode(vec![0, 0, 0]);
+        let mut stack = Stack::new();
+        stack.push(StackEntry::Bytes(msg));
+        stack.push(StackEntry::Signature(sig1));
+        stack.push(StackEntry::Num(1));
+        stack.push(StackEntry::Signature(sig2));
+        stack.push(StackEntry::Num(2));
+        stack.push(StackEntry::PubKey(pk1));
+        stack.push(StackEntry::PubKey(pk2));
+        stack.push(StackEntry::PubKey(pk3));
+        stack.push(StackEntry::Num(3));
+        let b = op_checkmultisig(&mut stack);
+        assert!(!b);
         /// no message
         /// op_checkmultisig([sig1,sig2,2,pk1,pk2,pk3,3]) -> fail
         let mut stack = Stack::new();
@@ -2346,7 +3745,7 @@ mod tests {
         /// too many pubkeys
         /// op_checkmultisigverify([MAX_PUB_KEYS_PER_MULTISIG+1]) -> fail
         let mut stack = Stack::new();
-        stack.push(StackEntry::Num(MAX_PUB_KEYS_PER_MULTISIG as usize + ONE));
+        stack.push(StackEntry::Num(MAX_PUB_KEYS_PER_MULTISIG + ONE));
         let b = op_checkmultisigverify(&mut stack);
         assert!(!b);
         /// not enough pubkeys
@@ -2406,14 +3805,121 @@ mod tests {
         let v = vec![StackEntry::Bytes("a".repeat(500)); 21];
         let script = Script::from(v);
         assert!(!script.is_valid());
+        assert_eq!(script.verify(), Err(ScriptError::ScriptTooLarge(10500)));
         // # opcodes <= 201
-        let v = vec![StackEntry::Op(OpCodes::OP_1); MAX_OPS_PER_SCRIPT as usize];
+        let v = vec![StackEntry::Op(OpCodes::OP_1); MAX_OPS_PER_SCRIPT];
         let script = Script::from(v);
         assert!(script.is_valid());
+        assert_eq!(script.verify(), Ok(()));
         // # opcodes > 201
-        let v = vec![StackEntry::Op(OpCodes::OP_1); (MAX_OPS_PER_SCRIPT + 1) as usize];
+        let v = vec![StackEntry::Op(OpCodes::OP_1); MAX_OPS_PER_SCRIPT + 1];
         let script = Script::from(v);
         assert!(!script.is_valid());
+        assert_eq!(script.verify(), Err(ScriptError::TooManyOps(202)));
+    }
+
+    #[test]
+    /// Checks that scripts breaking the limits fail verification before being executed
+    fn test_evaluate_verifies_first() {
+        let context = ExecutionContext::default();
+        // Executing the first OP_DROP would fail, but the opcode count is checked first
+        let v = vec![StackEntry::Op(OpCodes::OP_DROP); MAX_OPS_PER_SCRIPT + 1];
+        let script = Script::from(v);
+        assert_eq!(script.evaluate(&context), Err(ScriptError::TooManyOps(202)));
+        assert!(!script.interpret());
+
+        let v = vec![StackEntry::Op(OpCodes::OP_DROP); MAX_OPS_PER_SCRIPT];
+        let script = Script::from(v);
+        assert_eq!(script.evaluate(&context), Err(ScriptError::ExecutionFailed));
+
+        // The limits of the context are verified
+        let small_context = ExecutionContext {
+            max_script_size: 4,
+            ..Default::default()
+        };
+        let script = Script::from(vec![StackEntry::Op(OpCodes::OP_DROP), StackEntry::Num(1)]);
+        assert_eq!(
+            script.evaluate(&small_context),
+            Err(ScriptError::ScriptTooLarge(9))
+        );
+        assert_eq!(script.evaluate(&context), Err(ScriptError::ExecutionFailed));
+    }
+
+    #[test]
+    /// Checks that peak main, alt and combined stack depths are reported
+    fn test_evaluate_with_report() {
+        let context = ExecutionContext::default();
+        let num = |n: usize| StackEntry::Num(n);
+        let op = StackEntry::Op;
+        let v = [
+            vec![num(1), num(2), num(3), num(4), num(5)],
+            vec![op(OpCodes::OP_TOALTSTACK); 4],
+            vec![num(6), num(7), num(8)],
+            vec![op(OpCodes::OP_2DROP), op(OpCodes::OP_DROP)],
+            vec![op(OpCodes::OP_FROMALTSTACK)],
+        ]
+        .concat();
+        let script = Script::from(v);
+
+        let (result, report) = script.evaluate_with_report(&context);
+        assert_eq!(result, script.evaluate(&context));
+        assert_eq!(result.unwrap().main_stack(), &[num(1), num(2)]);
+        assert_eq!(
+            report,
+            ResourceReport {
+                peak_main_depth: 5,
+                peak_alt_depth: 4,
+                peak_combined_depth: 8,
+                fuel: 15,
+            }
+        );
+
+        // Usage is reported up to a failure, and not at all if verification fails
+        let script = Script::from(vec![
+            num(1),
+            op(OpCodes::OP_TOALTSTACK),
+            op(OpCodes::OP_DROP),
+        ]);
+        let (result, report) = script.evaluate_with_report(&context);
+        assert_eq!(result, Err(ScriptError::ExecutionFailed));
+        assert_eq!(report.peak_alt_depth, 1);
+
+        let script = Script::from(vec![op(OpCodes::OP_1); MAX_OPS_PER_SCRIPT + 1]);
+        let (result, report) = script.evaluate_with_report(&context);
+        assert!(matches!(result, Err(ScriptError::TooManyOps(_))));
+        assert_eq!(report, ResourceReport::default());
+    }
+
+    #[test]
+    /// Checks that executing a script by value matches interpreting it by reference
+    fn test_execute() {
+        let (pk, sk) = sign::gen_keypair();
+        let check_data = hex::encode([0; 32]);
+        let signature = sign::sign_detached(check_data.as_bytes(), &sk);
+        let p2pkh = Script::pay2pkh(check_data, signature, pk, None);
+        let too_many_ops = vec![StackEntry::Op(OpCodes::OP_DROP); MAX_OPS_PER_SCRIPT + 1];
+
+        let cases = vec![
+            (p2pkh, Ok(())),
+            (Script::from(vec![StackEntry::Num(1)]), Ok(())),
+            (
+                Script::from(vec![StackEntry::Num(0)]),
+                Err(ScriptError::ExecutionFailed),
+            ),
+            (
+                Script::from(vec![StackEntry::Num(1), StackEntry::Op(OpCodes::OP_IF)]),
+                Err(ScriptError::ExecutionFailed),
+            ),
+            (
+                Script::from(too_many_ops),
+                Err(ScriptError::TooManyOps(MAX_OPS_PER_SCRIPT + 1)),
+            ),
+        ];
+        for (script, expected) in cases {
+            assert_eq!(script.interpret(), expected.is_ok());
+            assert_eq!(script.interpret_full(), expected);
+            assert_eq!(script.execute(), expected);
+        }
     }
 
     #[test]
@@ -2423,15 +3929,582 @@ mod tests {
         let stack = Stack::from(v);
         assert!(stack.is_valid());
         // # items on interpreter stack <= 1000
-        let v = vec![StackEntry::Num(1); MAX_STACK_SIZE as usize];
+        let v = vec![StackEntry::Num(1); MAX_STACK_SIZE];
         let stack = Stack::from(v);
         assert!(stack.is_valid());
         // # items on interpreter stack > 1000
-        let v = vec![StackEntry::Num(1); (MAX_STACK_SIZE + 1) as usize];
+        let v = vec![StackEntry::Num(1); MAX_STACK_SIZE + 1];
         let stack = Stack::from(v);
         assert!(!stack.is_valid());
     }
 
+    #[test]
+    /// Checks that stack memory is tracked across the main and alt stacks
+    fn test_stack_memory_usage() {
+        let blob = StackEntry::Bytes("a".repeat(MAX_SCRIPT_ITEM_SIZE));
+        let mut stack = Stack::from(vec![
+            StackEntry::Num(1),
+            StackEntry::Bytes("ab".to_string()),
+        ]);
+        assert_eq!(stack.memory_usage(), 10);
+        stack.push_alt(StackEntry::Bytes("abc".to_string()));
+        assert_eq!(stack.memory_usage(), 13);
+        stack.pop();
+        assert_eq!(stack.memory_usage(), 11);
+        stack.pop_alt();
+        assert_eq!(stack.memory_usage(), 8);
+        // main and alt stacks count towards the same limit
+        let mut stack = Stack::from(vec![blob.clone(); 100]);
+        for _ in 0..92 {
+            assert!(stack.push_alt(blob.clone()));
+        }
+        assert!(!stack.push_alt(blob.clone()));
+        assert!(!stack.push(blob));
+        assert!(stack.is_valid());
+        assert!(stack.memory_usage() <= MAX_STACK_BYTES);
+    }
+
+    #[test]
+    /// Checks that scripts under MAX_STACK_SIZE fail once duplicated blobs exceed MAX_STACK_BYTES
+    fn test_interpret_script_stack_memory() {
+        let blob = StackEntry::Bytes("a".repeat(MAX_SCRIPT_ITEM_SIZE));
+        // 151 blobs of 520 bytes stay under the limit
+        let mut v = vec![blob.clone()];
+        v.extend(vec![StackEntry::Op(OpCodes::OP_DUP); 150]);
+        let script = Script::from(v);
+        assert!(script.interpret());
+        // 200 blobs of 520 bytes exceed the limit
+        let mut v = vec![blob.clone()];
+        v.extend(vec![StackEntry::Op(OpCodes::OP_DUP); 199]);
+        let script = Script::from(v);
+        assert!(script.is_valid());
+        assert_eq!(
+            script.interpret_full(),
+            Err(ScriptError::StackMemory(193 * MAX_SCRIPT_ITEM_SIZE))
+        );
+        // OP_2DUP exceeds the limit the same way
+        let mut v = vec![blob.clone(), blob];
+        v.extend(vec![StackEntry::Op(OpCodes::OP_2DUP); 99]);
+        let script = Script::from(v);
+        assert_eq!(
+            script.interpret_full(),
+            Err(ScriptError::StackMemory(193 * MAX_SCRIPT_ITEM_SIZE))
+        );
+    }
+
+    #[test]
+    /// Checks that scripts valid under raised limits are rejected under the default limits
+    fn test_interpret_script_context_limits() {
+        let default_context = ExecutionContext::default();
+        let raised_context = ExecutionContext {
+            max_script_item_size: 1000,
+            max_script_size: 20000,
+            ..Default::default()
+        };
+        assert_eq!(default_context.max_script_item_size, MAX_SCRIPT_ITEM_SIZE);
+        assert_eq!(default_context.max_script_size, MAX_SCRIPT_SIZE);
+        // item size
+        let v = vec![StackEntry::Bytes("a".repeat(600))];
+        let script = Script::from(v);
+        assert!(!script.interpret());
+        assert!(script.interpret_in_context(&raised_context));
+        // OP_CAT item size
+        let v = vec![
+            StackEntry::Bytes("a".repeat(300)),
+            StackEntry::Bytes("a".repeat(300)),
+            StackEntry::Op(OpCodes::OP_CAT),
+        ];
+        let script = Script::from(v);
+        assert!(!script.interpret_in_context(&default_context));
+        assert!(script.interpret_in_context(&raised_context));
+        // script size
+        let v = vec![StackEntry::Bytes("a".repeat(500)); 21];
+        let script = Script::from(v);
+        assert!(!script.is_valid());
+        assert!(script.is_valid_in_context(&raised_context));
+        assert!(script.interpret_in_context(&raised_context));
+    }
+
+    #[test]
+    /// Checks that a repeated OP_ELSE toggles its block again below the fork height, as it
+    /// always has, and only fails the script once the rule is active
+    fn test_interpret_duplicate_else_gate() {
+        let script = Script::from(vec![
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_IF),
+            StackEntry::Op(OpCodes::OP_ELSE),
+            StackEntry::Op(OpCodes::OP_0),
+            StackEntry::Op(OpCodes::OP_ELSE),
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+        ]);
+        let at_height = |block_height| ExecutionContext {
+            block_height,
+            ..Default::default()
+        };
+
+        assert!(script.interpret_in_context(&at_height(Some(0))));
+        assert!(!script.interpret_in_context(&at_height(None)));
+        assert!(!script.interpret());
+    }
+
+    #[test]
+    /// Checks that scripts may be nested three levels deep, but not four
+    fn test_execute_in_context_recursion_depth() {
+        let script = Script::from(vec![StackEntry::Op(OpCodes::OP_1)]);
+        let mut context = ExecutionContext::default();
+        assert_eq!(script.execute_in_context(&context), Ok(true));
+        // three enclosing scripts: top-level plus two levels of nesting
+        for _ in 0..3 {
+            context = context.nested().unwrap();
+        }
+        assert_eq!(script.execute_in_context(&context), Ok(true));
+        // four enclosing scripts
+        let context = context.nested().unwrap();
+        assert_eq!(
+            script.execute_in_context(&context),
+            Err(ScriptError::RecursionDepthExceeded(5))
+        );
+    }
+
+    #[test]
+    /// Checks that streamed construction stops at the first bad entry
+    fn test_try_from_iter_aborts_early() {
+        let mut consumed = 0;
+        let entries = vec![
+            Ok(StackEntry::Op(OpCodes::OP_1)),
+            Err(ScriptError::ExecutionFailed),
+            Ok(StackEntry::Op(OpCodes::OP_1)),
+        ];
+        let counted = entries.into_iter().inspect(|_| consumed += 1);
+        assert_eq!(
+            Script::try_from_iter(counted),
+            Err(ScriptError::ExecutionFailed)
+        );
+        assert_eq!(consumed, 2);
+
+        let mut consumed = 0;
+        let oversized = (0..).map(|_| Ok(StackEntry::Bytes("a".repeat(500))));
+        let counted = oversized.inspect(|_| consumed += 1);
+        assert_eq!(
+            Script::try_from_iter(counted),
+            Err(ScriptError::ScriptTooLarge(10500))
+        );
+        assert_eq!(consumed, 21);
+    }
+
+    #[test]
+    /// Checks that streamed construction and `is_valid` agree on the limits
+    fn test_try_from_iter_matches_is_valid() {
+        let max_size = MAX_SCRIPT_SIZE;
+        let max_ops = MAX_OPS_PER_SCRIPT;
+        let cases = vec![
+            vec![StackEntry::Bytes("a".repeat(max_size))],
+            vec![StackEntry::Bytes("a".repeat(max_size + 1))],
+            vec![StackEntry::Op(OpCodes::OP_NOP); max_ops],
+            vec![StackEntry::Op(OpCodes::OP_NOP); max_ops + 1],
+            [
+                vec![StackEntry::Bytes("a".repeat(max_size - max_ops))],
+                vec![StackEntry::Op(OpCodes::OP_NOP); max_ops],
+            ]
+            .concat(),
+            [
+                vec![StackEntry::Bytes("a".repeat(max_size - max_ops + 1))],
+                vec![StackEntry::Op(OpCodes::OP_NOP); max_ops],
+            ]
+            .concat(),
+        ];
+        // Lift the item size limit so that only the shared limits are compared
+        let context = ExecutionContext {
+            max_script_item_size: max_size + 1,
+            ..Default::default()
+        };
+
+        for (i, stack) in cases.into_iter().enumerate() {
+            let script = Script::from(stack.clone());
+            let mut builder = ScriptBuilder::with_context(&context);
+            let built = stack
+                .into_iter()
+                .try_for_each(|e| builder.push(e).map(|_| ()));
+            assert_eq!(built.is_ok(), script.is_valid(), "case {}", i);
+            assert_eq!(built.is_ok(), i % 2 == 0, "case {}", i);
+        }
+    }
+
+    #[test]
+    /// Checks that the builder rejects oversized items and is used by the templates
+    fn test_script_builder() {
+        let max_item = MAX_SCRIPT_ITEM_SIZE;
+        let mut builder = ScriptBuilder::new();
+        builder
+            .push(StackEntry::Bytes("a".repeat(max_item)))
+            .unwrap()
+            .push(StackEntry::Op(OpCodes::OP_DROP))
+            .unwrap();
+        assert_eq!(
+            builder
+                .push(StackEntry::Bytes("a".repeat(max_item + 1)))
+                .err(),
+            Some(ScriptError::ItemTooLarge(max_item + 1))
+        );
+        assert_eq!(builder.build().stack.len(), 2);
+
+        let (pk, sk) = sign::gen_keypair();
+        let sig = sign::sign_detached(b"data", &sk);
+        let templates = vec![
+            Script::pay2pkh("data".to_string(), sig, pk, None),
+            Script::member_multisig("data".to_string(), pk, sig),
+            Script::multisig_lock(1, 2, "data".to_string(), vec![pk, pk]),
+            Script::multisig_unlock("data".to_string(), vec![sig]),
+            Script::multisig_validation(1, 2, "data".to_string(), vec![sig], vec![pk, pk]),
+        ];
+        for script in templates {
+            let rebuilt = Script::try_from_iter(script.stack.iter().cloned().map(Ok));
+            assert_eq!(rebuilt, Ok(script));
+        }
+
+        // Oversized template data is kept, to be rejected when executed
+        let oversized = Script::pay2pkh("a".repeat(max_item + 1), sig, pk, None);
+        assert_eq!(
+            oversized.stack[0],
+            StackEntry::Bytes("a".repeat(max_item + 1))
+        );
+        assert!(!oversized.interpret());
+    }
+
+    #[test]
+    /// Checks that diagnostics report every problem of a transaction, where it occurs
+    fn test_diagnose_tx() {
+        let inputs = [
+            (5, None, None),
+            (3, None, None),
+            (1, Some("genesis_a"), None),
+        ];
+        let (utxo, tx) =
+            generate_tx_with_ins_and_outs_assets(&inputs, &[(8, None), (1, Some("genesis_a"))]);
+        assert!(tx_is_valid(&tx, 0, |v| utxo.get(v)).is_ok());
+        assert!(diagnose_tx(&tx, 0, |v| utxo.get(v)).is_empty());
+
+        let (mut utxo, mut tx) =
+            generate_tx_with_ins_and_outs_assets(&inputs, &[(8, None), (1, Some("genesis_b"))]);
+        let first_out = tx.inputs[0].previous_out.clone().unwrap();
+        let address = utxo[&first_out].script_public_key.clone();
+        utxo.get_mut(&first_out).unwrap().locktime = 10;
+        tx.inputs[1].script_signature = Script::new();
+        tx.fees.push(TxOut {
+            value: Asset::token_u64(2),
+            locktime: 5,
+            script_public_key: address,
+        });
+
+        let findings = diagnose_tx(&tx, 0, |v| utxo.get(v)).findings;
+        let expected = vec![
+            (
+                Location::Input(InputIndex(0)),
+                TxValidationError::Input(
+                    0,
+                    InputError::LocktimeNotMet {
+                        locktime: 10,
+                        current_block: 0,
+                    },
+                ),
+            ),
+            (
+                Location::Input(InputIndex(1)),
+                TxValidationError::Input(1, InputError::InvalidScript),
+            ),
+            (
+                Location::Output(OutputKind::Normal, OutputIndex(1)),
+                TxValidationError::Output(OutputError::UnknownGenesisHash(
+                    OutputKind::Normal,
+                    1,
+                    "genesis_b".to_owned(),
+                )),
+            ),
+            (
+                Location::Output(OutputKind::Fee, OutputIndex(0)),
+                TxValidationError::Output(OutputError::FeeHasLocktime(0)),
+            ),
+            (
+                Location::Transaction,
+                TxValidationError::unbalanced(
+                    AssetValues::new(
+                        TokenAmount(8),
+                        BTreeMap::from([("genesis_a".to_owned(), 1)]),
+                    ),
+                    AssetValues::new(
+                        TokenAmount(10),
+                        BTreeMap::from([("genesis_b".to_owned(), 1)]),
+                    ),
+                ),
+            ),
+        ];
+        assert_eq!(findings, expected);
+        // Consensus validation stops at the first of them
+        assert_eq!(
+            tx_is_valid(&tx, 0, |v| utxo.get(v)),
+            Err(findings[0].1.clone())
+        );
+    }
+
+    #[test]
+    /// Checks item metadata against the schema version it declares
+    fn test_validate_against_schema() {
+        let mut registry = ItemMetadataSchemaRegistry::new(UnknownSchemaPolicy::Reject);
+        registry.register(ItemMetadataSchema::v1());
+        let conforming =
+            r#"{"schema":1,"name":"test","description":"test","image":"test","url":"test"}"#;
+        let missing_field = r#"{"schema":1,"name":"test","description":"test","image":"test"}"#;
+        let unknown = r#"{"schema":2,"title":"test"}"#;
+
+        assert_eq!(validate_against_schema(conforming, &registry), Ok(()));
+        assert_eq!(
+            validate_against_schema(missing_field, &registry),
+            Err(MetadataError::MissingField(1, "url".to_owned()))
+        );
+        assert_eq!(
+            validate_against_schema(unknown, &registry),
+            Err(MetadataError::UnknownSchema(2))
+        );
+        assert_eq!(
+            validate_against_schema(r#"{"name":"test"}"#, &registry),
+            Err(MetadataError::MissingSchemaVersion)
+        );
+        assert_eq!(
+            validate_against_schema("[1]", &registry),
+            Err(MetadataError::NotAnObject)
+        );
+
+        let mut registry = ItemMetadataSchemaRegistry::new(UnknownSchemaPolicy::PassThrough);
+        registry.register(ItemMetadataSchema::v1());
+        assert_eq!(validate_against_schema(unknown, &registry), Ok(()));
+        assert_eq!(
+            validate_against_schema(missing_field, &registry),
+            Err(MetadataError::MissingField(1, "url".to_owned()))
+        );
+    }
+
+    #[test]
+    /// Checks key and signature entries are only created from bytes of the right length
+    fn test_stack_entry_from_bytes() {
+        let (pk, sk) = sign::gen_keypair();
+        let sig = sign::sign_detached(b"data", &sk);
+
+        assert_eq!(
+            StackEntry::from_pubkey_bytes(pk.as_ref()),
+            Ok(StackEntry::PubKey(pk))
+        );
+        assert_eq!(
+            StackEntry::from_signature_bytes(sig.as_ref()),
+            Ok(StackEntry::Signature(sig))
+        );
+        assert_eq!(
+            StackEntry::from_pubkey_bytes(&pk.as_ref()[1..]),
+            Err(CryptoError::InvalidKeyLength(31, 32))
+        );
+        assert_eq!(
+            StackEntry::from_signature_bytes(pk.as_ref()),
+            Err(CryptoError::InvalidSignatureLength(32, 64))
+        );
+        assert_eq!(
+            StackEntry::from_pubkey_bytes(sig.as_ref()),
+            Err(CryptoError::InvalidKeyLength(64, 32))
+        );
+    }
+
+    #[test]
+    /// Checks only entries in branches that can be taken are iterated over
+    fn test_active_iter() {
+        use OpCodes::*;
+        let script = |entries: Vec<StackEntry>| Script::from(entries);
+        let ops = |ops: Vec<OpCodes>| ops.into_iter().map(StackEntry::Op).collect::<Vec<_>>();
+
+        // A false OP_IF branch leaves only the outer opcodes
+        let false_branch = script(ops(vec![
+            OP_1, OP_0, OP_IF, OP_DUP, OP_DROP, OP_ENDIF, OP_1,
+        ]));
+        assert_eq!(
+            false_branch.active_iter().cloned().collect::<Vec<_>>(),
+            ops(vec![OP_1, OP_0, OP_IF, OP_ENDIF, OP_1])
+        );
+        assert_eq!(false_branch.count_active_ops(), 5);
+
+        // A false inner branch within a true outer one
+        let nested = script(ops(vec![
+            OP_1, OP_IF, OP_DUP, OP_0, OP_IF, OP_DROP, OP_ELSE, OP_NOP, OP_ENDIF, OP_ELSE, OP_2DUP,
+            OP_ENDIF,
+        ]));
+        assert_eq!(
+            nested.active_iter().cloned().collect::<Vec<_>>(),
+            ops(vec![
+                OP_1, OP_IF, OP_DUP, OP_0, OP_IF, OP_ELSE, OP_NOP, OP_ENDIF, OP_ELSE, OP_ENDIF,
+            ])
+        );
+        assert_eq!(nested.count_active_ops(), 10);
+
+        // Conditions from numbers, OP_NOTIF and branches within inactive branches
+        let notif = script(vec![
+            StackEntry::Num(0),
+            StackEntry::Op(OP_NOTIF),
+            StackEntry::Op(OP_2),
+            StackEntry::Op(OP_ELSE),
+            StackEntry::Op(OP_0),
+            StackEntry::Op(OP_IF),
+            StackEntry::Op(OP_ELSE),
+            StackEntry::Op(OP_3),
+            StackEntry::Op(OP_ENDIF),
+            StackEntry::Op(OP_ENDIF),
+        ]);
+        assert_eq!(notif.count_active_ops(), 4);
+        assert_eq!(
+            notif.active_iter().cloned().collect::<Vec<_>>(),
+            [
+                vec![StackEntry::Num(0)],
+                ops(vec![OP_NOTIF, OP_2, OP_ELSE, OP_ENDIF])
+            ]
+            .concat()
+        );
+
+        // Conditions depending on the stack at run time keep both branches
+        let unknown = script(ops(vec![
+            OP_1, OP_DUP, OP_IF, OP_2, OP_ELSE, OP_3, OP_ENDIF,
+        ]));
+        assert_eq!(unknown.count_active_ops(), 7);
+        let multisig = Script::multisig_lock(1, 1, "data".to_owned(), vec![sign::gen_keypair().0]);
+        assert!(multisig.active_iter().eq(multisig.stack.iter()));
+    }
+
+    #[test]
+    /// Checks that opcode queries only see opcode entries
+    fn test_script_ops() {
+        let pub_keys: Vec<PublicKey> = (0..3).map(|_| sign::gen_keypair().0).collect();
+        let script = Script::multisig_lock(2, 3, "check_data".to_owned(), pub_keys);
+        assert!(script.contains_op(OpCodes::OP_CHECKMULTISIG));
+        assert!(!script.contains_op(OpCodes::OP_BURN));
+        assert!(!script.contains_op(OpCodes::OP_2));
+
+        let script = Script::from(vec![
+            StackEntry::Num(1),
+            StackEntry::Op(OpCodes::OP_IF),
+            StackEntry::Bytes("00".to_owned()),
+            StackEntry::Op(OpCodes::OP_BURN),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+            StackEntry::Op(OpCodes::OP_1),
+        ]);
+        assert_eq!(
+            script.ops().collect::<Vec<_>>(),
+            vec![
+                OpCodes::OP_IF,
+                OpCodes::OP_BURN,
+                OpCodes::OP_ENDIF,
+                OpCodes::OP_1
+            ]
+        );
+        assert!(script.contains_op(OpCodes::OP_BURN));
+        assert_eq!(Script::new().ops().count(), 0);
+    }
+
+    #[test]
+    /// Checks the signers required by each standard template
+    fn test_required_signers() {
+        let (pk, sk) = sign::gen_keypair();
+        let keys: Vec<PublicKey> = (0..3).map(|_| sign::gen_keypair().0).collect();
+        let sig = sign::sign_detached(b"data", &sk);
+        let address = construct_address(&pk);
+        let single_address = SignerRequirements::Single(Signer::Address(address.clone()));
+        let single_key = SignerRequirements::Single(Signer::PubKey(pk));
+        let threshold = SignerRequirements::Threshold {
+            m: 2,
+            keys: keys.clone(),
+        };
+
+        let templates = vec![
+            (Script::p2pkh_locking(&address), single_address.clone()),
+            (
+                Script::pay2pkh("data".to_owned(), sig, pk, None),
+                single_address.clone(),
+            ),
+            (
+                Script::member_multisig("data".to_owned(), pk, sig),
+                single_key.clone(),
+            ),
+            (
+                Script::new_create_asset(0, 0, "data".to_owned(), sig, pk).unwrap(),
+                single_key.clone(),
+            ),
+            (
+                Script::multisig_lock(2, 3, "data".to_owned(), keys.clone()),
+                threshold.clone(),
+            ),
+            (
+                Script::multisig_validation(2, 3, "data".to_owned(), vec![sig, sig], keys.clone()),
+                threshold.clone(),
+            ),
+        ];
+        for (script, expected) in templates {
+            assert_eq!(script.required_signers(), expected, "{:?}", script.stack);
+        }
+
+        // OP_IF <p2pkh> OP_ELSE OP_NOTIF <member> OP_ELSE <multisig> OP_ENDIF OP_ENDIF
+        let branch = |entries: Vec<StackEntry>| Script::from(entries).stack;
+        let inner = [
+            vec![StackEntry::Op(OpCodes::OP_NOTIF)],
+            Script::member_multisig("data".to_owned(), pk, sig).stack,
+            vec![StackEntry::Op(OpCodes::OP_ELSE)],
+            Script::multisig_lock(2, 3, "data".to_owned(), keys.clone()).stack,
+            vec![StackEntry::Op(OpCodes::OP_ENDIF)],
+        ]
+        .concat();
+        let nested = [
+            vec![
+                StackEntry::Op(OpCodes::OP_1),
+                StackEntry::Op(OpCodes::OP_IF),
+            ],
+            Script::p2pkh_locking(&address).stack,
+            vec![StackEntry::Op(OpCodes::OP_ELSE)],
+            inner.clone(),
+            vec![StackEntry::Op(OpCodes::OP_ENDIF)],
+        ]
+        .concat();
+        assert_eq!(
+            Script::from(nested).required_signers(),
+            SignerRequirements::Branched(vec![
+                single_address,
+                SignerRequirements::Branched(vec![single_key, threshold]),
+            ])
+        );
+
+        // Non-standard scripts
+        let mut prefixed = Script::p2pkh_locking(&address);
+        prefixed.stack.insert(0, StackEntry::Op(OpCodes::OP_DROP));
+        let mut wrong_n = Script::multisig_lock(2, 3, "data".to_owned(), keys.clone());
+        wrong_n.stack.remove(2);
+        let no_else = [
+            vec![StackEntry::Op(OpCodes::OP_IF)],
+            Script::member_multisig("data".to_owned(), pk, sig).stack,
+            vec![StackEntry::Op(OpCodes::OP_ENDIF)],
+        ]
+        .concat();
+        let trailing = [inner, vec![StackEntry::Op(OpCodes::OP_1)]].concat();
+        let unknown = vec![
+            Script::multisig_unlock("data".to_owned(), vec![sig]),
+            Script::new_for_coinbase(0),
+            Script::from(vec![StackEntry::Op(OpCodes::OP_1)]),
+            prefixed,
+            wrong_n,
+            Script::from(no_else),
+            Script::from(trailing),
+        ];
+        for script in unknown {
+            assert_eq!(
+                script.required_signers(),
+                SignerRequirements::Unknown,
+                "{:?}",
+                script.stack
+            );
+        }
+    }
+
     #[test]
     fn test_interpret_script() {
         // empty script
@@ -2465,26 +4538,287 @@ mod tests {
         let script = Script::from(v);
         assert!(!script.interpret());
         // # opcodes <= 201
-        let v = vec![StackEntry::Op(OpCodes::OP_1); MAX_OPS_PER_SCRIPT as usize];
+        let v = vec![StackEntry::Op(OpCodes::OP_1); MAX_OPS_PER_SCRIPT];
         let script = Script::from(v);
         assert!(script.interpret());
         // # opcodes > 201
-        let v = vec![StackEntry::Op(OpCodes::OP_1); (MAX_OPS_PER_SCRIPT + 1) as usize];
+        let v = vec![StackEntry::Op(OpCodes::OP_1); MAX_OPS_PER_SCRIPT + 1];
         let script = Script::from(v);
         assert!(!script.interpret());
         // # items on interpreter stack <= 1000
-        let v = vec![StackEntry::Num(1); MAX_STACK_SIZE as usize];
+        let v = vec![StackEntry::Num(1); MAX_STACK_SIZE];
         let script = Script::from(v);
         assert!(script.interpret());
         // # items on interpreter stack > 1000
-        let v = vec![StackEntry::Num(1); (MAX_STACK_SIZE + 1) as usize];
+        let v = vec![StackEntry::Num(1); MAX_STACK_SIZE + 1];
+        let script = Script::from(v);
+        assert!(!script.interpret());
+    }
+
+    #[test]
+    /// Checks that scripts are built from iterators and chained in order
+    fn test_script_concat() {
+        let a: Script = vec![StackEntry::Num(1), StackEntry::Op(OpCodes::OP_DUP)]
+            .into_iter()
+            .collect();
+        let mut b = Script::from(vec![StackEntry::Bytes("b".to_string())]);
+        b.extend(vec![StackEntry::Op(OpCodes::OP_DROP)]);
+
+        let mut expected = a.stack.clone();
+        expected.extend(b.stack.clone());
+
+        assert_eq!(Script::concat(a, b.clone()).stack, expected);
+        assert_eq!(
+            Script::prefix(StackEntry::Num(0), b).stack,
+            vec![
+                StackEntry::Num(0),
+                StackEntry::Bytes("b".to_string()),
+                StackEntry::Op(OpCodes::OP_DROP)
+            ]
+        );
+    }
+
+    #[test]
+    /// Checks that the p2pkh locking script is unlocked by the scriptSig of a `pay2pkh` script
+    fn test_p2pkh_locking() {
+        let (pk, sk) = sign::gen_keypair();
+        let check_data = hex::encode(vec![0, 0, 0]);
+        let signature = sign::sign_detached(check_data.as_bytes(), &sk);
+        let unlocking = Script::from(vec![
+            StackEntry::Bytes(check_data.clone()),
+            StackEntry::Signature(signature),
+            StackEntry::PubKey(pk),
+        ]);
+
+        let script = Script::concat(
+            unlocking.clone(),
+            Script::p2pkh_locking(&construct_address(&pk)),
+        );
+        assert!(script.interpret());
+        assert_eq!(script, Script::pay2pkh(check_data, signature, pk, None));
+
+        let (other_pk, _) = sign::gen_keypair();
+        let script = Script::concat(
+            unlocking,
+            Script::p2pkh_locking(&construct_address(&other_pk)),
+        );
+        assert!(!script.interpret());
+    }
+
+    #[test]
+    /// Checks that known redundant patterns are removed and others are kept
+    fn test_optimize_script() {
+        // OP_1 OP_NOP OP_DUP OP_NOP OP_DROP OP_NOT OP_NOT -> OP_1
+        let v = vec![
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_NOP),
+            StackEntry::Op(OpCodes::OP_DUP),
+            StackEntry::Op(OpCodes::OP_NOP),
+            StackEntry::Op(OpCodes::OP_DROP),
+            StackEntry::Op(OpCodes::OP_NOT),
+            StackEntry::Op(OpCodes::OP_NOT),
+        ];
+        let script = Script::from(v);
+        assert_eq!(
+            script.optimize(),
+            Script::from(vec![StackEntry::Op(OpCodes::OP_1)])
+        );
+        // OP_2 OP_NOT OP_NOT is kept, as it maps 2 to 1
+        let v = vec![
+            StackEntry::Op(OpCodes::OP_2),
+            StackEntry::Op(OpCodes::OP_NOT),
+            StackEntry::Op(OpCodes::OP_NOT),
+        ];
+        let script = Script::from(v);
+        assert_eq!(script.optimize(), script);
+        // OP_DUP OP_DROP on an empty stack is kept, as it fails
+        let v = vec![
+            StackEntry::Op(OpCodes::OP_DUP),
+            StackEntry::Op(OpCodes::OP_DROP),
+            StackEntry::Op(OpCodes::OP_1),
+        ];
+        let script = Script::from(v);
+        assert_eq!(script.optimize(), script);
+        // OP_DUP OP_DROP is kept when the removed OP_DUP could exceed MAX_STACK_BYTES
+        let blob = StackEntry::Bytes("a".repeat(MAX_SCRIPT_ITEM_SIZE));
+        let mut v = vec![blob.clone()];
+        v.extend(vec![StackEntry::Op(OpCodes::OP_DUP); 190]);
+        v.extend(vec![
+            StackEntry::Bytes("a".repeat(500)),
+            StackEntry::Op(OpCodes::OP_DUP),
+            StackEntry::Op(OpCodes::OP_DROP),
+        ]);
         let script = Script::from(v);
         assert!(!script.interpret());
+        assert_eq!(script.optimize(), script);
+        // Invalid scripts are kept, as removing opcodes could make them valid
+        let mut v = vec![StackEntry::Op(OpCodes::OP_NOP); MAX_OPS_PER_SCRIPT];
+        v.push(StackEntry::Op(OpCodes::OP_1));
+        let script = Script::from(v);
+        assert_eq!(script.optimize(), script);
+    }
+
+    #[test]
+    /// Checks that optimizing random scripts never changes their interpretation
+    fn test_optimize_preserves_interpret() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let entries = [
+            StackEntry::Op(OpCodes::OP_0),
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_2),
+            StackEntry::Num(0),
+            StackEntry::Num(1),
+            StackEntry::Num(2),
+            StackEntry::Bytes("a".to_string()),
+            StackEntry::Op(OpCodes::OP_NOP),
+            StackEntry::Op(OpCodes::OP_DUP),
+            StackEntry::Op(OpCodes::OP_DROP),
+            StackEntry::Op(OpCodes::OP_NOT),
+            StackEntry::Op(OpCodes::OP_0NOTEQUAL),
+            StackEntry::Op(OpCodes::OP_IF),
+            StackEntry::Op(OpCodes::OP_NOTIF),
+            StackEntry::Op(OpCodes::OP_ELSE),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+            StackEntry::Op(OpCodes::OP_ADD),
+            StackEntry::Op(OpCodes::OP_SWAP),
+            StackEntry::Op(OpCodes::OP_EQUAL),
+            StackEntry::Op(OpCodes::OP_VERIFY),
+        ];
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut optimized_count = 0;
+
+        for _ in 0..10000 {
+            let len = rng.gen_range(1..16);
+            let v: Vec<StackEntry> = (0..len)
+                .map(|_| entries[rng.gen_range(0..entries.len())].clone())
+                .collect();
+            let script = Script::from(v);
+            let optimized = script.optimize();
+
+            if optimized != script {
+                optimized_count += 1;
+            }
+            assert_eq!(
+                optimized.interpret(),
+                script.interpret(),
+                "{:?}",
+                script.stack
+            );
+        }
+        assert!(optimized_count > 0);
+    }
+
+    #[test]
+    fn test_semantic_eq() {
+        use OpCodes::*;
+
+        // Padding and constant opcodes don't change the meaning of a script
+        let padded = Script::from(vec![
+            StackEntry::Op(OP_NOP),
+            StackEntry::Op(OP_2),
+            StackEntry::Op(OP_0),
+            StackEntry::Op(OP_IF),
+            StackEntry::Op(OP_NOP),
+            StackEntry::Op(OP_ENDIF),
+            StackEntry::Op(OP_16),
+        ]);
+        let canonical = Script::from(vec![
+            StackEntry::Num(2),
+            StackEntry::Num(0),
+            StackEntry::Op(OP_IF),
+            StackEntry::Op(OP_ENDIF),
+            StackEntry::Num(16),
+        ]);
+        assert_eq!(padded.normalized(), canonical);
+        assert_eq!(canonical.normalized(), canonical);
+        assert!(padded.semantic_eq(&canonical));
+        assert!(canonical.semantic_eq(&padded));
+        assert_ne!(padded, canonical);
+
+        // Data of different types is pushed as is
+        let bytes = Script::from(vec![StackEntry::Bytes("01".to_string())]);
+        let num = Script::from(vec![StackEntry::Op(OP_1)]);
+        assert!(!bytes.semantic_eq(&num));
+
+        // Constants are kept as opcodes when folding them would exceed MAX_SCRIPT_SIZE
+        let mut v = Vec::new();
+        for len in [500; 19].iter().chain(&[478]) {
+            v.extend(vec![
+                StackEntry::Bytes("a".repeat(*len)),
+                StackEntry::Op(OP_DROP),
+            ]);
+        }
+        let stripped = Script::from([v.clone(), vec![StackEntry::Op(OP_1)]].concat());
+        let large = Script::from([v, vec![StackEntry::Op(OP_NOP), StackEntry::Op(OP_1)]].concat());
+        assert!(large.is_valid());
+        assert_eq!(large.normalized(), stripped);
+        assert!(large.semantic_eq(&stripped));
+
+        // Invalid scripts are only equal to themselves
+        let invalid = Script::from(vec![StackEntry::Op(OP_NOP); MAX_OPS_PER_SCRIPT + 1]);
+        assert_eq!(invalid.normalized(), invalid);
+        assert!(!invalid.semantic_eq(&Script::new()));
+    }
+
+    #[test]
+    /// Checks that normalizing random scripts never changes their outcome or final stack
+    fn test_normalized_preserves_evaluate() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let entries = [
+            StackEntry::Op(OpCodes::OP_0),
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_2),
+            StackEntry::Op(OpCodes::OP_16),
+            StackEntry::Num(0),
+            StackEntry::Num(1),
+            StackEntry::Num(2),
+            StackEntry::Bytes("01".to_string()),
+            StackEntry::Op(OpCodes::OP_NOP),
+            StackEntry::Op(OpCodes::OP_DUP),
+            StackEntry::Op(OpCodes::OP_DROP),
+            StackEntry::Op(OpCodes::OP_DEPTH),
+            StackEntry::Op(OpCodes::OP_NOT),
+            StackEntry::Op(OpCodes::OP_IF),
+            StackEntry::Op(OpCodes::OP_NOTIF),
+            StackEntry::Op(OpCodes::OP_ELSE),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+            StackEntry::Op(OpCodes::OP_ADD),
+            StackEntry::Op(OpCodes::OP_SWAP),
+            StackEntry::Op(OpCodes::OP_EQUAL),
+            StackEntry::Op(OpCodes::OP_LOOSEEQUAL),
+            StackEntry::Op(OpCodes::OP_TOALTSTACK),
+            StackEntry::Op(OpCodes::OP_VERIFY),
+        ];
+        let context = ExecutionContext::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        let (mut normalized_count, mut success_count) = (0, 0);
+
+        for _ in 0..10000 {
+            let len = rng.gen_range(1..16);
+            let v: Vec<StackEntry> = (0..len)
+                .map(|_| entries[rng.gen_range(0..entries.len())].clone())
+                .collect();
+            let script = Script::from(v);
+            let normalized = script.normalized();
+
+            if normalized != script {
+                normalized_count += 1;
+            }
+            let result = script.evaluate(&context);
+            success_count += result.is_ok() as usize;
+            assert_eq!(normalized.evaluate(&context), result, "{:?}", script.stack);
+            assert_eq!(normalized.normalized(), normalized);
+            assert!(script.semantic_eq(&normalized));
+        }
+        assert!(normalized_count > 0);
+        assert!(success_count > 0);
     }
 
     #[test]
     fn test_conditionals() {
-        // OP_1 OP_IF OP_2 OP_ELSE OP_3 OP_ELSE OP_0 OP_ENDIF
+        // OP_1 OP_IF OP_2 OP_ELSE OP_3 OP_ELSE OP_4 OP_ENDIF fails, as a block has one OP_ELSE
         let v = vec![
             StackEntry::Op(OpCodes::OP_1),
             StackEntry::Op(OpCodes::OP_IF),
@@ -2492,11 +4826,32 @@ mod tests {
             StackEntry::Op(OpCodes::OP_ELSE),
             StackEntry::Op(OpCodes::OP_3),
             StackEntry::Op(OpCodes::OP_ELSE),
+            StackEntry::Op(OpCodes::OP_4),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+        ];
+        let script = Script::from(v);
+        assert!(!script.interpret());
+        // OP_1 OP_IF OP_0 OP_IF OP_2 OP_ELSE OP_3 OP_ENDIF OP_ELSE OP_4 OP_ENDIF
+        let v = vec![
+            StackEntry::Op(OpCodes::OP_1),
+            StackEntry::Op(OpCodes::OP_IF),
             StackEntry::Op(OpCodes::OP_0),
+            StackEntry::Op(OpCodes::OP_IF),
+            StackEntry::Op(OpCodes::OP_2),
+            StackEntry::Op(OpCodes::OP_ELSE),
+            StackEntry::Op(OpCodes::OP_3),
+            StackEntry::Op(OpCodes::OP_ENDIF),
+            StackEntry::Op(OpCodes::OP_ELSE),
+            StackEntry::Op(OpCodes::OP_4),
             StackEntry::Op(OpCodes::OP_ENDIF),
         ];
         let script = Script::from(v);
-        assert!(!script.interpret());
+        assert_eq!(
+            script
+                .evaluate(&ExecutionContext::default())
+                .map(|stack| stack.main_stack),
+            Ok(vec![StackEntry::Num(3)])
+        );
         // OP_1 OP_IF OP_2 OP_ELSE OP_3 OP_ENDIF
         let v = vec![
             StackEntry::Op(OpCodes::OP_1),
@@ -2705,10 +5060,271 @@ mod tests {
         let (pk, sk) = sign::gen_keypair();
         let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
 
-        let script = Script::new_create_asset(0, asset_hash, signature, pk);
+        let script = Script::new_create_asset(0, 0, asset_hash, signature, pk).unwrap();
         assert!(tx_has_valid_create_script(&script, &asset));
     }
 
+    #[test]
+    /// Checks that create transactions are only valid with a single output carrying
+    /// exactly the asset the create script signs
+    fn test_validate_create_tx() {
+        let (pk, sk) = sign::gen_keypair();
+        let genesis_hash = GenesisTxHashSpec::create_random();
+        let zero = construct_item_create_tx(0, 0, pk, &sk, 0, genesis_hash.clone(), None, None);
+        assert_eq!(zero, Err(CreateTxError::InvalidAmount));
+        let tx = construct_item_create_tx(0, 0, pk, &sk, 5, genesis_hash, None, None).unwrap();
+        assert_eq!(validate_create_tx(&tx), Ok(()));
+
+        let with_output = |f: &dyn Fn(&mut Vec<TxOut>)| {
+            let mut tx = tx.clone();
+            f(&mut tx.outputs);
+            validate_create_tx(&tx)
+        };
+        let with_item = |f: &dyn Fn(&mut ItemAsset)| {
+            with_output(&|tx_outs| match &mut tx_outs[0].value {
+                Asset::Item(item) => f(item),
+                _ => unreachable!(),
+            })
+        };
+
+        let invalid = Err(CreateTxError::InvalidScript);
+        assert_eq!(with_item(&|item| item.amount = 6), invalid);
+        assert_eq!(
+            with_item(&|item| item.amount = 0),
+            Err(CreateTxError::InvalidAmount)
+        );
+        // Any well formed genesis hash can be created, malformed ones can't
+        for genesis_hash in [
+            None,
+            Some(ITEM_DEFAULT_DRS_TX_HASH.to_owned()),
+            GenesisTxHashSpec::create_random().get_genesis_hash(),
+            Some(construct_tx_in_signable_hash(&OutPoint::new(
+                "g0".to_owned(),
+                0,
+            ))),
+        ] {
+            assert_eq!(
+                with_item(&|item| item.genesis_hash = genesis_hash.clone()),
+                Ok(())
+            );
+        }
+        let tx_hash = tx.outputs[0].value.genesis_hash().unwrap();
+        for genesis_hash in [
+            "genesis".to_owned(),
+            tx_hash.to_uppercase(),
+            tx_hash[1..].to_owned(),
+        ] {
+            assert_eq!(
+                with_item(&|item| item.genesis_hash = Some(genesis_hash.clone())),
+                Err(CreateTxError::GenesisHashConflict(genesis_hash.clone()))
+            );
+        }
+        assert_eq!(
+            with_output(&|tx_outs| tx_outs[0].value = Asset::token_u64(5)),
+            invalid
+        );
+        assert_eq!(
+            with_output(&|tx_outs| tx_outs.push(tx_outs[0].clone())),
+            Err(CreateTxError::OutputCount(2))
+        );
+
+        let mut spend = tx.clone();
+        spend.inputs[0].previous_out = Some(OutPoint::new("g0".to_owned(), 0));
+        assert_eq!(
+            validate_create_tx(&spend),
+            Err(CreateTxError::NotCreateInput)
+        );
+    }
+
+    #[test]
+    /// Checks that the canonical create script reports what it commits to
+    fn test_interpret_create_claims() {
+        let asset = Asset::item(1, None, None);
+        let asset_hash = construct_tx_in_signable_asset_hash(&asset);
+        let (pk, sk) = sign::gen_keypair();
+        let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
+        let script = Script::new_create_asset(7, 7, asset_hash.clone(), signature, pk).unwrap();
+
+        let context = CreateContext {
+            asset_hash: asset_hash.clone(),
+            block_number: Some(7),
+            current_height: None,
+        };
+        assert_eq!(
+            script.interpret_create(&context),
+            Ok(CreateClaims {
+                pub_key: pk,
+                asset_hash: asset_hash.clone(),
+                block_number: 7,
+            })
+        );
+
+        let wrong_block = CreateContext {
+            block_number: Some(8),
+            ..context.clone()
+        };
+        assert_eq!(
+            script.interpret_create(&wrong_block),
+            Err(ScriptError::BlockNumberMismatch(7))
+        );
+
+        let wrong_hash = CreateContext {
+            asset_hash: "other".to_owned(),
+            ..context
+        };
+        assert_eq!(
+            script.interpret_create(&wrong_hash),
+            Err(ScriptError::AssetHashNotSigned)
+        );
+    }
+
+    #[test]
+    /// Checks that create scripts are constructed and interpreted for the same block numbers,
+    /// within `CREATE_BLOCK_WINDOW` of the current height
+    fn test_create_block_number_window() {
+        let asset_hash = construct_tx_in_signable_asset_hash(&Asset::item(1, None, None));
+        let (pk, sk) = sign::gen_keypair();
+        let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
+        let height = 1000;
+
+        for (block_number, in_window) in [
+            (height, true),
+            (height - CREATE_BLOCK_WINDOW, true),
+            (height + CREATE_BLOCK_WINDOW, true),
+            (height - CREATE_BLOCK_WINDOW - 1, false),
+            (height + CREATE_BLOCK_WINDOW + 1, false),
+            (0, false),
+        ] {
+            let outside = ScriptError::BlockNumberOutsideWindow(block_number, height);
+            let constructed =
+                Script::new_create_asset(block_number, height, asset_hash.clone(), signature, pk);
+            assert_eq!(constructed.is_ok(), in_window, "{block_number}");
+            if !in_window {
+                assert_eq!(constructed, Err(outside.clone()));
+            }
+
+            let script = Script::new_create_asset(
+                block_number,
+                block_number,
+                asset_hash.clone(),
+                signature,
+                pk,
+            )
+            .unwrap();
+            let context = CreateContext {
+                asset_hash: asset_hash.clone(),
+                block_number: None,
+                current_height: Some(height),
+            };
+            let interpreted = script.interpret_create(&context);
+            assert_eq!(interpreted.is_ok(), in_window, "{block_number}");
+            if !in_window {
+                assert_eq!(interpreted, Err(outside));
+            }
+        }
+
+        // The window is clamped at the genesis block
+        assert!(Script::new_create_asset(0, 0, asset_hash.clone(), signature, pk).is_ok());
+
+        // Block numbers are never truncated to fit a `Num`
+        let max_u32 = u32::MAX as u64;
+        assert_eq!(block_number_as_num(max_u32, 32), Ok(u32::MAX as usize));
+        assert_eq!(
+            block_number_as_num(max_u32 + 1, 32),
+            Err(ScriptError::BlockNumberTooLarge(max_u32 + 1))
+        );
+        assert_eq!(block_number_as_num(u64::MAX, 64), Ok(u64::MAX as usize));
+    }
+
+    #[test]
+    /// Checks that create scripts may only use white-listed opcodes
+    fn test_interpret_create_rejects_opcodes() {
+        let asset = Asset::item(1, None, None);
+        let asset_hash = construct_tx_in_signable_asset_hash(&asset);
+        let (pk, sk) = sign::gen_keypair();
+        let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
+
+        let mut script = Script::new_create_asset(0, 0, asset_hash.clone(), signature, pk).unwrap();
+        script.extend(vec![StackEntry::Num(1), StackEntry::Op(OpCodes::OP_ADD)]);
+        let context = CreateContext {
+            asset_hash,
+            block_number: None,
+            current_height: None,
+        };
+
+        assert!(script.interpret());
+        assert_eq!(
+            script.interpret_create(&context),
+            Err(ScriptError::OpcodeNotAllowed(OpCodes::OP_ADD))
+        );
+        assert!(!tx_has_valid_create_script(&script, &asset));
+
+        let script = Script::from(script.stack[1..].to_vec());
+        assert_eq!(
+            script.interpret_create(&context),
+            Err(ScriptError::NotCreateScript)
+        );
+    }
+
+    #[test]
+    /// Checks that create scripts beyond the canonical pattern are accepted from
+    /// `CREATE_SCRIPT_INTERPRETER_VERSION`, with earlier versions keeping the exact pattern
+    fn test_create_script_versions() {
+        let asset = Asset::item(2, None, None);
+        let (pk, sk) = sign::gen_keypair();
+        let (co_pk, co_sk) = sign::gen_keypair();
+
+        // Mint co-signed by a second key, for a transaction of `version`
+        let co_signed = |version: usize| {
+            let asset_hash = SignableMessage::asset(&asset).hash_at_version(version);
+            let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
+            let co_signature = sign::sign_detached(asset_hash.as_bytes(), &co_sk);
+            Script::from(vec![
+                StackEntry::Op(OpCodes::OP_CREATE),
+                StackEntry::Num(0),
+                StackEntry::Op(OpCodes::OP_DROP),
+                StackEntry::Bytes(asset_hash.clone()),
+                StackEntry::Signature(signature),
+                StackEntry::PubKey(pk),
+                StackEntry::Op(OpCodes::OP_CHECKSIGVERIFY),
+                StackEntry::Bytes(asset_hash),
+                StackEntry::Signature(co_signature),
+                StackEntry::PubKey(co_pk),
+                StackEntry::Op(OpCodes::OP_CHECKSIG),
+            ])
+        };
+        let interpreted = CREATE_SCRIPT_INTERPRETER_VERSION;
+        let legacy = NETWORK_VERSION as usize;
+        assert!(legacy < interpreted);
+
+        let script = co_signed(interpreted);
+        let context = CreateContext {
+            asset_hash: SignableMessage::asset(&asset).hash_at_version(interpreted),
+            block_number: Some(0),
+            current_height: None,
+        };
+        assert_eq!(script.interpret_create(&context).unwrap().pub_key, pk);
+        assert!(tx_has_valid_create_script_at_version(
+            &script,
+            &asset,
+            interpreted
+        ));
+
+        // The same whitelisted script is not the exact pattern existing chain data uses
+        let script = co_signed(legacy);
+        assert!(!tx_has_valid_create_script_at_version(
+            &script, &asset, legacy
+        ));
+        assert!(!tx_has_valid_create_script(&script, &asset));
+
+        let asset_hash = SignableMessage::asset(&asset).hash_at_version(legacy);
+        let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
+        let canonical = Script::new_create_asset(0, 0, asset_hash, signature, pk).unwrap();
+        assert!(tx_has_valid_create_script_at_version(
+            &canonical, &asset, legacy
+        ));
+    }
+
     #[test]
     /// Checks that metadata is validated correctly if too large
     fn test_fail_create_item_script_invalid() {
@@ -2718,7 +5334,7 @@ mod tests {
         let (pk, sk) = sign::gen_keypair();
         let signature = sign::sign_detached(asset_hash.as_bytes(), &sk);
 
-        let script = Script::new_create_asset(0, asset_hash, signature, pk);
+        let script = Script::new_create_asset(0, 0, asset_hash, signature, pk).unwrap();
         assert!(!tx_has_valid_create_script(&script, &asset));
     }
 
@@ -2840,6 +5456,70 @@ mod tests {
         ));
     }
 
+    #[test]
+    /// Checks that dual signature outputs are spendable with both signatures only
+    fn test_dual_p2pkh_spend() {
+        let (pk_a, sk_a) = sign::gen_keypair();
+        let (pk_b, sk_b) = sign::gen_keypair();
+        let (pk_c, sk_c) = sign::gen_keypair();
+        let address = construct_dual_address(&pk_a, &pk_b);
+        assert_eq!(address, construct_dual_address(&pk_b, &pk_a));
+        assert_ne!(address, construct_dual_address(&pk_a, &pk_c));
+        assert_ne!(address, construct_address(&pk_a));
+        assert!(is_address(&address));
+
+        let out_point = OutPoint::new("g0".to_owned(), 0);
+        let utxo = UtxoMap::from([(
+            out_point.clone(),
+            TxOut::new_token_amount(address, TokenAmount(10), None),
+        )]);
+        let tx_outs = vec![TxOut::new_token_amount(
+            hex::encode([1; 32]),
+            TokenAmount(10),
+            None,
+        )];
+
+        // Each party signs on its own, then the input is assembled
+        let unsigned = TxIn::new_from_input(out_point.clone(), Script::new());
+        let message = SignableMessage::tx_in(&unsigned, &tx_outs);
+        let sign = |sk| message.sign_at_version(NETWORK_VERSION as usize, sk);
+        let (sig_a, sig_b, sig_c) = (sign(&sk_a), sign(&sk_b), sign(&sk_c));
+        let spend = |signatures: Vec<Signature>, pub_keys: Vec<PublicKey>| {
+            let tx_const = TxConstructor {
+                previous_out: out_point.clone(),
+                signatures,
+                pub_keys,
+                address_version: None,
+            };
+            let mut tx = Transaction::new();
+            tx.inputs = construct_dual_tx_ins(vec![tx_const], &tx_outs);
+            tx.outputs = tx_outs.clone();
+            tx_is_valid(&tx, 0, |v| utxo.get(v))
+        };
+
+        assert_eq!(spend(vec![sig_a, sig_b], vec![pk_a, pk_b]), Ok(()));
+        assert_eq!(spend(vec![sig_b, sig_a], vec![pk_b, pk_a]), Ok(()));
+
+        let invalid = Err(TxValidationError::Input(0, InputError::InvalidScript));
+        // Either signature alone
+        assert_eq!(spend(vec![sig_a], vec![pk_a, pk_b]), invalid);
+        assert_eq!(spend(vec![sig_b], vec![pk_a, pk_b]), invalid);
+        assert_eq!(spend(vec![sig_a, sig_a], vec![pk_a, pk_b]), invalid);
+        assert_eq!(spend(vec![sig_a, sig_c], vec![pk_a, pk_b]), invalid);
+        // Signatures swapped against the keys
+        assert_eq!(spend(vec![sig_b, sig_a], vec![pk_a, pk_b]), invalid);
+        // Valid signatures from keys the address doesn't commit to
+        assert_eq!(spend(vec![sig_a, sig_c], vec![pk_a, pk_c]), invalid);
+
+        // A single signature P2PKH script doesn't unlock a dual address
+        let mut tx = Transaction::new();
+        let hash = message.hash_at_version(NETWORK_VERSION as usize);
+        let script = Script::pay2pkh(hash, sig_a, pk_a, None);
+        tx.inputs = vec![TxIn::new_from_input(out_point.clone(), script)];
+        tx.outputs = tx_outs.clone();
+        assert_eq!(tx_is_valid(&tx, 0, |v| utxo.get(v)), invalid);
+    }
+
     #[test]
     /// Checks that invalid p2pkh transaction signatures are validated as such
     fn test_fail_p2pkh_sig_invalid() {
@@ -3061,20 +5741,131 @@ mod tests {
     }
 
     #[test]
-    /// Validate transaction that has no inputs and no outputs
+    /// Validate transactions that have no inputs or no outputs
     fn test_tx_is_invalid_empty() {
         let mut tx = Transaction::new();
+        let result = tx_is_valid(&tx, 500000000, |_| None);
+        assert_eq!(
+            result,
+            Err(TxValidationError::Structure(TxStructureError::NoOutputs))
+        );
+
         let tx_out = TxOut {
             value: Asset::Token(TokenAmount(0)),
             locktime: 0,
             script_public_key: Some("".to_string()),
         };
+        tx.fees.push(tx_out.clone());
+        tx.inputs = generate_unsigned_tx_ins();
+        let result = tx_is_valid(&tx, 500000000, |_| None);
+        assert_eq!(
+            result,
+            Err(TxValidationError::Structure(TxStructureError::NoOutputs))
+        );
 
         tx.outputs.push(tx_out);
-
+        tx.inputs.clear();
         let result = tx_is_valid(&tx, 500000000, |_| None);
-        assert!(!result.0);
-        assert_eq!(result.1, "Transaction has no inputs or outputs".to_string());
+        assert_eq!(
+            result,
+            Err(TxValidationError::Structure(TxStructureError::NoInputs))
+        );
+    }
+
+    #[test]
+    /// Checks that tx_is_valid reports why a transaction is invalid
+    fn test_tx_is_valid_errors() {
+        let (pk, sk) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let out_point = OutPoint::new("tx_hash".to_owned(), 0);
+        let utxo = BTreeMap::from([(
+            out_point.clone(),
+            TxOut::new_token_amount(address.clone(), TokenAmount(3), None),
+        )]);
+        let signed_tx = |outputs: Vec<TxOut>| {
+            let mut tx = Transaction::new();
+            tx.outputs = outputs;
+            let unsigned = TxIn::new_from_input(out_point.clone(), Script::new());
+            let message = SignableMessage::tx_in(&unsigned, &tx.outputs);
+            let script = Script::pay2pkh(
+                message.hash_at_version(tx.version),
+                message.sign_at_version(tx.version, &sk),
+                pk,
+                None,
+            );
+            tx.inputs = vec![TxIn::new_from_input(out_point.clone(), script)];
+            tx
+        };
+        let token = |address: &str, amount| {
+            TxOut::new_token_amount(address.to_owned(), TokenAmount(amount), None)
+        };
+        let valid_tx = signed_tx(vec![token(&address, 3)]);
+        assert_eq!(tx_is_valid(&valid_tx, 0, |v| utxo.get(v)), Ok(()));
+
+        let mut no_previous_out = valid_tx.clone();
+        no_previous_out.inputs[0].previous_out = None;
+        assert_eq!(
+            tx_is_valid(&no_previous_out, 0, |v| utxo.get(v)),
+            Err(TxValidationError::Input(0, InputError::NoPreviousOut))
+        );
+
+        assert_eq!(
+            tx_is_valid(&valid_tx, 0, |_| None),
+            Err(TxValidationError::Input(
+                0,
+                InputError::NotInUtxo(out_point.clone())
+            ))
+        );
+
+        let locked = TxOut {
+            locktime: 10,
+            ..utxo[&out_point].clone()
+        };
+        assert_eq!(
+            tx_is_valid(&valid_tx, 5, |_| Some(&locked)),
+            Err(TxValidationError::Input(
+                0,
+                InputError::LocktimeNotMet {
+                    locktime: 10,
+                    current_block: 5,
+                }
+            ))
+        );
+        assert_eq!(tx_is_valid(&valid_tx, 10, |_| Some(&locked)), Ok(()));
+
+        let mut unsigned = valid_tx;
+        unsigned.inputs[0].script_signature = Script::new();
+        assert_eq!(
+            tx_is_valid(&unsigned, 0, |v| utxo.get(v)),
+            Err(TxValidationError::Input(0, InputError::InvalidScript))
+        );
+
+        let unbalanced = signed_tx(vec![token(&address, 4)]);
+        assert_eq!(
+            tx_is_valid(&unbalanced, 0, |v| utxo.get(v)),
+            Err(TxValidationError::unbalanced(
+                AssetValues::token_u64(3),
+                AssetValues::token_u64(4),
+            ))
+        );
+
+        let new_item = TxOut::new_asset(address.clone(), Asset::item(1, None, None), None);
+        let item_on_spend = signed_tx(vec![token(&address, 3), new_item]);
+        assert_eq!(
+            tx_is_valid(&item_on_spend, 0, |v| utxo.get(v)),
+            Err(TxValidationError::Output(OutputError::ItemOnSpendInvalid(
+                OutputKind::Normal,
+                1
+            )))
+        );
+
+        let invalid_address = signed_tx(vec![token("addr", 3)]);
+        assert_eq!(
+            tx_is_valid(&invalid_address, 0, |v| utxo.get(v)),
+            Err(TxValidationError::Output(
+                OutputError::InvalidAddressLength(OutputKind::Normal, 0, "addr".to_owned())
+            ))
+        );
     }
 
     #[test]
@@ -3154,10 +5945,12 @@ mod tests {
                 ..Default::default()
             };
 
-            let result = tx_is_valid(&tx, 500000000, |v| {
-                Some(&tx_in_previous_out).filter(|_| v == &tx_outpoint)
-            });
-            actual_result.push(result.0);
+            let is_in_utxo = |v: &OutPoint| Some(&tx_in_previous_out).filter(|_| v == &tx_outpoint);
+            let result = tx_is_valid(&tx, 500000000, is_in_utxo);
+            let cache = ValidationCache::new(1);
+            assert_eq!(cache.check(&tx, 500000000, is_in_utxo), result);
+            assert_eq!(cache.check(&tx, 500000000, is_in_utxo), result);
+            actual_result.push(result.is_ok());
         }
 
         actual_result == inputs.iter().map(|(_, e)| *e).collect::<Vec<bool>>()
@@ -3175,7 +5968,7 @@ mod tests {
         test_tx_drs_common(
             &[(3, None, None), (2, None, None)],
             &[(3, None), (2, None)],
-            (true, "".to_string()),
+            Ok(()),
         );
     }
 
@@ -3192,7 +5985,7 @@ mod tests {
         test_tx_drs_common(
             &[(3, None, None), (2, None, None)],
             &[(3, None), (3, None)],
-            (false, "TxOuts spent don't match TxIns spent".to_string()),
+            Err("TxOuts spent don't match TxIns spent: tokens +1".to_string()),
         );
     }
 
@@ -3212,7 +6005,10 @@ mod tests {
                 (2, Some("genesis_hash_2"), None),
             ],
             &[(3, Some("genesis_hash_1")), (3, Some("genesis_hash_2"))],
-            (false, "TxOuts spent don't match TxIns spent".to_string()),
+            Err(
+                "Output 1 spends more items of genesis hash genesis_hash_2 than inputs provide"
+                    .to_string(),
+            ),
         );
     }
 
@@ -3235,7 +6031,10 @@ mod tests {
                 (3, Some("genesis_hash_1")),
                 (2, Some("invalid_genesis_hash")),
             ],
-            (false, "TxOuts spent don't match TxIns spent".to_string()),
+            Err(
+                "Output 1 references genesis hash invalid_genesis_hash not present in inputs"
+                    .to_string(),
+            ),
         );
     }
 
@@ -3252,7 +6051,7 @@ mod tests {
         test_tx_drs_common(
             &[(3, Some("genesis_hash"), None), (2, None, None)],
             &[(3, Some("genesis_hash")), (2, None)],
-            (true, "".to_string()),
+            Ok(()),
         );
     }
 
@@ -3269,7 +6068,7 @@ mod tests {
         test_tx_drs_common(
             &[(3, Some("genesis_hash"), None), (2, None, None)],
             &[(2, Some("genesis_hash")), (2, None)],
-            (false, "TxOuts spent don't match TxIns spent".to_string()),
+            Err("TxOuts spent don't match TxIns spent: item genesis_hash -1".to_string()),
         );
     }
 
@@ -3294,7 +6093,431 @@ mod tests {
                 (2, None, test_metadata),
             ],
             &[(1, Some("invalid_genesis_hash")), (1, None)],
-            (false, "TxOuts spent don't match TxIns spent".to_string()),
+            Err(
+                "Output 0 references genesis hash invalid_genesis_hash not present in inputs"
+                    .to_string(),
+            ),
+        );
+    }
+
+    #[test]
+    /// ### Test Case 8
+    ///
+    ///  - *Items only*
+    /// -  *Success*
+    ///
+    /// 1. Inputs contain two `TxIn`s for `Item`s of amounts `3` and `2` with the same `genesis_hash`
+    /// 2. Outputs contain `TxOut`s for `Item`s of amounts `4` and `1` with the same `genesis_hash`
+    /// 3. Partial amounts of a single `genesis_hash` split across outputs still balance
+    fn test_tx_drs_items_only_partial_amounts_success() {
+        test_tx_drs_common(
+            &[
+                (3, Some("genesis_hash"), None),
+                (2, Some("genesis_hash"), None),
+            ],
+            &[(4, Some("genesis_hash")), (1, Some("genesis_hash"))],
+            Ok(()),
+        );
+    }
+
+    #[test]
+    /// ### Test Case 9
+    ///
+    ///  - *Items only*
+    /// -  *Failure*
+    ///
+    /// 1. Inputs contain a `TxIn` for `Item`s of amount `2`
+    /// 2. Outputs contain a `TxOut` for `Item`s of amount `2` with a `genesis_hash` absent from inputs
+    /// 3. Failure identifies the offending output index and the unknown `genesis_hash`
+    fn test_tx_drs_items_only_failure_unknown_drs() {
+        test_tx_drs_common(
+            &[(2, Some("genesis_hash"), None)],
+            &[(2, Some("unknown_genesis_hash"))],
+            Err(
+                "Output 0 references genesis hash unknown_genesis_hash not present in inputs"
+                    .to_string(),
+            ),
+        );
+    }
+
+    #[test]
+    /// Checks every per-output rule for both output kinds
+    fn test_validate_output_rules() {
+        let addr = hex::encode([0; 32]);
+        let gh = || Some("genesis_hash".to_string());
+        let token = |locktime| TxOut::new_token_amount(addr.clone(), TokenAmount(1), locktime);
+        let item = |amount, genesis_hash, metadata| {
+            TxOut::new_item_amount(
+                addr.clone(),
+                ItemAsset::new(amount, genesis_hash, metadata),
+                None,
+            )
+        };
+        let bad_addr = TxOut::new_token_amount("addr".to_string(), TokenAmount(1), None);
+        let unknown = Some("unknown".to_string());
+        let md = Some("metadata".to_string());
+
+        use OutputError::*;
+        use OutputKind::*;
+        let cases = vec![
+            // (rule, output, kind, expected result)
+            ("valid token", token(None), Normal, Ok(())),
+            ("valid token fee", token(None), Fee, Ok(())),
+            ("valid item", item(2, gh(), None), Normal, Ok(())),
+            ("token locktime", token(Some(10)), Normal, Ok(())),
+            (
+                "address length",
+                bad_addr.clone(),
+                Normal,
+                Err(InvalidAddressLength(Normal, 0, "addr".to_string())),
+            ),
+            (
+                "address length",
+                bad_addr,
+                Fee,
+                Err(InvalidAddressLength(Fee, 0, "addr".to_string())),
+            ),
+            ("fee locktime", token(Some(10)), Fee, Err(FeeHasLocktime(0))),
+            (
+                "fee token-only",
+                item(2, gh(), None),
+                Fee,
+                Err(FeeNotToken(0)),
+            ),
+            (
+                "item genesis",
+                item(2, None, None),
+                Normal,
+                Err(ItemOnSpendInvalid(Normal, 0)),
+            ),
+            (
+                "item metadata",
+                item(2, gh(), md),
+                Normal,
+                Err(ItemOnSpendInvalid(Normal, 0)),
+            ),
+            (
+                "item zero amount",
+                item(0, gh(), None),
+                Normal,
+                Err(InvalidItem(Normal, 0, AssetError::ZeroItemAmount)),
+            ),
+            (
+                "item unknown genesis",
+                item(2, unknown.clone(), None),
+                Normal,
+                Err(UnknownGenesisHash(Normal, 0, "unknown".to_string())),
+            ),
+            (
+                "item insufficient",
+                item(3, gh(), None),
+                Normal,
+                Err(InsufficientItems(Normal, 0, "genesis_hash".to_string())),
+            ),
+        ];
+
+        for (rule, tx_out, kind, expected) in cases {
+            let mut items_remaining = BTreeMap::from([("genesis_hash".to_string(), 2)]);
+            let context = ValidationContext::default();
+            let actual = validate_output(0, &tx_out, kind, &mut items_remaining, &context);
+            assert_eq!(actual, expected, "rule: {rule}, kind: {kind}");
+        }
+    }
+
+    #[test]
+    /// Checks that output addresses must be canonical lowercase hex, unless in the hash
+    /// namespace grace period
+    fn test_validate_output_canonical_address() {
+        let (pk, _) = sign::gen_keypair();
+        let script = Script::from(vec![StackEntry::Op(OpCodes::OP_1)]);
+        let p2pkh = construct_address(&pk);
+        let p2sh = construct_p2sh_address(&script);
+        let accepted = [
+            p2pkh.clone(),
+            construct_address_for(&pk, Some(NETWORK_VERSION_V0)),
+            p2sh.clone(),
+        ];
+        let rejected = [
+            p2pkh.to_uppercase(),
+            format!("{}A", &p2pkh[1..]),
+            format!("{}{}", &p2sh[..1], p2sh[1..].to_uppercase()),
+            "z".repeat(64),
+            "0x".repeat(16),
+        ];
+
+        let validate = |address: &String, hash_namespace_grace: bool| {
+            let tx_out = TxOut::new_token_amount(address.clone(), TokenAmount(1), None);
+            let context = ValidationContext {
+                hash_namespace_grace,
+                ..Default::default()
+            };
+            validate_output(
+                0,
+                &tx_out,
+                OutputKind::Normal,
+                &mut BTreeMap::new(),
+                &context,
+            )
+        };
+        for address in &accepted {
+            assert_eq!(validate(address, false), Ok(()), "{}", address);
+            assert_eq!(validate(address, true), Ok(()), "{}", address);
+        }
+        for address in &rejected {
+            let expected = OutputError::MalformedAddress(OutputKind::Normal, 0, address.clone());
+            assert_eq!(validate(address, false), Err(expected), "{}", address);
+            assert_eq!(validate(address, true), Ok(()), "{}", address);
+        }
+    }
+
+    #[test]
+    /// Checks that fees are validated through the same rules as regular outputs
+    fn test_tx_outs_fee_rules() {
+        let address = hex::encode([0; 32]);
+        let tx_outs = vec![TxOut::new_token_amount(
+            address.clone(),
+            TokenAmount(2),
+            None,
+        )];
+        let fees = vec![TxOut::new_token_amount(address, TokenAmount(1), Some(5))];
+
+        let result = tx_outs_are_valid(&tx_outs, &fees, AssetValues::token_u64(3));
+
+        assert_eq!(
+            result,
+            Err(TxValidationError::Output(OutputError::FeeHasLocktime(0)))
+        );
+    }
+
+    #[test]
+    /// Checks that fees are accepted when paid in tokens and rejected when paid in items,
+    /// even if the item fee would balance against the inputs
+    fn test_tx_outs_fees_token_only() {
+        let address = hex::encode([0; 32]);
+        let tx_outs = vec![TxOut::new_token_amount(
+            address.clone(),
+            TokenAmount(2),
+            None,
+        )];
+        let token_fees = vec![TxOut::new_token_amount(
+            address.clone(),
+            TokenAmount(1),
+            None,
+        )];
+        let item_fees = vec![TxOut::new_item_amount(
+            address,
+            ItemAsset::new(1, Some("genesis_hash".to_string()), None),
+            None,
+        )];
+        let item_inputs = AssetValues::new(
+            TokenAmount(2),
+            BTreeMap::from([("genesis_hash".to_string(), 1)]),
+        );
+
+        let token_result = tx_outs_are_valid(&tx_outs, &token_fees, AssetValues::token_u64(3));
+        let item_result = tx_outs_are_valid(&tx_outs, &item_fees, item_inputs);
+
+        assert_eq!(token_result, Ok(()));
+        assert_eq!(
+            item_result,
+            Err(TxValidationError::Output(OutputError::FeeNotToken(0)))
+        );
+    }
+
+    #[test]
+    /// Checks that V0 length addresses are only accepted while legacy addresses are allowed
+    fn test_tx_outs_legacy_addresses() {
+        let (pk, _) = sign::gen_keypair();
+        let v0_address = construct_address_for(&pk, Some(NETWORK_VERSION_V0));
+        let tx_outs = vec![TxOut::new_token_amount(
+            v0_address.clone(),
+            TokenAmount(1),
+            None,
+        )];
+        let validate = |allow_legacy_addresses| {
+            let context = ValidationContext {
+                allow_legacy_addresses,
+                ..Default::default()
+            };
+            tx_outs_are_valid_in_context(&tx_outs, &[], AssetValues::token_u64(1), &context)
+        };
+
+        assert_eq!(
+            tx_outs_are_valid(&tx_outs, &[], AssetValues::token_u64(1)),
+            Ok(())
+        );
+        assert_eq!(validate(true), Ok(()));
+        assert_eq!(
+            validate(false),
+            Err(TxValidationError::Output(OutputError::LegacyAddress(
+                OutputKind::Normal,
+                0,
+                v0_address
+            )))
+        );
+    }
+
+    #[test]
+    /// Checks that a zero-amount item output is rejected even when the transaction balances
+    fn test_tx_outs_zero_item_amount() {
+        let address = hex::encode([0; 32]);
+        let item = |amount| {
+            TxOut::new_item_amount(
+                address.clone(),
+                ItemAsset::new(amount, Some("genesis_hash".to_string()), None),
+                None,
+            )
+        };
+        let item_inputs = || {
+            AssetValues::new(
+                TokenAmount(0),
+                BTreeMap::from([("genesis_hash".to_string(), 1)]),
+            )
+        };
+
+        let valid_result = tx_outs_are_valid(&[item(1)], &[], item_inputs());
+        let zero_result = tx_outs_are_valid(&[item(1), item(0)], &[], item_inputs());
+
+        assert_eq!(valid_result, Ok(()));
+        assert_eq!(
+            zero_result,
+            Err(TxValidationError::Output(OutputError::InvalidItem(
+                OutputKind::Normal,
+                1,
+                AssetError::ZeroItemAmount
+            )))
+        );
+    }
+
+    #[test]
+    /// Checks that create transactions, which have no item inputs, remain exempt
+    /// from the per-output `genesis_hash` check
+    fn test_create_tx_exempt_from_item_input_check() {
+        let (pk, sk) = sign::gen_keypair();
+        let tx = construct_item_create_tx(0, 0, pk, &sk, 1, GenesisTxHashSpec::Create, None, None)
+            .unwrap();
+
+        assert!(tx.is_create_tx());
+        assert!(tx_has_valid_create_script(
+            &tx.inputs[0].script_signature,
+            &tx.outputs[0].value
+        ));
+    }
+
+    #[test]
+    /// Checks that a token class create transaction is validated as such
+    fn test_pass_create_token_with_metadata() {
+        let (pk, sk) = sign::gen_keypair();
+        let metadata = TokenMetadata::new("Test Token".to_string(), "TST".to_string(), 2, 1000);
+        let tx = construct_token_create_tx(0, 0, pk, &sk, metadata.clone()).unwrap();
+
+        assert!(tx.is_create_tx());
+        assert!(!tx.is_coinbase());
+        assert_eq!(
+            tx.outputs[0].value,
+            Asset::token_with_metadata(1000, metadata.clone(), None)
+        );
+        assert!(tx_has_valid_create_script(
+            &tx.inputs[0].script_signature,
+            &tx.outputs[0].value
+        ));
+
+        // The whole metadata is signed, and a created class can't claim an existing one
+        let tampered = [
+            TokenMetadata::new("Renamed".to_string(), "TST".to_string(), 2, 1000),
+            TokenMetadata::new("Test Token".to_string(), "TST".to_string(), 8, 1000),
+            TokenMetadata::new("Test Token".to_string(), "TST".to_string(), 2, 10),
+        ];
+        for metadata in tampered {
+            let asset = Asset::token_with_metadata(1000, metadata, None);
+            assert!(!tx_has_valid_create_script(
+                &tx.inputs[0].script_signature,
+                &asset
+            ));
+        }
+        let existing = Asset::token_with_metadata(1000, metadata, Some("g_class".to_owned()));
+        assert!(!tx_has_valid_create_script(
+            &tx.inputs[0].script_signature,
+            &existing
+        ));
+    }
+
+    #[test]
+    /// Checks that a token class cannot be created with a total supply above `TOTAL_TOKENS`
+    fn test_fail_create_token_supply_exceeds_total_tokens() {
+        let (pk, sk) = sign::gen_keypair();
+        let metadata = TokenMetadata::new(
+            "Test Token".to_string(),
+            "TST".to_string(),
+            2,
+            TOTAL_TOKENS + 1,
+        );
+        let tx = construct_token_create_tx(0, 0, pk, &sk, metadata).unwrap();
+
+        assert!(!tx_has_valid_create_script(
+            &tx.inputs[0].script_signature,
+            &tx.outputs[0].value
+        ));
+    }
+
+    #[test]
+    /// Checks that tokens of a created token class can be transferred
+    fn test_tx_is_valid_token_with_metadata_transfer() {
+        //
+        // Arrange
+        //
+        let (pk, sk) = sign::gen_keypair();
+        let metadata = TokenMetadata::new("Test Token".to_string(), "TST".to_string(), 2, 1000);
+        let create_tx = construct_token_create_tx(0, 0, pk, &sk, metadata.clone()).unwrap();
+        let out_point = OutPoint::new(construct_tx_hash(&create_tx), 0);
+        let utxo_set = BTreeMap::from([(out_point.clone(), create_tx.outputs[0].clone())]);
+        let key_material = BTreeMap::from([(out_point.clone(), (pk, sk))]);
+        let genesis_hash = Some(out_point.t_hash.clone());
+
+        let tx_ins = vec![TxIn {
+            previous_out: Some(out_point),
+            script_signature: Script::new(),
+        }];
+        let (receiver_pk, _) = sign::gen_keypair();
+        let receiver = |asset| ReceiverInfo {
+            address: construct_address(&receiver_pk),
+            asset,
+        };
+        let class = |amount, genesis_hash: &Option<String>| {
+            receiver(Asset::token_with_metadata(
+                amount,
+                metadata.clone(),
+                genesis_hash.clone(),
+            ))
+        };
+        let pay = |receiver| {
+            construct_payment_tx(tx_ins.clone(), receiver, None, 0, &key_material).unwrap()
+        };
+
+        //
+        // Act
+        //
+        let valid_tx = pay(class(1000, &genesis_hash));
+        let overspend_tx = pay(class(1001, &genesis_hash));
+        let other_class_tx = pay(class(1000, &Some("g_other".to_owned())));
+        let no_genesis_hash_tx = pay(class(1000, &None));
+        let as_tokens_tx = pay(receiver(Asset::token_u64(1000)));
+
+        //
+        // Assert
+        //
+        assert_eq!(tx_is_valid(&valid_tx, 0, |v| utxo_set.get(v)), Ok(()));
+        for invalid_tx in [overspend_tx, other_class_tx, as_tokens_tx] {
+            assert!(matches!(
+                tx_is_valid(&invalid_tx, 0, |v| utxo_set.get(v)),
+                Err(TxValidationError::Unbalanced { .. })
+            ));
+        }
+        assert_eq!(
+            tx_is_valid(&no_genesis_hash_tx, 0, |v| utxo_set.get(v)),
+            Err(TxValidationError::Output(
+                OutputError::TokenClassOnSpendInvalid(OutputKind::Normal, 0)
+            ))
         );
     }
 
@@ -3303,7 +6526,7 @@ mod tests {
     fn test_tx_drs_common(
         inputs: &[(u64, Option<&str>, Option<String>)],
         outputs: &[(u64, Option<&str>)],
-        expected_result: (bool, String),
+        expected_result: Result<(), String>,
     ) {
         ///
         /// Arrange
@@ -3314,11 +6537,20 @@ mod tests {
         /// Act
         ///
         let actual_result = tx_is_valid(&tx, 100, |v| utxo.get(v));
+        let cache = ValidationCache::new(1);
+        let cached_results = [
+            cache.check(&tx, 100, |v| utxo.get(v)),
+            cache.check(&tx, 100, |v| utxo.get(v)),
+        ];
 
         ///
         /// Assert
         ///
-        assert_eq!(actual_result, expected_result);
+        assert_eq!(
+            actual_result.clone().map_err(|e| e.to_string()),
+            expected_result
+        );
+        assert_eq!(cached_results, [actual_result.clone(), actual_result]);
     }
 
     #[test]
@@ -1,12 +1,32 @@
+use crate::crypto::sha3_256;
 use crate::crypto::sign_ed25519::{self as sign};
 use crate::primitives::asset::Asset;
 use crate::primitives::{
     asset::TokenAmount,
     transaction::{OutPoint, Transaction, TxIn, TxOut},
 };
-use crate::script::lang::Script;
-use crate::utils::transaction_utils::{construct_address, construct_tx_in_out_signable_hash};
+use crate::script::lang::{Script, ScriptError};
+use crate::utils::druid_utils::generate_druid;
+use crate::utils::script_utils::{
+    tx_has_valid_create_script_at_version, tx_is_valid, TxValidationError,
+};
+use crate::utils::transaction_utils::{
+    construct_address, construct_tx_hash, SignableMessage, UtxoMap,
+};
+use bincode::{deserialize, serialize};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+/// Seed of the DRUID used by test fixtures
+pub const TEST_DRUID_SEED: u64 = 0;
+
+/// DRUID used by test fixtures, generated from `TEST_DRUID_SEED`
+pub fn test_druid() -> String {
+    generate_druid(&mut StdRng::seed_from_u64(TEST_DRUID_SEED))
+}
 
 /// Generate a transaction with valid Script values
 /// and accompanying UTXO set for testing a set of
@@ -56,14 +76,12 @@ pub fn generate_tx_with_ins_and_outs_assets(
             }
             None => TxOut::new_token_amount(spk.clone(), TokenAmount(*input_amount), None),
         };
-        let signable_hash = construct_tx_in_out_signable_hash(
-            &TxIn {
-                previous_out: Some(tx_previous_out.clone()),
-                script_signature: Script::new(),
-            },
-            &tx.outputs,
-        );
-        let signature = sign::sign_detached(signable_hash.as_bytes(), &sk);
+        let message = SignableMessage::TxIn {
+            previous_out: Some(&tx_previous_out),
+            tx_outs: &tx.outputs,
+        };
+        let signable_hash = message.hash();
+        let signature = message.sign(&sk);
         let tx_in = TxIn::new_from_input(
             tx_previous_out.clone(),
             Script::pay2pkh(signable_hash, signature, pk, None),
@@ -74,3 +92,286 @@ pub fn generate_tx_with_ins_and_outs_assets(
 
     (utxo_set, tx)
 }
+
+/// Generate a single unsigned input spending a placeholder outpoint, as a minimal
+/// fixture for transactions whose inputs aren't under test
+pub fn generate_unsigned_tx_ins() -> Vec<TxIn> {
+    vec![TxIn {
+        previous_out: Some(OutPoint::new("tx_hash".to_owned(), 0)),
+        script_signature: Script::new(),
+    }]
+}
+
+/// Error recorded by a replay fixture for an invalid create transaction, which has
+/// no error type of its own
+pub const INVALID_CREATE_VERDICT: &str = "InvalidCreateScript";
+
+/// Verdict of a replayed validation. Errors are recorded by variant name only, as
+/// `Input::InvalidScript` for errors wrapping another, so that changes to an error's
+/// payload or `Debug` form don't change the verdict
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayVerdict {
+    Valid,
+    Invalid(String),
+}
+
+impl ReplayVerdict {
+    /// Verdict of `tx_is_valid`
+    ///
+    /// ### Arguments
+    ///
+    /// * `verdict` - Result of `tx_is_valid`
+    pub fn of_tx(verdict: &Result<(), TxValidationError>) -> Self {
+        Self::of(verdict, |e| match e {
+            TxValidationError::Structure(e) => format!("Structure::{}", variant_name(e)),
+            TxValidationError::Input(_, e) => format!("Input::{}", variant_name(e)),
+            TxValidationError::Output(e) => format!("Output::{}", variant_name(e)),
+            TxValidationError::Druid(e) => format!("Druid::{}", variant_name(e)),
+            TxValidationError::Unbalanced { .. } => variant_name(e),
+        })
+    }
+
+    /// Verdict of `Script::interpret_full`
+    ///
+    /// ### Arguments
+    ///
+    /// * `verdict` - Result of `Script::interpret_full`
+    pub fn of_script(verdict: &Result<(), ScriptError>) -> Self {
+        Self::of(verdict, variant_name)
+    }
+
+    /// Verdict of validating a create transaction
+    ///
+    /// ### Arguments
+    ///
+    /// * `valid`   - Whether its create script was valid
+    pub fn of_create(valid: bool) -> Self {
+        match valid {
+            true => Self::Valid,
+            false => Self::Invalid(INVALID_CREATE_VERDICT.to_owned()),
+        }
+    }
+
+    fn of<E>(verdict: &Result<(), E>, name: impl Fn(&E) -> String) -> Self {
+        match verdict {
+            Ok(()) => Self::Valid,
+            Err(e) => Self::Invalid(name(e)),
+        }
+    }
+}
+
+/// A recorded validation verdict, replayed to catch changes in validation semantics.
+/// Fixtures are stored one per line as JSON, such as:
+///
+/// `{"kind":"tx","id":"g…","tx":"…","spent":"…","height":0,"expected":"valid"}`
+///
+/// * `tx`, `spent` and `script` are the hex encoded bincode serialization of
+///   a `Transaction`, a `UtxoMap` and a `Script`
+/// * `expected` is `"valid"`, or `{"invalid":"…"}` with the variant name of the error
+/// * `note` optionally describes the case for humans
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReplayFixture {
+    #[serde(flatten)]
+    pub case: ReplayCase,
+    pub expected: ReplayVerdict,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// What a replay fixture validates
+///
+/// * `Tx`      - Spend validated by `tx_is_valid` against `spent` at `height`
+/// * `Create`  - Create transaction validated by `tx_has_valid_create_script_at_version`
+/// * `Script`  - Bare script executed by `Script::interpret_full`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReplayCase {
+    Tx {
+        id: String,
+        tx: String,
+        spent: String,
+        height: u64,
+    },
+    Create {
+        id: String,
+        tx: String,
+    },
+    Script {
+        id: String,
+        script: String,
+    },
+}
+
+impl ReplayFixture {
+    /// Records the verdict of `tx_is_valid` for a spending transaction
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx`      - Transaction validated
+    /// * `spent`   - UTXO entries available to the transaction
+    /// * `height`  - Block height validated at
+    /// * `verdict` - Verdict of `tx_is_valid`
+    pub fn from_tx(
+        tx: &Transaction,
+        spent: &UtxoMap,
+        height: u64,
+        verdict: &Result<(), TxValidationError>,
+    ) -> Self {
+        Self::new(
+            ReplayCase::Tx {
+                id: construct_tx_hash(tx),
+                tx: encode(tx),
+                spent: encode(spent),
+                height,
+            },
+            ReplayVerdict::of_tx(verdict),
+        )
+    }
+
+    /// Records the verdict of validating a create transaction
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx`      - Create transaction validated
+    /// * `verdict` - Whether its create script was valid
+    pub fn from_create_tx(tx: &Transaction, verdict: bool) -> Self {
+        Self::new(
+            ReplayCase::Create {
+                id: construct_tx_hash(tx),
+                tx: encode(tx),
+            },
+            ReplayVerdict::of_create(verdict),
+        )
+    }
+
+    /// Records the verdict of `Script::interpret_full` for a bare script
+    ///
+    /// ### Arguments
+    ///
+    /// * `script`  - Script executed
+    /// * `verdict` - Verdict of `Script::interpret_full`
+    pub fn from_script(script: &Script, verdict: &Result<(), ScriptError>) -> Self {
+        let script = encode(script);
+        Self::new(
+            ReplayCase::Script {
+                id: hex::encode(sha3_256::digest(script.as_bytes())),
+                script,
+            },
+            ReplayVerdict::of_script(verdict),
+        )
+    }
+
+    /// Describes the fixture for humans
+    ///
+    /// ### Arguments
+    ///
+    /// * `note`    - Description of the case
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.note = Some(note.to_owned());
+        self
+    }
+
+    /// Identifier of the fixture: the transaction hash, or the hash of a bare script
+    pub fn id(&self) -> &str {
+        match &self.case {
+            ReplayCase::Tx { id, .. }
+            | ReplayCase::Create { id, .. }
+            | ReplayCase::Script { id, .. } => id,
+        }
+    }
+
+    /// Validates the recorded case again, returning its verdict.
+    /// Fails if the fixture can't be decoded, or if a recorded transaction no longer
+    /// encodes to the same bytes and hash
+    pub fn replay(&self) -> Result<ReplayVerdict, String> {
+        match &self.case {
+            ReplayCase::Tx {
+                id,
+                tx,
+                spent,
+                height,
+            } => {
+                let tx = decode_tx(id, tx)?;
+                let spent: UtxoMap = decode(spent)?;
+                let verdict = tx_is_valid(&tx, *height, |v| spent.get(v));
+                Ok(ReplayVerdict::of_tx(&verdict))
+            }
+            ReplayCase::Create { id, tx } => {
+                let tx = decode_tx(id, tx)?;
+                let (tx_in, tx_out) = match (tx.inputs.first(), tx.outputs.first()) {
+                    (Some(tx_in), Some(tx_out)) => (tx_in, tx_out),
+                    _ => return Err("Create transaction has no input or output".to_owned()),
+                };
+                let valid = tx_has_valid_create_script_at_version(
+                    &tx_in.script_signature,
+                    &tx_out.value,
+                    tx.version,
+                );
+                Ok(ReplayVerdict::of_create(valid))
+            }
+            ReplayCase::Script { script, .. } => {
+                let script: Script = decode(script)?;
+                Ok(ReplayVerdict::of_script(&script.interpret_full()))
+            }
+        }
+    }
+
+    fn new(case: ReplayCase, expected: ReplayVerdict) -> Self {
+        Self {
+            case,
+            expected,
+            note: None,
+        }
+    }
+}
+
+/// Exports the verdict of `tx_is_valid` for a spending transaction as a replay fixture
+/// line, so that cases seen on a live chain can be added to the replay corpus
+///
+/// ### Arguments
+///
+/// * `tx`      - Transaction validated
+/// * `spent`   - UTXO entries available to the transaction
+/// * `height`  - Block height validated at
+/// * `verdict` - Verdict of `tx_is_valid`
+pub fn export_fixture(
+    tx: &Transaction,
+    spent: &UtxoMap,
+    height: u64,
+    verdict: &Result<(), TxValidationError>,
+) -> String {
+    let fixture = ReplayFixture::from_tx(tx, spent, height, verdict);
+    serde_json::to_string(&fixture).unwrap_or_default()
+}
+
+/// Name of the variant of `value`, which leads its `Debug` form
+fn variant_name<E: Debug>(value: &E) -> String {
+    let debug = format!("{value:?}");
+    let end = debug
+        .find(|c: char| !c.is_alphanumeric() && c != '_')
+        .unwrap_or(debug.len());
+    debug[..end].to_owned()
+}
+
+/// Decodes a recorded transaction, checking that it still encodes to the recorded bytes
+/// and hash
+fn decode_tx(id: &str, tx: &str) -> Result<Transaction, String> {
+    let decoded: Transaction = decode(tx)?;
+    if encode(&decoded) != tx {
+        return Err("Transaction no longer encodes to the recorded bytes".to_owned());
+    }
+    if construct_tx_hash(&decoded) != id {
+        return Err(format!("Transaction no longer hashes to {id}"));
+    }
+    Ok(decoded)
+}
+
+fn encode<T: Serialize>(value: &T) -> String {
+    hex::encode(serialize(value).unwrap_or_default())
+}
+
+fn decode<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    let bytes = hex::decode(value).map_err(|e| e.to_string())?;
+    deserialize(&bytes).map_err(|e| e.to_string())
+}
@@ -1,13 +1,21 @@
 use crate::constants::*;
-use crate::crypto::sha3_256;
-use crate::crypto::sign_ed25519::{self as sign, sign_detached, PublicKey, SecretKey};
-use crate::primitives::asset::Asset;
+use crate::crypto::hd_ed25519::{HdKey, HARDENED_OFFSET};
+use crate::crypto::sha3_256::{self, Digest};
+use crate::crypto::sign_ed25519::{self as sign, sign_detached, PublicKey, SecretKey, Signature};
+use crate::primitives::asset::{
+    Asset, AssetError, AssetId, AssetValues, ItemAsset, TokenAmount, TokenMetadata,
+};
 use crate::primitives::druid::{DdeValues, DruidExpectation};
 use crate::primitives::transaction::*;
-use crate::script::lang::Script;
+use crate::script::lang::{Script, ScriptError};
 use crate::script::{OpCodes, StackEntry};
-use bincode::serialize;
-use std::collections::BTreeMap;
+use crate::utils::commitment_utils::commit_metadata;
+use crate::utils::script_utils::{check_item_metadata_to_construct, MetadataError};
+use rand::RngCore;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::Write;
 use tracing::debug;
 
 pub struct ReceiverInfo {
@@ -15,22 +23,239 @@ pub struct ReceiverInfo {
     pub asset: Asset,
 }
 
+/// Kind of an address, determining how outputs paying to it are unlocked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressKind {
+    P2PKH,
+    P2SH,
+}
+
+/// Kind of script unlocking a transaction input, used to estimate transaction sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptType {
+    P2PKH,
+    P2SH,
+    MultisigValidation,
+    Create,
+}
+
+impl ScriptType {
+    /// Serialized sizes in bytes of an input unlocked by this script type and of each
+    /// output such a transaction pays to. Multisig weights, and P2SH weights whose redeem
+    /// script is usually a multisig escrow, assume a 2-of-3 multisig
+    fn size_weights(self) -> (usize, usize) {
+        match self {
+            ScriptType::P2PKH => (357, 93),
+            ScriptType::P2SH => (445, 93),
+            ScriptType::MultisigValidation => (445, 93),
+            ScriptType::Create => (241, 95),
+        }
+    }
+}
+
+/// Classifies an address by its prefix: P2SH addresses start with `P2SH_PREPEND`,
+/// which never starts a hex encoded P2PKH address
+///
+/// ### Arguments
+///
+/// * `address` - Address to classify
+pub fn address_type(address: &str) -> AddressKind {
+    match address.as_bytes().first() {
+        Some(&P2SH_PREPEND) => AddressKind::P2SH,
+        _ => AddressKind::P2PKH,
+    }
+}
+
+/// Checks whether a string has the shape of a transaction hash: `TX_PREPEND`
+/// followed by lowercase hex, `TX_HASH_LENGTH` characters in total
+///
+/// ### Arguments
+///
+/// * `s` - String to check
+pub fn is_tx_hash(s: &str) -> bool {
+    s.len() == TX_HASH_LENGTH && s.as_bytes()[0] == TX_PREPEND && is_lower_hex(&s[1..])
+}
+
+/// Checks whether a string has the shape of an item genesis hash, as given by
+/// `GenesisTxHashSpec`: the default genesis hash, a transaction hash, or the
+/// lowercase hex hash of an outpoint
+///
+/// ### Arguments
+///
+/// * `s` - String to check
+pub fn is_genesis_hash(s: &str) -> bool {
+    s == ITEM_DEFAULT_DRS_TX_HASH || is_tx_hash(s) || (s.len() == 64 && is_lower_hex(s))
+}
+
+/// Generates `prefix` followed by random lowercase hex, `len` characters in total.
+/// Each hex character carries 4 bits of entropy
+///
+/// ### Arguments
+///
+/// * `prefix`  - Character to start with
+/// * `len`     - Length of the generated string
+/// * `rng`     - Source of randomness
+pub(crate) fn generate_prefixed_hex(prefix: u8, len: usize, rng: &mut impl RngCore) -> String {
+    let mut bytes = vec![0; len / TWO];
+    rng.fill_bytes(&mut bytes);
+    let mut hex = hex::encode(bytes);
+    hex.insert(ZERO, prefix as char);
+    hex.truncate(len);
+    hex
+}
+
+/// Checks whether a string has the shape of an address: lowercase hex of
+/// `STANDARD_ADDRESS_LENGTH` or V0 length, or a P2SH address starting with `P2SH_PREPEND`.
+/// No address is also a transaction hash
+///
+/// ### Arguments
+///
+/// * `s` - String to check
+pub fn is_address(s: &str) -> bool {
+    match address_type(s) {
+        AddressKind::P2SH => s.len() == STANDARD_ADDRESS_LENGTH && is_lower_hex(&s[1..]),
+        AddressKind::P2PKH => {
+            (s.len() == STANDARD_ADDRESS_LENGTH || s.len() == V0_ADDRESS_LENGTH * 2)
+                && is_lower_hex(s)
+        }
+    }
+}
+
+/// Checks whether a string only holds lowercase hex characters
+pub(crate) fn is_lower_hex(s: &str) -> bool {
+    s.bytes()
+        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Decodes hex in its canonical form: lowercase, with an even number of characters.
+/// Uppercase hex is rejected rather than normalized, so every byte string has exactly
+/// one accepted encoding
+///
+/// ### Arguments
+///
+/// * `s` - Hex string to decode
+pub fn decode_lower_hex(s: &str) -> Result<Vec<u8>, hex::FromHexError> {
+    match s.char_indices().find(|(_, c)| c.is_ascii_uppercase()) {
+        Some((index, c)) => Err(hex::FromHexError::InvalidHexCharacter { c, index }),
+        None => hex::decode(s),
+    }
+}
+
+/// Reasons a P2SH address or transaction can't be constructed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum P2SHError {
+    ScriptTooLarge(usize),
+    InvalidStructure(TxStructureError),
+}
+
+impl fmt::Display for P2SHError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ScriptTooLarge(size) => write!(
+                f,
+                "P2SH script of {size} bytes exceeds the {MAX_P2SH_SCRIPT_SIZE}-byte limit"
+            ),
+            Self::InvalidStructure(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for P2SHError {}
+
+impl From<TxStructureError> for P2SHError {
+    fn from(e: TxStructureError) -> Self {
+        Self::InvalidStructure(e)
+    }
+}
+
 /// Builds a P2SH address
 ///
 /// ### Arguments
 ///
 /// * `script` - Script to build address for
 pub fn construct_p2sh_address(script: &Script) -> String {
-    let bytes = match serialize(script) {
-        Ok(bytes) => bytes,
-        Err(_) => vec![],
-    };
-    let mut addr = hex::encode(sha3_256::digest(&bytes));
+    construct_p2sh_address_streaming(script)
+}
+
+/// Builds a P2SH address as `construct_p2sh_address` does, streaming the script's
+/// serialization into the hash rather than holding it in memory
+///
+/// ### Arguments
+///
+/// * `script` - Script to build address for
+pub fn construct_p2sh_address_streaming(script: &Script) -> String {
+    let writer = ConsensusHashWriter::hash_or_empty(script);
+    let mut addr = hex::encode(writer.finalize());
     addr.insert(ZERO, P2SH_PREPEND as char);
     addr.truncate(STANDARD_ADDRESS_LENGTH);
     addr
 }
 
+/// Builds a P2SH address over the script wire format of `Script::to_bytes`, hashed
+/// after `P2SH_ADDRESS_DOMAIN`, so that the address only depends on the documented
+/// script encoding. Scripts whose wire encoding exceeds `MAX_P2SH_SCRIPT_SIZE` are rejected
+///
+/// ### Arguments
+///
+/// * `script` - Script to build address for
+pub fn construct_p2sh_address_v2(script: &Script) -> Result<String, P2SHError> {
+    let bytes = script.to_bytes();
+    if bytes.len() > MAX_P2SH_SCRIPT_SIZE {
+        return Err(P2SHError::ScriptTooLarge(bytes.len()));
+    }
+
+    let mut addr = hex::encode(sha3_256::digest_all(
+        vec![P2SH_ADDRESS_DOMAIN, bytes.as_slice()].into_iter(),
+    ));
+    addr.insert(ZERO, P2SH_PREPEND as char);
+    addr.truncate(STANDARD_ADDRESS_LENGTH);
+    Ok(addr)
+}
+
+/// Builds the P2SH address a transaction of the given version pays a script to:
+/// `construct_p2sh_address_v2` from `P2SH_WIRE_ADDRESS_VERSION` onwards,
+/// `construct_p2sh_address` before
+///
+/// ### Arguments
+///
+/// * `script`      - Script to build address for
+/// * `tx_version`  - Version of the transaction paying to the address
+pub fn construct_p2sh_address_at_version(
+    script: &Script,
+    tx_version: usize,
+) -> Result<String, P2SHError> {
+    if tx_version < P2SH_WIRE_ADDRESS_VERSION {
+        return Ok(construct_p2sh_address(script));
+    }
+    construct_p2sh_address_v2(script)
+}
+
+/// Checks whether `address` is the P2SH address of a script in a format a transaction
+/// of the given version can spend. The address decides the format: v1 addresses stay
+/// spendable at every version, and v2 addresses, which only outputs created from
+/// `P2SH_WIRE_ADDRESS_VERSION` pay to, are spendable from that version
+///
+/// ### Arguments
+///
+/// * `script`      - Script spending the address
+/// * `address`     - P2SH address of the output spent
+/// * `tx_version`  - Version of the spending transaction
+pub fn is_p2sh_address_of(script: &Script, address: &str, tx_version: usize) -> bool {
+    if construct_p2sh_address(script) == address {
+        return true;
+    }
+    if tx_version < P2SH_WIRE_ADDRESS_VERSION {
+        return false;
+    }
+    match construct_p2sh_address_v2(script) {
+        Ok(p2sh_address) => p2sh_address == address,
+        Err(e) => {
+            debug!("Invalid P2SH script: {}", e);
+            false
+        }
+    }
+}
+
 /// Builds an address from a public key and a specified network version
 ///
 /// ### Arguments
@@ -51,7 +276,81 @@ pub fn construct_address_for(pub_key: &PublicKey, address_version: Option<u64>)
 ///
 /// * `pub_key` - A public key to build an address from
 pub fn construct_address(pub_key: &PublicKey) -> String {
-    hex::encode(sha3_256::digest(pub_key.as_ref()))
+    hex::encode(construct_address_raw(pub_key))
+}
+
+/// Derives the address of the hardened child of `master` at `index`, or `None` if `index`
+/// is not below `HARDENED_OFFSET`
+///
+/// ### Arguments
+///
+/// * `master`  - Key to derive from
+/// * `index`   - Index of the child key
+pub fn derive_address(master: &HdKey, index: u32) -> Option<String> {
+    Some(construct_address(&master.derive_child(index)?.public_key()))
+}
+
+/// Derives the addresses of `count` consecutive children of `master` starting at `start`,
+/// as `derive_address` would one by one. Stops early at `HARDENED_OFFSET`, the end of the
+/// index range
+///
+/// ### Arguments
+///
+/// * `master`  - Key to derive from
+/// * `start`   - Index of the first child key
+/// * `count`   - Number of addresses to derive
+pub fn derive_addresses(master: &HdKey, start: u32, count: u32) -> Vec<String> {
+    let end = start.saturating_add(count).min(HARDENED_OFFSET);
+    (start..end)
+        .filter_map(|index| derive_address(master, index))
+        .collect()
+}
+
+/// Builds the address of a dual signature (2-of-2) output, spendable by a
+/// `Script::pay2pkh_dual` signed by both keys. The keys are hashed in sorted order,
+/// after `DUAL_ADDRESS_DOMAIN`, so either party derives the same address
+///
+/// ### Arguments
+///
+/// * `pk_a` - Public key of one party
+/// * `pk_b` - Public key of the other party
+pub fn construct_dual_address(pk_a: &PublicKey, pk_b: &PublicKey) -> String {
+    let (first, second) = if pk_a.as_ref() <= pk_b.as_ref() {
+        (pk_a, pk_b)
+    } else {
+        (pk_b, pk_a)
+    };
+    hex::encode(sha3_256::digest_all(
+        vec![DUAL_ADDRESS_DOMAIN, first.as_ref(), second.as_ref()].into_iter(),
+    ))
+}
+
+/// Builds the P2SH address of an escrow between three parties. It only depends on the
+/// keys and timeout, so the buyer, seller and arbiter all derive the same address
+///
+/// ### Arguments
+///
+/// * `buyer_pk`        - Public key of the buyer
+/// * `seller_pk`       - Public key of the seller
+/// * `arbiter_pk`      - Public key of the arbiter
+/// * `timeout_block`   - Block height from which the arbiter can settle the escrow
+pub fn construct_escrow_address(
+    buyer_pk: PublicKey,
+    seller_pk: PublicKey,
+    arbiter_pk: PublicKey,
+    timeout_block: u64,
+) -> Result<String, P2SHError> {
+    let escrow = Script::new_escrow(buyer_pk, seller_pk, arbiter_pk, timeout_block);
+    construct_p2sh_address_at_version(&escrow, NETWORK_VERSION as usize)
+}
+
+/// Builds the raw address bytes from a public key, without allocating
+///
+/// ### Arguments
+///
+/// * `pub_key` - A public key to build an address from
+pub fn construct_address_raw(pub_key: &PublicKey) -> [u8; 32] {
+    sha3_256::digest(pub_key.as_ref()).into()
 }
 
 /// Builds an old (network version 0) address from a public key
@@ -59,6 +358,7 @@ pub fn construct_address(pub_key: &PublicKey) -> String {
 /// ### Arguments
 ///
 /// * `pub_key` - A public key to build an address from
+#[doc(hidden)]
 pub fn construct_address_v0(pub_key: &PublicKey) -> String {
     let first_pubkey_bytes = {
         // We used sodiumoxide serialization before with a 64 bit length prefix.
@@ -80,6 +380,7 @@ pub fn construct_address_v0(pub_key: &PublicKey) -> String {
 /// ### Arguments
 ///
 /// * `pub_key` - A public key to build an address from
+#[doc(hidden)]
 pub fn construct_address_temp(pub_key: &PublicKey) -> String {
     let base64_encoding = base64::encode(pub_key.as_ref());
     let hex_decoded = decode_base64_as_hex(&base64_encoding);
@@ -90,12 +391,16 @@ pub fn construct_address_temp(pub_key: &PublicKey) -> String {
 /// first character. If the decoding up to the first character fails, a default value of 0
 /// is used.
 ///
-/// TODO: Deprecate after addresses retire
+/// This lossy decoding reproduces the wallet's temporary address scheme exactly, and must
+/// not be used to decode anything else. It is only kept so that outputs locked to
+/// temporary addresses remain spendable
+///
+/// TODO: Remove after addresses retire
 ///
 /// ### Arguments
 ///
 /// * `s`   - Base64 encoded string
-pub fn decode_base64_as_hex(s: &str) -> Vec<u8> {
+fn decode_base64_as_hex(s: &str) -> Vec<u8> {
     (ZERO..s.len())
         .step_by(TWO)
         .map(|i| {
@@ -106,6 +411,176 @@ pub fn decode_base64_as_hex(s: &str) -> Vec<u8> {
         .collect()
 }
 
+/// A message signed by a transaction input or create script. Signer and verifier both
+/// build the bytes to sign from here, so they can't disagree on the encoding.
+///
+/// Every message is serialized to a preimage, hashed with SHA3-256 and hex encoded;
+/// the bytes of that hex string are what gets signed.
+///
+/// From `SIGNABLE_VERSION_COMMITMENT_VERSION`, the preimage commits to the version of the
+/// transaction signed, so a signature can't be replayed under another version's rules.
+/// It is then laid out as:
+/// * `SIGNABLE_HASH_DOMAIN`
+/// * the transaction version, as 8 big-endian bytes
+/// * one byte naming the message: `1` for `TxIn`, `2` for `Asset`, `3` for `OutPoint`
+/// * the preimage of earlier versions, as described for each message
+#[derive(Debug, Clone, Copy)]
+pub enum SignableMessage<'a> {
+    /// Spend of `previous_out` into `tx_outs`. The preimage is the JSON encoding of each
+    /// output, followed by the JSON encoding of the previous outpoint, without separators
+    TxIn {
+        previous_out: Option<&'a OutPoint>,
+        tx_outs: &'a [TxOut],
+    },
+    /// Creation of an asset. The preimage is `get_asset_signable_string`
+    Asset(&'a Asset),
+    /// Spend of an outpoint on its own. The preimage is `get_out_point_signable_string`
+    OutPoint(&'a OutPoint),
+}
+
+impl<'a> SignableMessage<'a> {
+    /// Message for spending `tx_in` into `tx_outs`. Only the previous outpoint of
+    /// `tx_in` is signed, so its script may be filled in afterwards
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx_in`   - Input being signed
+    /// * `tx_outs` - Outputs of the transaction
+    pub fn tx_in(tx_in: &'a TxIn, tx_outs: &'a [TxOut]) -> Self {
+        Self::TxIn {
+            previous_out: tx_in.previous_out.as_ref(),
+            tx_outs,
+        }
+    }
+
+    /// Message for creating `asset`
+    ///
+    /// ### Arguments
+    ///
+    /// * `asset`   - Asset being created
+    pub fn asset(asset: &'a Asset) -> Self {
+        Self::Asset(asset)
+    }
+
+    /// Message for spending `out_point`
+    ///
+    /// ### Arguments
+    ///
+    /// * `out_point`   - OutPoint being spent
+    pub fn out_point(out_point: &'a OutPoint) -> Self {
+        Self::OutPoint(out_point)
+    }
+
+    /// Bytes hashed to produce the message
+    pub fn preimage(&self) -> Vec<u8> {
+        match self {
+            Self::TxIn {
+                previous_out,
+                tx_outs,
+            } => {
+                let mut preimage = String::new();
+                for tx_out in tx_outs.iter() {
+                    debug!("txout: {:?}", tx_out);
+                    preimage.push_str(&serde_json::to_string(tx_out).unwrap_or_default());
+                }
+                preimage.push_str(&serde_json::to_string(previous_out).unwrap_or_default());
+                debug!("Formatted string for signing: {preimage}");
+                preimage.into_bytes()
+            }
+            Self::Asset(asset) => get_asset_signable_string(asset).into_bytes(),
+            Self::OutPoint(out_point) => get_out_point_signable_string(out_point).into_bytes(),
+        }
+    }
+
+    /// Bytes hashed to produce the message signed in a transaction of version `tx_version`
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx_version`  - Version of the transaction the message is signed in
+    pub fn preimage_at_version(&self, tx_version: usize) -> Vec<u8> {
+        if tx_version < SIGNABLE_VERSION_COMMITMENT_VERSION {
+            return self.preimage();
+        }
+        let kind: u8 = match self {
+            Self::TxIn { .. } => 1,
+            Self::Asset(_) => 2,
+            Self::OutPoint(_) => 3,
+        };
+        let mut preimage = SIGNABLE_HASH_DOMAIN.to_vec();
+        preimage.extend((tx_version as u64).to_be_bytes());
+        preimage.push(kind);
+        preimage.extend(self.preimage());
+        preimage
+    }
+
+    /// Hex encoded hash of the message, as pushed onto scripts.
+    /// This is the hash signed in transactions before `SIGNABLE_VERSION_COMMITMENT_VERSION`
+    pub fn hash(&self) -> String {
+        match self {
+            Self::OutPoint(out_point) => hex::encode(construct_tx_in_signable_hash_raw(out_point)),
+            _ => hex::encode(sha3_256::digest(&self.preimage())),
+        }
+    }
+
+    /// Hex encoded hash of the message signed in a transaction of version `tx_version`
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx_version`  - Version of the transaction the message is signed in
+    pub fn hash_at_version(&self, tx_version: usize) -> String {
+        if tx_version < SIGNABLE_VERSION_COMMITMENT_VERSION {
+            return self.hash();
+        }
+        hex::encode(sha3_256::digest(&self.preimage_at_version(tx_version)))
+    }
+
+    /// Signs the message
+    ///
+    /// ### Arguments
+    ///
+    /// * `secret_key`  - Key to sign with
+    pub fn sign(&self, secret_key: &SecretKey) -> Signature {
+        sign_detached(self.hash().as_bytes(), secret_key)
+    }
+
+    /// Verifies a signature of the message
+    ///
+    /// ### Arguments
+    ///
+    /// * `signature`   - Signature to verify
+    /// * `public_key`  - Key the signature was made with
+    pub fn verify(&self, signature: &Signature, public_key: &PublicKey) -> bool {
+        sign::verify_detached(signature, self.hash().as_bytes(), public_key)
+    }
+
+    /// Signs the message for a transaction of version `tx_version`
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx_version`  - Version of the transaction the message is signed in
+    /// * `secret_key`  - Key to sign with
+    pub fn sign_at_version(&self, tx_version: usize, secret_key: &SecretKey) -> Signature {
+        sign_detached(self.hash_at_version(tx_version).as_bytes(), secret_key)
+    }
+
+    /// Verifies a signature of the message for a transaction of version `tx_version`
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx_version`  - Version of the transaction the message is signed in
+    /// * `signature`   - Signature to verify
+    /// * `public_key`  - Key the signature was made with
+    pub fn verify_at_version(
+        &self,
+        tx_version: usize,
+        signature: &Signature,
+        public_key: &PublicKey,
+    ) -> bool {
+        let hash = self.hash_at_version(tx_version);
+        sign::verify_detached(signature, hash.as_bytes(), public_key)
+    }
+}
+
 /// Constructs signable string for OutPoint
 ///
 /// ### Arguments
@@ -121,9 +596,32 @@ pub fn get_out_point_signable_string(out_point: &OutPoint) -> String {
 ///
 /// * `previous_out`   - Previous transaction used as input
 pub fn construct_tx_in_signable_hash(previous_out: &OutPoint) -> String {
-    hex::encode(sha3_256::digest(
-        get_out_point_signable_string(previous_out).as_bytes(),
-    ))
+    SignableMessage::out_point(previous_out).hash()
+}
+
+/// Constructs the raw signable hash bytes for a TxIn, without allocating.
+///
+/// Streams the same content as `get_out_point_signable_string` into the hasher,
+/// so the result is identical to hashing the signable string.
+///
+/// ### Arguments
+///
+/// * `previous_out`   - Previous transaction used as input
+pub fn construct_tx_in_signable_hash_raw(previous_out: &OutPoint) -> [u8; 32] {
+    // Large enough for the decimal form of any i32, sign included
+    let mut n_buf = [0u8; 11];
+    let unused_len = {
+        let mut cursor = &mut n_buf[..];
+        let _ = write!(cursor, "{}", previous_out.n);
+        cursor.len()
+    };
+    let n_len = n_buf.len() - unused_len;
+
+    let mut hasher = sha3_256::Sha3_256::new();
+    hasher.update(&n_buf[..n_len]);
+    hasher.update(b"-");
+    hasher.update(previous_out.t_hash.as_bytes());
+    hasher.finalize().into()
 }
 
 /// Constructs signable string for an Asset
@@ -135,6 +633,13 @@ pub fn get_asset_signable_string(asset: &Asset) -> String {
     match asset {
         Asset::Token(token_amount) => format!("Token:{}", token_amount.0),
         Asset::Item(item) => format!("Item:{}", item.amount),
+        Asset::TokenWithMetadata {
+            amount, metadata, ..
+        } => format!(
+            "TokenWithMetadata:{}:{}",
+            amount.0,
+            serde_json::to_string(metadata).unwrap_or_default()
+        ),
     }
 }
 
@@ -144,9 +649,7 @@ pub fn get_asset_signable_string(asset: &Asset) -> String {
 ///
 /// * `asset`   - Asset to sign
 pub fn construct_tx_in_signable_asset_hash(asset: &Asset) -> String {
-    hex::encode(sha3_256::digest(
-        get_asset_signable_string(asset).as_bytes(),
-    ))
+    SignableMessage::asset(asset).hash()
 }
 
 /// Constructs signable string for a StackEntry
@@ -173,25 +676,9 @@ pub fn get_stack_entry_signable_string(entry: &StackEntry) -> String {
 /// * `tx_in`   - TxIn values
 /// * `tx_out`  - TxOut values
 pub fn construct_tx_in_out_signable_hash(tx_in: &TxIn, tx_out: &[TxOut]) -> String {
-    let mut signable_list = tx_out
-        .iter()
-        .map(|tx| {
-            debug!("txout: {:?}", tx);
-            serde_json::to_string(tx).unwrap_or("".to_string())
-        })
-        .collect::<Vec<String>>();
-
-    let tx_in_value = serde_json::to_string(&tx_in.previous_out).unwrap_or("".to_string());
-
-    signable_list.push(tx_in_value);
-    let signable = signable_list.join("");
-    debug!("Formatted string for signing: {signable}");
-    debug!(
-        "Hash: {:?}",
-        hex::encode(sha3_256::digest(signable.as_bytes()))
-    );
-
-    hex::encode(sha3_256::digest(signable.as_bytes()))
+    let hash = SignableMessage::tx_in(tx_in, tx_out).hash();
+    debug!("Hash: {:?}", hash);
+    hash
 }
 
 /// Constructs signable string for Script stack
@@ -222,18 +709,90 @@ pub fn get_tx_in_address_signable_string(tx_in: &TxIn) -> String {
     format!("{out_point_signable_string}-{script_signable_string}")
 }
 
-/// Constructs address for a TxIn collection
+/// Constructs the legacy address for a TxIn collection, used as the DRUID `from` address
+/// of transactions before `STABLE_TX_INS_ADDRESS_VERSION`.
+///
+/// The address commits to the input scripts, so it changes whenever the inputs are
+/// re-signed, and its `-` separators can be ambiguous with the rendered entries.
+/// Prefer `construct_tx_ins_address_stable`
 ///
 /// ### Arguments
 ///
 /// * `tx_ins`   - TxIn collection
 pub fn construct_tx_ins_address(tx_ins: &[TxIn]) -> String {
-    let signable_tx_ins = tx_ins
+    let mut hasher = sha3_256::Sha3_256::new();
+    for (idx, tx_in) in tx_ins.iter().enumerate() {
+        if idx > ZERO {
+            hasher.update(b"-");
+        }
+        hasher.update(get_tx_in_address_signable_string(tx_in).as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Constructs an address for a TxIn collection committing only to its previous outpoints,
+/// in sorted order, so that it is unaffected by input scripts and input order.
+///
+/// The outpoints are bincode-encoded, which length-prefixes every component,
+/// and hashed after `TX_INS_ADDRESS_DOMAIN`
+///
+/// ### Arguments
+///
+/// * `tx_ins`   - TxIn collection
+pub fn construct_tx_ins_address_stable(tx_ins: &[TxIn]) -> String {
+    let mut previous_outs: Vec<Option<&OutPoint>> =
+        tx_ins.iter().map(|i| i.previous_out.as_ref()).collect();
+    previous_outs.sort_unstable();
+    let mut writer = ConsensusHashWriter::new();
+    writer.update(TX_INS_ADDRESS_DOMAIN);
+    if writer.write_value(&previous_outs).is_err() {
+        writer = ConsensusHashWriter::new();
+        writer.update(TX_INS_ADDRESS_DOMAIN);
+    }
+    hex::encode(writer.finalize())
+}
+
+/// Constructs the DRUID `from` address for a TxIn collection in a transaction of the given
+/// version: `construct_tx_ins_address_stable` from `STABLE_TX_INS_ADDRESS_VERSION` onwards,
+/// `construct_tx_ins_address` before
+///
+/// ### Arguments
+///
+/// * `tx_ins`      - TxIn collection
+/// * `tx_version`  - Version of the transaction holding the inputs
+pub fn construct_tx_ins_address_at_version(tx_ins: &[TxIn], tx_version: usize) -> String {
+    if tx_version < STABLE_TX_INS_ADDRESS_VERSION {
+        return construct_tx_ins_address(tx_ins);
+    }
+    construct_tx_ins_address_stable(tx_ins)
+}
+
+/// Checks whether a transaction spends from or pays to a public key.
+///
+/// Inputs embed public keys directly in their scripts (p2pkh, multisig and create shapes),
+/// while outputs only hold the address derived from the key
+///
+/// ### Arguments
+///
+/// * `tx`  - Transaction to check
+/// * `pk`  - Public key to look for
+pub fn transaction_involves_key(tx: &Transaction, pk: &PublicKey) -> bool {
+    let spends_from_key = tx.inputs.iter().any(|tx_in| {
+        tx_in
+            .script_signature
+            .stack
+            .iter()
+            .any(|entry| entry == &StackEntry::PubKey(*pk))
+    });
+    if spends_from_key {
+        return true;
+    }
+
+    let address = construct_address(pk);
+    tx.outputs
         .iter()
-        .map(get_tx_in_address_signable_string)
-        .collect::<Vec<String>>()
-        .join("-");
-    hex::encode(sha3_256::digest(signable_tx_ins.as_bytes()))
+        .chain(tx.fees.iter())
+        .any(|tx_out| tx_out.script_public_key.as_ref() == Some(&address))
 }
 
 /// Get all the hash to remove from UTXO set for the utxo_entries
@@ -247,7 +806,7 @@ pub fn get_inputs_previous_out_point<'a>(
     utxo_entries
         .filter(|tx| !tx.is_create_tx())
         .flat_map(|val| val.inputs.iter())
-        .map(|input| input.previous_out.as_ref().unwrap())
+        .filter_map(|input| input.previous_out.as_ref())
 }
 
 /// Get all the OutPoint and Transaction from the (hash,transactions)
@@ -330,12 +889,181 @@ pub fn get_tx_out_with_out_point_cloned<'a>(
 ///
 /// * `current_utxo` - The current UTXO set to be updated.
 pub fn update_utxo_set(current_utxo: &mut BTreeMap<OutPoint, Transaction>) {
-    let value_set: Vec<OutPoint> = get_inputs_previous_out_point(current_utxo.values())
+    // Collect all spent outpoints first, then remove them from the set
+    let spent: BTreeSet<OutPoint> = get_inputs_previous_out_point(current_utxo.values())
         .cloned()
         .collect();
-    value_set.iter().for_each(move |t_hash| {
-        current_utxo.remove(t_hash);
+
+    for out_point in &spent {
+        current_utxo.remove(out_point);
+    }
+}
+
+/// Unspent outputs keyed by the outpoint spending them
+pub type UtxoMap = BTreeMap<OutPoint, TxOut>;
+
+/// An outpoint holding different outputs in two UTXO maps being merged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeConflict {
+    pub out_point: OutPoint,
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Outpoint {} holds different outputs in the merged UTXO sets",
+            self.out_point
+        )
+    }
+}
+
+impl std::error::Error for MergeConflict {}
+
+/// Merges the entries of `from` into `into`. Entries present in both must be identical;
+/// on a conflict `into` is left unchanged
+///
+/// ### Arguments
+///
+/// * `into`    - UTXO map to merge into
+/// * `from`    - UTXO map to take entries from
+pub fn merge_utxo(into: &mut UtxoMap, from: UtxoMap) -> Result<(), MergeConflict> {
+    for (out_point, incoming) in &from {
+        if into
+            .get(out_point)
+            .is_some_and(|existing| existing != incoming)
+        {
+            return Err(MergeConflict {
+                out_point: out_point.clone(),
+            });
+        }
+    }
+
+    into.extend(from);
+    Ok(())
+}
+
+/// Selects UTXO entries holding enough of `target` to pay it, largest first.
+/// Candidates are ordered by amount, then outpoint, so the selection only depends on
+/// the set of candidates and not the order they are given in.
+/// Returns the selected outpoints with everything they hold, as expected by
+/// `construct_payment_tx_with_change`
+///
+/// ### Arguments
+///
+/// * `candidates`  - UTXO entries to select from
+/// * `target`      - Asset to cover
+pub fn select_utxos<'a>(
+    candidates: impl IntoIterator<Item = (&'a OutPoint, &'a TxOut)>,
+    target: &Asset,
+) -> Result<(Vec<OutPoint>, AssetValues), PaymentTxError> {
+    let mut candidates: Vec<_> = candidates
+        .into_iter()
+        .map(|(out_point, tx_out)| (out_point, tx_out.value.clone().with_fixed_hash(out_point)))
+        .filter(|(_, value)| value.is_compatible_with(target))
+        .map(|(out_point, value)| {
+            let amount = match &value {
+                Asset::Item(item) => item.amount,
+                value => value.token_amount().0,
+            };
+            (amount, out_point, value)
+        })
+        .collect();
+    candidates.sort_by(|(a, a_out_point, _), (b, b_out_point, _)| {
+        b.cmp(a).then_with(|| a_out_point.cmp(b_out_point))
     });
+
+    let mut selected = Vec::new();
+    let mut total = AssetValues::default();
+    for (_, out_point, value) in candidates {
+        if total.has_enough(target) {
+            break;
+        }
+        selected.push(out_point.clone());
+        total.update_add(&value);
+    }
+
+    if total.has_enough(target) {
+        Ok((selected, total))
+    } else {
+        Err(PaymentTxError::InsufficientFunds)
+    }
+}
+
+/// Feeds the consensus (bincode) encoding of values straight into a SHA3-256 hash,
+/// so that large values are hashed without holding their whole encoding in memory.
+/// Hashes are identical to hashing the output of `bincode::serialize`
+#[derive(Clone, Default)]
+pub struct ConsensusHashWriter {
+    hasher: sha3_256::Sha3_256,
+    len: usize,
+}
+
+impl ConsensusHashWriter {
+    /// Creates a writer that has hashed nothing yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hashes the consensus encoding of a value, or nothing if it can't be serialized,
+    /// as hashing `serialize(value).unwrap_or_default()` would
+    ///
+    /// ### Arguments
+    ///
+    /// * `value`  - Value to hash
+    pub fn hash_or_empty<T: Serialize + ?Sized>(value: &T) -> Self {
+        let mut writer = Self::new();
+        match writer.write_value(value) {
+            Ok(()) => writer,
+            Err(_) => Self::new(),
+        }
+    }
+
+    /// Hashes raw bytes, such as a domain separator
+    ///
+    /// ### Arguments
+    ///
+    /// * `bytes`  - Bytes to hash
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.hasher.update(bytes);
+        self.len += bytes.len();
+    }
+
+    /// Hashes the consensus encoding of a value. On failure, the bytes written before
+    /// the serializer failed have already been hashed
+    ///
+    /// ### Arguments
+    ///
+    /// * `value`  - Value to hash
+    pub fn write_value<T: Serialize + ?Sized>(&mut self, value: &T) -> bincode::Result<()> {
+        bincode::serialize_into(self, value)
+    }
+
+    /// Number of bytes hashed so far
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether nothing has been hashed yet
+    pub fn is_empty(&self) -> bool {
+        self.len == ZERO
+    }
+
+    /// Returns the hash of everything written
+    pub fn finalize(self) -> sha3_256::Output<sha3_256::Sha3_256> {
+        self.hasher.finalize()
+    }
+}
+
+impl Write for ConsensusHashWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
 }
 
 /// Constructs a search-valid hash for a transaction to be added to the blockchain
@@ -344,69 +1072,292 @@ pub fn update_utxo_set(current_utxo: &mut BTreeMap<OutPoint, Transaction>) {
 ///
 /// * `tx`  - Transaction to hash
 pub fn construct_tx_hash(tx: &Transaction) -> String {
-    let bytes = match serialize(tx) {
-        Ok(bytes) => bytes,
-        Err(_) => vec![],
-    };
-    let mut hash = hex::encode(sha3_256::digest(&bytes));
+    construct_tx_hash_streaming(tx)
+}
+
+/// Constructs the hash of a transaction as `construct_tx_hash` does, streaming its
+/// serialization into the hash rather than holding it in memory
+///
+/// ### Arguments
+///
+/// * `tx`  - Transaction to hash
+pub fn construct_tx_hash_streaming(tx: &Transaction) -> String {
+    let writer = ConsensusHashWriter::hash_or_empty(tx);
+    let mut hash = hex::encode(writer.finalize());
     hash.insert(ZERO, TX_PREPEND as char);
     hash.truncate(TX_HASH_LENGTH);
     hash
 }
 
+/// Finds which transactions of a set spend outputs of others in the set, as
+/// `(parent_idx, child_idx)` pairs ordered by child then parent. Outputs are identified
+/// by `construct_tx_hash` and index, and a child spending several outputs of the same
+/// parent gives a single pair
+///
+/// ### Arguments
+///
+/// * `txs`  - Transactions to find dependencies between, such as a wallet's unconfirmed ones
+pub fn transaction_dependencies(txs: &[Transaction]) -> Vec<(usize, usize)> {
+    let parents: BTreeMap<String, usize> = txs
+        .iter()
+        .enumerate()
+        .map(|(idx, tx)| (construct_tx_hash(tx), idx))
+        .collect();
+
+    let mut dependencies = Vec::new();
+    for (child_idx, tx) in txs.iter().enumerate() {
+        let spent_parents: BTreeSet<usize> = tx
+            .inputs
+            .iter()
+            .filter_map(|tx_in| tx_in.previous_out.as_ref())
+            .filter_map(|out_point| {
+                let parent_idx = *parents.get(&out_point.t_hash)?;
+                txs[parent_idx].output(out_point.output_index()?)?;
+                Some(parent_idx)
+            })
+            .collect();
+        dependencies.extend(spent_parents.into_iter().map(|p| (p, child_idx)));
+    }
+    dependencies
+}
+
+/// Estimates the serialized size in bytes of a transaction without building it
+///
+/// ### Arguments
+///
+/// * `num_inputs`  - Number of inputs
+/// * `input_type`  - Script type unlocking every input
+/// * `num_outputs` - Number of outputs
+pub fn estimate_tx_size(num_inputs: usize, input_type: ScriptType, num_outputs: usize) -> usize {
+    let (input_size, output_size) = input_type.size_weights();
+    TX_BASE_SIZE + num_inputs * input_size + num_outputs * output_size
+}
+
+/// Estimates the fee for a transaction of the given shape, rounding up to whole units.
+/// Negative or NaN fee rates give no fee
+///
+/// ### Arguments
+///
+/// * `num_inputs`  - Number of inputs
+/// * `input_type`  - Script type unlocking every input
+/// * `num_outputs` - Number of outputs
+/// * `fee_rate`    - Fee in smallest token units per byte
+pub fn estimate_fee(
+    num_inputs: usize,
+    input_type: ScriptType,
+    num_outputs: usize,
+    fee_rate: f64,
+) -> TokenAmount {
+    let size = estimate_tx_size(num_inputs, input_type, num_outputs);
+    TokenAmount((size as f64 * fee_rate).ceil() as u64)
+}
+
 /// Constructs a valid TxIn for a new create asset transaction
 ///
 /// ### Arguments
 ///
-/// * `block_num`   - Block number
-/// * `asset`       - Asset to create
-/// * `public_key`  - Public key to sign with
-/// * `secret_key`  - Corresponding private key
+/// * `block_num`       - Block number
+/// * `current_height`  - Current height of the chain, which `block_num` must be near
+/// * `asset`           - Asset to create
+/// * `public_key`      - Public key to sign with
+/// * `secret_key`      - Corresponding private key
 pub fn construct_create_tx_in(
     block_num: u64,
+    current_height: u64,
     asset: &Asset,
     public_key: PublicKey,
     secret_key: &SecretKey,
-) -> Vec<TxIn> {
-    let asset_hash = construct_tx_in_signable_asset_hash(asset);
-    let signature = sign::sign_detached(asset_hash.as_bytes(), secret_key);
-
-    vec![TxIn {
+) -> Result<Vec<TxIn>, ScriptError> {
+    let message = SignableMessage::asset(asset);
+    let asset_hash = message.hash_at_version(NETWORK_VERSION as usize);
+    let signature = message.sign_at_version(NETWORK_VERSION as usize, secret_key);
+    let script_signature =
+        Script::new_create_asset(block_num, current_height, asset_hash, signature, public_key)?;
+
+    Ok(vec![TxIn {
         previous_out: None,
-        script_signature: Script::new_create_asset(block_num, asset_hash, signature, public_key),
-    }]
+        script_signature,
+    }])
+}
+
+/// Reasons a create transaction can't be constructed or is invalid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CreateTxError {
+    InvalidMetadata(MetadataError),
+    InvalidAmount,
+    GenesisHashConflict(String),
+    InvalidStructure(TxStructureError),
+    InvalidBlockNumber(ScriptError),
+    NotCreateInput,
+    OutputCount(usize),
+    InvalidScript,
+}
+
+impl fmt::Display for CreateTxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidMetadata(e) => write!(f, "{e}"),
+            Self::InvalidAmount => write!(f, "Amount of assets to create must be non-zero"),
+            Self::GenesisHashConflict(hash) => {
+                write!(f, "Genesis hash {hash} is not a transaction hash")
+            }
+            Self::InvalidStructure(e) => write!(f, "{e}"),
+            Self::InvalidBlockNumber(e) => write!(f, "{e}"),
+            Self::NotCreateInput => {
+                write!(
+                    f,
+                    "Create transaction must have a single input spending nothing"
+                )
+            }
+            Self::OutputCount(n) => {
+                write!(
+                    f,
+                    "Create transaction must have exactly one output, found {n}"
+                )
+            }
+            Self::InvalidScript => write!(f, "Create script doesn't sign the created asset"),
+        }
+    }
+}
+
+impl std::error::Error for CreateTxError {}
+
+impl From<MetadataError> for CreateTxError {
+    fn from(e: MetadataError) -> Self {
+        Self::InvalidMetadata(e)
+    }
+}
+
+impl From<TxStructureError> for CreateTxError {
+    fn from(e: TxStructureError) -> Self {
+        Self::InvalidStructure(e)
+    }
 }
 
-/// Constructs a item data asset for use in accepting payments
+impl From<AssetError> for CreateTxError {
+    fn from(_: AssetError) -> Self {
+        Self::InvalidAmount
+    }
+}
+
+impl From<ScriptError> for CreateTxError {
+    fn from(e: ScriptError) -> Self {
+        Self::InvalidBlockNumber(e)
+    }
+}
+
+/// Constructs a item data asset for use in accepting payments,
+/// failing early on anything `tx_has_valid_create_script` would reject
 /// TODO: On compute, figure out a way to ease flow of items without issue for users
 ///
 /// ### Arguments
 ///
 /// * `block_num`           - Block number
+/// * `current_height`      - Current height of the chain, which `block_num` must be near
 /// * `public_key`          - Public key for the output address
 /// * `secret_key`          - Corresponding secret key for signing data
 /// * `amount`              - Amount of item assets to create
+/// * `genesis_hash_spec`   - Genesis hash to assign to the items
+/// * `fee`                 - Fee to pay, if any
+/// * `metadata`            - Metadata of the items, if any
+#[allow(clippy::too_many_arguments)]
 pub fn construct_item_create_tx(
     block_num: u64,
+    current_height: u64,
     public_key: PublicKey,
     secret_key: &SecretKey,
     amount: u64,
     genesis_hash_spec: GenesisTxHashSpec,
     fee: Option<ReceiverInfo>,
     metadata: Option<String>,
-) -> Transaction {
+) -> Result<Transaction, CreateTxError> {
+    ItemAsset::new(amount, None, None).validate_amount()?;
+    if let GenesisTxHashSpec::Existing(hash) = &genesis_hash_spec {
+        if !genesis_hash_spec.is_well_formed() {
+            return Err(CreateTxError::GenesisHashConflict(hash.clone()));
+        }
+    }
+    check_item_metadata_to_construct(metadata.as_deref())?;
+
     let genesis_hash = genesis_hash_spec.get_genesis_hash();
     let asset = Asset::item(amount, genesis_hash, metadata);
     let receiver_address = construct_address(&public_key);
 
-    let tx_ins = construct_create_tx_in(block_num, &asset, public_key, secret_key);
+    let tx_ins = construct_create_tx_in(block_num, current_height, &asset, public_key, secret_key)?;
     let tx_out = TxOut {
         value: asset,
         script_public_key: Some(receiver_address),
         ..Default::default()
     };
 
-    construct_tx_core(tx_ins, vec![tx_out], fee)
+    Ok(construct_tx_core(tx_ins, vec![tx_out], fee)?)
+}
+
+/// Constructs a item create transaction whose metadata stays private,
+/// holding only a commitment to it on chain
+///
+/// ### Arguments
+///
+/// * `block_num`           - Block number
+/// * `current_height`      - Current height of the chain, which `block_num` must be near
+/// * `public_key`          - Public key for the output address
+/// * `secret_key`          - Corresponding secret key for signing data
+/// * `amount`              - Amount of item assets to create
+/// * `plaintext`           - Private metadata to commit to
+/// * `salt`                - Salt for the commitment, needed to reveal the metadata later
+#[allow(clippy::too_many_arguments)]
+pub fn construct_item_create_tx_committed(
+    block_num: u64,
+    current_height: u64,
+    public_key: PublicKey,
+    secret_key: &SecretKey,
+    amount: u64,
+    genesis_hash_spec: GenesisTxHashSpec,
+    fee: Option<ReceiverInfo>,
+    plaintext: &[u8],
+    salt: &[u8; 32],
+) -> Result<Transaction, CreateTxError> {
+    let commitment = commit_metadata(plaintext, salt);
+    construct_item_create_tx(
+        block_num,
+        current_height,
+        public_key,
+        secret_key,
+        amount,
+        genesis_hash_spec,
+        fee,
+        Some(commitment.to_metadata()),
+    )
+}
+
+/// Constructs a create transaction for a new fungible token class,
+/// minting its whole `total_supply`
+///
+/// ### Arguments
+///
+/// * `block_num`           - Block number
+/// * `current_height`      - Current height of the chain, which `block_num` must be near
+/// * `public_key`          - Public key for the output address
+/// * `secret_key`          - Corresponding secret key for signing data
+/// * `metadata`            - Metadata of the token class to create
+pub fn construct_token_create_tx(
+    block_num: u64,
+    current_height: u64,
+    public_key: PublicKey,
+    secret_key: &SecretKey,
+    metadata: TokenMetadata,
+) -> Result<Transaction, CreateTxError> {
+    let asset = Asset::token_with_metadata(metadata.total_supply, metadata, None);
+    let receiver_address = construct_address(&public_key);
+
+    let tx_ins = construct_create_tx_in(block_num, current_height, &asset, public_key, secret_key)?;
+    let tx_out = TxOut {
+        value: asset,
+        script_public_key: Some(receiver_address),
+        ..Default::default()
+    };
+
+    Ok(construct_tx_core(tx_ins, vec![tx_out], None)?)
 }
 
 /// Constructs a transaction to pay a receiver
@@ -423,20 +1374,145 @@ pub fn construct_item_create_tx(
 /// * `locktime`            - Block height below which the payment is restricted. "0" means no locktime
 pub fn construct_payment_tx(
     tx_ins: Vec<TxIn>,
-    receiver: ReceiverInfo,
+    receiver: ReceiverInfo,
+    fee: Option<ReceiverInfo>,
+    locktime: u64,
+    key_material: &BTreeMap<OutPoint, (PublicKey, SecretKey)>,
+) -> Result<Transaction, TxStructureError> {
+    let tx_out = TxOut {
+        value: receiver.asset,
+        locktime,
+        script_public_key: Some(receiver.address),
+    };
+    let tx_outs = vec![tx_out];
+    let final_tx_ins = update_input_signatures(&tx_ins, &tx_outs, key_material);
+
+    construct_tx_core(final_tx_ins, tx_outs, fee)
+}
+
+/// Reasons a payment transaction with change, or a consolidation transaction, can't be
+/// constructed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentTxError {
+    InsufficientFunds,
+    EmptyOutput,
+    FeeNotToken,
+    InvalidStructure(TxStructureError),
+    UnknownTokenClass(String),
+}
+
+impl fmt::Display for PaymentTxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InsufficientFunds => {
+                write!(f, "Inputs don't cover the payment and fee")
+            }
+            Self::EmptyOutput => write!(f, "Nothing is left to pay after the fee"),
+            Self::FeeNotToken => write!(f, "Fee is not a token amount"),
+            Self::InvalidStructure(e) => write!(f, "{e}"),
+            Self::UnknownTokenClass(genesis_hash) => write!(
+                f,
+                "Metadata of token class {genesis_hash} is unknown, so its change can't be returned"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PaymentTxError {}
+
+impl From<TxStructureError> for PaymentTxError {
+    fn from(e: TxStructureError) -> Self {
+        Self::InvalidStructure(e)
+    }
+}
+
+/// Constructs a transaction to pay a receiver, returning whatever the inputs hold beyond
+/// the payment and fee to `change_address`, rather than burning it.
+/// One unlocked change output is added per asset left over; none if nothing is left.
+/// Token class change takes its metadata from the receiver, so it is only returned for
+/// the token class paid
+///
+/// ### Arguments
+///
+/// * `tx_ins`              - Input/s to pay from
+/// * `inputs_total`        - Total of the assets held by the previous outputs of `tx_ins`
+/// * `receiver`            - Address and asset to pay
+/// * `fee`                 - Fee to pay, if any
+/// * `change_address`      - Address to return the change to
+/// * `locktime`            - Block height below which the payment is restricted. "0" means no locktime
+/// * `key_material`        - Key material for signing
+pub fn construct_payment_tx_with_change(
+    tx_ins: Vec<TxIn>,
+    inputs_total: AssetValues,
+    receiver: ReceiverInfo,
+    fee: Option<ReceiverInfo>,
+    change_address: String,
+    locktime: u64,
+    key_material: &BTreeMap<OutPoint, (PublicKey, SecretKey)>,
+) -> Result<Transaction, PaymentTxError> {
+    let mut change = inputs_total;
+    for spent in std::iter::once(&receiver.asset).chain(fee.as_ref().map(|fee| &fee.asset)) {
+        if !change.has_enough(spent) {
+            return Err(PaymentTxError::InsufficientFunds);
+        }
+        change.update_sub(spent);
+    }
+
+    let mut tx_outs = vec![TxOut {
+        value: receiver.asset,
+        locktime,
+        script_public_key: Some(receiver.address),
+    }];
+    for (asset_id, amount) in change.iter().filter(|(_, amount)| amount.0 > 0) {
+        let value = match asset_id {
+            AssetId::Token => Asset::Token(amount),
+            AssetId::Item(genesis_hash) => Asset::item(amount.0, Some(genesis_hash), None),
+            AssetId::TokenClass(genesis_hash) => match tx_outs[0].value.get_token_metadata() {
+                Some(metadata) if tx_outs[0].value.genesis_hash() == Some(&genesis_hash) => {
+                    Asset::token_with_metadata(amount.0, metadata.clone(), Some(genesis_hash))
+                }
+                _ => return Err(PaymentTxError::UnknownTokenClass(genesis_hash)),
+            },
+        };
+        tx_outs.push(TxOut::new_asset(change_address.clone(), value, None));
+    }
+    let final_tx_ins = update_input_signatures(&tx_ins, &tx_outs, key_material);
+
+    Ok(construct_tx_core(final_tx_ins, tx_outs, fee)?)
+}
+
+/// Constructs a transaction sweeping many token inputs into a single output,
+/// paying `destination` whatever is left of `inputs_total` after the fee
+///
+/// ### Arguments
+///
+/// * `tx_ins`              - Input/s to sweep
+/// * `inputs_total`        - Total of the tokens held by the previous outputs of `tx_ins`
+/// * `destination`         - Address to sweep to
+/// * `fee`                 - Fee to pay in tokens, if any
+/// * `key_material`        - Key material for signing
+pub fn construct_consolidation_tx(
+    tx_ins: Vec<TxIn>,
+    inputs_total: TokenAmount,
+    destination: String,
     fee: Option<ReceiverInfo>,
-    locktime: u64,
     key_material: &BTreeMap<OutPoint, (PublicKey, SecretKey)>,
-) -> Transaction {
-    let tx_out = TxOut {
-        value: receiver.asset,
-        locktime,
-        script_public_key: Some(receiver.address),
+) -> Result<Transaction, PaymentTxError> {
+    let fee_amount = match &fee {
+        Some(fee) if !fee.asset.is_token() => return Err(PaymentTxError::FeeNotToken),
+        Some(fee) => fee.asset.token_amount(),
+        None => TokenAmount(0),
     };
-    let tx_outs = vec![tx_out];
+    let amount = match inputs_total.0.checked_sub(fee_amount.0) {
+        Some(0) => return Err(PaymentTxError::EmptyOutput),
+        Some(amount) => TokenAmount(amount),
+        None => return Err(PaymentTxError::InsufficientFunds),
+    };
+
+    let tx_outs = vec![TxOut::new_token_amount(destination, amount, None)];
     let final_tx_ins = update_input_signatures(&tx_ins, &tx_outs, key_material);
 
-    construct_tx_core(final_tx_ins, tx_outs, fee)
+    Ok(construct_tx_core(final_tx_ins, tx_outs, fee)?)
 }
 
 /// Constructs a P2SH transaction to pay a receiver
@@ -455,8 +1531,8 @@ pub fn construct_p2sh_tx(
     asset: Asset,
     locktime: u64,
     key_material: &BTreeMap<OutPoint, (PublicKey, SecretKey)>,
-) -> Transaction {
-    let script_hash = construct_p2sh_address(script);
+) -> Result<Transaction, P2SHError> {
+    let script_hash = construct_p2sh_address_at_version(script, NETWORK_VERSION as usize)?;
 
     let tx_out = TxOut {
         value: asset,
@@ -466,7 +1542,61 @@ pub fn construct_p2sh_tx(
     let tx_outs = vec![tx_out];
     let final_tx_ins = update_input_signatures(&tx_ins, &tx_outs, key_material);
 
-    construct_tx_core(final_tx_ins, tx_outs, fee)
+    Ok(construct_tx_core(final_tx_ins, tx_outs, fee)?)
+}
+
+/// Constructs a transaction funding an escrow built by `Script::new_escrow`
+///
+/// ### Arguments
+///
+/// * `tx_ins`              - Input/s to pay from
+/// * `fee`                 - Fee to pay, if any
+/// * `escrow`              - Escrow script to pay to
+/// * `asset`               - Asset to hold in escrow
+/// * `key_material`        - Key material for signing
+pub fn construct_escrow_tx(
+    tx_ins: Vec<TxIn>,
+    fee: Option<ReceiverInfo>,
+    escrow: &Script,
+    asset: Asset,
+    key_material: &BTreeMap<OutPoint, (PublicKey, SecretKey)>,
+) -> Result<Transaction, P2SHError> {
+    construct_p2sh_tx(tx_ins, fee, escrow, asset, 0, key_material)
+}
+
+/// Builds the check data the parties to an escrow sign to settle it, paying `tx_outs`
+///
+/// ### Arguments
+///
+/// * `escrow_out`  - Outpoint of the escrow output
+/// * `tx_outs`     - Outputs the escrow is settled to
+pub fn construct_escrow_check_data(escrow_out: &OutPoint, tx_outs: &[TxOut]) -> String {
+    let tx_in = TxIn::new_from_input(escrow_out.clone(), Script::new());
+    SignableMessage::tx_in(&tx_in, tx_outs).hash_at_version(NETWORK_VERSION as usize)
+}
+
+/// Constructs a transaction settling an escrow, with an unlocking script built by
+/// `Script::escrow_release`, `Script::escrow_refund_via_arbiter` or
+/// `Script::escrow_seller_claim_via_arbiter` over `construct_escrow_check_data`
+///
+/// ### Arguments
+///
+/// * `escrow_out`  - Outpoint of the escrow output
+/// * `escrow`      - Escrow script the output was paid to
+/// * `unlock`      - Unlocking script of the settlement path
+/// * `tx_outs`     - Outputs the escrow is settled to
+/// * `fee`         - Fee to pay, if any
+pub fn construct_escrow_settlement_tx(
+    escrow_out: OutPoint,
+    escrow: &Script,
+    unlock: Script,
+    tx_outs: Vec<TxOut>,
+    fee: Option<ReceiverInfo>,
+) -> Result<Transaction, P2SHError> {
+    let script_signature = Script::concat(unlock, escrow.clone());
+    let tx_ins = vec![TxIn::new_from_input(escrow_out, script_signature)];
+
+    Ok(construct_tx_core(tx_ins, tx_outs, fee)?)
 }
 
 /// Constructs a P2SH transaction to burn tokens
@@ -478,10 +1608,10 @@ pub fn construct_burn_tx(
     tx_ins: Vec<TxIn>,
     fee: Option<ReceiverInfo>,
     key_material: &BTreeMap<OutPoint, (PublicKey, SecretKey)>,
-) -> Transaction {
+) -> Result<Transaction, P2SHError> {
     let s = vec![StackEntry::Op(OpCodes::OP_BURN)];
     let script = Script::from(s);
-    let script_hash = construct_p2sh_address(&script);
+    let script_hash = construct_p2sh_address_at_version(&script, NETWORK_VERSION as usize)?;
 
     let tx_out = TxOut {
         script_public_key: Some(script_hash),
@@ -491,7 +1621,7 @@ pub fn construct_burn_tx(
 
     let final_tx_ins = update_input_signatures(&tx_ins, &tx_outs, key_material);
 
-    construct_tx_core(final_tx_ins, tx_outs, fee)
+    Ok(construct_tx_core(final_tx_ins, tx_outs, fee)?)
 }
 
 /// Constructs a transaction to pay a receiver
@@ -502,6 +1632,8 @@ pub fn construct_burn_tx(
 /// TODO: Check whether the `amount` is valid in the TxIns
 /// TODO: Call this a charity tx or something, as a payment is an exchange of goods
 ///
+/// Fails if the transaction doesn't meet `Transaction::validate_structure`
+///
 /// ### Arguments
 ///
 /// * `tx_ins`     - Address/es to pay from
@@ -510,7 +1642,7 @@ pub fn construct_tx_core(
     tx_ins: Vec<TxIn>,
     tx_outs: Vec<TxOut>,
     fee: Option<ReceiverInfo>,
-) -> Transaction {
+) -> Result<Transaction, TxStructureError> {
     let fee_tx_out = match fee {
         Some(fee) => vec![TxOut {
             value: fee.asset,
@@ -520,12 +1652,15 @@ pub fn construct_tx_core(
         None => vec![],
     };
 
-    Transaction {
+    let tx = Transaction {
         inputs: tx_ins,
         outputs: tx_outs,
         fees: fee_tx_out,
         ..Default::default()
-    }
+    };
+    tx.validate_structure()?;
+
+    Ok(tx)
 }
 
 /// Constructs a core item-based payment transaction
@@ -545,19 +1680,15 @@ pub fn construct_rb_tx_core(
     druid: String,
     druid_expectation: Vec<DruidExpectation>,
     key_material: &BTreeMap<OutPoint, (PublicKey, SecretKey)>,
-) -> Transaction {
-    let mut tx = construct_tx_core(tx_ins, tx_outs, fee);
+) -> Result<Transaction, TxStructureError> {
+    let mut tx = construct_tx_core(tx_ins, tx_outs, fee)?;
 
     tx.inputs = update_input_signatures(&tx.inputs, &tx.outputs, key_material);
 
-    tx.druid_info = Some(DdeValues {
-        druid,
-        participants: 2,
-        expectations: druid_expectation,
-        genesis_hash: None,
-    });
+    tx.set_druid_info(druid, 2, druid_expectation);
+    tx.validate_structure()?;
 
-    tx
+    Ok(tx)
 }
 
 /// Updates the input signatures with output information
@@ -576,27 +1707,19 @@ pub fn update_input_signatures(
 
     debug!("TxOuts: {:?}", tx_outs);
     for tx_in in tx_ins.iter_mut() {
-        let signable_prev_out = TxIn {
-            previous_out: tx_in.previous_out.clone(),
-            script_signature: Script::new(),
-        };
-
-        debug!("Signable prev out: {:?}", signable_prev_out.previous_out);
-        let signable_hash = construct_tx_in_out_signable_hash(&signable_prev_out, tx_outs);
-        let previous_out = signable_prev_out.previous_out;
+        debug!("Signable prev out: {:?}", tx_in.previous_out);
+        let message = SignableMessage::tx_in(tx_in, tx_outs);
+        let signable_hash = message.hash_at_version(NETWORK_VERSION as usize);
 
         debug!("Signable hash: {:?}", signable_hash);
 
-        if previous_out.is_some() && key_material.get(&previous_out.clone().unwrap()).is_some() {
-            let pk = key_material.get(&previous_out.clone().unwrap()).unwrap().0;
-            let sk = &key_material.get(&previous_out.unwrap()).unwrap().1;
-
-            let script_signature = Script::pay2pkh(
-                signable_hash.clone(),
-                sign_detached(signable_hash.as_bytes(), sk),
-                pk,
-                None,
-            );
+        let keys = tx_in
+            .previous_out
+            .as_ref()
+            .and_then(|out| key_material.get(out));
+        if let Some((pk, sk)) = keys {
+            let signature = message.sign_at_version(NETWORK_VERSION as usize, sk);
+            let script_signature = Script::pay2pkh(signable_hash, signature, *pk, None);
 
             tx_in.script_signature = script_signature;
         }
@@ -613,6 +1736,7 @@ pub fn update_input_signatures(
 /// * `receiver_address`    - Own address to receive item to
 /// * `amount`              - Amount of token to send
 /// * `locktime`            - Block height to lock the current transaction to
+/// * `druid_info`          - DRUID values
 pub fn construct_rb_payments_send_tx(
     tx_ins: Vec<TxIn>,
     mut tx_outs: Vec<TxOut>,
@@ -621,7 +1745,7 @@ pub fn construct_rb_payments_send_tx(
     locktime: u64,
     druid_info: DdeValues,
     key_material: &BTreeMap<OutPoint, (PublicKey, SecretKey)>,
-) -> Transaction {
+) -> Result<Transaction, TxStructureError> {
     let out = TxOut {
         value: receiver.asset,
         locktime,
@@ -649,7 +1773,7 @@ pub fn construct_rb_payments_send_tx(
 /// * `own_address`         - Own address to receive tokens to
 /// * `amount`              - Number of tokens expected
 /// * `locktime`            - Block height below which the payment item is restricted. "0" means no locktime
-/// * `druid`               - The matching DRUID value
+/// * `druid_info`          - DRUID values
 pub fn construct_rb_receive_payment_tx(
     tx_ins: Vec<TxIn>,
     mut tx_outs: Vec<TxOut>,
@@ -658,7 +1782,7 @@ pub fn construct_rb_receive_payment_tx(
     locktime: u64,
     druid_info: DdeValues,
     key_material: &BTreeMap<OutPoint, (PublicKey, SecretKey)>,
-) -> Transaction {
+) -> Result<Transaction, TxStructureError> {
     let out = TxOut {
         value: Asset::item(1, druid_info.genesis_hash, None),
         locktime,
@@ -700,6 +1824,35 @@ pub fn construct_payment_tx_ins(tx_values: Vec<TxConstructor>) -> Vec<TxIn> {
     tx_ins
 }
 
+/// Constructs a set of TxIns spending dual signature outputs into `tx_outs`. Each party
+/// signs `SignableMessage::tx_in` for the input on its own, so the signatures may be
+/// collected asynchronously before the inputs are assembled here.
+///
+/// Each constructor holds both signatures and both public keys, in matching order.
+/// Inputs whose constructor doesn't hold exactly two of each are left unsigned
+///
+/// ### Arguments
+///
+/// * `tx_values`   - Series of values required for TxIn construction
+/// * `tx_outs`     - Outputs of the transaction, committed to by the signatures
+pub fn construct_dual_tx_ins(tx_values: Vec<TxConstructor>, tx_outs: &[TxOut]) -> Vec<TxIn> {
+    tx_values
+        .into_iter()
+        .map(|entry| {
+            let mut tx_in = TxIn::new_from_input(entry.previous_out, Script::new());
+            if let ([sig_a, sig_b], [pk_a, pk_b]) =
+                (entry.signatures.as_slice(), entry.pub_keys.as_slice())
+            {
+                let signable_hash = SignableMessage::tx_in(&tx_in, tx_outs)
+                    .hash_at_version(NETWORK_VERSION as usize);
+                tx_in.script_signature =
+                    Script::pay2pkh_dual(signable_hash, *sig_a, *sig_b, *pk_a, *pk_b);
+            }
+            tx_in
+        })
+        .collect()
+}
+
 /// Constructs the TxIn for a P2SH redemption. The redeemer must supply a script that
 /// matches the scriptPubKey of the output being spent.
 ///
@@ -735,13 +1888,14 @@ pub fn construct_dde_tx(
     tx_outs: Vec<TxOut>,
     fee: Option<ReceiverInfo>,
     key_material: &BTreeMap<OutPoint, (PublicKey, SecretKey)>,
-) -> Transaction {
-    let mut tx = construct_tx_core(tx_ins, tx_outs, fee);
+) -> Result<Transaction, TxStructureError> {
+    let mut tx = construct_tx_core(tx_ins, tx_outs, fee)?;
 
     tx.inputs = update_input_signatures(&tx.inputs, &tx.outputs, key_material);
     tx.druid_info = Some(druid_info);
+    tx.validate_structure()?;
 
-    tx
+    Ok(tx)
 }
 
 /*---- TESTS ----*/
@@ -752,7 +1906,358 @@ mod tests {
     use crate::crypto::sign_ed25519::{self as sign, Signature};
     use crate::primitives::asset::{AssetValues, ItemAsset, TokenAmount};
     use crate::script::OpCodes;
-    use crate::utils::script_utils::{tx_has_valid_p2sh_script, tx_outs_are_valid};
+    use crate::utils::script_utils::{
+        tx_has_valid_create_script_at_version, tx_has_valid_p2sh_script,
+        tx_has_valid_p2sh_script_in_context, tx_is_valid, tx_outs_are_valid, InputError,
+        TxValidationError,
+    };
+    use crate::utils::test_utils::{generate_unsigned_tx_ins, test_druid};
+    use bincode::serialize;
+    use std::convert::TryFrom;
+
+    #[test]
+    /// Checks that P2SH and P2PKH addresses are told apart by their prefix
+    fn test_address_type() {
+        let (pk, _) = sign::gen_keypair();
+        let script = Script::from(vec![StackEntry::Op(OpCodes::OP_1)]);
+
+        assert_eq!(
+            address_type(&construct_p2sh_address(&script)),
+            AddressKind::P2SH
+        );
+        assert_eq!(address_type(&construct_address(&pk)), AddressKind::P2PKH);
+        assert_eq!(
+            address_type(&construct_address_for(&pk, Some(NETWORK_VERSION_V0))),
+            AddressKind::P2PKH
+        );
+        assert_eq!(address_type(""), AddressKind::P2PKH);
+    }
+
+    #[test]
+    /// Checks that only lowercase hex of even length is decoded
+    fn test_decode_lower_hex() {
+        for (s, bytes) in [("", vec![]), ("00ff", vec![0, 255]), ("0a1b", vec![10, 27])] {
+            assert_eq!(decode_lower_hex(s), Ok(bytes), "{}", s);
+        }
+
+        let invalid_char = |c, index| hex::FromHexError::InvalidHexCharacter { c, index };
+        let rejected = [
+            ("0", hex::FromHexError::OddLength),
+            ("abc", hex::FromHexError::OddLength),
+            ("FF", invalid_char('F', 0)),
+            ("0aB1", invalid_char('B', 2)),
+            ("zz", invalid_char('z', 0)),
+            ("0x00", invalid_char('x', 1)),
+            (" 00 ", invalid_char(' ', 0)),
+        ];
+        for (s, error) in rejected {
+            assert_eq!(decode_lower_hex(s), Err(error), "{}", s);
+        }
+    }
+
+    #[test]
+    /// Checks that only spends of outputs within the set are dependencies
+    fn test_transaction_dependencies() {
+        let address = hex::encode([0; 32]);
+        let spending = |t_hash: &str, n| {
+            let mut tx = Transaction::new();
+            tx.inputs.push(TxIn::new_from_input(
+                OutPoint::new(t_hash.to_owned(), n),
+                Script::new(),
+            ));
+            tx.outputs.push(TxOut::new_token_amount(
+                address.clone(),
+                TokenAmount(1),
+                None,
+            ));
+            tx
+        };
+        let parent = spending("g0", 0);
+        let parent_hash = construct_tx_hash(&parent);
+        let child = spending(&parent_hash, 0);
+        let unrelated = spending("g1", 0);
+        let missing_output = spending(&parent_hash, 1);
+
+        assert_eq!(
+            transaction_dependencies(&[child.clone(), parent.clone()]),
+            vec![(1, 0)]
+        );
+        assert_eq!(
+            transaction_dependencies(&[parent.clone(), unrelated.clone()]),
+            vec![]
+        );
+        assert_eq!(transaction_dependencies(&[parent, missing_output]), vec![]);
+        assert_eq!(transaction_dependencies(&[child, unrelated]), vec![]);
+    }
+
+    #[test]
+    /// Checks that streamed hashes match hashing the whole serialization, for transaction
+    /// hashes and P2SH addresses on both sides of the script size limit
+    fn test_streaming_hashes_match_serialization() {
+        let (pk, sk) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let mut large = Transaction::new();
+        large.outputs = (0..5000)
+            .map(|i| TxOut::new_token_amount(address.clone(), TokenAmount(i), None))
+            .collect();
+        let mut with_druid = Transaction::new();
+        with_druid.inputs = generate_unsigned_tx_ins();
+        with_druid.druid_info = Some(DdeValues {
+            druid: test_druid(),
+            participants: 3,
+            expectations: Vec::new(),
+            genesis_hash: None,
+        });
+        let genesis_hash = GenesisTxHashSpec::Create;
+        let corpus = vec![
+            Transaction::new(),
+            large,
+            with_druid,
+            construct_item_create_tx(0, 0, pk, &sk, 1, genesis_hash, None, None).unwrap(),
+        ];
+
+        for tx in &corpus {
+            let mut expected = hex::encode(sha3_256::digest(&serialize(tx).unwrap()));
+            expected.insert(ZERO, TX_PREPEND as char);
+            expected.truncate(TX_HASH_LENGTH);
+            assert_eq!(construct_tx_hash_streaming(tx), expected);
+            assert_eq!(construct_tx_hash(tx), expected);
+        }
+
+        let scripts = [
+            Script::new(),
+            Script::from(vec![StackEntry::Op(OpCodes::OP_1)]),
+            Script::from(vec![StackEntry::Bytes("a".repeat(MAX_P2SH_SCRIPT_SIZE))]),
+        ];
+        for script in &scripts {
+            let bytes = serialize(script).unwrap();
+            let mut expected = hex::encode(sha3_256::digest(&bytes));
+            expected.insert(ZERO, P2SH_PREPEND as char);
+            expected.truncate(STANDARD_ADDRESS_LENGTH);
+            assert_eq!(construct_p2sh_address_streaming(script), expected);
+            assert_eq!(construct_p2sh_address(script), expected);
+        }
+    }
+
+    #[test]
+    /// Checks that the encoding and hash of a P2PKH transaction are unchanged from
+    /// baseline releases, so consensus encodings can't shift unnoticed
+    fn test_p2pkh_tx_encoding_is_stable() {
+        let pk = PublicKey::from_slice(&[1; 32]).unwrap();
+        let sig = Signature::from_slice(&[2; 64]).unwrap();
+        let out_point = OutPoint::new("g0000000000000000000000000000000".to_owned(), 0);
+        let script = Script::pay2pkh("check_data".to_owned(), sig, pk, None);
+        let mut tx = Transaction::new();
+        tx.inputs.push(TxIn::new_from_input(out_point, script));
+        tx.outputs.push(TxOut::new_token_amount(
+            construct_address(&pk),
+            TokenAmount(10),
+            None,
+        ));
+
+        let expected = concat!(
+            "010000000000000001200000000000000067303030303030303030303030303030303030",
+            "30303030303030303030303030000000000800000000000000040000000a000000000000",
+            "00636865636b5f6461746101000000400000000000000002020202020202020202020202",
+            "020202020202020202020202020202020202020202020202020202020202020202020202",
+            "020202020202020202020202020202020000002000000000000000010101010101010101",
+            "010101010101010101010101010101010101010101010100000000230000000000000050",
+            "000000040000004000000000000000306263646430646636613265313037383465313234",
+            "313534316539316363623839373534316166373133653332376639353535323331643134",
+            "31633939363434000000003500000000000000530000000100000000000000000000000a",
+            "000000000000000000000000000000014000000000000000306263646430646636613265",
+            "313037383465313234313534316539316363623839373534316166373133653332376639",
+            "353535323331643134316339393634340600000000000000000000000000000000",
+        );
+        assert_eq!(hex::encode(serialize(&tx).unwrap()), expected);
+        assert_eq!(construct_tx_hash(&tx), "g3dfc4f35716dc77a1b487218ca55244");
+    }
+
+    #[test]
+    /// Checks that serializer failures are returned by `write_value`, and hash as an empty
+    /// serialization through `hash_or_empty`
+    fn test_consensus_hash_writer_errors() {
+        struct Unserializable;
+        impl Serialize for Unserializable {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("unserializable"))
+            }
+        }
+
+        let mut writer = ConsensusHashWriter::new();
+        let error = writer.write_value(&Unserializable).unwrap_err();
+        assert_eq!(error.to_string(), "unserializable");
+
+        let writer = ConsensusHashWriter::hash_or_empty(&Unserializable);
+        assert!(writer.is_empty());
+        assert_eq!(writer.finalize(), sha3_256::digest(&[]));
+
+        let mut writer = ConsensusHashWriter::new();
+        writer.write_value(&(1u8, "ab")).unwrap();
+        assert_eq!(writer.len(), serialize(&(1u8, "ab")).unwrap().len());
+    }
+
+    #[test]
+    /// Checks transaction hashes and addresses are told apart
+    fn test_hash_namespaces() {
+        let (pk, _) = sign::gen_keypair();
+        let script = Script::from(vec![StackEntry::Op(OpCodes::OP_1)]);
+        let tx_hash = construct_tx_hash(&Transaction::new());
+        let addresses = vec![
+            construct_address(&pk),
+            construct_address_for(&pk, Some(NETWORK_VERSION_V0)),
+            construct_p2sh_address(&script),
+        ];
+        let neither = vec![
+            String::new(),
+            "tx_hash".to_owned(),
+            tx_hash.to_uppercase(),
+            construct_address(&pk).to_uppercase(),
+        ];
+
+        assert!(is_tx_hash(&tx_hash));
+        assert!(!is_address(&tx_hash));
+        for address in &addresses {
+            assert!(is_address(address), "{}", address);
+            assert!(!is_tx_hash(address), "{}", address);
+        }
+        for s in &neither {
+            assert!(!is_address(s) && !is_tx_hash(s), "{}", s);
+        }
+
+        let address = construct_address(&pk);
+        assert_eq!(
+            OutPoint::try_new(address.clone(), 0),
+            Err(OutPointParseError::InvalidTxHash(address))
+        );
+        assert_eq!(
+            OutPoint::try_new(tx_hash.clone(), 0),
+            Ok(OutPoint::new(tx_hash, 0))
+        );
+    }
+
+    #[test]
+    /// Checks the minimum transaction structure is enforced at construction
+    fn test_construct_tx_core_structure() {
+        let tx_out = TxOut::new_token_amount("".to_string(), TokenAmount(1), None);
+        let fee = || ReceiverInfo {
+            address: "".to_string(),
+            asset: Asset::token_u64(1),
+        };
+
+        assert_eq!(
+            construct_tx_core(vec![], vec![], None),
+            Err(TxStructureError::NoOutputs)
+        );
+        assert_eq!(
+            construct_tx_core(vec![], vec![tx_out.clone()], None),
+            Err(TxStructureError::NoInputs)
+        );
+        assert_eq!(
+            construct_tx_core(generate_unsigned_tx_ins(), vec![], Some(fee())),
+            Err(TxStructureError::NoOutputs)
+        );
+        assert!(construct_tx_core(generate_unsigned_tx_ins(), vec![tx_out.clone()], None).is_ok());
+
+        let druid_info = DdeValues {
+            druid: test_druid(),
+            participants: 2,
+            expectations: vec![],
+            genesis_hash: None,
+        };
+        let dde_tx = |tx_outs| {
+            construct_dde_tx(
+                druid_info.clone(),
+                generate_unsigned_tx_ins(),
+                tx_outs,
+                Some(fee()),
+                &BTreeMap::new(),
+            )
+        };
+        assert_eq!(dde_tx(vec![]), Err(TxStructureError::NoOutputs));
+        assert!(dde_tx(vec![tx_out]).is_ok());
+
+        // Create transactions carry their create script as their only input
+        let (pk, sk) = sign::gen_keypair();
+        let create_tx =
+            construct_item_create_tx(0, 0, pk, &sk, 1, GenesisTxHashSpec::Create, None, None)
+                .unwrap();
+        assert!(create_tx.inputs[0].previous_out.is_none());
+        assert_eq!(create_tx.validate_structure(), Ok(()));
+    }
+
+    #[test]
+    /// Checks that item create transactions are rejected before construction
+    /// when they would fail validation
+    fn test_construct_item_create_tx_errors() {
+        let (pk, sk) = sign::gen_keypair();
+        let create = |amount, spec, metadata| {
+            construct_item_create_tx(0, 0, pk, &sk, amount, spec, None, metadata)
+        };
+
+        assert_eq!(
+            create(0, GenesisTxHashSpec::Create, None),
+            Err(CreateTxError::InvalidAmount)
+        );
+        assert_eq!(
+            create(1, GenesisTxHashSpec::Existing("genesis".to_owned()), None),
+            Err(CreateTxError::GenesisHashConflict("genesis".to_owned()))
+        );
+        assert_eq!(
+            create(1, GenesisTxHashSpec::Create, Some("a".repeat(801))),
+            Err(CreateTxError::InvalidMetadata(MetadataError::TooLarge(801)))
+        );
+
+        let genesis_hash = construct_tx_hash(&Transaction::new());
+        let tx = create(1, GenesisTxHashSpec::Existing(genesis_hash.clone()), None).unwrap();
+        assert_eq!(
+            tx.outputs[0].value,
+            Asset::item(1, Some(genesis_hash), None)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "validate-metadata")]
+    /// Checks that plaintext item metadata must be JSON to construct a create transaction,
+    /// unlike committed metadata, while validation still accepts any metadata
+    fn test_construct_item_create_tx_metadata_json() {
+        let (pk, sk) = sign::gen_keypair();
+        let create = |metadata: &str| {
+            construct_item_create_tx(
+                0,
+                0,
+                pk,
+                &sk,
+                1,
+                GenesisTxHashSpec::Create,
+                None,
+                Some(metadata.to_owned()),
+            )
+        };
+
+        assert!(matches!(
+            create("{\"name\": "),
+            Err(CreateTxError::InvalidMetadata(MetadataError::InvalidJson(
+                _
+            )))
+        ));
+        assert!(create("{\"name\": \"item\"}").is_ok());
+        let validate = crate::utils::script_utils::validate_item_metadata;
+        assert_eq!(validate(Some("{\"name\": ")), Ok(()));
+
+        let tx = construct_item_create_tx_committed(
+            0,
+            0,
+            pk,
+            &sk,
+            1,
+            GenesisTxHashSpec::Create,
+            None,
+            b"not json",
+            &[0; 32],
+        );
+        assert!(tx.is_ok());
+    }
 
     #[test]
     // Creates a valid payment transaction
@@ -801,6 +2306,66 @@ mod tests {
         (tx_ins, drs_block_hash, key_material)
     }
 
+    #[test]
+    /// Checks that transactions paying to or spending from a key involve it
+    fn test_transaction_involves_key() {
+        let (pk, sk) = sign::gen_keypair();
+        let (other_pk, other_sk) = sign::gen_keypair();
+        let out_point = OutPoint::new("t_hash".to_string(), 0);
+        let tx_in = TxIn {
+            previous_out: Some(out_point.clone()),
+            script_signature: Script::new(),
+        };
+        let receiver = |pk| ReceiverInfo {
+            address: construct_address(pk),
+            asset: Asset::token_u64(1),
+        };
+        let p2pkh_keys = |pk, sk| BTreeMap::from([(out_point.clone(), (pk, sk))]);
+
+        let pays_to_key = construct_payment_tx(
+            vec![tx_in.clone()],
+            receiver(&pk),
+            None,
+            0,
+            &p2pkh_keys(other_pk, other_sk.clone()),
+        )
+        .unwrap();
+        let spends_from_key = construct_payment_tx(
+            vec![tx_in.clone()],
+            receiver(&other_pk),
+            None,
+            0,
+            &p2pkh_keys(pk, sk.clone()),
+        )
+        .unwrap();
+        let multisig_spend = Transaction {
+            inputs: vec![TxIn {
+                previous_out: Some(out_point.clone()),
+                script_signature: Script::multisig_validation(
+                    1,
+                    2,
+                    "t_hash".to_string(),
+                    vec![sign::sign_detached(b"t_hash", &sk)],
+                    vec![other_pk, pk],
+                ),
+            }],
+            ..Default::default()
+        };
+        let unrelated = construct_payment_tx(
+            vec![tx_in],
+            receiver(&other_pk),
+            None,
+            0,
+            &p2pkh_keys(other_pk, other_sk),
+        )
+        .unwrap();
+
+        assert!(transaction_involves_key(&pays_to_key, &pk));
+        assert!(transaction_involves_key(&spends_from_key, &pk));
+        assert!(transaction_involves_key(&multisig_spend, &pk));
+        assert!(!transaction_involves_key(&unrelated, &pk));
+    }
+
     #[test]
     fn test_construct_a_valid_p2sh_tx() {
         let token_amount = TokenAmount(400000);
@@ -816,7 +2381,8 @@ mod tests {
             Asset::Token(token_amount),
             0,
             &key_material,
-        );
+        )
+        .unwrap();
 
         let spending_tx_hash = construct_tx_hash(&p2sh_tx);
 
@@ -837,18 +2403,240 @@ mod tests {
             None,
             0,
             &key_material,
-        );
+        )
+        .unwrap();
         let p2sh_script_pub_key = p2sh_tx.outputs[0].script_public_key.as_ref().unwrap();
 
-        assert_eq!(Asset::Token(token_amount), p2sh_tx.outputs[0].value);
-        assert_eq!(p2sh_script_pub_key.as_bytes()[0], P2SH_PREPEND);
-        assert_eq!(p2sh_script_pub_key.len(), STANDARD_ADDRESS_LENGTH);
-        assert!(tx_has_valid_p2sh_script(
-            &redeeming_tx.inputs[0].script_signature,
-            p2sh_tx.outputs[0].script_public_key.as_ref().unwrap()
-        ));
+        assert_eq!(Asset::Token(token_amount), p2sh_tx.outputs[0].value);
+        assert_eq!(p2sh_script_pub_key.as_bytes()[0], P2SH_PREPEND);
+        assert_eq!(p2sh_script_pub_key.len(), STANDARD_ADDRESS_LENGTH);
+        assert_eq!(
+            construct_p2sh_address_at_version(&script, p2sh_tx.version).as_ref(),
+            Ok(p2sh_script_pub_key)
+        );
+        assert!(tx_has_valid_p2sh_script(
+            &redeeming_tx.inputs[0].script_signature,
+            p2sh_tx.outputs[0].script_public_key.as_ref().unwrap()
+        ));
+
+        // Outputs funded before `P2SH_WIRE_ADDRESS_VERSION` stay spendable from it
+        let spent = UtxoMap::from([(
+            redeeming_tx.inputs[0].previous_out.clone().unwrap(),
+            p2sh_tx.outputs[0].clone(),
+        )]);
+        for version in [p2sh_tx.version, P2SH_WIRE_ADDRESS_VERSION] {
+            let mut tx = redeeming_tx.clone();
+            tx.version = version;
+            assert_eq!(tx_is_valid(&tx, 0, |v| spent.get(v)), Ok(()), "{}", version);
+        }
+    }
+
+    #[test]
+    /// Checks that only v2 P2SH addresses limit scripts to `MAX_P2SH_SCRIPT_SIZE` bytes of
+    /// wire encoding, so v1 addresses of scripts with a larger bincode encoding, such as
+    /// a standard 3-of-5 multisig, stay payable and spendable
+    fn test_p2sh_script_size_limit() {
+        let script_of_size = |size: usize| {
+            let padded = |len: usize| {
+                Script::from(vec![
+                    StackEntry::Num(1),
+                    StackEntry::Bytes("0".repeat(len)),
+                    StackEntry::Op(OpCodes::OP_DROP),
+                ])
+            };
+            (0..size)
+                .map(padded)
+                .find(|script| script.to_bytes().len() == size)
+                .unwrap()
+        };
+        let context = crate::script::lang::ExecutionContext::default();
+        let spendable = |script: &Script, address: &str, version| {
+            tx_has_valid_p2sh_script_in_context(script, address, version, &context)
+        };
+
+        let check_data = construct_tx_hash(&Transaction::new());
+        let keys: Vec<_> = (0..5).map(|_| sign::gen_keypair()).collect();
+        let sigs = keys[..3]
+            .iter()
+            .map(|(_, sk)| sign::sign_detached(check_data.as_bytes(), sk))
+            .collect();
+        let pks = keys.iter().map(|(pk, _)| *pk).collect();
+        let multisig = Script::multisig_validation(3, 5, check_data, sigs, pks);
+        assert_eq!(serialize(&multisig).unwrap().len(), 532);
+        assert!(multisig.to_bytes().len() <= MAX_P2SH_SCRIPT_SIZE);
+        let v1 = construct_p2sh_address(&multisig);
+        assert!(tx_has_valid_p2sh_script(&multisig, &v1));
+        assert!(spendable(&multisig, &v1, P2SH_WIRE_ADDRESS_VERSION));
+        let v2 = construct_p2sh_address_v2(&multisig).unwrap();
+        assert!(spendable(&multisig, &v2, P2SH_WIRE_ADDRESS_VERSION));
+
+        let at_limit = script_of_size(MAX_P2SH_SCRIPT_SIZE);
+        let address = construct_p2sh_address_v2(&at_limit).unwrap();
+        assert!(spendable(&at_limit, &address, P2SH_WIRE_ADDRESS_VERSION));
+
+        let over_limit = script_of_size(MAX_P2SH_SCRIPT_SIZE + 1);
+        let too_large = P2SHError::ScriptTooLarge(MAX_P2SH_SCRIPT_SIZE + 1);
+        assert_eq!(
+            construct_p2sh_address_v2(&over_limit),
+            Err(too_large.clone())
+        );
+        assert_eq!(
+            construct_p2sh_address_at_version(&over_limit, P2SH_WIRE_ADDRESS_VERSION),
+            Err(too_large)
+        );
+        let v1 = construct_p2sh_address(&over_limit);
+        assert!(spendable(&over_limit, &v1, P2SH_WIRE_ADDRESS_VERSION));
+    }
+
+    #[test]
+    /// Checks that v2 P2SH addresses are stable for equal scripts, distinct from v1 ones,
+    /// and only paid to or spent from `P2SH_WIRE_ADDRESS_VERSION`, while v1 addresses
+    /// stay spendable
+    fn test_p2sh_address_v2() {
+        let (pk, _) = sign::gen_keypair();
+        let script = |pk| {
+            Script::from(vec![
+                StackEntry::PubKey(pk),
+                StackEntry::Num(3),
+                StackEntry::Bytes("00ff".to_owned()),
+                StackEntry::Op(OpCodes::OP_2DROP),
+                StackEntry::Op(OpCodes::OP_DROP),
+                StackEntry::Op(OpCodes::OP_1),
+            ])
+        };
+        let v1 = construct_p2sh_address(&script(pk));
+        let v2 = construct_p2sh_address_v2(&script(pk)).unwrap();
+
+        assert_eq!(construct_p2sh_address_v2(&script(pk)), Ok(v2.clone()));
+        assert_eq!(address_type(&v2), AddressKind::P2SH);
+        assert_eq!(v2.len(), STANDARD_ADDRESS_LENGTH);
+        assert_ne!(v1, v2);
+        assert_eq!(
+            construct_p2sh_address_v2(&Script::from(vec![StackEntry::Op(OpCodes::OP_1)])),
+            Ok("H34efde1638477840b624a03a01a211a1ddca51ca5b083d157fa0cd2993edcfb".to_owned())
+        );
+
+        let before = P2SH_WIRE_ADDRESS_VERSION - 1;
+        let from = P2SH_WIRE_ADDRESS_VERSION;
+        assert_eq!(
+            construct_p2sh_address_at_version(&script(pk), before),
+            Ok(v1.clone())
+        );
+        assert_eq!(
+            construct_p2sh_address_at_version(&script(pk), from),
+            Ok(v2.clone())
+        );
+
+        let context = crate::script::lang::ExecutionContext::default();
+        let valid = |address: &str, version| {
+            tx_has_valid_p2sh_script_in_context(&script(pk), address, version, &context)
+        };
+        assert!(valid(&v1, before) && !valid(&v2, before));
+        assert!(valid(&v1, from) && valid(&v2, from));
+        assert!(is_p2sh_address_of(&script(pk), &v1, from));
+
+        let oversized = Script::from(vec![StackEntry::Bytes("0".repeat(MAX_P2SH_SCRIPT_SIZE))]);
+        let wire_len = oversized.to_bytes().len();
+        assert_eq!(
+            construct_p2sh_address_v2(&oversized),
+            Err(P2SHError::ScriptTooLarge(wire_len))
+        );
+    }
+
+    #[test]
+    /// Checks the escrow flow: release by both parties at any time, and settlement by
+    /// the arbiter with either party once the timeout block is reached
+    fn test_escrow_spend() {
+        let (buyer_pk, buyer_sk) = sign::gen_keypair();
+        let (seller_pk, seller_sk) = sign::gen_keypair();
+        let (arbiter_pk, arbiter_sk) = sign::gen_keypair();
+        let (_, other_sk) = sign::gen_keypair();
+        let timeout_block = 100;
+
+        // All parties derive the same address from the agreed terms
+        let escrow = Script::new_escrow(buyer_pk, seller_pk, arbiter_pk, timeout_block);
+        let address = construct_p2sh_address(&escrow);
+        assert_eq!(
+            construct_escrow_address(buyer_pk, seller_pk, arbiter_pk, timeout_block),
+            Ok(address.clone())
+        );
+        assert_ne!(
+            construct_escrow_address(seller_pk, buyer_pk, arbiter_pk, timeout_block),
+            Ok(address.clone())
+        );
+        assert_ne!(
+            construct_escrow_address(buyer_pk, seller_pk, arbiter_pk, timeout_block + 1),
+            Ok(address.clone())
+        );
+
+        // The buyer funds the escrow
+        let (tx_ins, _drs_block_hash, key_material) = test_construct_valid_inputs(None);
+        let funding =
+            construct_escrow_tx(tx_ins, None, &escrow, Asset::token_u64(10), &key_material)
+                .unwrap();
+        assert_eq!(funding.outputs[0].script_public_key, Some(address));
+
+        let escrow_out = OutPoint::new(construct_tx_hash(&funding), 0);
+        let utxo = UtxoMap::from([(escrow_out.clone(), funding.outputs[0].clone())]);
+        let tx_outs = vec![TxOut::new_token_amount(
+            hex::encode([1; 32]),
+            TokenAmount(10),
+            None,
+        )];
+
+        let check_data = construct_escrow_check_data(&escrow_out, &tx_outs);
+        let sign = |sk| sign::sign_detached(check_data.as_bytes(), sk);
+        let (buyer_sig, seller_sig) = (sign(&buyer_sk), sign(&seller_sk));
+        let (arbiter_sig, other_sig) = (sign(&arbiter_sk), sign(&other_sk));
+        let settle = |unlock: Script, block: u64| {
+            let tx = construct_escrow_settlement_tx(
+                escrow_out.clone(),
+                &escrow,
+                unlock,
+                tx_outs.clone(),
+                None,
+            )
+            .unwrap();
+            tx_is_valid(&tx, block, |v| utxo.get(v))
+        };
+        let release = |b, s| Script::escrow_release(check_data.clone(), b, s);
+        let refund = |b, a| Script::escrow_refund_via_arbiter(check_data.clone(), b, a);
+        let claim = |s, a| Script::escrow_seller_claim_via_arbiter(check_data.clone(), s, a);
+
+        let invalid = Err(TxValidationError::Input(0, InputError::InvalidScript));
+        for block in [0, timeout_block - 1, timeout_block] {
+            assert_eq!(settle(release(buyer_sig, seller_sig), block), Ok(()));
+            assert_eq!(settle(release(seller_sig, buyer_sig), block), Ok(()));
+            assert_eq!(settle(release(buyer_sig, other_sig), block), invalid);
+            assert_eq!(settle(release(other_sig, seller_sig), block), invalid);
+        }
+
+        // The arbiter can't settle before the timeout
+        for block in [0, timeout_block - 1] {
+            assert_eq!(settle(refund(buyer_sig, arbiter_sig), block), invalid);
+            assert_eq!(settle(claim(seller_sig, arbiter_sig), block), invalid);
+        }
+
+        for block in [timeout_block, timeout_block + 1] {
+            assert_eq!(settle(refund(buyer_sig, arbiter_sig), block), Ok(()));
+            assert_eq!(settle(claim(seller_sig, arbiter_sig), block), Ok(()));
+            assert_eq!(settle(refund(other_sig, arbiter_sig), block), invalid);
+            assert_eq!(settle(claim(seller_sig, other_sig), block), invalid);
+            assert_eq!(settle(refund(buyer_sig, seller_sig), block), invalid);
+            assert_eq!(settle(claim(arbiter_sig, arbiter_sig), block), invalid);
+        }
 
-        // TODO: Add assertion for full tx validity
+        // Signatures are bound to the settlement outputs
+        let mut tx = construct_escrow_settlement_tx(
+            escrow_out.clone(),
+            &escrow,
+            release(buyer_sig, seller_sig),
+            tx_outs.clone(),
+            None,
+        )
+        .unwrap();
+        tx.outputs[0].script_public_key = Some(hex::encode([2; 32]));
+        assert_eq!(tx_is_valid(&tx, 0, |v| utxo.get(v)), invalid);
     }
 
     #[test]
@@ -857,7 +2645,7 @@ mod tests {
         let (tx_ins, _drs_block_hash, key_material) =
             test_construct_valid_inputs(Some(NETWORK_VERSION_V0));
 
-        let burn_tx = construct_burn_tx(tx_ins, None, &key_material);
+        let burn_tx = construct_burn_tx(tx_ins, None, &key_material).unwrap();
 
         let spending_tx_hash = construct_tx_hash(&burn_tx);
 
@@ -881,7 +2669,8 @@ mod tests {
             None,
             0,
             &key_material,
-        );
+        )
+        .unwrap();
         let burn_script_pub_key = burn_tx.outputs[0].script_public_key.as_ref().unwrap();
         debug!("{:?}", burn_script_pub_key);
 
@@ -909,7 +2698,8 @@ mod tests {
             None,
             0,
             &key_material,
-        );
+        )
+        .unwrap();
         assert_eq!(Asset::Token(token_amount), payment_tx.outputs[0].value);
         assert_eq!(
             payment_tx.outputs[0].script_public_key,
@@ -936,11 +2726,183 @@ mod tests {
             }),
             0,
             &key_material,
-        );
+        )
+        .unwrap();
         assert_eq!(Asset::Token(token_amount), payment_tx.outputs[0].value);
         assert_eq!(Asset::Token(fee_amount), payment_tx.fees[0].value);
     }
 
+    #[test]
+    /// Checks that payments return the excess of their inputs as change
+    fn test_construct_payment_tx_with_change() {
+        let (pk, sk) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let token_out = OutPoint::new("g0".to_owned(), 0);
+        let item_out = OutPoint::new("g1".to_owned(), 0);
+        let utxo = UtxoMap::from([
+            (
+                token_out.clone(),
+                TxOut::new_token_amount(address.clone(), TokenAmount(1000), None),
+            ),
+            (
+                item_out.clone(),
+                TxOut::new_item_amount(
+                    address.clone(),
+                    ItemAsset::new(5, Some("genesis_a".to_owned()), None),
+                    None,
+                ),
+            ),
+        ]);
+        let key_material = BTreeMap::from([
+            (token_out.clone(), (pk, sk.clone())),
+            (item_out.clone(), (pk, sk)),
+        ]);
+        let tx_ins: Vec<TxIn> = vec![token_out, item_out]
+            .into_iter()
+            .map(|out_point| TxIn {
+                previous_out: Some(out_point),
+                script_signature: Script::new(),
+            })
+            .collect();
+        let inputs_total = AssetValues::new(
+            TokenAmount(1000),
+            BTreeMap::from([("genesis_a".to_owned(), 5)]),
+        );
+        let receiver = |asset| ReceiverInfo {
+            address: hex::encode([1; 32]),
+            asset,
+        };
+        let fee = |amount| {
+            Some(ReceiverInfo {
+                address: hex::encode([2; 32]),
+                asset: Asset::token_u64(amount),
+            })
+        };
+        let construct = |asset, fee| {
+            construct_payment_tx_with_change(
+                tx_ins.clone(),
+                inputs_total.clone(),
+                receiver(asset),
+                fee,
+                address.clone(),
+                10,
+                &key_material,
+            )
+        };
+
+        // Paying items with a token fee returns the remaining tokens and items
+        let tx = construct(Asset::item(2, Some("genesis_a".to_owned()), None), fee(100)).unwrap();
+        let change: Vec<_> = tx.outputs[1..]
+            .iter()
+            .map(|out| (&out.value, out.locktime))
+            .collect();
+        assert_eq!(
+            change,
+            vec![
+                (&Asset::token_u64(900), 0),
+                (&Asset::item(3, Some("genesis_a".to_owned()), None), 0)
+            ]
+        );
+        assert_eq!(tx.outputs[0].locktime, 10);
+        assert!(tx.outputs[1..]
+            .iter()
+            .all(|out| out.script_public_key.as_ref() == Some(&address)));
+        assert!(tx_is_valid(&tx, 10, |v| utxo.get(v)).is_ok());
+
+        // Spending everything adds no change output
+        let tx = construct(Asset::token_u64(900), fee(100)).unwrap();
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(
+            tx.outputs[1].value,
+            Asset::item(5, Some("genesis_a".to_owned()), None)
+        );
+
+        // Payments and fees exceeding the inputs fail
+        assert_eq!(
+            construct(Asset::token_u64(950), fee(100)),
+            Err(PaymentTxError::InsufficientFunds)
+        );
+        assert_eq!(
+            construct(Asset::item(6, Some("genesis_a".to_owned()), None), None),
+            Err(PaymentTxError::InsufficientFunds)
+        );
+        assert_eq!(
+            construct(Asset::item(1, Some("genesis_b".to_owned()), None), None),
+            Err(PaymentTxError::InsufficientFunds)
+        );
+    }
+
+    #[test]
+    /// Checks that a consolidation sweeps all inputs into one output, less the fee
+    fn test_construct_consolidation_tx() {
+        let (pk, sk) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let out_points: Vec<_> = (0..5).map(|n| OutPoint::new(format!("g{n}"), 0)).collect();
+        let utxo: UtxoMap = out_points
+            .iter()
+            .map(|op| {
+                let tx_out = TxOut::new_token_amount(address.clone(), TokenAmount(20), None);
+                (op.clone(), tx_out)
+            })
+            .collect();
+        let key_material: BTreeMap<_, _> = out_points
+            .iter()
+            .map(|op| (op.clone(), (pk, sk.clone())))
+            .collect();
+        let tx_ins: Vec<TxIn> = out_points
+            .into_iter()
+            .map(|op| TxIn::new_from_input(op, Script::new()))
+            .collect();
+        let fee = |asset| {
+            Some(ReceiverInfo {
+                address: hex::encode([2; 32]),
+                asset,
+            })
+        };
+        let destination = hex::encode([1; 32]);
+        let construct = |fee| {
+            construct_consolidation_tx(
+                tx_ins.clone(),
+                TokenAmount(100),
+                destination.clone(),
+                fee,
+                &key_material,
+            )
+        };
+
+        let tx = construct(fee(Asset::token_u64(15))).unwrap();
+        assert_eq!(tx.inputs.len(), 5);
+        assert_eq!(
+            tx.outputs,
+            vec![TxOut::new_token_amount(
+                destination.clone(),
+                TokenAmount(85),
+                None
+            )]
+        );
+        assert_eq!(tx.fees[0].value, Asset::token_u64(15));
+        assert!(tx_is_valid(&tx, 0, |v| utxo.get(v)).is_ok());
+
+        let tx = construct(None).unwrap();
+        assert_eq!(tx.outputs[0].value, Asset::token_u64(100));
+        assert!(tx.fees.is_empty());
+        assert!(tx_is_valid(&tx, 0, |v| utxo.get(v)).is_ok());
+
+        // Fees at or beyond the total, or not in tokens, are rejected
+        assert_eq!(
+            construct(fee(Asset::token_u64(100))),
+            Err(PaymentTxError::EmptyOutput)
+        );
+        assert_eq!(
+            construct(fee(Asset::token_u64(101))),
+            Err(PaymentTxError::InsufficientFunds)
+        );
+        assert_eq!(
+            construct(fee(Asset::item(1, Some("genesis_a".to_owned()), None))),
+            Err(PaymentTxError::FeeNotToken)
+        );
+    }
+
     #[test]
     /// Creates a valid payment transaction including fees
     fn test_token_onspend_with_fees() {
@@ -974,18 +2936,17 @@ mod tests {
             }),
             0,
             &key_material,
-        );
+        )
+        .unwrap();
 
         let tx_ins_spent = AssetValues::new(tokens + fees, BTreeMap::new());
 
-        assert!(
-            tx_outs_are_valid(
-                &payment_tx_valid.outputs,
-                &payment_tx_valid.fees,
-                tx_ins_spent
-            )
-            .0
-        );
+        assert!(tx_outs_are_valid(
+            &payment_tx_valid.outputs,
+            &payment_tx_valid.fees,
+            tx_ins_spent
+        )
+        .is_ok());
     }
 
     #[test]
@@ -1007,8 +2968,8 @@ mod tests {
             address_version: Some(2),
         };
 
-        let drs_tx_hash = "item_tx_hash".to_string();
-        let item_asset_valid = ItemAsset::new(1000, Some(drs_tx_hash.clone()), None);
+        let genesis_hash = "item_tx_hash".to_string();
+        let item_asset_valid = ItemAsset::new(1000, Some(genesis_hash.clone()), None);
 
         let tx_ins = construct_payment_tx_ins(vec![tx_const]);
         let payment_tx_valid = construct_payment_tx(
@@ -1023,20 +2984,19 @@ mod tests {
             }),
             0,
             &key_material,
-        );
+        )
+        .unwrap();
 
         let mut btree = BTreeMap::new();
-        btree.insert(drs_tx_hash, 1000);
+        btree.insert(genesis_hash, 1000);
         let tx_ins_spent = AssetValues::new(fees, btree);
 
-        assert!(
-            tx_outs_are_valid(
-                &payment_tx_valid.outputs,
-                &payment_tx_valid.fees,
-                tx_ins_spent
-            )
-            .0
-        );
+        assert!(tx_outs_are_valid(
+            &payment_tx_valid.outputs,
+            &payment_tx_valid.fees,
+            tx_ins_spent
+        )
+        .is_ok());
     }
 
     #[test]
@@ -1070,13 +3030,14 @@ mod tests {
             None,
             0,
             &key_material,
-        );
+        )
+        .unwrap();
 
         let mut btree = BTreeMap::new();
         btree.insert(genesis_hash, 1000);
         let tx_ins_spent = AssetValues::new(TokenAmount(0), btree);
 
-        assert!(tx_outs_are_valid(&payment_tx_valid.outputs, &[], tx_ins_spent).0);
+        assert!(tx_outs_are_valid(&payment_tx_valid.outputs, &[], tx_ins_spent).is_ok());
     }
 
     #[test]
@@ -1125,7 +3086,8 @@ mod tests {
             None,
             0,
             &key_material,
-        );
+        )
+        .unwrap();
         let tx_1_hash = construct_tx_hash(&payment_tx_1);
         let tx_1_out_p = OutPoint::new(tx_1_hash.clone(), 0);
         key_material.insert(tx_1_out_p.clone(), (pk, sk));
@@ -1143,7 +3105,7 @@ mod tests {
             token_amount,
             None,
         )];
-        let payment_tx_2 = construct_tx_core(tx_ins_2, tx_outs, None);
+        let payment_tx_2 = construct_tx_core(tx_ins_2, tx_outs, None).unwrap();
 
         let tx_2_hash = construct_tx_hash(&payment_tx_2);
         let tx_2_out_p = OutPoint::new(tx_2_hash, 0);
@@ -1155,11 +3117,39 @@ mod tests {
 
         update_utxo_set(&mut btree);
 
-        // Check that only one entry remains
+        // Only the output spent within the same set is removed
         assert_eq!(btree.len(), 1);
         assert_ne!(btree.get(&tx_2_out_p), None);
     }
 
+    #[test]
+    // Ensures transactions without inputs, or without previous outpoints, remove nothing
+    fn test_update_utxo_set_no_inputs() {
+        let mut no_inputs_tx = Transaction::new();
+        no_inputs_tx.outputs.push(TxOut::new_token_amount(
+            hex::encode(vec![0; 32]),
+            TokenAmount(1),
+            None,
+        ));
+        let mut coinbase_tx = no_inputs_tx.clone();
+        coinbase_tx
+            .inputs
+            .push(TxIn::new_from_script(Script::new_for_coinbase(1)));
+
+        let no_inputs_out_p = OutPoint::new(construct_tx_hash(&no_inputs_tx), 0);
+        let coinbase_out_p = OutPoint::new(construct_tx_hash(&coinbase_tx), 0);
+
+        let mut btree = BTreeMap::new();
+        btree.insert(no_inputs_out_p.clone(), no_inputs_tx);
+        btree.insert(coinbase_out_p.clone(), coinbase_tx);
+
+        update_utxo_set(&mut btree);
+
+        assert_eq!(btree.len(), 2);
+        assert!(btree.contains_key(&no_inputs_out_p));
+        assert!(btree.contains_key(&coinbase_out_p));
+    }
+
     #[test]
     // Creates a valid DDE transaction
     fn test_construct_a_valid_dde_tx() {
@@ -1208,10 +3198,7 @@ mod tests {
             ..Default::default()
         }];
 
-        let bytes = match serialize(&tx_ins) {
-            Ok(bytes) => bytes,
-            Err(_) => vec![],
-        };
+        let bytes = serialize(&tx_ins).unwrap_or_default();
         let from_addr = hex::encode(bytes);
 
         // DDE params
@@ -1230,11 +3217,11 @@ mod tests {
             expectations: expects.clone(),
             genesis_hash: None,
         };
-        let dde = construct_dde_tx(druid_info, tx_ins, tx_outs, None, &key_material);
+        let dde = construct_dde_tx(druid_info, tx_ins, tx_outs, None, &key_material).unwrap();
 
-        assert_eq!(dde.druid_info.clone().unwrap().druid, druid);
+        assert_eq!(dde.druid(), Some(druid.as_str()));
         assert_eq!(dde.outputs[0].clone().value, data);
-        assert_eq!(dde.druid_info.unwrap().participants, participants);
+        assert_eq!(dde.participants(), Some(participants));
     }
 
     #[test]
@@ -1244,9 +3231,9 @@ mod tests {
         //
         let amount = TokenAmount(33);
         let payment = TokenAmount(11);
-        let druid = "VALUE".to_owned();
+        let druid = test_druid();
 
-        let tx_input = construct_payment_tx_ins(vec![]);
+        let tx_input = generate_unsigned_tx_ins();
         let from_addr = construct_tx_ins_address(&tx_input);
 
         let alice_addr = "1111".to_owned();
@@ -1262,7 +3249,7 @@ mod tests {
         let send_tx = {
             let tx_ins = {
                 // constructors with enough money for amount and excess, caller responsibility.
-                construct_payment_tx_ins(vec![])
+                generate_unsigned_tx_ins()
             };
             key_material.insert(OutPoint::new("".to_string(), 0), (pk, sk));
 
@@ -1291,7 +3278,8 @@ mod tests {
                     genesis_hash: None,
                 },
                 &key_material,
-            );
+            )
+            .unwrap();
 
             tx.outputs.push(excess_tx_out);
 
@@ -1301,8 +3289,7 @@ mod tests {
         let recv_tx = {
             let tx_ins = {
                 // constructors with enough money for amount and excess, caller responsibility.
-                let tx_ins_constructor = vec![];
-                construct_payment_tx_ins(tx_ins_constructor)
+                generate_unsigned_tx_ins()
             };
             let expectation = DruidExpectation {
                 from: from_addr,
@@ -1327,22 +3314,42 @@ mod tests {
                 druid_info,
                 &key_material,
             )
+            .unwrap()
         };
 
         // Assert
+        assert_eq!(send_tx.druid(), Some(druid.as_str()));
+        assert_eq!(send_tx.participants(), Some(2));
+        assert_eq!(recv_tx.druid(), Some(druid.as_str()));
+        assert_eq!(recv_tx.participants(), Some(2));
+    }
+
+    #[test]
+    // Ensures the raw hashing variants match their String-returning counterparts
+    fn test_raw_hashes_match_string_hashes() {
+        for i in 0..1000 {
+            let n = if i % 2 == 0 { i } else { -i };
+            let out_point = OutPoint::new(construct_tx_hash(&Transaction::new()), n);
+            let (pk, _) = sign::gen_keypair();
+
+            assert_eq!(
+                hex::encode(construct_tx_in_signable_hash_raw(&out_point)),
+                hex::encode(sha3_256::digest(
+                    get_out_point_signable_string(&out_point).as_bytes()
+                ))
+            );
+            assert_eq!(
+                hex::encode(construct_address_raw(&pk)),
+                hex::encode(sha3_256::digest(pk.as_ref()))
+            );
+        }
+
+        let extreme = OutPoint::new("t_hash".to_owned(), i32::MIN);
         assert_eq!(
-            send_tx
-                .druid_info
-                .as_ref()
-                .map(|v| (&v.druid, v.participants)),
-            Some((&druid, 2))
-        );
-        assert_eq!(
-            recv_tx
-                .druid_info
-                .as_ref()
-                .map(|v| (&v.druid, v.participants)),
-            Some((&druid, 2))
+            hex::encode(construct_tx_in_signable_hash_raw(&extreme)),
+            hex::encode(sha3_256::digest(
+                get_out_point_signable_string(&extreme).as_bytes()
+            ))
         );
     }
 
@@ -1375,7 +3382,7 @@ mod tests {
         ]
         .iter()
         .map(|v| hex::decode(v).unwrap())
-        .map(|v| PublicKey::from_slice(&v).unwrap())
+        .map(|v| PublicKey::try_from(v.as_slice()).unwrap())
         .collect::<Vec<PublicKey>>();
 
         //
@@ -1418,7 +3425,7 @@ mod tests {
         //
         // Arrange
         //
-        let out_points = vec![
+        let out_points = [
             OutPoint::new("000000".to_owned(), 0),
             OutPoint::new("000001".to_owned(), 0),
             OutPoint::new("000002".to_owned(), 0),
@@ -1450,7 +3457,7 @@ mod tests {
         //
         // Arrange
         //
-        let assets = vec![Asset::token_u64(1), Asset::item(1, None, None)];
+        let assets = [Asset::token_u64(1), Asset::item(1, None, None)];
 
         //
         // Act
@@ -1471,6 +3478,448 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    /// Checks that items created from an outpoint get a genesis hash unique to it
+    fn test_genesis_hash_from_out_point() {
+        let (pk, sk) = sign::gen_keypair();
+        let tx_hash = construct_tx_hash(&Transaction::new());
+        let genesis_hash = |n| {
+            let spec = GenesisTxHashSpec::FromOutPoint(OutPoint::new(tx_hash.clone(), n));
+            let tx = construct_item_create_tx(0, 0, pk, &sk, 1, spec, None, None).unwrap();
+            tx.outputs[0].value.genesis_hash().map(str::to_owned)
+        };
+
+        assert_eq!(
+            genesis_hash(0),
+            Some(construct_tx_in_signable_hash(&OutPoint::new(
+                tx_hash.clone(),
+                0
+            )))
+        );
+        assert_eq!(genesis_hash(0), genesis_hash(0));
+        assert_ne!(genesis_hash(0), genesis_hash(1));
+    }
+
+    #[test]
+    /// Checks batch address derivation matches deriving addresses one by one
+    fn test_derive_addresses() {
+        let master = HdKey::from_seed(b"test seed");
+
+        assert_eq!(
+            derive_addresses(&master, 0, 3),
+            (0..3)
+                .map(|index| derive_address(&master, index).unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            derive_addresses(&master, 0, 3)[0],
+            construct_address(&master.derive_child(0).unwrap().public_key())
+        );
+        assert_eq!(
+            derive_addresses(&master, 2, 1),
+            derive_addresses(&master, 0, 3)[2..]
+        );
+        assert_ne!(
+            derive_addresses(&master, 0, 3),
+            derive_addresses(&HdKey::from_seed(b"other seed"), 0, 3)
+        );
+        assert!(derive_addresses(&master, 5, 0).is_empty());
+
+        // Indices stop at the end of the hardened range
+        assert_eq!(derive_address(&master, HARDENED_OFFSET), None);
+        assert_eq!(derive_addresses(&master, HARDENED_OFFSET - 2, 5).len(), 2);
+        assert!(derive_addresses(&master, u32::MAX, 5).is_empty());
+    }
+
+    #[test]
+    /// Checks that merging UTXO maps only fails on conflicting entries
+    fn test_merge_utxo() {
+        let out_point = |n| OutPoint::new(construct_tx_hash(&Transaction::new()), n);
+        let tx_out =
+            |amount| TxOut::new_token_amount("address".to_owned(), TokenAmount(amount), None);
+        let snapshot = UtxoMap::from([(out_point(0), tx_out(1)), (out_point(1), tx_out(2))]);
+
+        // Clean merge
+        let mut merged = snapshot.clone();
+        let recent = UtxoMap::from([(out_point(2), tx_out(3))]);
+        assert_eq!(merge_utxo(&mut merged, recent), Ok(()));
+        assert_eq!(merged.len(), 3);
+
+        // Identical entries
+        let mut merged = snapshot.clone();
+        assert_eq!(merge_utxo(&mut merged, snapshot.clone()), Ok(()));
+        assert_eq!(merged, snapshot);
+
+        // Conflicting entries
+        let mut merged = snapshot.clone();
+        let conflicting = UtxoMap::from([(out_point(2), tx_out(3)), (out_point(1), tx_out(4))]);
+        let conflict = merge_utxo(&mut merged, conflicting).unwrap_err();
+        assert_eq!(conflict.out_point, out_point(1));
+        assert!(conflict.to_string().contains(&out_point(1).to_string()));
+        assert_eq!(merged, snapshot);
+    }
+
+    #[test]
+    /// Checks that selection doesn't depend on the order of the candidates
+    fn test_select_utxos_deterministic() {
+        let tx_out = |value| TxOut::new_asset("address".to_owned(), value, None);
+        let item = |amount| Asset::item(amount, Some("g9".to_owned()), None);
+        let candidates: Vec<_> = [5, 3, 5, 8, 3, 5, 1]
+            .iter()
+            .enumerate()
+            .map(|(n, amount)| (format!("g{n}"), Asset::token_u64(*amount)))
+            .chain([("g9".to_owned(), item(9))])
+            .map(|(t_hash, value)| (OutPoint::new(t_hash, 0), tx_out(value)))
+            .collect();
+        let target = Asset::token_u64(15);
+
+        // Orders a HashMap might iterate in
+        let mut reversed = candidates.clone();
+        reversed.reverse();
+        let mut rotated = candidates.clone();
+        rotated.rotate_left(3);
+        let selections: Vec<Vec<u8>> = [&candidates, &reversed, &rotated]
+            .iter()
+            .map(|entries| {
+                let selection = select_utxos(entries.iter().map(|(op, out)| (op, out)), &target);
+                serialize(&selection.unwrap()).unwrap()
+            })
+            .collect();
+        assert!(selections.iter().all(|s| s == &selections[0]));
+
+        // Largest first, ties going to the lowest outpoint
+        let utxo: UtxoMap = candidates.into_iter().collect();
+        let (selected, total) = select_utxos(&utxo, &target).unwrap();
+        let t_hashes: Vec<_> = selected.iter().map(|op| op.t_hash.as_str()).collect();
+        assert_eq!(t_hashes, vec!["g3", "g0", "g2"]);
+        assert_eq!(total, AssetValues::token_u64(18));
+
+        let (selected, _) = select_utxos(&utxo, &item(9)).unwrap();
+        assert_eq!(selected, vec![OutPoint::new("g9".to_owned(), 0)]);
+        assert_eq!(
+            select_utxos(&utxo, &item(10)),
+            Err(PaymentTxError::InsufficientFunds)
+        );
+        assert_eq!(
+            select_utxos(&utxo, &Asset::token_u64(31)),
+            Err(PaymentTxError::InsufficientFunds)
+        );
+    }
+
+    #[test]
+    /// Checks size estimates are within 10% of the serialized size of real transactions
+    fn test_estimate_tx_size() {
+        let keys: Vec<_> = (0..3).map(|_| sign::gen_keypair()).collect();
+        let pub_keys: Vec<PublicKey> = keys.iter().map(|(pk, _)| *pk).collect();
+        let address = construct_address(&keys[0].0);
+        let out_points: Vec<_> = (0..4)
+            .map(|n| OutPoint::new(construct_tx_hash(&Transaction::new()), n))
+            .collect();
+        let outputs = |address: &str, count| {
+            (0..count)
+                .map(|_| TxOut::new_token_amount(address.to_owned(), TokenAmount(1000), None))
+                .collect::<Vec<_>>()
+        };
+
+        let multisig = {
+            let check_data = construct_tx_hash(&Transaction::new());
+            let signatures = keys[..2]
+                .iter()
+                .map(|(_, sk)| sign::sign_detached(check_data.as_bytes(), sk))
+                .collect();
+            Script::multisig_validation(2, 3, check_data, signatures, pub_keys.clone())
+        };
+        let multisig_tx = |num_inputs: usize, address: &str, num_outputs| Transaction {
+            inputs: out_points[..num_inputs]
+                .iter()
+                .flat_map(|out_point| {
+                    let tx_const = TxConstructor {
+                        previous_out: out_point.clone(),
+                        signatures: vec![],
+                        pub_keys: vec![],
+                        address_version: None,
+                    };
+                    construct_p2sh_redeem_tx_ins(tx_const, multisig.clone())
+                })
+                .collect(),
+            outputs: outputs(address, num_outputs),
+            ..Default::default()
+        };
+
+        let p2pkh_tx = |num_inputs: usize, num_outputs| {
+            let key_material = out_points[..num_inputs]
+                .iter()
+                .map(|out_point| (out_point.clone(), keys[0].clone()))
+                .collect();
+            let tx_outs = outputs(&address, num_outputs);
+            let tx_ins = construct_payment_tx_ins(
+                out_points[..num_inputs]
+                    .iter()
+                    .map(|out_point| TxConstructor {
+                        previous_out: out_point.clone(),
+                        signatures: vec![],
+                        pub_keys: vec![],
+                        address_version: None,
+                    })
+                    .collect(),
+            );
+            Transaction {
+                inputs: update_input_signatures(&tx_ins, &tx_outs, &key_material),
+                outputs: tx_outs,
+                ..Default::default()
+            }
+        };
+        let create_tx = construct_item_create_tx(
+            0,
+            0,
+            keys[0].0,
+            &keys[0].1,
+            1,
+            GenesisTxHashSpec::Create,
+            None,
+            None,
+        )
+        .unwrap();
+        let p2sh_address = construct_p2sh_address(&multisig);
+
+        let cases = vec![
+            (ScriptType::P2PKH, p2pkh_tx(1, 1), 1, 1),
+            (ScriptType::P2PKH, p2pkh_tx(4, 2), 4, 2),
+            (ScriptType::P2SH, multisig_tx(1, &address, 1), 1, 1),
+            (ScriptType::P2SH, multisig_tx(2, &address, 3), 2, 3),
+            (
+                ScriptType::MultisigValidation,
+                multisig_tx(3, &p2sh_address, 2),
+                3,
+                2,
+            ),
+            (ScriptType::Create, create_tx, 1, 1),
+        ];
+        for (script_type, tx, num_inputs, num_outputs) in cases {
+            let actual = serialize(&tx).unwrap().len();
+            let estimate = estimate_tx_size(num_inputs, script_type, num_outputs);
+            assert!(
+                estimate.abs_diff(actual) * 10 <= actual,
+                "{:?}: estimated {} bytes, serialized {}",
+                script_type,
+                estimate,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    /// Checks fees scale with the estimated size and round up
+    fn test_estimate_fee() {
+        let size = estimate_tx_size(2, ScriptType::P2PKH, 2);
+        assert_eq!(
+            estimate_fee(2, ScriptType::P2PKH, 2, 1.0),
+            TokenAmount(size as u64)
+        );
+        assert_eq!(
+            estimate_fee(2, ScriptType::P2PKH, 2, 0.5),
+            TokenAmount((size as u64).div_ceil(2))
+        );
+        assert_eq!(
+            estimate_fee(2, ScriptType::P2PKH, 2, 10.0),
+            TokenAmount(size as u64 * 10)
+        );
+        assert!(
+            estimate_fee(1, ScriptType::Create, 1, 1.0) < estimate_fee(1, ScriptType::P2SH, 1, 1.0)
+        );
+        assert_eq!(estimate_fee(1, ScriptType::P2PKH, 1, 0.0), TokenAmount(0));
+        assert_eq!(estimate_fee(1, ScriptType::P2PKH, 1, -1.0), TokenAmount(0));
+        assert_eq!(
+            estimate_fee(1, ScriptType::P2PKH, 1, f64::NAN),
+            TokenAmount(0)
+        );
+    }
+
+    #[test]
+    // Pins the bytes of each signable message; changing them breaks existing signatures
+    fn test_signable_message_bytes() {
+        //
+        // Arrange
+        //
+        let out_point = OutPoint::new("000000".to_owned(), 0);
+        let tx_in = TxIn::new_from_input(out_point.clone(), Script::new());
+        let tx_outs = vec![TxOut::new_token_amount(
+            "address".to_owned(),
+            TokenAmount(1),
+            None,
+        )];
+        let asset = Asset::token_u64(1);
+        let (pk, sk) = sign::gen_keypair();
+
+        //
+        // Act
+        //
+        let messages = [
+            SignableMessage::out_point(&out_point),
+            SignableMessage::asset(&asset),
+            SignableMessage::tx_in(&tx_in, &tx_outs),
+        ];
+        let actual: Vec<(String, String)> = messages
+            .iter()
+            .map(|m| (String::from_utf8(m.preimage()).unwrap(), m.hash()))
+            .collect();
+
+        //
+        // Assert
+        //
+        let expected = vec![
+            (
+                "0-000000".to_owned(),
+                "927b3411743452e5e0d73e9e40a4fa3c842b3d00dabde7f9af7e44661ce02c88".to_owned(),
+            ),
+            (
+                "Token:1".to_owned(),
+                "a5b2f5e8dcf824aee45b81294ff8049b680285b976cc6c8fa45eb070acfc5974".to_owned(),
+            ),
+            (
+                concat!(
+                    r#"{"value":{"Token":1},"locktime":0,"script_public_key":"address"}"#,
+                    r#"{"t_hash":"000000","n":0}"#
+                )
+                .to_owned(),
+                "8a688d158bbfc523c3efa64430b58799c38db2424b1948f8b162133491e50523".to_owned(),
+            ),
+        ];
+        assert_eq!(actual, expected);
+        assert_eq!(
+            messages[2].hash(),
+            construct_tx_in_out_signable_hash(&tx_in, &tx_outs)
+        );
+        for message in &messages {
+            let signature = message.sign(&sk);
+            assert!(message.verify(&signature, &pk));
+            assert!(sign::verify_detached(
+                &signature,
+                message.hash().as_bytes(),
+                &pk
+            ));
+        }
+        assert!(!messages[0].verify(&messages[1].sign(&sk), &pk));
+    }
+
+    #[test]
+    /// Checks that signable hashes commit to the transaction version from
+    /// `SIGNABLE_VERSION_COMMITMENT_VERSION`, and keep their bytes before it
+    fn test_signable_message_versions() {
+        let out_point = OutPoint::new("000000".to_owned(), 0);
+        let tx_in = TxIn::new_from_input(out_point.clone(), Script::new());
+        let tx_outs = vec![TxOut::new_token_amount(
+            "address".to_owned(),
+            TokenAmount(1),
+            None,
+        )];
+        let asset = Asset::token_u64(1);
+        let messages = [
+            SignableMessage::tx_in(&tx_in, &tx_outs),
+            SignableMessage::asset(&asset),
+            SignableMessage::out_point(&out_point),
+        ];
+        let v2 = SIGNABLE_VERSION_COMMITMENT_VERSION;
+        let v1 = v2 - 1;
+
+        for (kind, message) in (1u8..).zip(&messages) {
+            assert_eq!(message.preimage_at_version(v1), message.preimage());
+            assert_eq!(message.hash_at_version(v1), message.hash());
+
+            let expected = [
+                SIGNABLE_HASH_DOMAIN,
+                &(v2 as u64).to_be_bytes(),
+                &[kind],
+                &message.preimage(),
+            ]
+            .concat();
+            assert_eq!(message.preimage_at_version(v2), expected);
+            assert_eq!(
+                message.hash_at_version(v2),
+                hex::encode(sha3_256::digest(&expected))
+            );
+            assert_ne!(message.hash_at_version(v2), message.hash_at_version(v2 + 1));
+        }
+
+        // Signatures only verify for the version they were made for
+        let (pk, sk) = sign::gen_keypair();
+        for message in &messages {
+            let v1_signature = message.sign_at_version(v1, &sk);
+            let v2_signature = message.sign_at_version(v2, &sk);
+            assert!(message.verify_at_version(v1, &v1_signature, &pk));
+            assert!(message.verify_at_version(v2, &v2_signature, &pk));
+            assert!(!message.verify_at_version(v2, &v1_signature, &pk));
+            assert!(!message.verify_at_version(v1, &v2_signature, &pk));
+            assert!(!message.verify_at_version(v2 + 1, &v2_signature, &pk));
+        }
+    }
+
+    #[test]
+    /// Checks that transactions signed for one version are invalid under another
+    fn test_cross_version_replay() {
+        let (pk, sk) = sign::gen_keypair();
+        let address = construct_address(&pk);
+        let out_point = OutPoint::new("g0".to_owned(), 0);
+        let utxo = UtxoMap::from([(
+            out_point.clone(),
+            TxOut::new_token_amount(address, TokenAmount(10), None),
+        )]);
+        let signed_tx = |version: usize| {
+            let mut tx = Transaction::new();
+            tx.outputs = vec![TxOut::new_token_amount(
+                hex::encode([1; 32]),
+                TokenAmount(10),
+                None,
+            )];
+            let unsigned = TxIn::new_from_input(out_point.clone(), Script::new());
+            let message = SignableMessage::tx_in(&unsigned, &tx.outputs);
+            let script = Script::pay2pkh(
+                message.hash_at_version(version),
+                message.sign_at_version(version, &sk),
+                pk,
+                None,
+            );
+            tx.inputs = vec![TxIn::new_from_input(out_point.clone(), script)];
+            tx.version = version;
+            tx
+        };
+        let v2 = SIGNABLE_VERSION_COMMITMENT_VERSION;
+        let v1 = v2 - 1;
+
+        for (signed, replayed) in [(v1, v1), (v2, v2), (v1, v2), (v2, v1)] {
+            let mut tx = signed_tx(signed);
+            tx.version = replayed;
+            assert_eq!(
+                tx_is_valid(&tx, 0, |v| utxo.get(v)).is_ok(),
+                signed == replayed,
+                "signed at {}, replayed at {}",
+                signed,
+                replayed
+            );
+        }
+
+        // Create scripts are checked against the version of their transaction
+        let asset = Asset::item(1, None, None);
+        for (signed, replayed) in [(v1, v1), (v2, v2), (v1, v2), (v2, v1)] {
+            let message = SignableMessage::asset(&asset);
+            let script = Script::new_create_asset(
+                0,
+                0,
+                message.hash_at_version(signed),
+                message.sign_at_version(signed, &sk),
+                pk,
+            )
+            .unwrap();
+            assert_eq!(
+                tx_has_valid_create_script_at_version(&script, &asset, replayed),
+                signed == replayed,
+                "signed at {}, replayed at {}",
+                signed,
+                replayed
+            );
+        }
+    }
+
     #[test]
     // Test valid TxIn address construction; should correlate with test on wallet
     fn test_construct_valid_tx_ins_address() {
@@ -1493,7 +3942,7 @@ mod tests {
             "5585c6f74d5c55f1ab457c31671822ba28c78c397cce1e11680b9f3852f96edb",
         ];
 
-        let previous_out_points = vec![
+        let previous_out_points = [
             OutPoint::new("000000".to_owned(), 0),
             OutPoint::new("000001".to_owned(), 0),
             OutPoint::new("000002".to_owned(), 0),
@@ -1506,8 +3955,8 @@ mod tests {
             .map(|n| {
                 let sig_data = signable_data[n].to_owned();
                 let sig =
-                    Signature::from_slice(hex::decode(signatures[n]).unwrap().as_ref()).unwrap();
-                let pk = PublicKey::from_slice(hex::decode(pub_keys[n]).unwrap().as_ref()).unwrap();
+                    Signature::try_from(hex::decode(signatures[n]).unwrap().as_ref()).unwrap();
+                let pk = PublicKey::try_from(hex::decode(pub_keys[n]).unwrap().as_ref()).unwrap();
 
                 let script = Script::pay2pkh(sig_data, sig, pk, None);
                 let out_p = previous_out_points[n].clone();
@@ -1525,4 +3974,78 @@ mod tests {
         //
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    /// Checks that the stable DRUID input address ignores scripts and input order,
+    /// and distinguishes inputs the legacy address conflates
+    fn test_construct_stable_tx_ins_address() {
+        let tx_in = |t_hash: &str, n: i32, entries: Vec<StackEntry>| {
+            let script = Script::from(entries);
+            TxIn::new_from_input(OutPoint::new(t_hash.to_owned(), n), script)
+        };
+        let (pk, sk) = sign::gen_keypair();
+        let sig_1 = sign::sign_detached(b"outputs_1", &sk);
+        let sig_2 = sign::sign_detached(b"outputs_2", &sk);
+        let ins_1 = vec![
+            tx_in(
+                "a",
+                0,
+                vec![StackEntry::Signature(sig_1), StackEntry::PubKey(pk)],
+            ),
+            tx_in("b", 1, vec![]),
+        ];
+        let ins_2 = vec![
+            tx_in("b", 1, vec![]),
+            tx_in(
+                "a",
+                0,
+                vec![StackEntry::Signature(sig_2), StackEntry::PubKey(pk)],
+            ),
+        ];
+
+        // Re-signing and reordering inputs
+        assert_ne!(
+            construct_tx_ins_address(&ins_1),
+            construct_tx_ins_address(&ins_2)
+        );
+        assert_eq!(
+            construct_tx_ins_address_stable(&ins_1),
+            construct_tx_ins_address_stable(&ins_2)
+        );
+
+        // Distinct inputs with colliding legacy addresses
+        let collisions = vec![
+            (
+                vec![tx_in("a-Bytes:b", 0, vec![StackEntry::Num(1)])],
+                vec![tx_in(
+                    "a",
+                    0,
+                    vec![StackEntry::Bytes("b".to_owned()), StackEntry::Num(1)],
+                )],
+            ),
+            (
+                vec![tx_in("a", 0, vec![]), tx_in("b", 1, vec![])],
+                vec![tx_in("a--1-b", 0, vec![])],
+            ),
+        ];
+        for (left, right) in collisions {
+            assert_eq!(
+                construct_tx_ins_address(&left),
+                construct_tx_ins_address(&right)
+            );
+            assert_ne!(
+                construct_tx_ins_address_stable(&left),
+                construct_tx_ins_address_stable(&right)
+            );
+        }
+
+        assert_eq!(
+            construct_tx_ins_address_at_version(&ins_1, STABLE_TX_INS_ADDRESS_VERSION - 1),
+            construct_tx_ins_address(&ins_1)
+        );
+        assert_eq!(
+            construct_tx_ins_address_at_version(&ins_1, STABLE_TX_INS_ADDRESS_VERSION),
+            construct_tx_ins_address_stable(&ins_1)
+        );
+    }
 }
@@ -0,0 +1,418 @@
+//! UTXO set that remembers which transaction spent each recently spent output.
+//!
+//! Spent outputs are kept in a spent index for `retention` blocks after the block spending
+//! them, so that a double spend can be reported along with the transaction that got there
+//! first, and so that applied transactions can be undone when a block is rolled back.
+
+use crate::constants::SPENT_INDEX_RETENTION;
+use crate::primitives::transaction::{OutPoint, Transaction, TxOut};
+use crate::utils::script_utils::{tx_is_valid, InputError, TxValidationError};
+use crate::utils::transaction_utils::{construct_tx_hash, UtxoMap};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// What a `UtxoSet` knows about an outpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UtxoLookup<'a> {
+    Unspent(&'a TxOut),
+    SpentBy(&'a str),
+    Unknown,
+}
+
+/// Reasons a transaction can't be applied to or undone from a `UtxoSet`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UtxoSetError {
+    Input(usize, InputError),
+    NotRestorable(OutPoint),
+    OutputSpent(OutPoint),
+}
+
+impl fmt::Display for UtxoSetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Input(idx, e) => write!(f, "Input {idx}: {e}"),
+            Self::NotRestorable(out_point) => {
+                write!(
+                    f,
+                    "Spent output {out_point} is no longer in the spent index"
+                )
+            }
+            Self::OutputSpent(out_point) => write!(f, "Output {out_point} has been spent since"),
+        }
+    }
+}
+
+impl std::error::Error for UtxoSetError {}
+
+/// Output removed from the UTXO set, along with where it was spent
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SpentEntry {
+    tx_hash: String,
+    block_number: u64,
+    tx_out: TxOut,
+}
+
+/// UTXO map with a spent index bounded by a retention period in blocks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UtxoSet {
+    utxo: UtxoMap,
+    spent: BTreeMap<OutPoint, SpentEntry>,
+    spent_at: BTreeMap<u64, Vec<OutPoint>>,
+    retention: u64,
+}
+
+impl Default for UtxoSet {
+    fn default() -> Self {
+        Self::new(UtxoMap::new(), SPENT_INDEX_RETENTION)
+    }
+}
+
+impl UtxoSet {
+    /// Creates a set holding `utxo`, with an empty spent index
+    ///
+    /// ### Arguments
+    ///
+    /// * `utxo`        - Unspent outputs to start from
+    /// * `retention`   - Blocks spent outputs stay in the spent index for
+    pub fn new(utxo: UtxoMap, retention: u64) -> Self {
+        Self {
+            utxo,
+            spent: BTreeMap::new(),
+            spent_at: BTreeMap::new(),
+            retention,
+        }
+    }
+
+    /// Unspent outputs of the set
+    pub fn utxo(&self) -> &UtxoMap {
+        &self.utxo
+    }
+
+    /// Number of spent outputs currently kept in the spent index
+    pub fn spent_len(&self) -> usize {
+        self.spent.len()
+    }
+
+    /// Looks up an outpoint in the unspent outputs, then in the spent index
+    ///
+    /// ### Arguments
+    ///
+    /// * `out_point`   - Outpoint to look up
+    pub fn lookup(&self, out_point: &OutPoint) -> UtxoLookup<'_> {
+        if let Some(tx_out) = self.utxo.get(out_point) {
+            UtxoLookup::Unspent(tx_out)
+        } else if let Some(entry) = self.spent.get(out_point) {
+            UtxoLookup::SpentBy(&entry.tx_hash)
+        } else {
+            UtxoLookup::Unknown
+        }
+    }
+
+    /// Validates a transaction against the unspent outputs, reporting inputs spending
+    /// an output in the spent index with the transaction that spent it
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx`              - Transaction to validate
+    /// * `block_number`    - Current block number
+    pub fn validate(&self, tx: &Transaction, block_number: u64) -> Result<(), TxValidationError> {
+        tx_is_valid(tx, block_number, |out_point| self.utxo.get(out_point)).map_err(|e| match e {
+            TxValidationError::Input(idx, InputError::NotInUtxo(out_point)) => {
+                TxValidationError::Input(idx, self.missing_input(out_point))
+            }
+            e => e,
+        })
+    }
+
+    /// Spends the outputs used by a transaction and adds its own, returning its hash.
+    /// Scripts and amounts are not checked, which `validate` should be used for.
+    /// Nothing is changed if any input can't be spent
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx`              - Transaction to apply
+    /// * `block_number`    - Block number of the block holding `tx`
+    pub fn apply_transaction(
+        &mut self,
+        tx: &Transaction,
+        block_number: u64,
+    ) -> Result<String, UtxoSetError> {
+        let tx_hash = construct_tx_hash(tx);
+        let mut spending = BTreeSet::new();
+        for (idx, out_point) in previous_outs(tx) {
+            if !self.utxo.contains_key(out_point) {
+                let e = self.missing_input(out_point.clone());
+                return Err(UtxoSetError::Input(idx, e));
+            }
+            if !spending.insert(out_point) {
+                let e = InputError::SpentBy {
+                    out_point: out_point.clone(),
+                    tx_hash,
+                };
+                return Err(UtxoSetError::Input(idx, e));
+            }
+        }
+
+        for out_point in spending {
+            let tx_out = self.utxo.remove(out_point).unwrap_or_default();
+            let entry = SpentEntry {
+                tx_hash: tx_hash.clone(),
+                block_number,
+                tx_out,
+            };
+            self.spent.insert(out_point.clone(), entry);
+            let spent_at = self.spent_at.entry(block_number).or_default();
+            spent_at.push(out_point.clone());
+        }
+        for (n, tx_out) in tx.outputs.iter().enumerate() {
+            let out_point = OutPoint::new(tx_hash.clone(), n as i32);
+            self.utxo.insert(out_point, tx_out.clone());
+        }
+
+        self.prune(block_number);
+        Ok(tx_hash)
+    }
+
+    /// Removes the outputs of an applied transaction and restores the ones it spent
+    /// from the spent index. Nothing is changed if any of its outputs has been spent
+    /// since, or any output it spent is no longer in the spent index
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx`  - Transaction to undo
+    pub fn undo_transaction(&mut self, tx: &Transaction) -> Result<(), UtxoSetError> {
+        let tx_hash = construct_tx_hash(tx);
+        for (_, out_point) in previous_outs(tx) {
+            match self.spent.get(out_point) {
+                Some(entry) if entry.tx_hash == tx_hash => (),
+                _ => return Err(UtxoSetError::NotRestorable(out_point.clone())),
+            }
+        }
+        let outputs: Vec<_> = (0..tx.outputs.len())
+            .map(|n| OutPoint::new(tx_hash.clone(), n as i32))
+            .collect();
+        if let Some(out_point) = outputs.iter().find(|o| !self.utxo.contains_key(o)) {
+            return Err(UtxoSetError::OutputSpent(out_point.clone()));
+        }
+
+        for out_point in &outputs {
+            self.utxo.remove(out_point);
+        }
+        for (_, out_point) in previous_outs(tx) {
+            if let Some(entry) = self.spent.remove(out_point) {
+                self.utxo.insert(out_point.clone(), entry.tx_out);
+            }
+        }
+        Ok(())
+    }
+
+    /// Undoes the transactions of a block, last first. Stops at the first transaction
+    /// that can't be undone, with the ones after it already undone
+    ///
+    /// ### Arguments
+    ///
+    /// * `txs` - Transactions of the block, in the order they were applied
+    pub fn undo_block(&mut self, txs: &[Transaction]) -> Result<(), UtxoSetError> {
+        txs.iter()
+            .rev()
+            .try_for_each(|tx| self.undo_transaction(tx))
+    }
+
+    /// Drops spent index entries spent more than `retention` blocks before `block_number`
+    ///
+    /// ### Arguments
+    ///
+    /// * `block_number`    - Current block number
+    pub fn prune(&mut self, block_number: u64) {
+        let oldest_kept = block_number.saturating_sub(self.retention);
+        let kept = self.spent_at.split_off(&oldest_kept);
+        let pruned = std::mem::replace(&mut self.spent_at, kept);
+
+        for (spent_block, out_points) in pruned {
+            for out_point in out_points {
+                // Outpoints restored by an undo and spent again are under another block
+                if self
+                    .spent
+                    .get(&out_point)
+                    .is_some_and(|e| e.block_number == spent_block)
+                {
+                    self.spent.remove(&out_point);
+                }
+            }
+        }
+    }
+
+    /// Input error for an outpoint missing from the unspent outputs
+    ///
+    /// ### Arguments
+    ///
+    /// * `out_point`   - Outpoint not in the unspent outputs
+    fn missing_input(&self, out_point: OutPoint) -> InputError {
+        match self.spent.get(&out_point) {
+            Some(entry) => InputError::SpentBy {
+                out_point,
+                tx_hash: entry.tx_hash.clone(),
+            },
+            None => InputError::NotInUtxo(out_point),
+        }
+    }
+}
+
+/// Outpoints spent by a transaction, with the index of their input
+///
+/// ### Arguments
+///
+/// * `tx`  - Transaction to get the outpoints of
+fn previous_outs(tx: &Transaction) -> impl Iterator<Item = (usize, &OutPoint)> {
+    tx.inputs
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, tx_in)| Some((idx, tx_in.previous_out.as_ref()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::asset::TokenAmount;
+    use crate::primitives::transaction::TxIn;
+    use crate::script::lang::Script;
+
+    /// Transaction spending `spends` into a single output of `amount`
+    fn spend_tx(spends: &[&OutPoint], amount: u64) -> Transaction {
+        let mut tx = Transaction::new();
+        tx.inputs = spends
+            .iter()
+            .map(|out_point| TxIn::new_from_input((*out_point).clone(), Script::new()))
+            .collect();
+        tx.outputs = vec![TxOut::new_token_amount(
+            hex::encode([1; 32]),
+            TokenAmount(amount),
+            None,
+        )];
+        tx
+    }
+
+    /// Set holding one output per amount, from a made up transaction hash
+    fn funded_set(amounts: &[u64], retention: u64) -> (UtxoSet, Vec<OutPoint>) {
+        let out_points: Vec<_> = (0..amounts.len())
+            .map(|n| OutPoint::new("g0".to_owned(), n as i32))
+            .collect();
+        let utxo = out_points
+            .iter()
+            .zip(amounts)
+            .map(|(o, a)| {
+                (
+                    o.clone(),
+                    TxOut::new_token_amount(String::new(), TokenAmount(*a), None),
+                )
+            })
+            .collect();
+        (UtxoSet::new(utxo, retention), out_points)
+    }
+
+    #[test]
+    /// Checks that spent outputs are reported with the transaction spending them
+    fn test_utxo_set_spent_by() {
+        let (mut utxo_set, out_points) = funded_set(&[10, 20], 10);
+        let tx = spend_tx(&[&out_points[0]], 10);
+        let tx_hash = utxo_set.apply_transaction(&tx, 1).unwrap();
+
+        assert_eq!(
+            utxo_set.lookup(&out_points[0]),
+            UtxoLookup::SpentBy(&tx_hash)
+        );
+        assert!(matches!(
+            utxo_set.lookup(&OutPoint::new(tx_hash.clone(), 0)),
+            UtxoLookup::Unspent(_)
+        ));
+        assert_eq!(
+            utxo_set.lookup(&OutPoint::new("g1".to_owned(), 0)),
+            UtxoLookup::Unknown
+        );
+
+        // Double spends, in another transaction or within the same one
+        let spent_by = InputError::SpentBy {
+            out_point: out_points[0].clone(),
+            tx_hash: tx_hash.clone(),
+        };
+        assert_eq!(
+            utxo_set.validate(&spend_tx(&[&out_points[0]], 10), 1),
+            Err(TxValidationError::Input(0, spent_by.clone()))
+        );
+        let double_spend = spend_tx(&[&out_points[1], &out_points[0]], 30);
+        assert_eq!(
+            utxo_set.apply_transaction(&double_spend, 2),
+            Err(UtxoSetError::Input(1, spent_by))
+        );
+        let twice = spend_tx(&[&out_points[1], &out_points[1]], 40);
+        assert!(matches!(
+            utxo_set.apply_transaction(&twice, 2),
+            Err(UtxoSetError::Input(1, InputError::SpentBy { .. }))
+        ));
+        assert!(matches!(
+            utxo_set.lookup(&out_points[1]),
+            UtxoLookup::Unspent(_)
+        ));
+    }
+
+    #[test]
+    /// Checks that undoing a block restores the outputs its transactions spent
+    fn test_utxo_set_undo_block() {
+        let (mut utxo_set, out_points) = funded_set(&[10, 20], 10);
+        let before = utxo_set.clone();
+
+        let first = spend_tx(&[&out_points[0]], 10);
+        let first_hash = utxo_set.apply_transaction(&first, 1).unwrap();
+        let first_out = OutPoint::new(first_hash, 0);
+        let second = spend_tx(&[&first_out, &out_points[1]], 30);
+        utxo_set.apply_transaction(&second, 1).unwrap();
+
+        assert_eq!(
+            utxo_set.undo_transaction(&first),
+            Err(UtxoSetError::OutputSpent(first_out.clone()))
+        );
+        utxo_set.undo_block(&[first, second]).unwrap();
+        assert!(matches!(
+            utxo_set.lookup(&out_points[0]),
+            UtxoLookup::Unspent(_)
+        ));
+        assert_eq!(utxo_set.lookup(&first_out), UtxoLookup::Unknown);
+        assert_eq!(utxo_set.utxo(), before.utxo());
+        assert_eq!(utxo_set.spent_len(), 0);
+    }
+
+    #[test]
+    /// Checks that the spent index only keeps outputs spent within the retention period
+    fn test_utxo_set_prune() {
+        let retention = 5;
+        let (mut utxo_set, out_points) = funded_set(&[1; 50], retention);
+
+        for (n, out_point) in out_points.iter().enumerate() {
+            let tx = spend_tx(&[out_point], n as u64);
+            utxo_set.apply_transaction(&tx, n as u64).unwrap();
+            assert!(utxo_set.spent_len() <= retention as usize + 1);
+        }
+        assert_eq!(utxo_set.lookup(&out_points[0]), UtxoLookup::Unknown);
+        assert!(matches!(
+            utxo_set.lookup(&out_points[49]),
+            UtxoLookup::SpentBy(_)
+        ));
+
+        // Pruned outputs can no longer be restored, nor reported as spent
+        let tx = spend_tx(&[&out_points[0]], 0);
+        assert_eq!(
+            utxo_set.undo_transaction(&tx),
+            Err(UtxoSetError::NotRestorable(out_points[0].clone()))
+        );
+        assert_eq!(
+            utxo_set.validate(&spend_tx(&[&out_points[0]], 1), 50),
+            Err(TxValidationError::Input(
+                0,
+                InputError::NotInUtxo(out_points[0].clone())
+            ))
+        );
+
+        utxo_set.prune(100);
+        assert_eq!(utxo_set.spent_len(), 0);
+    }
+}
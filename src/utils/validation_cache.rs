@@ -0,0 +1,307 @@
+use crate::crypto::sha3_256;
+use crate::primitives::transaction::{OutPoint, Transaction, TxOut};
+use crate::utils::script_utils::{tx_is_valid, TxValidationError};
+use bincode::serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Hash of the full encoding of a transaction, including its scripts
+type Wtxid = [u8; 32];
+
+/// UTXO entries a transaction spends, with the hash of the `TxOut` each
+/// outpoint resolved to, or `None` if it was not in the UTXO set
+type SpentEntries = Vec<(OutPoint, Option<[u8; 32]>)>;
+
+/// Verdict of `tx_is_valid` along with everything it depends on
+#[derive(Debug, Clone)]
+struct CachedVerdict {
+    verdict: Result<(), TxValidationError>,
+    spent: SpentEntries,
+    min_height: u64,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    entries: HashMap<Wtxid, CachedVerdict>,
+    recency: BTreeMap<u64, Wtxid>,
+    tick: u64,
+}
+
+/// Bounded cache of transaction validation verdicts, so that transactions
+/// already checked on mempool acceptance aren't fully re-validated in a block.
+///
+/// A verdict is reused only while every UTXO entry the transaction spends
+/// is unchanged, and only at heights where all of their locktimes have
+/// expired. Above that height a verdict no longer depends on the block
+/// number, so verdicts computed below it are never cached.
+/// The least recently used verdict is evicted once `capacity` is reached
+#[derive(Debug)]
+pub struct ValidationCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+impl ValidationCache {
+    /// Creates an empty cache
+    ///
+    /// ### Arguments
+    ///
+    /// * `capacity` - Maximum number of verdicts held
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Default::default(),
+        }
+    }
+
+    /// Number of verdicts held
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Whether no verdicts are held
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Validates a transaction as `tx_is_valid` would, reusing a cached verdict
+    /// when the spent UTXO entries and block number allow it
+    ///
+    /// ### Arguments
+    ///
+    /// * `tx`                   - Transaction to validate
+    /// * `current_block_number` - Current block number
+    /// * `is_in_utxo`           - Function to check if a `TxOut` is in the UTXO set
+    pub fn check<'a>(
+        &self,
+        tx: &Transaction,
+        current_block_number: u64,
+        is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut> + 'a,
+    ) -> Result<(), TxValidationError> {
+        let wtxid = wtxid(tx);
+        let (spent, min_height) = spent_entries(tx, &is_in_utxo);
+
+        if let Some(verdict) = self.lock().get(&wtxid, &spent, current_block_number) {
+            return verdict;
+        }
+
+        let verdict = tx_is_valid(tx, current_block_number, is_in_utxo);
+        if current_block_number >= min_height && self.capacity > 0 {
+            let mut state = self.lock();
+            state.insert(wtxid, verdict.clone(), spent, min_height);
+            state.evict_to(self.capacity);
+        }
+        verdict
+    }
+
+    fn lock(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl CacheState {
+    /// Gets a verdict if it was computed against the same spent entries and
+    /// holds at the given height, marking it as most recently used
+    fn get(
+        &mut self,
+        wtxid: &Wtxid,
+        spent: &SpentEntries,
+        current_block_number: u64,
+    ) -> Option<Result<(), TxValidationError>> {
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = self
+            .entries
+            .get_mut(wtxid)
+            .filter(|e| &e.spent == spent && current_block_number >= e.min_height)?;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(tick, *wtxid);
+        entry.last_used = tick;
+        Some(entry.verdict.clone())
+    }
+
+    fn insert(
+        &mut self,
+        wtxid: Wtxid,
+        verdict: Result<(), TxValidationError>,
+        spent: SpentEntries,
+        min_height: u64,
+    ) {
+        self.tick += 1;
+        let entry = CachedVerdict {
+            verdict,
+            spent,
+            min_height,
+            last_used: self.tick,
+        };
+        if let Some(old) = self.entries.insert(wtxid, entry) {
+            self.recency.remove(&old.last_used);
+        }
+        self.recency.insert(self.tick, wtxid);
+    }
+
+    fn evict_to(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            match self.recency.pop_first() {
+                Some((_, wtxid)) => self.entries.remove(&wtxid),
+                None => break,
+            };
+        }
+    }
+}
+
+/// Hashes the full encoding of a transaction
+///
+/// ### Arguments
+///
+/// * `tx` - Transaction to hash
+fn wtxid(tx: &Transaction) -> Wtxid {
+    let bytes = serialize(tx).unwrap_or_default();
+    sha3_256::digest(&bytes).into()
+}
+
+/// Resolves the UTXO entries spent by a transaction, returning them along
+/// with the block number from which all of their locktimes have expired
+///
+/// ### Arguments
+///
+/// * `tx`          - Transaction spending the entries
+/// * `is_in_utxo`  - Function to check if a `TxOut` is in the UTXO set
+fn spent_entries<'a>(
+    tx: &Transaction,
+    is_in_utxo: impl Fn(&OutPoint) -> Option<&'a TxOut>,
+) -> (SpentEntries, u64) {
+    let mut min_height = 0;
+    let spent = tx
+        .inputs
+        .iter()
+        .filter_map(|tx_in| tx_in.previous_out.as_ref())
+        .map(|out_point| {
+            let tx_out = is_in_utxo(out_point);
+            if let Some(tx_out) = tx_out {
+                min_height = min_height.max(tx_out.locktime);
+            }
+            (out_point.clone(), tx_out.map(tx_out_hash))
+        })
+        .collect();
+    (spent, min_height)
+}
+
+/// Hashes a UTXO entry
+///
+/// ### Arguments
+///
+/// * `tx_out` - Entry to hash
+fn tx_out_hash(tx_out: &TxOut) -> [u8; 32] {
+    let bytes = serialize(tx_out).unwrap_or_default();
+    sha3_256::digest(&bytes).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::sign_ed25519 as sign;
+    use crate::primitives::asset::TokenAmount;
+    use crate::primitives::transaction::TxIn;
+    use crate::script::lang::Script;
+    use crate::utils::transaction_utils::{construct_address, SignableMessage, UtxoMap};
+
+    /// Builds a UTXO set holding one entry, and a transaction validly spending it
+    fn spending_tx(locktime: Option<u64>) -> (UtxoMap, Transaction) {
+        let (pk, sk) = sign::gen_keypair();
+        let out_point = OutPoint::new("g0".to_owned(), 0);
+        let tx_out = TxOut::new_token_amount(construct_address(&pk), TokenAmount(10), locktime);
+        let utxo = UtxoMap::from([(out_point.clone(), tx_out)]);
+
+        let mut tx = Transaction::new();
+        tx.outputs = vec![TxOut::new_token_amount(
+            hex::encode([1; 32]),
+            TokenAmount(10),
+            None,
+        )];
+        let unsigned = TxIn::new_from_input(out_point.clone(), Script::new());
+        let message = SignableMessage::tx_in(&unsigned, &tx.outputs);
+        let script = Script::pay2pkh(
+            message.hash_at_version(tx.version),
+            message.sign_at_version(tx.version, &sk),
+            pk,
+            None,
+        );
+        tx.inputs = vec![TxIn::new_from_input(out_point, script)];
+        (utxo, tx)
+    }
+
+    #[test]
+    /// Checks that a cached verdict isn't reused once a spent UTXO entry changes
+    fn test_reverify_changed_utxo() {
+        let cache = ValidationCache::new(10);
+        let (mut utxo, tx) = spending_tx(None);
+        let out_point = tx.inputs[0].previous_out.clone().unwrap();
+
+        assert_eq!(cache.check(&tx, 0, |v| utxo.get(v)), Ok(()));
+        assert_eq!(cache.check(&tx, 0, |v| utxo.get(v)), Ok(()));
+        assert_eq!(cache.len(), 1);
+
+        // A reorg replaces the spent entry with one the transaction can't unlock
+        let original = utxo.insert(
+            out_point.clone(),
+            TxOut::new_token_amount(hex::encode([2; 32]), TokenAmount(10), None),
+        );
+        let expected = tx_is_valid(&tx, 0, |v| utxo.get(v));
+        assert!(expected.is_err());
+        assert_eq!(cache.check(&tx, 0, |v| utxo.get(v)), expected);
+
+        // The entry disappearing is a change too
+        utxo.remove(&out_point);
+        let expected = tx_is_valid(&tx, 0, |v| utxo.get(v));
+        assert!(expected.is_err());
+        assert_eq!(cache.check(&tx, 0, |v| utxo.get(v)), expected);
+
+        utxo.insert(out_point, original.unwrap());
+        assert_eq!(cache.check(&tx, 0, |v| utxo.get(v)), Ok(()));
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    /// Checks that verdicts aren't reused below the height their locktimes expire at
+    fn test_locktime_threshold() {
+        let cache = ValidationCache::new(10);
+        let (utxo, tx) = spending_tx(Some(10));
+
+        // Verdicts below the threshold depend on the height, so aren't cached
+        let below = tx_is_valid(&tx, 5, |v| utxo.get(v));
+        assert!(below.is_err());
+        assert_eq!(cache.check(&tx, 5, |v| utxo.get(v)), below);
+        assert!(cache.is_empty());
+
+        assert_eq!(cache.check(&tx, 20, |v| utxo.get(v)), Ok(()));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.check(&tx, 5, |v| utxo.get(v)), below);
+        assert!(cache.check(&tx, 9, |v| utxo.get(v)).is_err());
+        assert_eq!(cache.check(&tx, 10, |v| utxo.get(v)), Ok(()));
+    }
+
+    #[test]
+    /// Checks that the least recently used verdict is evicted
+    fn test_lru_eviction() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ValidationCache>();
+
+        let cache = ValidationCache::new(2);
+        let txs: Vec<_> = (0..3).map(|_| spending_tx(None)).collect();
+        let cached = |idx: usize| cache.lock().entries.contains_key(&wtxid(&txs[idx].1));
+
+        for (utxo, tx) in &txs[..2] {
+            assert!(cache.check(tx, 0, |v| utxo.get(v)).is_ok());
+        }
+        let (utxo, tx) = &txs[0];
+        assert!(cache.check(tx, 0, |v| utxo.get(v)).is_ok());
+        let (utxo, tx) = &txs[2];
+        assert!(cache.check(tx, 0, |v| utxo.get(v)).is_ok());
+
+        assert_eq!(cache.len(), 2);
+        assert!(cached(0) && !cached(1) && cached(2));
+        assert!(ValidationCache::new(0).is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+//! Walks a set of assets through a complete UTXO lifecycle:
+//! coinbase -> P2PKH payment -> multisig escrow -> P2SH redeem -> item creation
+//! -> item transfer -> burn.
+//!
+//! Spending transactions are checked with `tx_is_valid` through a `UtxoSet`.
+//! Coinbase and create transactions have no previous outpoints, so they are checked
+//! through their create scripts instead.
+
+use std::collections::BTreeMap;
+use tw_chain::crypto::sign_ed25519::{self as sign, PublicKey, SecretKey};
+use tw_chain::primitives::asset::{Asset, ItemAsset, TokenAmount};
+use tw_chain::primitives::transaction::{
+    GenesisTxHashSpec, OutPoint, Transaction, TxConstructor, TxIn, TxOut,
+};
+use tw_chain::script::lang::Script;
+use tw_chain::script::{OpCodes, StackEntry};
+use tw_chain::utils::script_utils::{
+    tx_has_valid_create_script, InputError, OutputError, OutputKind, TxValidationError,
+};
+use tw_chain::utils::transaction_utils::{
+    construct_address, construct_item_create_tx, construct_p2sh_address,
+    construct_p2sh_redeem_tx_ins, construct_p2sh_tx, construct_payment_tx, construct_tx_hash,
+    ReceiverInfo,
+};
+use tw_chain::utils::utxo_set::UtxoSet;
+
+const COINBASE_AMOUNT: u64 = 1000;
+const INVALID_SCRIPT: TxValidationError = TxValidationError::Input(0, InputError::InvalidScript);
+
+/// Input spending `previous_out`, to be signed by `construct_payment_tx`
+fn unsigned_input(previous_out: &OutPoint) -> Vec<TxIn> {
+    vec![TxIn {
+        previous_out: Some(previous_out.clone()),
+        script_signature: Script::new(),
+    }]
+}
+
+/// Pays `asset` from `previous_out`, owned by `owner`, to `to`
+fn pay(
+    previous_out: &OutPoint,
+    owner: &(PublicKey, SecretKey),
+    to: String,
+    asset: Asset,
+) -> Transaction {
+    let key_material = BTreeMap::from([(previous_out.clone(), owner.clone())]);
+    let receiver = ReceiverInfo { address: to, asset };
+    construct_payment_tx(
+        unsigned_input(previous_out),
+        receiver,
+        None,
+        0,
+        &key_material,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_utxo_lifecycle() {
+    let miner = sign::gen_keypair();
+    let alice = sign::gen_keypair();
+    let bob = sign::gen_keypair();
+    let parties = [
+        sign::gen_keypair(),
+        sign::gen_keypair(),
+        sign::gen_keypair(),
+    ];
+    let redeemer = &parties[0];
+    let tokens = Asset::token_u64(COINBASE_AMOUNT);
+    let mut utxo_set = UtxoSet::default();
+
+    // (1) Coinbase paying the miner
+    let coinbase_tx = Transaction {
+        inputs: vec![TxIn::new_from_script(Script::new_for_coinbase(0))],
+        outputs: vec![TxOut::new_token_amount(
+            construct_address(&miner.0),
+            TokenAmount(COINBASE_AMOUNT),
+            None,
+        )],
+        ..Default::default()
+    };
+    assert!(coinbase_tx.is_coinbase());
+    let coinbase_out = OutPoint::new(utxo_set.apply_transaction(&coinbase_tx, 0).unwrap(), 0);
+
+    // (2) P2PKH payment from the miner to Alice
+    let alice_address = construct_address(&alice.0);
+    let tampered = pay(
+        &coinbase_out,
+        &miner,
+        alice_address.clone(),
+        Asset::token_u64(1001),
+    );
+    assert!(matches!(
+        utxo_set.validate(&tampered, 0),
+        Err(TxValidationError::Unbalanced { .. })
+    ));
+    let wrong_key = pay(&coinbase_out, &alice, alice_address.clone(), tokens.clone());
+    assert_eq!(utxo_set.validate(&wrong_key, 0), Err(INVALID_SCRIPT));
+
+    let payment_tx = pay(&coinbase_out, &miner, alice_address, tokens.clone());
+    assert_eq!(utxo_set.validate(&payment_tx, 0), Ok(()));
+    let alice_out = OutPoint::new(utxo_set.apply_transaction(&payment_tx, 0).unwrap(), 0);
+
+    // (3) Alice locks the tokens in a 2-of-3 multisig escrow, committed to through P2SH
+    let check_data = construct_tx_hash(&payment_tx);
+    let pub_keys: Vec<PublicKey> = parties.iter().map(|(pk, _)| *pk).collect();
+    let sign_escrow = |signers: &[&(PublicKey, SecretKey)]| {
+        let signatures = signers
+            .iter()
+            .map(|(_, sk)| sign::sign_detached(check_data.as_bytes(), sk))
+            .collect();
+        Script::multisig_validation(2, 3, check_data.clone(), signatures, pub_keys.clone())
+    };
+    let escrow_script = sign_escrow(&[&parties[0], &parties[1]]);
+    assert!(escrow_script.interpret());
+
+    let escrow_key_material = BTreeMap::from([(alice_out.clone(), alice.clone())]);
+    let escrow_tx = construct_p2sh_tx(
+        unsigned_input(&alice_out),
+        None,
+        &escrow_script,
+        tokens.clone(),
+        0,
+        &escrow_key_material,
+    )
+    .unwrap();
+    assert_eq!(
+        escrow_tx.outputs[0].script_public_key,
+        Some(construct_p2sh_address(&escrow_script))
+    );
+    assert_eq!(utxo_set.validate(&escrow_tx, 0), Ok(()));
+    let escrow_out = OutPoint::new(utxo_set.apply_transaction(&escrow_tx, 0).unwrap(), 0);
+
+    // (4) Two of the three parties redeem the escrow through its P2SH script
+    let redeem = |script: Script| {
+        let tx_const = TxConstructor {
+            previous_out: escrow_out.clone(),
+            signatures: vec![],
+            pub_keys: vec![],
+            address_version: None,
+        };
+        let receiver = ReceiverInfo {
+            address: construct_address(&redeemer.0),
+            asset: tokens.clone(),
+        };
+        let tx_ins = construct_p2sh_redeem_tx_ins(tx_const, script);
+        construct_payment_tx(tx_ins, receiver, None, 0, &BTreeMap::new()).unwrap()
+    };
+    let tampered = redeem(sign_escrow(&[&parties[0], &parties[2]]));
+    assert_eq!(utxo_set.validate(&tampered, 0), Err(INVALID_SCRIPT));
+
+    let redeem_tx = redeem(escrow_script);
+    assert_eq!(utxo_set.validate(&redeem_tx, 0), Ok(()));
+    utxo_set.apply_transaction(&redeem_tx, 0).unwrap();
+
+    // (5) The redeemer creates an item asset
+    let item_create_tx = construct_item_create_tx(
+        0,
+        0,
+        redeemer.0,
+        &redeemer.1,
+        1,
+        GenesisTxHashSpec::Create,
+        None,
+        None,
+    )
+    .unwrap();
+    assert!(item_create_tx.is_create_tx());
+    assert!(tx_has_valid_create_script(
+        &item_create_tx.inputs[0].script_signature,
+        &item_create_tx.outputs[0].value
+    ));
+    let genesis_hash = utxo_set.apply_transaction(&item_create_tx, 0).unwrap();
+    let item_out = OutPoint::new(genesis_hash.clone(), 0);
+    let item = Asset::Item(ItemAsset::new(1, Some(genesis_hash), None));
+
+    // (6) The item is transferred to Bob
+    let bob_address = construct_address(&bob.0);
+    let wrong_genesis = Asset::Item(ItemAsset::new(1, Some("genesis".to_owned()), None));
+    let tampered = pay(&item_out, redeemer, bob_address.clone(), wrong_genesis);
+    assert_eq!(
+        utxo_set.validate(&tampered, 0),
+        Err(TxValidationError::Output(OutputError::UnknownGenesisHash(
+            OutputKind::Normal,
+            0,
+            "genesis".to_owned()
+        )))
+    );
+
+    let transfer_tx = pay(&item_out, redeemer, bob_address, item.clone());
+    assert_eq!(utxo_set.validate(&transfer_tx, 0), Ok(()));
+    let bob_out = OutPoint::new(utxo_set.apply_transaction(&transfer_tx, 0).unwrap(), 0);
+
+    // (7) Bob burns the item by sending it to the P2SH address of an `OP_BURN` script
+    let burn_script = Script::from(vec![StackEntry::Op(OpCodes::OP_BURN)]);
+    let burn_key_material = BTreeMap::from([(bob_out.clone(), bob.clone())]);
+    let burn_tx = construct_p2sh_tx(
+        unsigned_input(&bob_out),
+        None,
+        &burn_script,
+        item.clone(),
+        0,
+        &burn_key_material,
+    )
+    .unwrap();
+    assert_eq!(utxo_set.validate(&burn_tx, 0), Ok(()));
+    let burnt_out = OutPoint::new(utxo_set.apply_transaction(&burn_tx, 0).unwrap(), 0);
+
+    // The burnt item can never be spent again
+    let tx_const = TxConstructor {
+        previous_out: burnt_out,
+        signatures: vec![],
+        pub_keys: vec![],
+        address_version: None,
+    };
+    let receiver = ReceiverInfo {
+        address: construct_address(&bob.0),
+        asset: item,
+    };
+    let spend_burnt = construct_payment_tx(
+        construct_p2sh_redeem_tx_ins(tx_const, burn_script),
+        receiver,
+        None,
+        0,
+        &BTreeMap::new(),
+    )
+    .unwrap();
+    assert_eq!(utxo_set.validate(&spend_burnt, 0), Err(INVALID_SCRIPT));
+}
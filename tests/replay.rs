@@ -0,0 +1,806 @@
+//! Replays recorded validation verdicts, so that any change flipping the verdict of a
+//! recorded transaction or script fails loudly, naming the fixture.
+//!
+//! Fixtures are read from every `.jsonl` file in `tests/fixtures/replay`, one JSON
+//! `ReplayFixture` per line; blank lines and lines starting with `#` are skipped.
+//! Adding a case only takes adding a line, such as one from `export_fixture`.
+//!
+//! `generated.jsonl` locks the behaviour of the crate's own constructors across script
+//! templates, opcodes and version paths. It is deterministic, and regenerated with:
+//!
+//! `cargo test --test replay -- --ignored regenerate_replay_corpus`
+//!
+//! Regenerating is only expected when a verdict changes on purpose.
+//!
+//! `baseline.jsonl` pins transactions encoded by earlier releases of the crate. Each must
+//! still decode to the same bytes and hash, and is never regenerated.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tw_chain::constants::{NETWORK_VERSION, NETWORK_VERSION_TEMP, NETWORK_VERSION_V0};
+use tw_chain::crypto::sign_ed25519::{self as sign, PublicKey, SecretKey};
+use tw_chain::primitives::asset::{Asset, ItemAsset, TokenAmount, TokenMetadata};
+use tw_chain::primitives::druid::{DdeValues, DruidExpectation};
+use tw_chain::primitives::transaction::{
+    GenesisTxHashSpec, OutPoint, Transaction, TxConstructor, TxIn, TxOut,
+};
+use tw_chain::script::lang::{Script, TxOutConstraint};
+use tw_chain::script::{OpCodes, StackEntry};
+use tw_chain::utils::script_utils::tx_is_valid;
+use tw_chain::utils::test_utils::{ReplayCase, ReplayFixture};
+use tw_chain::utils::transaction_utils::{
+    construct_address, construct_address_for, construct_burn_tx, construct_consolidation_tx,
+    construct_dde_tx, construct_dual_address, construct_dual_tx_ins, construct_item_create_tx,
+    construct_p2sh_address, construct_p2sh_redeem_tx_ins, construct_p2sh_tx, construct_payment_tx,
+    construct_payment_tx_with_change, construct_token_create_tx, construct_tx_ins_address,
+    ReceiverInfo, SignableMessage, UtxoMap,
+};
+
+const GENERATED_CORPUS: &str = "generated.jsonl";
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay")
+}
+
+/// Reads every fixture, along with the file and line it was read from
+fn load_fixtures() -> Vec<(String, ReplayFixture)> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(fixtures_dir())
+        .expect("replay fixtures directory exists")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect();
+    paths.sort();
+
+    let mut fixtures = Vec::new();
+    for path in paths {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let contents = fs::read_to_string(&path).unwrap();
+        for (idx, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let location = format!("{}:{}", name, idx + 1);
+            match serde_json::from_str(line) {
+                Ok(fixture) => fixtures.push((location, fixture)),
+                Err(e) => panic!("{}: malformed fixture: {}", location, e),
+            }
+        }
+    }
+    fixtures
+}
+
+#[test]
+fn replay_fixtures() {
+    let fixtures = load_fixtures();
+    assert!(!fixtures.is_empty());
+
+    let failures: Vec<String> = fixtures
+        .iter()
+        .filter_map(|(location, fixture)| {
+            let actual = match fixture.replay() {
+                Ok(actual) if actual == fixture.expected => return None,
+                Ok(actual) => format!("{:?}", actual),
+                Err(e) => format!("unreplayable fixture ({})", e),
+            };
+            Some(format!(
+                "{} {} ({}): expected {:?}, got {}",
+                location,
+                fixture.id(),
+                fixture.note.as_deref().unwrap_or("no note"),
+                fixture.expected,
+                actual
+            ))
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "{} of {} replayed verdicts changed:\n{}",
+        failures.len(),
+        fixtures.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn replay_corpus_covers_every_opcode() {
+    let mut used = BTreeSet::new();
+    for (_, fixture) in load_fixtures() {
+        let scripts = match &fixture.case {
+            ReplayCase::Tx { tx, .. } | ReplayCase::Create { tx, .. } => {
+                let tx: Transaction = decode(tx);
+                tx.inputs.into_iter().map(|i| i.script_signature).collect()
+            }
+            ReplayCase::Script { script, .. } => vec![decode(script)],
+        };
+        for script in scripts {
+            used.extend(script.ops().map(|op| format!("{:?}", op)));
+        }
+    }
+
+    // Opcodes serialize as their variant index, so every variant is found by index
+    let missing: Vec<String> = (0u32..)
+        .map_while(|idx| bincode::deserialize::<OpCodes>(&idx.to_le_bytes()).ok())
+        .map(|op| format!("{:?}", op))
+        .filter(|op| !used.contains(op))
+        .collect();
+    assert!(missing.is_empty(), "Opcodes never replayed: {:?}", missing);
+}
+
+#[test]
+#[ignore]
+fn regenerate_replay_corpus() {
+    let lines: Vec<String> = generate_corpus()
+        .iter()
+        .map(|fixture| serde_json::to_string(fixture).unwrap())
+        .collect();
+    let path = fixtures_dir().join(GENERATED_CORPUS);
+    fs::write(path, lines.join("\n") + "\n").unwrap();
+}
+
+fn decode<T: serde::de::DeserializeOwned>(value: &str) -> T {
+    bincode::deserialize(&hex::decode(value).unwrap()).unwrap()
+}
+
+/*---- CORPUS GENERATION ----*/
+
+fn key(seed: u8) -> (PublicKey, SecretKey) {
+    sign::keypair_from_seed(&[seed; 32])
+}
+
+fn out_point(n: u32) -> OutPoint {
+    OutPoint::new(format!("g{:031x}", n), 0)
+}
+
+fn tokens(amount: u64) -> Asset {
+    Asset::Token(TokenAmount(amount))
+}
+
+fn receiver(address: String, asset: Asset) -> ReceiverInfo {
+    ReceiverInfo { address, asset }
+}
+
+fn unsigned(previous_out: &OutPoint) -> TxIn {
+    TxIn::new_from_input(previous_out.clone(), Script::new())
+}
+
+/// Input spending `previous_out` with a P2PKH script signed for `tx_version`
+fn p2pkh_input(
+    previous_out: &OutPoint,
+    tx_outs: &[TxOut],
+    (pk, sk): &(PublicKey, SecretKey),
+    tx_version: usize,
+    address_version: Option<u64>,
+) -> TxIn {
+    let unsigned = unsigned(previous_out);
+    let message = SignableMessage::tx_in(&unsigned, tx_outs);
+    let script = Script::pay2pkh(
+        message.hash_at_version(tx_version),
+        message.sign_at_version(tx_version, sk),
+        *pk,
+        address_version,
+    );
+    TxIn::new_from_input(previous_out.clone(), script)
+}
+
+/// Transaction paying `tx_outs` from `tx_ins`, at the current version
+fn tx_with(tx_ins: Vec<TxIn>, tx_outs: Vec<TxOut>) -> Transaction {
+    let mut tx = Transaction::new();
+    tx.inputs = tx_ins;
+    tx.outputs = tx_outs;
+    tx
+}
+
+fn tx_fixture(note: &str, tx: &Transaction, spent: &UtxoMap, height: u64) -> ReplayFixture {
+    let verdict = tx_is_valid(tx, height, |v| spent.get(v));
+    ReplayFixture::from_tx(tx, spent, height, &verdict).with_note(note)
+}
+
+fn script_fixture(note: &str, script: Script) -> ReplayFixture {
+    ReplayFixture::from_script(&script, &script.interpret_full()).with_note(note)
+}
+
+fn generate_corpus() -> Vec<ReplayFixture> {
+    let mut corpus = Vec::new();
+    corpus.extend(spend_fixtures());
+    corpus.extend(create_fixtures());
+    corpus.extend(template_fixtures());
+    corpus.extend(opcode_fixtures());
+    corpus
+}
+
+/// Spends of every address kind, version path and input failure
+fn spend_fixtures() -> Vec<ReplayFixture> {
+    let (alice, bob, carol) = (key(1), key(2), key(3));
+    let alice_address = construct_address(&alice.0);
+    let bob_address = construct_address(&bob.0);
+    let pay_bob = vec![TxOut::new_token_amount(
+        bob_address.clone(),
+        TokenAmount(10),
+        None,
+    )];
+    let funded = |n: u32, address: String, asset: Asset, locktime: u64| {
+        let tx_out = TxOut {
+            value: asset,
+            locktime,
+            script_public_key: Some(address),
+        };
+        UtxoMap::from([(out_point(n), tx_out)])
+    };
+    let alice_utxo = funded(0, alice_address.clone(), tokens(10), 0);
+    let keys =
+        |n: u32, owner: &(PublicKey, SecretKey)| BTreeMap::from([(out_point(n), owner.clone())]);
+    let mut corpus = Vec::new();
+
+    // P2PKH builders
+    let tx = construct_payment_tx(
+        vec![unsigned(&out_point(0))],
+        receiver(bob_address.clone(), tokens(10)),
+        None,
+        0,
+        &keys(0, &alice),
+    )
+    .unwrap();
+    corpus.push(tx_fixture("p2pkh payment", &tx, &alice_utxo, 0));
+
+    let tx = construct_payment_tx(
+        vec![unsigned(&out_point(0))],
+        receiver(bob_address.clone(), tokens(9)),
+        Some(receiver(construct_address(&carol.0), tokens(1))),
+        0,
+        &keys(0, &alice),
+    )
+    .unwrap();
+    corpus.push(tx_fixture("p2pkh payment with fee", &tx, &alice_utxo, 0));
+
+    let tx = construct_payment_tx_with_change(
+        vec![unsigned(&out_point(0))],
+        tw_chain::primitives::asset::AssetValues::token_u64(10),
+        receiver(bob_address.clone(), tokens(4)),
+        None,
+        alice_address.clone(),
+        0,
+        &keys(0, &alice),
+    )
+    .unwrap();
+    corpus.push(tx_fixture("p2pkh payment with change", &tx, &alice_utxo, 0));
+
+    let sweep_utxo: UtxoMap = (0..4)
+        .flat_map(|n| funded(n, alice_address.clone(), tokens(5), 0))
+        .collect();
+    let sweep_keys: BTreeMap<_, _> = (0..4).flat_map(|n| keys(n, &alice)).collect();
+    let tx = construct_consolidation_tx(
+        (0..4).map(|n| unsigned(&out_point(n))).collect(),
+        TokenAmount(20),
+        bob_address.clone(),
+        Some(receiver(construct_address(&carol.0), tokens(2))),
+        &sweep_keys,
+    )
+    .unwrap();
+    corpus.push(tx_fixture("consolidation", &tx, &sweep_utxo, 0));
+
+    // Address versions
+    for (address_version, note) in [
+        (Some(NETWORK_VERSION_V0), "p2pkh v0 address"),
+        (Some(NETWORK_VERSION_TEMP), "p2pkh temp address"),
+    ] {
+        let address = construct_address_for(&alice.0, address_version);
+        let utxo = funded(0, address, tokens(10), 0);
+        let tx_in = p2pkh_input(
+            &out_point(0),
+            &pay_bob,
+            &alice,
+            NETWORK_VERSION as usize,
+            address_version,
+        );
+        corpus.push(tx_fixture(
+            note,
+            &tx_with(vec![tx_in], pay_bob.clone()),
+            &utxo,
+            0,
+        ));
+    }
+
+    // Transaction versions: signatures commit to the version from 7
+    let current = NETWORK_VERSION as usize;
+    for (tx_version, signed_version, note) in [
+        (current, current, "current version"),
+        (7, 7, "version 7 signed at 7"),
+        (7, current, "version 7 signed at current version"),
+        (current, 7, "current version signed at 7"),
+    ] {
+        let tx_in = p2pkh_input(&out_point(0), &pay_bob, &alice, signed_version, None);
+        let mut tx = tx_with(vec![tx_in], pay_bob.clone());
+        tx.version = tx_version;
+        corpus.push(tx_fixture(note, &tx, &alice_utxo, 0));
+    }
+
+    // Input failures
+    let tx_in = p2pkh_input(&out_point(0), &pay_bob, &carol, current, None);
+    let tx = tx_with(vec![tx_in], pay_bob.clone());
+    corpus.push(tx_fixture("signed by the wrong key", &tx, &alice_utxo, 0));
+    corpus.push(tx_fixture(
+        "spent output not in utxo",
+        &tx,
+        &UtxoMap::new(),
+        0,
+    ));
+    corpus.push(tx_fixture(
+        "no previous outpoint",
+        &tx_with(vec![TxIn::new()], pay_bob.clone()),
+        &alice_utxo,
+        0,
+    ));
+    corpus.push(tx_fixture(
+        "no outputs",
+        &tx_with(vec![unsigned(&out_point(0))], vec![]),
+        &alice_utxo,
+        0,
+    ));
+
+    let locked_utxo = funded(0, alice_address.clone(), tokens(10), 10);
+    let tx_in = p2pkh_input(&out_point(0), &pay_bob, &alice, current, None);
+    let tx = tx_with(vec![tx_in], pay_bob.clone());
+    corpus.push(tx_fixture("locktime not met", &tx, &locked_utxo, 9));
+    corpus.push(tx_fixture("locktime met", &tx, &locked_utxo, 10));
+
+    let overspend = vec![TxOut::new_token_amount(
+        bob_address.clone(),
+        TokenAmount(11),
+        None,
+    )];
+    let tx_in = p2pkh_input(&out_point(0), &overspend, &alice, current, None);
+    corpus.push(tx_fixture(
+        "outputs exceed inputs",
+        &tx_with(vec![tx_in], overspend),
+        &alice_utxo,
+        0,
+    ));
+
+    // Items keep the genesis hash of the output they were created in
+    let item =
+        |genesis_hash: &str| Asset::Item(ItemAsset::new(1, Some(genesis_hash.to_owned()), None));
+    let item_utxo = funded(0, alice_address.clone(), Asset::item(1, None, None), 0);
+    for (genesis_hash, note) in [
+        (out_point(0).t_hash, "item payment"),
+        ("g_wrong".to_owned(), "item payment with wrong genesis hash"),
+    ] {
+        let tx = construct_payment_tx(
+            vec![unsigned(&out_point(0))],
+            receiver(bob_address.clone(), item(&genesis_hash)),
+            None,
+            0,
+            &keys(0, &alice),
+        )
+        .unwrap();
+        corpus.push(tx_fixture(note, &tx, &item_utxo, 0));
+    }
+
+    // DDE halves are spends like any other
+    let druid_info = DdeValues {
+        druid: "D0000000000000000000000000000000".to_owned(),
+        participants: 2,
+        expectations: vec![DruidExpectation {
+            from: construct_tx_ins_address(&[unsigned(&out_point(1))]),
+            to: alice_address.clone(),
+            asset: item(ITEM_GENESIS),
+        }],
+        genesis_hash: None,
+    };
+    let tx = construct_dde_tx(
+        druid_info,
+        vec![unsigned(&out_point(0))],
+        pay_bob.clone(),
+        None,
+        &keys(0, &alice),
+    )
+    .unwrap();
+    corpus.push(tx_fixture("dde half", &tx, &alice_utxo, 0));
+
+    // P2SH: a multisig escrow, redeemed with and without enough signatures
+    let parties = [key(4), key(5), key(6)];
+    let pub_keys: Vec<PublicKey> = parties.iter().map(|(pk, _)| *pk).collect();
+    let check_data = out_point(7).t_hash;
+    let escrow = |signers: &[usize]| {
+        let signatures = signers
+            .iter()
+            .map(|idx| sign::sign_detached(check_data.as_bytes(), &parties[*idx].1))
+            .collect();
+        Script::multisig_validation(2, 3, check_data.clone(), signatures, pub_keys.clone())
+    };
+    let escrow_tx = construct_p2sh_tx(
+        vec![unsigned(&out_point(0))],
+        None,
+        &escrow(&[0, 1]),
+        tokens(10),
+        0,
+        &keys(0, &alice),
+    )
+    .unwrap();
+    corpus.push(tx_fixture("p2sh funding", &escrow_tx, &alice_utxo, 0));
+
+    let escrow_utxo = UtxoMap::from([(out_point(1), escrow_tx.outputs[0].clone())]);
+    for (signers, note) in [
+        (vec![0, 1], "p2sh multisig redeem"),
+        (vec![0, 2], "p2sh redeem with another script"),
+    ] {
+        let tx_const = TxConstructor {
+            previous_out: out_point(1),
+            signatures: vec![],
+            pub_keys: vec![],
+            address_version: None,
+        };
+        let tx_ins = construct_p2sh_redeem_tx_ins(tx_const, escrow(&signers));
+        corpus.push(tx_fixture(
+            note,
+            &tx_with(tx_ins, pay_bob.clone()),
+            &escrow_utxo,
+            0,
+        ));
+    }
+
+    let burn_tx = construct_burn_tx(vec![unsigned(&out_point(0))], None, &keys(0, &alice)).unwrap();
+    corpus.push(tx_fixture("burn", &burn_tx, &alice_utxo, 0));
+    let burnt_utxo = UtxoMap::from([(
+        out_point(1),
+        TxOut {
+            value: tokens(10),
+            ..burn_tx.outputs[0].clone()
+        },
+    )]);
+    let tx_const = TxConstructor {
+        previous_out: out_point(1),
+        signatures: vec![],
+        pub_keys: vec![],
+        address_version: None,
+    };
+    let burn_script = Script::from(vec![StackEntry::Op(OpCodes::OP_BURN)]);
+    let tx_ins = construct_p2sh_redeem_tx_ins(tx_const, burn_script);
+    corpus.push(tx_fixture(
+        "spend of a burnt output",
+        &tx_with(tx_ins, pay_bob.clone()),
+        &burnt_utxo,
+        0,
+    ));
+
+    // P2SH covenant: a vault only spendable to a single output of its whole amount
+    let vault = Script::from(vec![StackEntry::Op(OpCodes::OP_1)])
+        .with_output_constraint(TxOutConstraint::ValueEquals(0, TokenAmount(10)))
+        .with_output_constraint(TxOutConstraint::Count(1));
+    let vault_utxo = funded(1, construct_p2sh_address(&vault), tokens(10), 0);
+    let pay_bob_split = vec![
+        TxOut::new_token_amount(bob_address.clone(), TokenAmount(6), None),
+        TxOut::new_token_amount(bob_address.clone(), TokenAmount(4), None),
+    ];
+    for (outputs, note) in [
+        (pay_bob.clone(), "p2sh vault spend"),
+        (pay_bob_split, "p2sh vault spend to two outputs"),
+    ] {
+        let tx_const = TxConstructor {
+            previous_out: out_point(1),
+            signatures: vec![],
+            pub_keys: vec![],
+            address_version: None,
+        };
+        let tx_ins = construct_p2sh_redeem_tx_ins(tx_const, vault.clone());
+        corpus.push(tx_fixture(note, &tx_with(tx_ins, outputs), &vault_utxo, 0));
+    }
+
+    // Dual signature (2-of-2) P2PKH
+    let dual_utxo = funded(0, construct_dual_address(&alice.0, &bob.0), tokens(10), 0);
+    let message_in = unsigned(&out_point(0));
+    let message = SignableMessage::tx_in(&message_in, &pay_bob);
+    let sig = |(_, sk): &(PublicKey, SecretKey)| message.sign_at_version(current, sk);
+    for (signatures, note) in [
+        (vec![sig(&alice), sig(&bob)], "dual p2pkh"),
+        (
+            vec![sig(&bob), sig(&alice)],
+            "dual p2pkh with swapped signatures",
+        ),
+        (vec![sig(&alice)], "dual p2pkh with one signature"),
+    ] {
+        let tx_const = TxConstructor {
+            previous_out: out_point(0),
+            signatures,
+            pub_keys: vec![alice.0, bob.0],
+            address_version: None,
+        };
+        let tx_ins = construct_dual_tx_ins(vec![tx_const], &pay_bob);
+        corpus.push(tx_fixture(
+            note,
+            &tx_with(tx_ins, pay_bob.clone()),
+            &dual_utxo,
+            0,
+        ));
+    }
+
+    corpus
+}
+
+const ITEM_GENESIS: &str = "g0000000000000000000000000000001";
+
+/// Create transactions through both the interpreted and pattern matched paths
+fn create_fixtures() -> Vec<ReplayFixture> {
+    let (pk, sk) = key(8);
+    let item_tx = construct_item_create_tx(
+        0,
+        0,
+        pk,
+        &sk,
+        1,
+        GenesisTxHashSpec::Existing(ITEM_GENESIS.to_owned()),
+        None,
+        None,
+    )
+    .unwrap();
+    let metadata = TokenMetadata::new("Replay".to_owned(), "RPL".to_owned(), 2, 1000);
+    let token_tx = construct_token_create_tx(0, 0, pk, &sk, metadata).unwrap();
+
+    let mut legacy_tx = item_tx.clone();
+    legacy_tx.version = 5;
+    let mut tampered_tx = item_tx.clone();
+    tampered_tx.outputs[0].value = Asset::item(2, Some(ITEM_GENESIS.to_owned()), None);
+
+    vec![
+        (item_tx, "item create"),
+        (token_tx, "token create with metadata"),
+        (
+            legacy_tx,
+            "item create before the interpreted create version",
+        ),
+        (
+            tampered_tx,
+            "item create with a different amount than signed",
+        ),
+    ]
+    .into_iter()
+    .map(|(tx, note)| {
+        let fixture = ReplayFixture::from_create_tx(&tx, true);
+        let expected = fixture.replay().unwrap();
+        ReplayFixture {
+            expected,
+            ..fixture
+        }
+        .with_note(note)
+    })
+    .collect()
+}
+
+/// Every script template the crate constructs
+fn template_fixtures() -> Vec<ReplayFixture> {
+    let (pk, sk) = key(9);
+    let (other_pk, other_sk) = key(10);
+    let check_data = out_point(9).t_hash;
+    let sig = sign::sign_detached(check_data.as_bytes(), &sk);
+    let other_sig = sign::sign_detached(check_data.as_bytes(), &other_sk);
+
+    let mut corpus = vec![
+        script_fixture("coinbase", Script::new_for_coinbase(1)),
+        script_fixture(
+            "create asset",
+            Script::new_create_asset(1, 1, check_data.clone(), sig, pk).unwrap(),
+        ),
+        script_fixture(
+            "member multisig",
+            Script::member_multisig(check_data.clone(), pk, sig),
+        ),
+        script_fixture(
+            "multisig lock and unlock",
+            Script::concat(
+                Script::multisig_unlock(check_data.clone(), vec![sig]),
+                Script::multisig_lock(1, 2, check_data.clone(), vec![pk, other_pk]),
+            ),
+        ),
+        script_fixture(
+            "dual p2pkh",
+            Script::pay2pkh_dual(check_data.clone(), sig, other_sig, pk, other_pk),
+        ),
+    ];
+    for address_version in [None, Some(NETWORK_VERSION_V0), Some(NETWORK_VERSION_TEMP)] {
+        let note = format!("p2pkh at address version {:?}", address_version);
+        let script = Script::pay2pkh(check_data.clone(), sig, pk, address_version);
+        corpus.push(script_fixture(&note, script));
+    }
+    corpus
+}
+
+/// A short script around every opcode, recording the current verdict
+fn opcode_fixtures() -> Vec<ReplayFixture> {
+    use OpCodes::*;
+    use StackEntry::{Bytes, Num, Op};
+
+    let (pk, sk) = key(11);
+    let check_data = out_point(11).t_hash;
+    let sig = StackEntry::Signature(sign::sign_detached(check_data.as_bytes(), &sk));
+    let msg = || Bytes(check_data.clone());
+    let bytes = |s: &str| Bytes(s.to_owned());
+    let nums = |ns: &[usize]| ns.iter().map(|n| Num(*n)).collect::<Vec<_>>();
+    let with = |entries: Vec<StackEntry>, ops: Vec<OpCodes>| {
+        let mut entries = entries;
+        entries.extend(ops.into_iter().map(Op));
+        entries
+    };
+
+    let constants = vec![
+        OP_0, OP_1, OP_2, OP_3, OP_4, OP_5, OP_6, OP_7, OP_8, OP_9, OP_10, OP_11, OP_12, OP_13,
+        OP_14, OP_15, OP_16,
+    ];
+    let reserved = vec![
+        OP_NOP, OP_NOP1, OP_NOP2, OP_NOP3, OP_NOP4, OP_NOP5, OP_NOP6, OP_NOP7, OP_NOP8, OP_NOP9,
+        OP_NOP10, OP_CREATE,
+    ];
+    let unary = vec![
+        OP_1ADD,
+        OP_1SUB,
+        OP_2MUL,
+        OP_2DIV,
+        OP_NOT,
+        OP_0NOTEQUAL,
+        OP_INVERT,
+    ];
+    let binary = vec![
+        OP_AND,
+        OP_OR,
+        OP_XOR,
+        OP_ADD,
+        OP_SUB,
+        OP_MUL,
+        OP_DIV,
+        OP_MOD,
+        OP_LSHIFT,
+        OP_RSHIFT,
+        OP_BOOLAND,
+        OP_BOOLOR,
+        OP_NUMEQUAL,
+        OP_NUMNOTEQUAL,
+        OP_LESSTHAN,
+        OP_GREATERTHAN,
+        OP_LESSTHANOREQUAL,
+        OP_GREATERTHANOREQUAL,
+        OP_MIN,
+        OP_MAX,
+    ];
+
+    let mut cases: Vec<(String, Vec<StackEntry>)> = Vec::new();
+    let mut case = |op: &str, entries: Vec<StackEntry>| cases.push((op.to_owned(), entries));
+    for op in constants {
+        case(&format!("{:?}", op), with(vec![], vec![op]));
+    }
+    for op in reserved {
+        case(&format!("{:?}", op), with(nums(&[1]), vec![op]));
+    }
+    for op in unary {
+        case(&format!("{:?}", op), with(nums(&[2]), vec![op]));
+    }
+    for op in binary {
+        case(&format!("{:?}", op), with(nums(&[6, 2]), vec![op]));
+    }
+
+    // flow control
+    case(
+        "OP_IF",
+        with(nums(&[1]), vec![OP_IF, OP_1, OP_ELSE, OP_0, OP_ENDIF]),
+    );
+    case(
+        "OP_NOTIF",
+        with(nums(&[1]), vec![OP_NOTIF, OP_0, OP_ELSE, OP_1, OP_ENDIF]),
+    );
+    case("OP_ENDIF without OP_IF", with(nums(&[1]), vec![OP_ENDIF]));
+    case(
+        "OP_IF without OP_ENDIF",
+        with(nums(&[1]), vec![OP_IF, OP_1]),
+    );
+    case("OP_VERIFY", with(nums(&[1]), vec![OP_VERIFY, OP_1]));
+    case("OP_BURN", with(nums(&[1]), vec![OP_BURN]));
+    // stack
+    case(
+        "OP_TOALTSTACK",
+        with(nums(&[1]), vec![OP_TOALTSTACK, OP_FROMALTSTACK]),
+    );
+    case(
+        "OP_FROMALTSTACK without items",
+        with(vec![], vec![OP_FROMALTSTACK]),
+    );
+    case("OP_2DROP", with(nums(&[1, 2, 3]), vec![OP_2DROP]));
+    case("OP_2DUP", with(nums(&[1, 2]), vec![OP_2DUP]));
+    case("OP_3DUP", with(nums(&[1, 2, 3]), vec![OP_3DUP]));
+    case("OP_2OVER", with(nums(&[1, 2, 3, 4]), vec![OP_2OVER]));
+    case("OP_2ROT", with(nums(&[1, 2, 3, 4, 5, 6]), vec![OP_2ROT]));
+    case("OP_2SWAP", with(nums(&[1, 2, 3, 4]), vec![OP_2SWAP]));
+    case("OP_IFDUP", with(nums(&[1]), vec![OP_IFDUP]));
+    case("OP_DEPTH", with(nums(&[1]), vec![OP_DEPTH]));
+    case("OP_DROP", with(nums(&[1, 2]), vec![OP_DROP]));
+    case("OP_DUP", with(nums(&[1]), vec![OP_DUP]));
+    case("OP_NIP", with(nums(&[1, 2]), vec![OP_NIP]));
+    case("OP_OVER", with(nums(&[1, 2]), vec![OP_OVER]));
+    case("OP_PICK", with(nums(&[1, 2, 3, 1]), vec![OP_PICK]));
+    case("OP_ROLL", with(nums(&[1, 2, 3, 1]), vec![OP_ROLL]));
+    case("OP_ROT", with(nums(&[1, 2, 3]), vec![OP_ROT]));
+    case("OP_SWAP", with(nums(&[1, 2]), vec![OP_SWAP]));
+    case("OP_TUCK", with(nums(&[1, 2]), vec![OP_TUCK]));
+    // splice
+    case("OP_CAT", with(vec![bytes("ab"), bytes("cd")], vec![OP_CAT]));
+    case(
+        "OP_SUBSTR",
+        with(vec![bytes("abcdef"), Num(1), Num(2)], vec![OP_SUBSTR]),
+    );
+    case(
+        "OP_LEFT",
+        with(vec![bytes("abcdef"), Num(2)], vec![OP_LEFT]),
+    );
+    case(
+        "OP_RIGHT",
+        with(vec![bytes("abcdef"), Num(2)], vec![OP_RIGHT]),
+    );
+    case("OP_SIZE", with(vec![bytes("abcdef")], vec![OP_SIZE]));
+    // equality
+    case("OP_EQUAL", with(nums(&[1, 1]), vec![OP_EQUAL]));
+    case(
+        "OP_EQUAL of Num and Bytes",
+        with(vec![Num(5), bytes("05")], vec![OP_EQUAL]),
+    );
+    case(
+        "OP_EQUALVERIFY",
+        with(nums(&[1, 1]), vec![OP_EQUALVERIFY, OP_1]),
+    );
+    case(
+        "OP_LOOSEEQUAL",
+        with(vec![Num(255), bytes("ff")], vec![OP_LOOSEEQUAL]),
+    );
+    case(
+        "OP_LOOSEEQUAL of Num and uppercase hex",
+        with(vec![Num(255), bytes("FF")], vec![OP_LOOSEEQUAL]),
+    );
+    case(
+        "OP_LOOSEEQUALVERIFY",
+        with(
+            vec![Num(255), bytes("00ff")],
+            vec![OP_LOOSEEQUALVERIFY, OP_1],
+        ),
+    );
+    // arithmetic
+    case("OP_DIV by zero", with(nums(&[6, 0]), vec![OP_DIV]));
+    case(
+        "OP_NUMEQUALVERIFY",
+        with(nums(&[2, 2]), vec![OP_NUMEQUALVERIFY, OP_1]),
+    );
+    case("OP_WITHIN", with(nums(&[2, 1, 3]), vec![OP_WITHIN]));
+    // crypto
+    case("OP_SHA3", with(vec![bytes("ab")], vec![OP_SHA3]));
+    for op in [OP_HASH256, OP_HASH256_V0, OP_HASH256_TEMP] {
+        case(
+            &format!("{:?}", op),
+            with(vec![StackEntry::PubKey(pk)], vec![op]),
+        );
+    }
+    let signed = vec![msg(), sig.clone(), StackEntry::PubKey(pk)];
+    case("OP_CHECKSIG", with(signed.clone(), vec![OP_CHECKSIG]));
+    case(
+        "OP_CHECKSIGVERIFY",
+        with(signed, vec![OP_CHECKSIGVERIFY, OP_1]),
+    );
+    let multisig = vec![msg(), sig, Num(1), StackEntry::PubKey(pk), Num(1)];
+    case(
+        "OP_CHECKMULTISIG",
+        with(multisig.clone(), vec![OP_CHECKMULTISIG]),
+    );
+    case(
+        "OP_CHECKMULTISIGVERIFY",
+        with(multisig, vec![OP_CHECKMULTISIGVERIFY, OP_1]),
+    );
+    // introspection, which standalone scripts have no transaction for
+    for op in [OP_TXINCOUNT, OP_TXOUTPUTCOUNT] {
+        case(&format!("{:?} without a transaction", op), vec![Op(op)]);
+    }
+    case(
+        "OP_TXOUTVALUE without a transaction",
+        with(nums(&[0]), vec![OP_TXOUTVALUE]),
+    );
+
+    cases
+        .into_iter()
+        .map(|(note, entries)| script_fixture(&note, Script::from(entries)))
+        .collect()
+}